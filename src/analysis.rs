@@ -0,0 +1,417 @@
+//! Template analysis module
+//!
+//! This module walks the Tera AST of a template to find which context
+//! variables, filters and loops it uses, following inheritance and includes.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use tera::ast::{Expr, ExprVal, LogicOperator, Node};
+use tera::Tera;
+
+/// Context variables a template reads, including its parents and includes
+///
+/// Variables only used behind `default(...)`, an `is defined` test or as an
+/// `if` condition are treated as optional and left out.
+pub fn required_variables(tera: &Tera, template_name: &str) -> Result<BTreeSet<String>> {
+    Ok(analyze(tera, template_name)?.variables)
+}
+
+/// What a template uses from its context, found statically
+#[derive(Debug, Default, PartialEq)]
+pub struct TemplateUsage {
+    /// Context variables the template needs
+    pub variables: BTreeSet<String>,
+    /// Context variables only read behind `default(...)`, `is defined` or as an `if` condition
+    pub optional: BTreeSet<String>,
+    /// Filters applied anywhere in the template
+    pub filters: BTreeSet<String>,
+    /// `for` loops, in template order
+    pub loops: Vec<LoopUsage>,
+    /// Context paths read, such as `name` or `entity.name`, loop containers included
+    pub paths: BTreeSet<String>,
+    /// Context paths only tested by `if` and never printed, such as `{% if public %}`
+    pub conditions: BTreeSet<String>,
+    /// Context paths passed to filters that take arrays, such as `join`
+    pub lists: BTreeSet<String>,
+}
+
+/// Filters that only apply to arrays
+const LIST_FILTERS: &[&str] = &[
+    "join", "first", "last", "nth", "sort", "unique", "slice", "group_by", "filter", "map",
+    "concat",
+];
+
+/// A `for` loop and the attributes it reads from each element
+#[derive(Debug, PartialEq)]
+pub struct LoopUsage {
+    /// Loop variable, `value` or `key, value`
+    pub binding: String,
+    /// Iterated expression, e.g. `fields` or `entity.fields`
+    pub container: String,
+    /// Attributes read from the loop variable
+    pub attributes: BTreeSet<String>,
+}
+
+impl fmt::Display for LoopUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "for {} in {}", self.binding, self.container)?;
+        if !self.attributes.is_empty() {
+            let attributes: Vec<_> = self.attributes.iter().map(String::as_str).collect();
+            write!(f, " (uses {})", attributes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Analyze a template, including its parents and includes
+pub fn analyze(tera: &Tera, template_name: &str) -> Result<TemplateUsage> {
+    let mut walker = Walker::default();
+    let mut pending = vec![template_name.to_string()];
+    let mut visited = HashSet::new();
+
+    while let Some(name) = pending.pop() {
+        if !visited.insert(name.clone()) {
+            continue;
+        }
+
+        let template = tera
+            .get_template(&name)
+            .with_context(|| format!("Template '{}' not found", name))?;
+
+        walker.visit_nodes(&template.ast);
+        pending.extend(template.parents.iter().cloned());
+        // Includes of unknown templates only fail at render time
+        pending.extend(
+            walker
+                .includes
+                .drain(..)
+                .filter(|include| tera.get_template(include).is_ok()),
+        );
+    }
+
+    let mut usage = walker.usage;
+    usage
+        .optional
+        .retain(|name| !usage.variables.contains(name));
+    usage.conditions.retain(|path| !usage.paths.contains(path));
+    Ok(usage)
+}
+
+#[derive(Default)]
+struct Walker {
+    /// Names bound by `for` and `set`, innermost scope last
+    scopes: Vec<HashSet<String>>,
+    /// Indices into `usage.loops` of the enclosing loops, innermost last
+    active_loops: Vec<usize>,
+    usage: TemplateUsage,
+    includes: Vec<String>,
+}
+
+impl Walker {
+    fn visit_nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.visit_node(node);
+        }
+    }
+
+    fn visit_node(&mut self, node: &Node) {
+        match node {
+            Node::VariableBlock(_, expr) => self.visit_expr(expr),
+            Node::Set(_, set) => {
+                self.visit_expr(&set.value);
+                self.bind(&set.key, set.global);
+            }
+            Node::Include(_, names, _) => self.includes.extend(names.iter().cloned()),
+            Node::FilterSection(_, section, _) => {
+                self.usage.filters.insert(section.filter.name.clone());
+                section
+                    .filter
+                    .args
+                    .values()
+                    .for_each(|arg| self.visit_expr(arg));
+                self.visit_nodes(&section.body);
+            }
+            Node::Block(_, block, _) => self.visit_nodes(&block.body),
+            Node::Forloop(_, forloop, _) => {
+                self.visit_expr(&forloop.container);
+
+                let binding = match &forloop.key {
+                    Some(key) => format!("{}, {}", key, forloop.value),
+                    None => forloop.value.clone(),
+                };
+                let container = match &forloop.container.val {
+                    ExprVal::Ident(ident) => ident.clone(),
+                    _ => "<expression>".to_string(),
+                };
+                self.usage.loops.push(LoopUsage {
+                    binding,
+                    container,
+                    attributes: BTreeSet::new(),
+                });
+
+                let mut scope = HashSet::from([forloop.value.clone(), "loop".to_string()]);
+                scope.extend(forloop.key.clone());
+                self.scopes.push(scope);
+                self.active_loops.push(self.usage.loops.len() - 1);
+                self.visit_nodes(&forloop.body);
+                self.active_loops.pop();
+                self.scopes.pop();
+
+                if let Some(empty_body) = &forloop.empty_body {
+                    self.visit_nodes(empty_body);
+                }
+            }
+            Node::If(if_node, _) => {
+                for (_, condition, body) in &if_node.conditions {
+                    self.visit_condition(condition);
+
+                    // Names tested with `is defined` are safe to use in the body
+                    let mut guarded = HashSet::new();
+                    defined_guards(condition, &mut guarded);
+                    self.scopes.push(guarded);
+                    self.visit_nodes(body);
+                    self.scopes.pop();
+                }
+                if let Some((_, body)) = &if_node.otherwise {
+                    self.visit_nodes(body);
+                }
+            }
+            // Macros only see their own arguments
+            Node::MacroDefinition(..) => {}
+            _ => {}
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        for filter in &expr.filters {
+            self.usage.filters.insert(filter.name.clone());
+            filter.args.values().for_each(|arg| self.visit_expr(arg));
+        }
+
+        if let ExprVal::Ident(ident) = &expr.val {
+            let listed = expr
+                .filters
+                .iter()
+                .any(|filter| LIST_FILTERS.contains(&filter.name.as_str()));
+            if listed
+                && !self
+                    .scopes
+                    .iter()
+                    .any(|scope| scope.contains(root_of(ident)))
+            {
+                self.usage.lists.insert(context_path(ident));
+            }
+        }
+
+        match &expr.val {
+            ExprVal::Ident(ident) if expr.has_default_filter() => self.use_optional(ident),
+            ExprVal::Ident(ident) => self.use_ident(ident),
+            ExprVal::Math(math) => {
+                self.visit_expr(&math.lhs);
+                self.visit_expr(&math.rhs);
+            }
+            ExprVal::Logic(logic) => {
+                self.visit_expr(&logic.lhs);
+                self.visit_expr(&logic.rhs);
+            }
+            ExprVal::Test(test) => {
+                if matches!(test.name.as_str(), "defined" | "undefined") {
+                    self.use_optional(&test.ident);
+                } else {
+                    self.use_ident(&test.ident);
+                }
+                test.args.iter().for_each(|arg| self.visit_expr(arg));
+            }
+            ExprVal::MacroCall(call) => call.args.values().for_each(|arg| self.visit_expr(arg)),
+            ExprVal::FunctionCall(call) => call.args.values().for_each(|arg| self.visit_expr(arg)),
+            ExprVal::Array(values) => values.iter().for_each(|value| self.visit_expr(value)),
+            ExprVal::StringConcat(concat) => {
+                for value in &concat.values {
+                    if let ExprVal::Ident(ident) = value {
+                        self.use_ident(ident);
+                    }
+                }
+            }
+            ExprVal::In(in_expr) => {
+                self.visit_expr(&in_expr.lhs);
+                self.visit_expr(&in_expr.rhs);
+            }
+            _ => {}
+        }
+    }
+
+    /// Visit an `if` condition, recording the paths it tests as is
+    ///
+    /// Tera treats a missing variable tested as is as false, so it is optional.
+    fn visit_condition(&mut self, condition: &Expr) {
+        match &condition.val {
+            ExprVal::Ident(ident) if condition.filters.is_empty() => {
+                if let Some(root) = self.context_root(ident) {
+                    self.usage.optional.insert(root.to_string());
+                    self.usage.conditions.insert(context_path(ident));
+                }
+            }
+            ExprVal::Logic(logic)
+                if matches!(logic.operator, LogicOperator::And | LogicOperator::Or) =>
+            {
+                self.visit_condition(&logic.lhs);
+                self.visit_condition(&logic.rhs);
+            }
+            _ => self.visit_expr(condition),
+        }
+    }
+
+    fn bind(&mut self, name: &str, global: bool) {
+        if self.scopes.is_empty() {
+            self.scopes.push(HashSet::new());
+        }
+        let scope = if global { 0 } else { self.scopes.len() - 1 };
+        self.scopes[scope].insert(name.to_string());
+    }
+
+    fn use_ident(&mut self, ident: &str) {
+        if let Some(root) = self.context_root(ident) {
+            self.usage.variables.insert(root.to_string());
+            self.usage.paths.insert(context_path(ident));
+        }
+    }
+
+    fn use_optional(&mut self, ident: &str) {
+        if let Some(root) = self.context_root(ident) {
+            self.usage.optional.insert(root.to_string());
+            self.usage.paths.insert(context_path(ident));
+        }
+    }
+
+    /// Root name of an identifier read from the context, or `None` for locals
+    ///
+    /// Attributes read from a loop variable are recorded on that loop.
+    fn context_root<'a>(&mut self, ident: &'a str) -> Option<&'a str> {
+        let root = root_of(ident);
+        if root.is_empty() || root == "__tera_context" {
+            return None;
+        }
+
+        if let Some(attribute) = ident
+            .strip_prefix(root)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .and_then(|rest| rest.split(['.', '[']).next())
+        {
+            let innermost =
+                self.active_loops.iter().rev().copied().find(|&index| {
+                    self.usage.loops[index].binding.rsplit(", ").next() == Some(root)
+                });
+            if let Some(index) = innermost {
+                self.usage.loops[index]
+                    .attributes
+                    .insert(attribute.to_string());
+            }
+        }
+
+        if self.scopes.iter().any(|scope| scope.contains(root)) {
+            return None;
+        }
+        Some(root)
+    }
+}
+
+/// First name of a dotted identifier, such as `entity` for `entity.name`
+fn root_of(ident: &str) -> &str {
+    ident.split(['.', '[']).next().unwrap_or(ident)
+}
+
+/// Dotted path of an identifier, up to its first index such as `[0]`
+fn context_path(ident: &str) -> String {
+    ident.split('[').next().unwrap_or(ident).to_string()
+}
+
+/// Collect the roots of identifiers a condition requires to be defined
+fn defined_guards(condition: &Expr, guarded: &mut HashSet<String>) {
+    if condition.negated {
+        return;
+    }
+
+    match &condition.val {
+        ExprVal::Test(test) if test.name == "defined" && !test.negated => {
+            let root = root_of(&test.ident);
+            guarded.insert(root.to_string());
+        }
+        ExprVal::Logic(logic) if logic.operator == LogicOperator::And => {
+            defined_guards(&logic.lhs, guarded);
+            defined_guards(&logic.rhs, guarded);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(templates: &[(&str, &str)], name: &str) -> Vec<String> {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(templates.to_vec()).unwrap();
+        required_variables(&tera, name)
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_required_variables_skips_locals() {
+        let template = "pub struct {{ name | capitalize }} {\n\
+            {% for field in fields %}    pub {{ field.name }}: {{ field.ty }},\n{% endfor %}\
+            {% set derive = extra_derive | default(value=\"Debug\") %}{{ derive }}\n}";
+
+        assert_eq!(variables(&[("t", template)], "t"), vec!["fields", "name"]);
+    }
+
+    #[test]
+    fn test_required_variables_follows_inheritance_and_includes() {
+        let templates = [
+            ("base", "{% block body %}{{ header }}{% endblock %}"),
+            ("part", "{{ footer }}"),
+            (
+                "child",
+                "{% extends \"base\" %}{% block body %}{% include \"part\" %}{{ name }}{% endblock %}",
+            ),
+        ];
+
+        assert_eq!(
+            variables(&templates, "child"),
+            vec!["footer", "header", "name"]
+        );
+    }
+
+    #[test]
+    fn test_analyze_reports_filters_loops_and_optional() {
+        let template =
+            "{% for field in fields %}{{ field.name | upper }}: {{ field.ty }}\n{% endfor %}\
+            {% for key, value in extra %}{{ key }}{% endfor %}\
+            {% if doc is defined %}{{ doc }}{% endif %}{{ derive | default(value=\"Debug\") }}\
+            {% if public %}pub {% endif %}";
+
+        let mut tera = Tera::default();
+        tera.add_raw_template("t", template).unwrap();
+        let usage = analyze(&tera, "t").unwrap();
+
+        assert_eq!(
+            usage.variables.into_iter().collect::<Vec<_>>(),
+            vec!["extra", "fields"]
+        );
+        assert_eq!(
+            usage.optional.into_iter().collect::<Vec<_>>(),
+            vec!["derive", "doc", "public"]
+        );
+        assert_eq!(
+            usage.filters.into_iter().collect::<Vec<_>>(),
+            vec!["default", "upper"]
+        );
+        assert_eq!(
+            usage.loops[0].to_string(),
+            "for field in fields (uses name, ty)"
+        );
+        assert_eq!(usage.loops[1].to_string(), "for key, value in extra");
+    }
+}
@@ -0,0 +1,109 @@
+//! In-memory API module
+//!
+//! This module is the library entry point for embedding the generator in
+//! servers and tests: [`run`] renders a template given as a string, merges it
+//! into existing code given as a string, and never touches the filesystem.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::ast_parser::ParsedFile;
+use crate::diagnostics::{with_origin, with_template, GENERATED_CODE};
+use crate::diff::compute_patch;
+use crate::generator::{merge_generated, GenerateOptions};
+use crate::merger::{messages, MergeStrategy};
+use crate::summary::describe_file_header;
+use crate::template::TemplateEngine;
+
+/// Template, context and existing code to generate from
+#[derive(Debug, Clone)]
+pub struct GenerateRequest {
+    /// Tera template source
+    pub template_src: String,
+    /// Context data the template is rendered with
+    pub context: HashMap<String, Value>,
+    /// Code to merge into, `None` to return the rendered template as is
+    pub existing_src: Option<String>,
+    /// How conflicts with manual edits are resolved
+    pub strategy: MergeStrategy,
+}
+
+/// Merged code with the changes and conflicts of the merge
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerateResponse {
+    /// Final source code
+    pub merged_src: String,
+    /// Conflicts that were resolved by the merge strategy
+    pub conflicts: Vec<String>,
+    /// Summary of the template changes applied to the existing code
+    pub patch: Vec<String>,
+}
+
+/// Render a template and merge it into existing code, in memory
+pub fn run(request: GenerateRequest) -> Result<GenerateResponse> {
+    let engine = TemplateEngine::from_string("template", &request.template_src)?;
+    let generated_code = engine.render("template", &request.context)?;
+
+    let existing_src = request.existing_src.as_deref().unwrap_or("");
+    let generated_ast = ParsedFile::parse(&generated_code)
+        .map_err(|error| with_template(with_origin(error, GENERATED_CODE), &request.template_src))
+        .context("Failed to parse generated code")?;
+    let existing_ast = ParsedFile::parse(existing_src)
+        .map_err(|error| with_origin(error, "existing code"))
+        .context("Failed to parse existing code")?;
+    let patch = compute_patch(existing_ast.items(), generated_ast.items())?;
+    let header = describe_file_header(&existing_ast.syntax_tree, &generated_ast.syntax_tree);
+    let patch = header.into_iter().chain(patch.summary()).collect();
+
+    let options = GenerateOptions {
+        strategy: request.strategy,
+        ..GenerateOptions::default()
+    };
+    let output = merge_generated(
+        generated_code,
+        request.existing_src.as_deref(),
+        options,
+        None,
+    )?;
+
+    Ok(GenerateResponse {
+        merged_src: output.code,
+        conflicts: messages(&output.conflicts),
+        patch,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::create_context;
+    use serde_json::json;
+
+    #[test]
+    fn test_run_in_memory() {
+        let request = GenerateRequest {
+            template_src: "pub struct {{ name }};\npub fn new_{{ name | lower }}() {}".to_string(),
+            context: create_context(vec![("name", json!("User"))]),
+            existing_src: Some("pub struct User;\npub fn helper() {}\n".to_string()),
+            strategy: MergeStrategy::PreferManual,
+        };
+
+        let response = run(request.clone()).unwrap();
+        assert!(response.merged_src.contains("pub fn helper()"));
+        assert!(response.merged_src.contains("pub fn new_user()"));
+        assert_eq!(response.conflicts.len(), 1);
+        assert_eq!(
+            response.patch,
+            vec!["Insert fn `new_user`", "Delete `helper`"]
+        );
+
+        let response = run(GenerateRequest {
+            existing_src: None,
+            ..request
+        })
+        .unwrap();
+        assert!(response.conflicts.is_empty());
+        assert_eq!(response.patch.len(), 2);
+    }
+}
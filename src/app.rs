@@ -0,0 +1,1746 @@
+//! Command dispatch module
+//!
+//! This module runs the parsed CLI commands and is shared by the
+//! `rust-patchs-templates` and `cargo-rpt` binaries.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::ast_parser::ParsedFile;
+use crate::audit::{self, AuditRecord};
+use crate::backend::{Engine, RenderOptions};
+use crate::cargo_toml::diff_toml;
+use crate::check::{check_manifest, find_changes, Finding, Severity, TargetCheck};
+use crate::cli::{Cli, Commands, HookAction};
+use crate::commit_hook;
+use crate::config::{Config, Target};
+use crate::diagnostics::{dump_render, with_origin, GENERATED_CODE, LAST_RENDER_FILE};
+use crate::diff::{compute_patch, unified_patch};
+use crate::edition::Edition;
+use crate::events::{Event, EventSink};
+use crate::front_matter::FrontMatter;
+use crate::generator::{
+    check_conflicts, diff_sources, explain, generate_cached, generate_files, is_up_to_date,
+    merge_generated, render_template_file, show_diff, GenerateOptions, Language, MergedOutput,
+    OnParseError,
+};
+use crate::github::to_annotations;
+use crate::golden::{run_cases, CaseOutcome};
+use crate::incremental::{hash_target, RegenerationCache};
+use crate::input::{is_stdio, read_source};
+use crate::limits::{self, Limits};
+use crate::lockfile::{Lockfile, LOCK_FILE};
+use crate::markdown::diff_sections;
+use crate::matching::{with_comparison, with_generics, Comparison, GenericsMatching};
+use crate::merger::{messages, Warning};
+use crate::ordering::Ordering;
+use crate::outcome::{FileOutcome, GenerateOutcome, VerificationOutcome};
+use crate::pager::print_paged;
+use crate::placement::PlacementRules;
+use crate::preview::{self, FilePreview};
+use crate::progress::ProgressBar;
+use crate::regions::diff_regions;
+use crate::remote::RemoteTemplate;
+use crate::report::{classify, to_html, Origin, TargetReport};
+use crate::resolutions::Resolutions;
+use crate::sarif::to_sarif;
+use crate::status::{target_status, TargetStatus};
+use crate::stored_patch::StoredPatch;
+use crate::strategy::StrategyRules;
+use crate::target_refs::{dependencies, generation_order, planned_context, target_context};
+use crate::template::{parse_context, TemplateCache, TemplateEngine};
+use crate::templatize::templatize;
+use crate::three_way::merge_three_way;
+use crate::timings::{time, Stage};
+use crate::verify::Verification;
+use crate::{
+    completions, context_types, hooks, init, lint, modules, server, snapshot, timings, verify,
+};
+
+/// Run a parsed command line with the given project configuration
+pub fn run(cli: Cli, config: Config) -> Result<()> {
+    let dump = cli
+        .debug_render
+        .clone()
+        .unwrap_or_else(|| config.resolve_path(Path::new(LAST_RENDER_FILE)));
+    run_command(cli, config).map_err(|error| dump_render(error, &dump))
+}
+
+/// Run a command, see [`run`]
+fn run_command(mut cli: Cli, mut config: Config) -> Result<()> {
+    let render = RenderOptions {
+        engine: Engine::parse(&cli.engine)?,
+        lenient: cli.lenient,
+    };
+
+    // Matching flags add to the `[matching]` table, which every command reads
+    config.matching.threshold = cli.match_threshold.or(config.matching.threshold);
+    config.matching.ignore_docs |= cli.ignore_docs;
+    config
+        .matching
+        .ignore_attrs
+        .extend(cli.ignore_attrs.iter().cloned());
+
+    crate::template::install_template_dirs(
+        config
+            .template_dirs
+            .iter()
+            .map(|dir| config.resolve_path(dir))
+            .collect(),
+    );
+    crate::template::install_overrides_dir(config.overrides_dir());
+    config.deterministic |= cli.deterministic;
+    if config.deterministic {
+        crate::deterministic::enable();
+    }
+    if cli.timings {
+        timings::enable();
+    }
+
+    #[cfg(feature = "plugins")]
+    crate::plugins::install(crate::plugins::Plugins::load(&config)?);
+    #[cfg(not(feature = "plugins"))]
+    if !config.plugins.is_empty() {
+        anyhow::bail!("[plugins] requires building with the `plugins` feature");
+    }
+
+    // Resolve built-in templates, check out those given as git URLs, pinned by
+    // the lockfile, then use the project's overrides of shared templates
+    if let Some(template) = cli.command.template_mut() {
+        let mut lockfile = Lockfile::load(&config)?;
+        *template = resolve_template(
+            &config,
+            &mut lockfile,
+            template,
+            Path::new(""),
+            cli.template_ref.as_deref(),
+        )?;
+        lockfile.save()?;
+    }
+    if cli.command.uses_targets() {
+        let mut lockfile = Lockfile::load(&config)?;
+        let templates = config
+            .targets
+            .iter()
+            .map(|target| {
+                resolve_template(&config, &mut lockfile, &target.template, &config.root, None)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        lockfile.save()?;
+        for (target, template) in config.targets.iter_mut().zip(templates) {
+            if template != config.root.join(&target.template) {
+                target.template = std::path::absolute(template)?;
+            }
+        }
+    }
+
+    if cli.command.stdin_inputs() > 1 {
+        anyhow::bail!("Only one of --template and --existing can be read from stdin");
+    }
+
+    // Commands comparing items outside of a merge match them as the config says
+    let generics = GenericsMatching::from_config(&config)?;
+    let comparison = Comparison::from_config(&config)?;
+    with_generics(generics, || {
+        with_comparison(comparison, || run_subcommand(cli, config, render))
+    })
+}
+
+/// Run the command of `cli` once the project is set up, see [`run_command`]
+fn run_subcommand(cli: Cli, config: Config, render: RenderOptions) -> Result<()> {
+    match cli.command {
+        Commands::Init { dir, force } => {
+            for path in init::scaffold(&dir, force)? {
+                println!("✓ Created {}", path.display());
+            }
+            println!("Run `rust-patchs-templates generate-all` to generate the example");
+        }
+
+        Commands::Generate {
+            template,
+            context,
+            output,
+            existing,
+            base,
+            strategy,
+            respect_order,
+            formatter,
+            verify,
+            check,
+            language,
+            on_parse_error,
+            provenance,
+            format,
+            report,
+            max_conflicts,
+            fail_on_warnings,
+        } => {
+            let report = report.as_deref().map(preview::report_path).transpose()?;
+            // Flags the command line leaves out fall back to the template's front matter
+            let front_matter = if is_stdio(&template) || template.is_file() {
+                FrontMatter::from_template_file(&template)?
+            } else {
+                FrontMatter::default()
+            };
+            let output = match output {
+                Some(output) => output,
+                None => front_matter
+                    .output_path(&load_context_data(&context)?)?
+                    .context("No --output given and the template declares no output path")?,
+            };
+            let strategy = strategy
+                .or(front_matter.strategy)
+                .unwrap_or_else(|| "manual".to_string());
+            let verify = verify.as_deref().map(Verification::parse).transpose()?;
+            if verify.is_some() && is_stdio(&output) {
+                anyhow::bail!("--verify needs an output file, not stdout");
+            }
+            let json = format == "json";
+            if json && is_stdio(&output) {
+                anyhow::bail!("--format json needs an output file, not stdout");
+            }
+
+            // Without --base, a snapshot of the output is the base
+            let snapshotted = match (&base, template.is_dir() || is_stdio(&output)) {
+                (None, false) => snapshot::relative_output(&config, &output)
+                    .ok()
+                    .filter(|relative| snapshot::base_of(&config, relative).is_some()),
+                _ => None,
+            };
+            let base = base.or_else(|| {
+                snapshotted
+                    .as_ref()
+                    .map(|relative| snapshot::base_path(&config, relative))
+            });
+
+            // Parse strategy and formatter
+            let options = GenerateOptions {
+                render,
+                strategy: Commands::parse_strategy(&strategy),
+                respect_order,
+                formatter: Commands::parse_formatter(&formatter),
+                policy: crate::policy::configured(&config)?,
+                strategies: StrategyRules::from_config(&config)?
+                    .with_resolutions(Resolutions::load(&config)?),
+                placement: PlacementRules::from_config(&config)?,
+                ordering: Ordering::from_config(&config)?,
+                language: Commands::parse_language(&language),
+                on_parse_error: Commands::parse_on_parse_error(&on_parse_error),
+                edition: Edition::from_config(&config)?,
+                base,
+                events: None,
+                matcher: None,
+                generics: GenericsMatching::from_config(&config)?,
+                comparison: Comparison::from_config(&config)?,
+                provenance: provenance || config.provenance,
+            };
+            let back_up = options.on_parse_error == OnParseError::OverwriteWithBackup;
+
+            if check {
+                let context_data = load_context_data(&context)?;
+                let edition = options.edition;
+                let files = generate_files(
+                    &template,
+                    context_data,
+                    &output,
+                    existing.as_deref(),
+                    options,
+                )?;
+
+                let mut stale = false;
+                for file in &files {
+                    let edition = Edition::resolve(edition, file.path.parent());
+                    if is_up_to_date(&file.path, &file.code, edition)? {
+                        println!("✓ {} is up to date", file.path.display());
+                    } else {
+                        println!("✗ {} is out of date", file.path.display());
+                        stale = true;
+                    }
+                }
+                if stale {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+
+            // Run pre-generation hooks, any failure aborts generation
+            hooks::run_hooks(
+                "pre",
+                &config.hooks.pre,
+                &config.root,
+                Some((&template, &output)),
+            )?;
+
+            // Load context data
+            let context_data = load_context_data(&context)?;
+
+            // Generate code
+            let files = generate_files(
+                &template,
+                context_data.clone(),
+                &output,
+                existing.as_deref(),
+                options,
+            )?;
+
+            let limits = Limits {
+                max_conflicts,
+                fail_on_warnings,
+            };
+            let warnings: Vec<Warning> = files
+                .iter()
+                .flat_map(|file| limits::warnings_of(&file.path, &file.conflicts))
+                .collect();
+            limits.check(&warnings)?;
+
+            // Write output
+            let language = Commands::parse_language(&language);
+            let mut outcome = GenerateOutcome::default();
+            let mut warnings = Vec::new();
+            let mut previews = Vec::new();
+            for file in files {
+                if is_stdio(&file.path) {
+                    print!("{}", file.code);
+                    continue;
+                }
+                if let Some(parent) = file.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let created = !file.path.exists();
+                let previous = fs::read_to_string(&file.path).ok();
+                if report.is_some() {
+                    previews.push(FilePreview::new(
+                        &file.path,
+                        previous.as_deref(),
+                        &file.code,
+                        messages(&file.conflicts),
+                    ));
+                }
+                let mut file_outcome = FileOutcome::new(
+                    file.path.clone(),
+                    previous.as_deref(),
+                    &file.code,
+                    Language::of_path(&file.path).unwrap_or(language),
+                    messages(&file.conflicts),
+                );
+                if back_up {
+                    if let Some(backup) = back_up_unparsable(&file.path, previous.as_deref())? {
+                        if !json {
+                            println!("✓ Backed up unparsable file to: {}", backup.display());
+                        }
+                        file_outcome.backup = Some(backup);
+                    }
+                }
+                let written = time(Stage::Write, || match verify {
+                    Some(verification) => {
+                        verify::write_verified(&file.path, &file.code, verification)
+                    }
+                    None => fs::write(&file.path, &file.code)
+                        .map(|()| Vec::new())
+                        .map_err(Into::into),
+                });
+                let lints = match (written, verify) {
+                    (Ok(lints), _) => lints,
+                    // The failure is part of the result, along with the files written before
+                    (Err(error), Some(verification)) if json => {
+                        outcome.verification = Some(VerificationOutcome {
+                            mode: verification.as_str().to_string(),
+                            passed: false,
+                            warnings,
+                            error: Some(format!("{:#}", error)),
+                        });
+                        println!("{}", serde_json::to_string_pretty(&outcome)?);
+                        std::process::exit(1);
+                    }
+                    (Err(error), _) => return Err(error),
+                };
+                if json {
+                    warnings.extend(lints);
+                } else {
+                    for lint in lints {
+                        eprintln!("Warning: {}", lint);
+                    }
+                }
+                if config.audit {
+                    audit::append(
+                        &config,
+                        &AuditRecord::new(
+                            &template,
+                            &context,
+                            &file.path,
+                            &strategy,
+                            previous.as_deref(),
+                            &file.code,
+                            messages(&file.conflicts),
+                        ),
+                    )?;
+                }
+
+                if json {
+                    if created {
+                        file_outcome.declared_in = modules::declare_module(&file.path)?;
+                    }
+                    outcome.files.push(file_outcome);
+                    continue;
+                }
+                println!("✓ Generated code written to: {}", file.path.display());
+                if created {
+                    declare_new_module(&file.path)?;
+                }
+            }
+            if json {
+                outcome.verification = verify.map(|verification| VerificationOutcome {
+                    mode: verification.as_str().to_string(),
+                    passed: true,
+                    warnings,
+                    error: None,
+                });
+                println!("{}", serde_json::to_string_pretty(&outcome)?);
+            } else if verify.is_some() {
+                println!("✓ Output verified");
+            }
+            if let Some(report) = &report {
+                preview::write_report(report, &previews)?;
+                if !json {
+                    println!("✓ Report written to: {}", report.display());
+                }
+            }
+            if let Some(relative) = &snapshotted {
+                advance_base(&config, relative, &template, &context_data, render)?;
+            }
+            if cli.timings {
+                eprint!("{}", timings::report(&[(output.clone(), timings::take())]));
+            }
+
+            // Run post-generation hooks
+            hooks::run_hooks(
+                "post",
+                &config.hooks.post,
+                &config.root,
+                Some((&template, &output)),
+            )?;
+        }
+
+        Commands::GenerateAll {
+            force,
+            report,
+            max_conflicts,
+            fail_on_warnings,
+        } => {
+            if config.targets.is_empty() {
+                anyhow::bail!("No [[targets]] defined in the config file");
+            }
+            let report = report.as_deref().map(preview::report_path).transpose()?;
+
+            hooks::run_hooks("pre", &config.hooks.pre, &config.root, None)?;
+
+            let mut cache = TemplateCache::new();
+            let mut regeneration = RegenerationCache::load(&config);
+            let mut lockfile = Lockfile::load(&config)?;
+            let mut timed = Vec::new();
+            let mut previews = Vec::new();
+            let order = generation_order(&config)?;
+            let progress = Arc::new(ProgressBar::new(order.len()));
+
+            // Merge every stale target in memory first when limits may abort the run, each
+            // against the planned outputs of the targets it depends on
+            let limits = Limits {
+                max_conflicts,
+                fail_on_warnings,
+            };
+            let mut planned = HashMap::new();
+            if limits.is_set() {
+                let mut codes = HashMap::new();
+                let mut warnings = Vec::new();
+                for target in &order {
+                    let dependency_changed =
+                        dependencies(&config, target)?.iter().any(|dependency| {
+                            codes.get(&dependency.output).is_some_and(|code: &String| {
+                                fs::read_to_string(config.resolve_path(&dependency.output))
+                                    .ok()
+                                    .as_ref()
+                                    != Some(code)
+                            })
+                        });
+                    if !force
+                        && !dependency_changed
+                        && regeneration.is_fresh(target, &hash_target(&config, target, render)?)
+                    {
+                        continue;
+                    }
+                    let options = GenerateOptions {
+                        events: Some(progress.clone()),
+                        ..target_options(&config, target, render)?
+                    };
+                    let result = generate_target(&mut cache, &config, target, options, &codes)?;
+                    warnings.extend(limits::warnings_of(&target.output, &result.conflicts));
+                    codes.insert(target.output.clone(), result.code.clone());
+                    planned.insert(target.output.clone(), result);
+                }
+                limits.check(&warnings)?;
+            }
+            for target in order {
+                let output = config.resolve_path(&target.output);
+                // Planned targets are written as they were checked
+                let result = match planned.remove(&target.output) {
+                    Some(result) => result,
+                    None => {
+                        let hashes = hash_target(&config, target, render)?;
+                        if !force && regeneration.is_fresh(target, &hashes) {
+                            lockfile.record_output(target, &hashes);
+                            progress.println(&format!("• Up to date: {}", output.display()));
+                            progress.advance();
+                            continue;
+                        }
+
+                        let options = GenerateOptions {
+                            events: Some(progress.clone()),
+                            ..target_options(&config, target, render)?
+                        };
+                        generate_target(&mut cache, &config, target, options, &HashMap::new())?
+                    }
+                };
+
+                if let Some(parent) = output.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let created = !output.exists();
+                let previous = fs::read_to_string(&output).ok();
+                if report.is_some() {
+                    previews.push(FilePreview::new(
+                        &output,
+                        previous.as_deref(),
+                        &result.code,
+                        messages(&result.conflicts),
+                    ));
+                }
+                time(Stage::Write, || fs::write(&output, &result.code))?;
+                timed.push((target.output.clone(), timings::take()));
+                progress.emit(Event::FileWritten {
+                    path: output.clone(),
+                });
+                let hashes = hash_target(&config, target, render)?;
+                lockfile.record_output(target, &hashes);
+                regeneration.record(target, hashes);
+                if snapshot::base_of(&config, &target.output).is_some() {
+                    advance_base(
+                        &config,
+                        &target.output,
+                        &config.resolve_path(&target.template),
+                        &target_context(&config, target)?,
+                        render,
+                    )?;
+                }
+                if config.audit {
+                    audit::append(
+                        &config,
+                        &AuditRecord::new(
+                            &config.resolve_path(&target.template),
+                            &config.resolve_path(&target.context),
+                            &target.output,
+                            &target.strategy,
+                            previous.as_deref(),
+                            &result.code,
+                            messages(&result.conflicts),
+                        ),
+                    )?;
+                }
+
+                progress.println(&format!(
+                    "✓ Generated code written to: {}",
+                    output.display()
+                ));
+                if created {
+                    declare_new_module(&output)?;
+                }
+            }
+            progress.finish();
+            if let Some(report) = &report {
+                preview::write_report(report, &previews)?;
+                println!("✓ Report written to: {}", report.display());
+            }
+
+            // Outputs of targets removed from the manifest that were deleted too
+            for output in regeneration.prune(&config) {
+                let output = config.resolve_path(&output);
+                if output.exists() {
+                    continue;
+                }
+                if let Some(parent) = modules::undeclare_module(&output)? {
+                    println!(
+                        "✓ Removed declaration of {} from: {}",
+                        output.display(),
+                        parent.display()
+                    );
+                }
+            }
+            regeneration.save()?;
+            lockfile.prune_outputs(&config);
+            lockfile.save()?;
+            if cli.timings {
+                eprint!("{}", timings::report(&timed));
+            }
+
+            hooks::run_hooks("post", &config.hooks.post, &config.root, None)?;
+        }
+
+        Commands::Status => {
+            if config.targets.is_empty() {
+                anyhow::bail!("No [[targets]] defined in the config file");
+            }
+
+            let cache = RegenerationCache::load(&config);
+            let mut conflicted = false;
+            for target in &config.targets {
+                let context_data = target_context(&config, target)?;
+                let options = target_options(&config, target, render)?;
+                let status = target_status(&config, target, &cache, context_data, options)?;
+
+                println!(
+                    "{:<19}{}",
+                    format!("{}:", status.label()),
+                    target.output.display()
+                );
+                if let TargetStatus::Conflict(conflicts) = status {
+                    conflicted = true;
+                    for conflict in conflicts {
+                        println!("  - {}", conflict);
+                    }
+                }
+            }
+
+            if conflicted {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Report { format } => {
+            if !["text", "json", "html"].contains(&format.as_str()) {
+                anyhow::bail!(
+                    "Unknown report format '{}', expected text, json or html",
+                    format
+                );
+            }
+            if config.targets.is_empty() {
+                anyhow::bail!("No [[targets]] defined in the config file");
+            }
+
+            let mut reports = Vec::new();
+            for target in &config.targets {
+                let output = config.resolve_path(&target.output);
+                let Ok(existing) = fs::read_to_string(&output) else {
+                    eprintln!("• Not generated: {}", target.output.display());
+                    continue;
+                };
+                let context_data = target_context(&config, target)?;
+                let generated = render_template_file(
+                    &config.resolve_path(&target.template),
+                    &context_data,
+                    render,
+                )?;
+
+                let generated = ParsedFile::parse(&generated)
+                    .map_err(|error| with_origin(error, GENERATED_CODE))
+                    .context("Failed to parse generated code")?;
+                let existing = ParsedFile::parse(&existing)
+                    .map_err(|error| with_origin(error, &output.display().to_string()))
+                    .with_context(|| format!("Failed to parse {}", output.display()))?;
+                reports.push(TargetReport {
+                    output: target.output.clone(),
+                    items: classify(&existing, &generated),
+                });
+            }
+
+            match format.as_str() {
+                "json" => println!("{}", serde_json::to_string_pretty(&reports)?),
+                "html" => print!("{}", to_html(&reports)),
+                _ => {
+                    for report in &reports {
+                        println!(
+                            "{}: {} generated, {} modified, {} manual",
+                            report.output.display(),
+                            report.count(Origin::Generated),
+                            report.count(Origin::Modified),
+                            report.count(Origin::Manual)
+                        );
+                        for item in &report.items {
+                            println!(
+                                "  {:<10} {} {}",
+                                item.origin.label(),
+                                item.info.kind,
+                                item.info.name.as_deref().unwrap_or("-")
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        Commands::Render {
+            template,
+            context,
+            output,
+        } => {
+            render_to(
+                &template,
+                &context,
+                output.as_deref(),
+                render,
+                &mut std::io::stdout(),
+            )?;
+        }
+
+        Commands::Diff {
+            template,
+            context,
+            existing,
+            format,
+            language,
+            ..
+        } if format == "patch" => {
+            if is_stdio(&existing) {
+                anyhow::bail!("--format patch needs an existing file, not stdin");
+            }
+            let current = fs::read_to_string(&existing)
+                .with_context(|| format!("Failed to read {}", existing.display()))?;
+            let options = GenerateOptions {
+                render,
+                policy: crate::policy::configured(&config)?,
+                strategies: StrategyRules::from_config(&config)?
+                    .with_resolutions(Resolutions::load(&config)?),
+                placement: PlacementRules::from_config(&config)?,
+                ordering: Ordering::from_config(&config)?,
+                language: Commands::parse_language(&language),
+                edition: Edition::from_config(&config)?,
+                generics: GenericsMatching::from_config(&config)?,
+                comparison: Comparison::from_config(&config)?,
+                // The header is regenerated as `generate` would, not removed
+                provenance: config.provenance || current.starts_with(crate::provenance::MARKER),
+                ..GenerateOptions::default()
+            };
+            let files = generate_files(
+                &template,
+                load_context_data(&context)?,
+                &existing,
+                Some(&existing),
+                options,
+            )?;
+
+            let patch: String = files
+                .iter()
+                .map(|file| unified_patch(&patch_path(&file.path), &current, &file.code))
+                .collect();
+            print_paged(&patch, !cli.no_pager)?;
+        }
+
+        Commands::Diff {
+            template,
+            context,
+            existing,
+            format,
+            language,
+            ..
+        } if format != "text" && language == "rust" => {
+            let edition = Edition::resolve(Edition::from_config(&config)?, existing.parent());
+            let check = file_check(&template, &context, &existing, false, render, edition)?;
+            // Every difference is a pending update
+            let check = TargetCheck {
+                findings: check
+                    .findings
+                    .into_iter()
+                    .map(|finding| Finding {
+                        severity: Severity::Info,
+                        ..finding
+                    })
+                    .collect(),
+                ..check
+            };
+            print!("{}", format_checks(&format, &[check])?);
+        }
+
+        Commands::Diff {
+            template,
+            context,
+            existing,
+            verbose,
+            format,
+            language,
+        } => {
+            if format != "text" {
+                anyhow::bail!("--format {} only applies to Rust outputs", format);
+            }
+
+            // Load context data
+            let context_data = load_context_data(&context)?;
+
+            // Show diff
+            let diff = if language == "rust" {
+                let edition = Edition::resolve(Edition::from_config(&config)?, existing.parent());
+                show_diff(&template, context_data, &existing, verbose, render, edition)?
+            } else {
+                let generated = render_template_file(&template, &context_data, render)?;
+                let existing = read_source(&existing).context("Failed to read existing file")?;
+                match language.as_str() {
+                    "markdown" => diff_sections(&generated, &existing),
+                    "toml" => diff_toml(&generated, &existing)?,
+                    _ => diff_regions(&generated, &existing)?,
+                }
+            };
+
+            print_paged(&format!("{}\n", diff), !cli.no_pager)?;
+        }
+
+        Commands::DiffFiles {
+            old,
+            new,
+            verbose,
+            format,
+        } => {
+            let old_code = fs::read_to_string(&old)
+                .with_context(|| format!("Failed to read {}", old.display()))?;
+            let new_code = fs::read_to_string(&new)
+                .with_context(|| format!("Failed to read {}", new.display()))?;
+            if format == "json" {
+                let parse = |code: &str, path: &Path| {
+                    ParsedFile::parse(code)
+                        .map_err(|error| with_origin(error, &path.display().to_string()))
+                        .with_context(|| format!("Failed to parse {}", path.display()))
+                };
+                let (old_ast, new_ast) = (parse(&old_code, &old)?, parse(&new_code, &new)?);
+                let patch = compute_patch(old_ast.items(), new_ast.items())?;
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&StoredPatch::new(&patch))?
+                );
+                return Ok(());
+            }
+
+            let edition = Edition::resolve(Edition::from_config(&config)?, new.parent());
+            let diff = diff_sources(&old_code, &new_code, verbose, edition)?;
+            print_paged(&format!("{}\n", diff), !cli.no_pager)?;
+        }
+
+        Commands::MergeFiles {
+            base,
+            ours,
+            theirs,
+            output,
+            strategy,
+            respect_order,
+            formatter,
+        } => {
+            let options = GenerateOptions {
+                strategy: Commands::parse_strategy(&strategy),
+                respect_order,
+                formatter: Commands::parse_formatter(&formatter),
+                policy: crate::policy::configured(&config)?,
+                strategies: StrategyRules::from_config(&config)?
+                    .with_resolutions(Resolutions::load(&config)?),
+                placement: PlacementRules::from_config(&config)?,
+                ordering: Ordering::from_config(&config)?,
+                generics: GenericsMatching::from_config(&config)?,
+                comparison: Comparison::from_config(&config)?,
+                ..GenerateOptions::default()
+            };
+            let MergedOutput { code, conflicts } =
+                merge_files(base.as_deref(), &ours, &theirs, output.as_deref(), options)?;
+
+            // Three-way conflicts are left as markers, two-way ones are resolved
+            let label = if base.is_some() {
+                "Conflict"
+            } else {
+                "Warning"
+            };
+            for conflict in &conflicts {
+                eprintln!("{}: {}", label, conflict);
+            }
+
+            match output {
+                Some(output) => println!("✓ Merged code written to: {}", output.display()),
+                None => print!("{}", code),
+            }
+
+            if base.is_some() && !conflicts.is_empty() {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Check {
+            all: true,
+            fail_on,
+            format,
+            ..
+        } => {
+            if config.targets.is_empty() {
+                anyhow::bail!("No [[targets]] defined in the config file");
+            }
+            let fail_on = Severity::parse(&fail_on)?;
+            let checks = check_manifest(&config, render)?;
+            let failed = checks.iter().any(|check| check.severity() >= Some(fail_on));
+
+            if format != "text" {
+                print!("{}", format_checks(&format, &checks)?);
+                if failed {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+
+            let mut counts = [0; 3];
+            for check in checks {
+                if check.findings.is_empty() {
+                    println!("✓ {}", check.output.display());
+                    continue;
+                }
+
+                println!("{}", check.output.display());
+                for finding in &check.findings {
+                    counts[finding.severity as usize] += 1;
+                    println!(
+                        "  {:<9}{}",
+                        format!("{}:", finding.severity.label()),
+                        finding.message
+                    );
+                }
+            }
+
+            println!(
+                "{} error(s), {} warning(s), {} info",
+                counts[Severity::Error as usize],
+                counts[Severity::Warning as usize],
+                counts[Severity::Info as usize]
+            );
+            if failed {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Check {
+            template,
+            context,
+            existing,
+            format,
+            ..
+        } => {
+            let (Some(template), Some(context), Some(existing)) = (template, context, existing)
+            else {
+                anyhow::bail!("check needs --template, --context and --existing, or --all");
+            };
+
+            let edition = Edition::resolve(Edition::from_config(&config)?, existing.parent());
+            if format != "text" {
+                // Without a generation record, the file counts as edited by hand
+                let check = file_check(&template, &context, &existing, true, render, edition)?;
+                print!("{}", format_checks(&format, std::slice::from_ref(&check))?);
+                if check.severity() == Some(Severity::Error) {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+
+            // Load context data
+            let context_data = load_context_data(&context)?;
+
+            // Check for conflicts
+            let report = check_conflicts(&template, context_data, &existing, render, edition)?;
+
+            if !report.changes.is_empty() {
+                println!("Pending changes:");
+                for change in &report.changes {
+                    println!("  - {}", change);
+                }
+            }
+
+            if !report.type_changes.is_empty() {
+                println!("⚠ Field types changed, code using these fields may break:");
+                for change in &report.type_changes {
+                    println!("  - {}", change);
+                }
+            }
+
+            if report.conflicts.is_empty() {
+                println!("✓ No conflicts detected. Safe to merge.");
+            } else {
+                println!("⚠ Conflicts detected:");
+                for conflict in report.conflicts {
+                    println!("  - {}", conflict);
+                }
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Explain {
+            template,
+            context,
+            existing,
+            strategy,
+            respect_order,
+        } => {
+            let options = GenerateOptions {
+                render,
+                strategy: Commands::parse_strategy(&strategy),
+                respect_order,
+                policy: crate::policy::configured(&config)?,
+                strategies: StrategyRules::from_config(&config)?
+                    .with_resolutions(Resolutions::load(&config)?),
+                edition: Edition::from_config(&config)?,
+                generics: GenericsMatching::from_config(&config)?,
+                comparison: Comparison::from_config(&config)?,
+                ..GenerateOptions::default()
+            };
+
+            // Load context data
+            let context_data = load_context_data(&context)?;
+
+            for explanation in explain(&template, context_data, &existing, options)? {
+                println!("{}", explanation);
+            }
+        }
+
+        #[cfg(feature = "tui")]
+        Commands::Review {
+            template,
+            context,
+            output,
+            formatter,
+        } => run_review(
+            &config,
+            &template,
+            &context,
+            &output,
+            Commands::parse_formatter(&formatter),
+            render,
+        )?,
+
+        Commands::Lint { template, context } => {
+            let template_name = template
+                .file_name()
+                .and_then(|n| n.to_str())
+                .context("Invalid template file name")?;
+            let template_content =
+                read_source(&template).context("Failed to read template file")?;
+            let context_data = load_context_data(&context)?;
+
+            let issues = lint::lint_template(template_name, &template_content, &context_data);
+            if issues.is_empty() {
+                println!("✓ No issues found in {}", template.display());
+            } else {
+                for issue in &issues {
+                    match issue.line {
+                        Some(line) => {
+                            println!("{}:{}: {}", template.display(), line, issue.message)
+                        }
+                        None => println!("{}: {}", template.display(), issue.message),
+                    }
+                }
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Test { dir, update } => {
+            let results = run_cases(&dir, update)?;
+            if results.is_empty() {
+                anyhow::bail!("No test cases found in {}", dir.display());
+            }
+
+            let mut failed = 0;
+            for result in &results {
+                match &result.outcome {
+                    CaseOutcome::Passed => println!("✓ {}", result.name),
+                    CaseOutcome::Updated => println!("✓ {} (snapshot updated)", result.name),
+                    CaseOutcome::Missing => {
+                        failed += 1;
+                        println!("✗ {}: missing expected.rs (run with --update)", result.name);
+                    }
+                    CaseOutcome::Failed { diff } => {
+                        failed += 1;
+                        println!("✗ {}: output differs from snapshot", result.name);
+                        println!("{}", diff);
+                    }
+                }
+            }
+
+            println!("\n{} passed, {} failed", results.len() - failed, failed);
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::ListTemplates { template } => {
+            let engine = if template.is_dir() {
+                TemplateEngine::new(&template)?
+            } else {
+                TemplateEngine::from_file(&template, &read_source(&template)?)?
+            };
+
+            let mut names = engine.get_template_names();
+            names.sort_unstable();
+            for name in names {
+                let parents = engine.get_parents(name)?;
+                if parents.is_empty() {
+                    println!("{}", name);
+                } else {
+                    println!("{} (extends {})", name, parents.join(" < "));
+                }
+
+                if let Some(description) = engine
+                    .front_matter(name)
+                    .and_then(|matter| matter.description.as_deref())
+                {
+                    println!("    {}", description);
+                }
+
+                let variables = engine.required_variables(name)?;
+                if !variables.is_empty() {
+                    let variables: Vec<_> = variables.into_iter().collect();
+                    println!("    variables: {}", variables.join(", "));
+                }
+            }
+        }
+
+        Commands::Vars { template } => {
+            let name = template
+                .file_name()
+                .and_then(|n| n.to_str())
+                .context("Invalid template file name")?;
+            let engine = TemplateEngine::from_file(&template, &read_source(&template)?)?;
+            let usage = engine.analyze(name)?;
+
+            let print_set = |label: &str, names: &std::collections::BTreeSet<String>| {
+                if !names.is_empty() {
+                    let names: Vec<_> = names.iter().map(String::as_str).collect();
+                    println!("{}: {}", label, names.join(", "));
+                }
+            };
+            print_set("variables", &usage.variables);
+            print_set("optional", &usage.optional);
+            print_set("filters", &usage.filters);
+            if !usage.loops.is_empty() {
+                println!("loops:");
+                for forloop in &usage.loops {
+                    println!("    {}", forloop);
+                }
+            }
+        }
+
+        Commands::ContextTypes {
+            template,
+            output,
+            name,
+        } => {
+            let file_name = template
+                .file_name()
+                .and_then(|n| n.to_str())
+                .context("Invalid template file name")?;
+            let engine = TemplateEngine::from_file(&template, &read_source(&template)?)?;
+            let usage = engine.analyze(file_name)?;
+
+            let name = name.unwrap_or_else(|| {
+                let stem = file_name.split('.').next().unwrap_or(file_name);
+                format!("{}Context", context_types::pascal_case(stem))
+            });
+            let code = context_types::context_types(&usage, &name);
+            match output {
+                Some(output) => {
+                    fs::write(&output, code)?;
+                    println!("✓ Context types written to: {}", output.display());
+                }
+                None => print!("{}", code),
+            }
+        }
+
+        Commands::Templatize {
+            file,
+            vars,
+            output,
+            context,
+            force,
+        } => {
+            let source = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let templatized = templatize(&source, &vars)?;
+
+            let stem = file
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .context("Invalid file name")?;
+            let output = output.unwrap_or_else(|| format!("templates/{}.rs.tera", stem).into());
+            let context = context.unwrap_or_else(|| format!("contexts/{}.json", stem).into());
+            let writes = [
+                (output, templatized.template),
+                (
+                    context,
+                    serde_json::to_string_pretty(&templatized.context)? + "\n",
+                ),
+            ];
+            if !force {
+                if let Some((path, _)) = writes.iter().find(|(path, _)| path.exists()) {
+                    anyhow::bail!(
+                        "{} already exists, use --force to replace it",
+                        path.display()
+                    );
+                }
+            }
+            for (path, content) in writes {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, content)?;
+                println!("✓ Created {}", path.display());
+            }
+        }
+
+        Commands::Parse { file, json } => {
+            let source = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            let items = ParsedFile::parse(&source)?.inventory();
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&items)?);
+            } else {
+                println!(
+                    "{:<9}  {:<8}  {:<24}  {:<10}  DOC",
+                    "LINES", "KIND", "NAME", "VISIBILITY"
+                );
+                for item in items {
+                    let line = format!(
+                        "{:<9}  {:<8}  {:<24}  {:<10}  {}",
+                        format!("{}-{}", item.start_line, item.end_line),
+                        item.kind,
+                        item.name.as_deref().unwrap_or("-"),
+                        item.visibility.as_deref().unwrap_or("-"),
+                        item.doc.as_deref().unwrap_or("")
+                    );
+                    println!("{}", line.trim_end());
+                }
+            }
+        }
+
+        Commands::UpdateLock => {
+            let mut lockfile = Lockfile::load(&config)?;
+            let updated = lockfile.update()?;
+            lockfile.save()?;
+
+            if updated.is_empty() {
+                println!("✓ All locked templates are up to date");
+            }
+            for template in updated {
+                println!("✓ Updated {}", template);
+            }
+        }
+
+        Commands::Verify => {
+            if config.targets.is_empty() {
+                anyhow::bail!("No [[targets]] defined in the config file");
+            }
+
+            let drifted = Lockfile::load(&config)?.drift(&config)?;
+            if !drifted.is_empty() {
+                eprintln!("✗ Outputs are out of date with their inputs:");
+                for drift in drifted {
+                    eprintln!("  - {}", drift);
+                }
+                std::process::exit(1);
+            }
+            println!(
+                "✓ All {} targets match their inputs in {}",
+                config.targets.len(),
+                LOCK_FILE
+            );
+        }
+
+        Commands::Snapshot { paths, all } => {
+            let outputs = if all {
+                if config.targets.is_empty() {
+                    anyhow::bail!("No [[targets]] defined in the config file");
+                }
+                config
+                    .targets
+                    .iter()
+                    .map(|target| target.output.clone())
+                    .filter(|output| {
+                        let exists = config.resolve_path(output).exists();
+                        if !exists {
+                            println!("• Not generated: {}", output.display());
+                        }
+                        exists
+                    })
+                    .collect()
+            } else {
+                paths
+            };
+
+            for output in outputs {
+                snapshot::snapshot(&config, &output)?;
+                println!("✓ Recorded merge base of: {}", output.display());
+            }
+        }
+
+        Commands::Serve { stdio } => {
+            if !stdio {
+                anyhow::bail!("Only --stdio transport is supported");
+            }
+            server::serve_stdio()?;
+        }
+
+        Commands::Completions { shell } => {
+            completions::write_registration(
+                &shell.to_string(),
+                "rust-patchs-templates",
+                &mut std::io::stdout(),
+            )?;
+        }
+
+        Commands::MergeDriver { base, ours, theirs } => {
+            let clean = run_merge_driver(&base, &ours, &theirs)?;
+            if !clean {
+                std::process::exit(1);
+            }
+        }
+
+        Commands::Hook { action } => match action {
+            HookAction::Install {
+                force,
+                pre_commit_framework,
+            } => {
+                if pre_commit_framework {
+                    print!("{}", commit_hook::PRE_COMMIT_CONFIG);
+                } else {
+                    let path = commit_hook::install(force)?;
+                    println!("✓ Pre-commit hook installed at: {}", path.display());
+                }
+            }
+
+            HookAction::Run => {
+                let mut cache = TemplateCache::new();
+                let mut stale = Vec::new();
+                for target in commit_hook::staged_targets(&config)? {
+                    let options = target_options(&config, target, render)?;
+                    let output = config.resolve_path(&target.output);
+                    let edition = Edition::resolve(options.edition, output.parent());
+                    let result =
+                        generate_target(&mut cache, &config, target, options, &HashMap::new())?;
+                    if !is_up_to_date(&output, &result.code, edition)? {
+                        stale.push(target.output.display().to_string());
+                    }
+                }
+
+                if !stale.is_empty() {
+                    eprintln!("⚠ Generated files are out of date, regenerate before committing:");
+                    for output in stale {
+                        eprintln!("  - {}", output);
+                    }
+                    std::process::exit(1);
+                }
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Record the template's output as the next merge base of a snapshotted output
+///
+/// Templates emitting several files have no single base and are skipped.
+fn advance_base(
+    config: &Config,
+    output: &Path,
+    template: &Path,
+    context_data: &std::collections::HashMap<String, serde_json::Value>,
+    render: RenderOptions,
+) -> Result<()> {
+    let rendered = render_template_file(template, context_data, render)?;
+    if crate::file_blocks::split_files(&rendered)?.is_none() {
+        snapshot::record(config, output, rendered.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Render and merge a manifest target in memory, without writing it
+///
+/// Other targets' outputs in `planned` are read from there, see [`planned_context`].
+fn generate_target(
+    cache: &mut TemplateCache,
+    config: &Config,
+    target: &Target,
+    options: GenerateOptions,
+    planned: &HashMap<PathBuf, String>,
+) -> Result<MergedOutput> {
+    let context_data = planned_context(config, target, planned)?;
+
+    generate_cached(
+        cache,
+        &config.resolve_path(&target.template),
+        context_data,
+        &config.resolve_path(&target.output),
+        options,
+    )
+}
+
+/// Render a template as is, without parsing or merging, to `output` or `out` when it is missing or `-`
+fn render_to(
+    template: &Path,
+    context: &Path,
+    output: Option<&Path>,
+    render: RenderOptions,
+    out: &mut dyn std::io::Write,
+) -> Result<()> {
+    let context_data = load_context_data(context)?;
+    let rendered = render_template_file(template, &context_data, render)?;
+
+    match output {
+        Some(output) if !is_stdio(output) => fs::write(output, rendered)
+            .with_context(|| format!("Failed to write {}", output.display())),
+        _ => Ok(out.write_all(rendered.as_bytes())?),
+    }
+}
+
+/// Merge `theirs` into `ours`, writing the result to `output` when given
+///
+/// With a `base`, the merge is three-way and conflicts are left as markers in
+/// the code; without one, `theirs` is merged like generated code into `ours`
+/// and conflicts are resolved by `options`.
+fn merge_files(
+    base: Option<&Path>,
+    ours: &Path,
+    theirs: &Path,
+    output: Option<&Path>,
+    options: GenerateOptions,
+) -> Result<MergedOutput> {
+    let read = |path: &Path| {
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+    };
+    let ours_code = read(ours)?;
+    let theirs_code = read(theirs)?;
+
+    let merged = match base {
+        Some(base) => {
+            let result = merge_three_way(&read(base)?, &ours_code, &theirs_code)?;
+            MergedOutput {
+                code: result.merged,
+                conflicts: result
+                    .conflicts
+                    .into_iter()
+                    .map(Warning::unresolved)
+                    .collect(),
+            }
+        }
+        None => merge_generated(theirs_code, Some(&ours_code), options, ours.parent())?,
+    };
+
+    if let Some(output) = output {
+        fs::write(output, &merged.code)
+            .with_context(|| format!("Failed to write {}", output.display()))?;
+    }
+    Ok(merged)
+}
+
+/// Resolve a template argument to the file to render
+///
+/// `builtin:<name>` templates resolve to their bundled file, git templates to
+/// their checkout pinned by the lockfile and other paths against `dir`. The
+/// project's override of a shared template wins, see [`crate::overrides`].
+fn resolve_template(
+    config: &Config,
+    lockfile: &mut Lockfile,
+    argument: &Path,
+    dir: &Path,
+    git_ref: Option<&str>,
+) -> Result<PathBuf> {
+    let resolved = match crate::builtin::resolve(config, argument)? {
+        Some(path) => path,
+        None if RemoteTemplate::parse(argument).is_some() => lockfile.resolve(argument, git_ref)?,
+        None => dir.join(argument),
+    };
+    Ok(crate::overrides::apply(config, argument, &resolved))
+}
+
+/// Path of a file in a patch: relative to the current directory, with `/` separators
+fn patch_path(path: &Path) -> String {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path);
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+/// Copy a Rust file that doesn't parse to `<file>.bak` before it is overwritten
+fn back_up_unparsable(path: &Path, previous: Option<&str>) -> Result<Option<PathBuf>> {
+    let Some(previous) = previous else {
+        return Ok(None);
+    };
+    if Language::of_path(path) != Some(Language::Rust) || ParsedFile::parse(previous).is_ok() {
+        return Ok(None);
+    }
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+    fs::write(&backup, previous)
+        .with_context(|| format!("Failed to back up {}", path.display()))?;
+    Ok(Some(backup))
+}
+
+/// Declare a newly generated file in its parent module
+fn declare_new_module(path: &Path) -> Result<()> {
+    if let Some(parent) = modules::declare_module(path)? {
+        println!("✓ Module declared in: {}", parent.display());
+    }
+    Ok(())
+}
+
+/// Merge options of a manifest target, with the project's merge rules
+fn target_options(
+    config: &Config,
+    target: &Target,
+    render: RenderOptions,
+) -> Result<GenerateOptions> {
+    Ok(GenerateOptions {
+        render,
+        strategy: Commands::parse_strategy(&target.strategy),
+        policy: crate::policy::configured(config)?,
+        strategies: StrategyRules::from_config(config)?
+            .with_resolutions(Resolutions::load(config)?),
+        placement: PlacementRules::from_config(config)?,
+        ordering: Ordering::from_config(config)?,
+        language: Commands::parse_language(&target.language),
+        edition: Edition::from_config(config)?,
+        base: snapshot::base_of(config, &target.output),
+        generics: GenericsMatching::from_config(config)?,
+        comparison: Comparison::from_config(config)?,
+        provenance: config.provenance,
+        ..GenerateOptions::default()
+    })
+}
+
+/// Review template changes interactively and write the accepted result
+#[cfg(feature = "tui")]
+fn run_review(
+    config: &Config,
+    template: &Path,
+    context: &Path,
+    output: &Path,
+    formatter: crate::merger::Formatter,
+    render: RenderOptions,
+) -> Result<()> {
+    let context_data = load_context_data(context)?;
+    let generated = render_template_file(template, &context_data, render)?;
+    let existing = if output.exists() {
+        fs::read_to_string(output)?
+    } else {
+        String::new()
+    };
+
+    let mut review = crate::review::Review::new(&existing, &generated)?;
+    if review.entries.is_empty() {
+        println!("No changes to review.");
+        return Ok(());
+    }
+
+    let mut resolutions = Resolutions::load(config)?;
+    review.apply_recorded(&resolutions);
+    if !crate::tui::run(&mut review)? {
+        println!("Review aborted, nothing written.");
+        return Ok(());
+    }
+
+    let code = crate::merger::format_merged_code(
+        review.header.clone(),
+        review.apply(),
+        &Ordering::from_config(config)?,
+        formatter,
+        Edition::resolve(Edition::from_config(config)?, output.parent()),
+        output.parent(),
+    )?;
+    fs::write(output, code)?;
+    println!("✓ Reviewed code written to: {}", output.display());
+
+    review.record(&mut resolutions);
+    resolutions.save()?;
+
+    Ok(())
+}
+
+/// Merge `ours` in place the way git expects from a custom merge driver
+///
+/// Returns whether the merge was clean. Falls back to `git merge-file` when one
+/// of the versions doesn't parse as Rust.
+fn run_merge_driver(base: &Path, ours: &Path, theirs: &Path) -> Result<bool> {
+    let base_src = fs::read_to_string(base)?;
+    let ours_src = fs::read_to_string(ours)?;
+    let theirs_src = fs::read_to_string(theirs)?;
+
+    match merge_three_way(&base_src, &ours_src, &theirs_src) {
+        Ok(result) => {
+            fs::write(ours, &result.merged)?;
+            for conflict in &result.conflicts {
+                eprintln!("Conflict: {}", conflict);
+            }
+            Ok(result.conflicts.is_empty())
+        }
+        Err(err) => {
+            eprintln!("Warning: {:#}, falling back to text merge", err);
+            let status = std::process::Command::new("git")
+                .arg("merge-file")
+                .args(["-L", "ours", "-L", "base", "-L", "theirs"])
+                .args([ours, base, theirs])
+                .status()?;
+            Ok(status.success())
+        }
+    }
+}
+
+/// Findings for an existing file and a template, see [`crate::check::find_changes`]
+fn file_check(
+    template: &Path,
+    context: &Path,
+    existing: &Path,
+    edited: bool,
+    render: RenderOptions,
+    edition: Edition,
+) -> Result<TargetCheck> {
+    let context_data = load_context_data(context)?;
+    let generated = render_template_file(template, &context_data, render)?;
+    let existing_code = read_source(existing).context("Failed to read existing file")?;
+
+    Ok(TargetCheck {
+        output: existing.to_path_buf(),
+        findings: find_changes(&existing_code, &generated, edited, edition)?,
+    })
+}
+
+/// Print findings as SARIF or GitHub Actions annotations
+fn format_checks(format: &str, checks: &[TargetCheck]) -> Result<String> {
+    match format {
+        "sarif" => Ok(format!("{}\n", to_sarif(checks))),
+        "github" => Ok(to_annotations(checks)),
+        other => anyhow::bail!("Unknown format '{}', expected text, sarif or github", other),
+    }
+}
+
+/// Load context data from JSON file
+pub fn load_context_data(
+    path: &Path,
+) -> Result<std::collections::HashMap<String, serde_json::Value>> {
+    let content = fs::read_to_string(path)?;
+    parse_context(&content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_target_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "templates/builtin/builder.tera",
+            "templates/shared.tera",
+            "templates-overrides/shared.tera",
+            "local.tera",
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        fs::write(dir.path().join("rpt.toml"), "").unwrap();
+        let config = Config::load(&dir.path().join("rpt.toml")).unwrap();
+        let mut lockfile = Lockfile::load(&config).unwrap();
+        let mut resolve = |template: &str| {
+            resolve_template(
+                &config,
+                &mut lockfile,
+                Path::new(template),
+                &config.root,
+                None,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            resolve("builtin:builder"),
+            dir.path().join("templates/builtin/builder.tera")
+        );
+        assert_eq!(
+            resolve("templates/shared.tera"),
+            dir.path().join("templates-overrides/shared.tera")
+        );
+        assert_eq!(resolve("local.tera"), dir.path().join("local.tera"));
+    }
+
+    #[test]
+    fn test_render_prints_output_as_is() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("partial.tera");
+        fs::write(&template, "impl {{ name }} {\n    fn half(").unwrap();
+        let context = dir.path().join("context.json");
+        fs::write(&context, r#"{"name": "User"}"#).unwrap();
+        let render = |output: Option<&Path>| {
+            let mut out = Vec::new();
+            render_to(
+                &template,
+                &context,
+                output,
+                RenderOptions::default(),
+                &mut out,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // Not valid Rust, printed anyway
+        assert_eq!(render(None), "impl User {\n    fn half(");
+        assert_eq!(render(Some(Path::new("-"))), "impl User {\n    fn half(");
+
+        let output = dir.path().join("partial.rs");
+        assert_eq!(render(Some(&output)), "");
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "impl User {\n    fn half("
+        );
+    }
+
+    #[test]
+    fn test_merge_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, code: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, code).unwrap();
+            path
+        };
+        let base = file("base.rs", "pub fn f() -> u8 {\n    0\n}\n");
+        let ours = file("ours.rs", "pub fn f() -> u8 {\n    1\n}\n");
+        let theirs = file(
+            "theirs.rs",
+            "pub fn f() -> u8 {\n    2\n}\npub struct New;\n",
+        );
+        let output = dir.path().join("merged.rs");
+
+        // Two-way: theirs is merged like generated code, manual edits win by default
+        let merged = merge_files(
+            None,
+            &ours,
+            &theirs,
+            Some(&output),
+            GenerateOptions::default(),
+        )
+        .unwrap();
+        assert!(merged.code.contains("    1\n") && merged.code.contains("pub struct New;"));
+        assert_eq!(fs::read_to_string(&output).unwrap(), merged.code);
+
+        // Three-way: both sides changed `f`, the conflict is left as markers
+        let merged = merge_files(
+            Some(&base),
+            &ours,
+            &theirs,
+            None,
+            GenerateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(merged.conflicts.len(), 1);
+        assert!(merged.code.contains("<<<<<<< ours"));
+        assert!(merged.code.contains("pub struct New;"));
+    }
+}
@@ -2,30 +2,76 @@
 //!
 //! This module provides functionality to parse Rust source code into an AST
 //! and extract meaningful structural information for comparison and merging.
+//!
+//! Parsed files are cached by the SHA-256 of their source, so batch commands
+//! and server mode parse each version of a file once. Syntax trees can't leave
+//! the thread that parsed them, so each thread keeps its own cache.
+//!
+//! Parse errors are [`ParseDiagnostic`]s, showing the offending lines.
 
 use anyhow::{Context, Result};
-use syn::{File, Item};
+use quote::ToTokens;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use syn::spanned::Spanned;
+use syn::{Attribute, File, Item, Visibility};
+
+use crate::diagnostics::ParseDiagnostic;
+use crate::edition::Edition;
+use crate::hash::sha256_hex;
+
+/// Files kept in a thread's parse cache before it is emptied
+const PARSE_CACHE_CAPACITY: usize = 256;
+
+thread_local! {
+    /// Syntax trees by SHA-256 of their source and edition
+    static PARSE_CACHE: RefCell<HashMap<String, File>> = RefCell::new(HashMap::new());
+}
 
 /// Parsed Rust file with AST representation
 #[derive(Debug, Clone)]
 pub struct ParsedFile {
     pub syntax_tree: File,
-    pub items: Vec<Item>,
 }
 
 impl ParsedFile {
-    /// Parse Rust source code into a structured AST
+    /// Parse Rust source code into a structured AST, reusing the tree of a source parsed before
     pub fn parse(source: &str) -> Result<Self> {
-        let syntax_tree = syn::parse_file(source).context("Failed to parse Rust source code")?;
+        Self::parse_edition(source, Edition::default())
+    }
+
+    /// Same as [`ParsedFile::parse`], for source code of the given edition
+    pub fn parse_edition(source: &str, edition: Edition) -> Result<Self> {
+        let hash = format!("{}-{}", sha256_hex(source), edition.as_str());
+        if let Some(syntax_tree) = PARSE_CACHE.with(|cache| cache.borrow().get(&hash).cloned()) {
+            return Ok(ParsedFile { syntax_tree });
+        }
+
+        let syntax_tree = edition
+            .prepare(source)
+            .and_then(|prepared| syn::parse_file(&prepared))
+            .map_err(|error| ParseDiagnostic::new("source", source, &error))
+            .context("Failed to parse Rust source code")?;
+        PARSE_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            if cache.len() >= PARSE_CACHE_CAPACITY {
+                cache.clear();
+            }
+            cache.insert(hash, syntax_tree.clone());
+        });
 
-        let items = syntax_tree.items.clone();
+        Ok(ParsedFile { syntax_tree })
+    }
 
-        Ok(ParsedFile { syntax_tree, items })
+    /// Top-level items of the file
+    pub fn items(&self) -> &[Item] {
+        &self.syntax_tree.items
     }
 
     /// Get item by identifier (function name, struct name, etc.)
     pub fn find_item(&self, name: &str) -> Option<&Item> {
-        self.items.iter().find(|item| match item {
+        self.items().iter().find(|item| match item {
             Item::Fn(func) => func.sig.ident == name,
             Item::Struct(s) => s.ident == name,
             Item::Enum(e) => e.ident == name,
@@ -33,6 +79,8 @@ impl ParsedFile {
             Item::Type(t) => t.ident == name,
             Item::Const(c) => c.ident == name,
             Item::Static(s) => s.ident == name,
+            Item::Union(u) => u.ident == name,
+            Item::TraitAlias(t) => t.ident == name,
             Item::Impl(i) => {
                 if let Some((_, path, _)) = &i.trait_ {
                     path.segments
@@ -49,26 +97,167 @@ impl ParsedFile {
 
     /// Extract all item identifiers
     pub fn get_item_names(&self) -> Vec<String> {
-        self.items
+        self.items()
             .iter()
-            .filter_map(|item| match item {
-                Item::Fn(func) => Some(func.sig.ident.to_string()),
-                Item::Struct(s) => Some(s.ident.to_string()),
-                Item::Enum(e) => Some(e.ident.to_string()),
-                Item::Trait(t) => Some(t.ident.to_string()),
-                Item::Type(t) => Some(t.ident.to_string()),
-                Item::Const(c) => Some(c.ident.to_string()),
-                Item::Static(s) => Some(s.ident.to_string()),
-                _ => None,
-            })
+            .filter_map(item_ident)
+            .map(ToString::to_string)
             .collect()
     }
 }
 
+/// Identifier of a named item: a function, type, trait, const or static
+pub fn item_ident(item: &Item) -> Option<&syn::Ident> {
+    match item {
+        Item::Fn(func) => Some(&func.sig.ident),
+        Item::Struct(s) => Some(&s.ident),
+        Item::Enum(e) => Some(&e.ident),
+        Item::Trait(t) => Some(&t.ident),
+        Item::Type(t) => Some(&t.ident),
+        Item::Const(c) => Some(&c.ident),
+        Item::Static(s) => Some(&s.ident),
+        Item::Union(u) => Some(&u.ident),
+        Item::TraitAlias(t) => Some(&t.ident),
+        _ => None,
+    }
+}
+
+/// Summary of one top-level item, as listed by the `parse` command
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ItemInfo {
+    /// Item name; impl blocks are named after their type and trait
+    pub name: Option<String>,
+    pub kind: &'static str,
+    /// `pub`, `pub(crate)`, ... or `private`; `None` for items without one
+    pub visibility: Option<String>,
+    /// First and last line of the item, 1-based
+    pub start_line: usize,
+    pub end_line: usize,
+    /// First line of the item's doc comment
+    pub doc: Option<String>,
+}
+
+impl ItemInfo {
+    /// Describe an item parsed from source
+    pub fn of(item: &Item) -> Self {
+        let span = item.span();
+        let (attrs, vis) = attrs_and_vis(item);
+
+        ItemInfo {
+            name: impl_name(item)
+                .or_else(|| crate::diff::item_key(item, crate::matching::generics())),
+            kind: item_kind(item),
+            visibility: vis.map(|vis| match vis {
+                Visibility::Inherited => "private".to_string(),
+                vis => vis.to_token_stream().to_string().replace(" (", "("),
+            }),
+            start_line: span.start().line,
+            end_line: span.end().line,
+            doc: attrs.iter().find_map(doc_line),
+        }
+    }
+}
+
+impl ParsedFile {
+    /// Describe every top-level item of the file
+    pub fn inventory(&self) -> Vec<ItemInfo> {
+        self.items().iter().map(ItemInfo::of).collect()
+    }
+}
+
+/// `Type` or `Trait for Type` of an impl block
+pub(crate) fn impl_name(item: &Item) -> Option<String> {
+    let Item::Impl(item) = item else {
+        return None;
+    };
+    let self_ty = item.self_ty.to_token_stream().to_string();
+
+    Some(match &item.trait_ {
+        Some((_, path, _)) => format!("{} for {}", path.to_token_stream(), self_ty),
+        None => self_ty,
+    })
+}
+
+/// Outer attributes and visibility of an item
+pub(crate) fn attrs_and_vis(item: &Item) -> (&[Attribute], Option<&Visibility>) {
+    match item {
+        Item::Fn(i) => (&i.attrs, Some(&i.vis)),
+        Item::Struct(i) => (&i.attrs, Some(&i.vis)),
+        Item::Enum(i) => (&i.attrs, Some(&i.vis)),
+        Item::Trait(i) => (&i.attrs, Some(&i.vis)),
+        Item::Type(i) => (&i.attrs, Some(&i.vis)),
+        Item::Const(i) => (&i.attrs, Some(&i.vis)),
+        Item::Static(i) => (&i.attrs, Some(&i.vis)),
+        Item::Mod(i) => (&i.attrs, Some(&i.vis)),
+        Item::Use(i) => (&i.attrs, Some(&i.vis)),
+        Item::Union(i) => (&i.attrs, Some(&i.vis)),
+        Item::TraitAlias(i) => (&i.attrs, Some(&i.vis)),
+        Item::ExternCrate(i) => (&i.attrs, Some(&i.vis)),
+        Item::Impl(i) => (&i.attrs, None),
+        Item::ForeignMod(i) => (&i.attrs, None),
+        Item::Macro(i) => (&i.attrs, None),
+        _ => (&[], None),
+    }
+}
+
+/// Trimmed text of a non-empty `///` line
+pub(crate) fn doc_line(attr: &Attribute) -> Option<String> {
+    if !attr.path().is_ident("doc") {
+        return None;
+    }
+    let syn::Meta::NameValue(meta) = &attr.meta else {
+        return None;
+    };
+    let syn::Expr::Lit(syn::ExprLit {
+        lit: syn::Lit::Str(text),
+        ..
+    }) = &meta.value
+    else {
+        return None;
+    };
+
+    let text = text.value().trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Short keyword describing the kind of an item (`fn`, `struct`, ...)
+pub fn item_kind(item: &Item) -> &'static str {
+    match item {
+        Item::Fn(_) => "fn",
+        Item::Struct(_) => "struct",
+        Item::Enum(_) => "enum",
+        Item::Trait(_) => "trait",
+        Item::Type(_) => "type",
+        Item::Const(_) => "const",
+        Item::Static(_) => "static",
+        Item::Mod(_) => "mod",
+        Item::Impl(_) => "impl",
+        Item::Use(_) => "use",
+        Item::Union(_) => "union",
+        Item::TraitAlias(_) => "trait alias",
+        Item::ForeignMod(_) => "extern block",
+        Item::Macro(_) => "macro",
+        Item::ExternCrate(_) => "extern crate",
+        _ => "item",
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_cache() {
+        let code = "pub fn cached() -> u8 { 1 }";
+        let first = ParsedFile::parse(code).unwrap();
+        let key = format!("{}-2021", sha256_hex(code));
+        assert!(PARSE_CACHE.with(|cache| cache.borrow().contains_key(&key)));
+
+        let second = ParsedFile::parse(code).unwrap();
+        assert_eq!(first.syntax_tree, second.syntax_tree);
+        assert_eq!(second.items()[0].span().start().line, 1);
+        assert!(ParsedFile::parse("pub fn broken(").is_err());
+    }
+
     #[test]
     fn test_parse_simple_function() {
         let code = r#"
@@ -78,7 +267,7 @@ mod tests {
         "#;
 
         let parsed = ParsedFile::parse(code).unwrap();
-        assert_eq!(parsed.items.len(), 1);
+        assert_eq!(parsed.items().len(), 1);
         assert!(parsed.find_item("hello").is_some());
     }
 
@@ -94,4 +283,32 @@ mod tests {
         let names = parsed.get_item_names();
         assert_eq!(names, vec!["foo", "Bar", "Baz"]);
     }
+
+    #[test]
+    fn test_inventory() {
+        let code = "/// A user\n///\n/// Details\npub(crate) struct User {\n    id: u32,\n}\n\nimpl Display for User {}\n";
+
+        let parsed = ParsedFile::parse(code).unwrap();
+        assert_eq!(
+            parsed.inventory(),
+            vec![
+                ItemInfo {
+                    name: Some("User".to_string()),
+                    kind: "struct",
+                    visibility: Some("pub(crate)".to_string()),
+                    start_line: 1,
+                    end_line: 6,
+                    doc: Some("A user".to_string()),
+                },
+                ItemInfo {
+                    name: Some("Display for User".to_string()),
+                    kind: "impl",
+                    visibility: None,
+                    start_line: 8,
+                    end_line: 8,
+                    doc: None,
+                },
+            ]
+        );
+    }
 }
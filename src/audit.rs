@@ -0,0 +1,163 @@
+//! Audit log module
+//!
+//! This module appends a record of every generation to `.rpt/audit.jsonl` when
+//! `audit = true` is set in `rpt.toml`, one JSON object per line:
+//!
+//! ```json
+//! {"timestamp":"2026-10-15T09:12:03Z","output":"src/user.rs","template":"templates/user.rs.tera",
+//!  "template_hash":"3f2a…","context_hash":"9b1c…","strategy":"manual",
+//!  "operations":["Modify fn `validate`: 40% changed"],"conflicts":[]}
+//! ```
+//!
+//! Hashes are the same SHA-256 the regeneration cache records, so a record can
+//! be traced back to the template version that produced it, e.g. with
+//! `grep validate .rpt/audit.jsonl`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::ast_parser::ParsedFile;
+use crate::config::Config;
+use crate::diff::compute_patch;
+use crate::hash::sha256_path;
+use crate::summary::describe_file_header;
+
+/// Location of the audit log, relative to the config root
+pub const AUDIT_FILE: &str = ".rpt/audit.jsonl";
+
+/// One generation, as written to the audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// When the file was written, RFC 3339 in UTC
+    pub timestamp: String,
+    pub output: PathBuf,
+    pub template: PathBuf,
+    /// Hash of the template, missing when it was read from stdin
+    pub template_hash: Option<String>,
+    /// Hash of the context file, missing when it was read from stdin
+    pub context_hash: Option<String>,
+    pub strategy: String,
+    /// Changes made to the previous version of the file
+    pub operations: Vec<String>,
+    /// Conflicts met while merging, with how they were resolved
+    pub conflicts: Vec<String>,
+}
+
+impl AuditRecord {
+    /// Record the generation of `output` from `previous` to `code`, made now
+    pub fn new(
+        template: &Path,
+        context: &Path,
+        output: &Path,
+        strategy: &str,
+        previous: Option<&str>,
+        code: &str,
+        conflicts: Vec<String>,
+    ) -> Self {
+        AuditRecord {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            output: output.to_path_buf(),
+            template: template.to_path_buf(),
+            template_hash: sha256_path(template).ok(),
+            context_hash: sha256_path(context).ok(),
+            strategy: strategy.to_string(),
+            operations: operations(previous, code),
+            conflicts,
+        }
+    }
+}
+
+/// Changes turning `previous` into `code`, as listed by `diff`
+///
+/// Files that didn't exist are recorded as created; files that don't parse as
+/// Rust are recorded as rewritten.
+pub fn operations(previous: Option<&str>, code: &str) -> Vec<String> {
+    let Some(previous) = previous else {
+        return vec!["Create file".to_string()];
+    };
+    if previous == code {
+        return Vec::new();
+    }
+
+    let (Ok(old), Ok(new)) = (ParsedFile::parse(previous), ParsedFile::parse(code)) else {
+        return vec!["Rewrite file".to_string()];
+    };
+    let Ok(patch) = compute_patch(old.items(), new.items()) else {
+        return vec!["Rewrite file".to_string()];
+    };
+
+    describe_file_header(&old.syntax_tree, &new.syntax_tree)
+        .into_iter()
+        .chain(patch.summary())
+        .collect()
+}
+
+/// Append a record to the project's audit log
+pub fn append(config: &Config, record: &AuditRecord) -> Result<()> {
+    let path = config.resolve_path(Path::new(AUDIT_FILE));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let template = dir.path().join("t.tera");
+        fs::write(&template, "pub fn a() {}").unwrap();
+
+        let created = AuditRecord::new(
+            &template,
+            Path::new("-"),
+            Path::new("a.rs"),
+            "manual",
+            None,
+            "pub fn a() {}",
+            Vec::new(),
+        );
+        let modified = AuditRecord::new(
+            &template,
+            Path::new("-"),
+            Path::new("a.rs"),
+            "manual",
+            Some("pub fn a() {}"),
+            "pub fn a() -> u8 { 1 }",
+            vec!["Function 'a' has manual edits, keeping manual version".to_string()],
+        );
+        append(&config, &created).unwrap();
+        append(&config, &modified).unwrap();
+
+        let log = fs::read_to_string(dir.path().join(AUDIT_FILE)).unwrap();
+        let records: Vec<AuditRecord> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records, vec![created, modified]);
+        assert_eq!(records[0].operations, vec!["Create file"]);
+        assert_eq!(records[0].context_hash, None);
+        assert!(records[1].operations[0].starts_with("Modify fn `a`"));
+        assert_eq!(
+            records[1].template_hash.as_deref(),
+            Some(crate::hash::sha256_hex("pub fn a() {}").as_str())
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Template backend module
+//!
+//! This module abstracts the template language behind [`TemplateBackend`], so
+//! teams can keep templates written for another engine. Tera is the default;
+//! Handlebars is available with the default `handlebars` feature. Tera-only
+//! features (`{% file %}` blocks, template directories, static analysis) stay
+//! on [`TemplateEngine`].
+
+#[cfg(feature = "handlebars")]
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[cfg(feature = "handlebars")]
+use crate::front_matter::FrontMatter;
+use crate::template::TemplateEngine;
+
+/// A template language that can compile and render named templates
+pub trait TemplateBackend {
+    /// Add a template from its source
+    fn add_template(&mut self, name: &str, source: &str) -> Result<()>;
+
+    /// Render a template; `lenient` renders missing values as empty
+    fn render_template(
+        &self,
+        name: &str,
+        context: &HashMap<String, Value>,
+        lenient: bool,
+    ) -> Result<String>;
+
+    /// Names of the added templates
+    fn list_templates(&self) -> Vec<&str>;
+}
+
+/// Template language used to render templates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    #[default]
+    Tera,
+    #[cfg(feature = "handlebars")]
+    Handlebars,
+}
+
+impl Engine {
+    /// Get the engine from its name
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "tera" => Ok(Engine::Tera),
+            #[cfg(feature = "handlebars")]
+            "handlebars" | "hbs" => Ok(Engine::Handlebars),
+            _ => anyhow::bail!("Unknown template engine '{}'", name),
+        }
+    }
+
+    /// Create a backend for a template file, named by its file name
+    ///
+    /// Tera also loads the templates it includes, see [`TemplateEngine::from_file`].
+    pub fn from_file(self, path: &Path, source: &str) -> Result<Box<dyn TemplateBackend>> {
+        match self {
+            Engine::Tera => Ok(Box::new(TemplateEngine::from_file(path, source)?)),
+            #[cfg(feature = "handlebars")]
+            _ => {
+                let name = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .context("Invalid template file name")?;
+                self.from_string(name, source)
+            }
+        }
+    }
+
+    /// Create a backend holding a single template
+    pub fn from_string(self, name: &str, source: &str) -> Result<Box<dyn TemplateBackend>> {
+        match self {
+            Engine::Tera => Ok(Box::new(TemplateEngine::from_string(name, source)?)),
+            #[cfg(feature = "handlebars")]
+            Engine::Handlebars => {
+                let mut backend = HandlebarsEngine::new();
+                backend.add_template(name, source)?;
+                Ok(Box::new(backend))
+            }
+        }
+    }
+}
+
+/// How templates are rendered
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderOptions {
+    /// Template language
+    pub engine: Engine,
+    /// Render missing or null context variables as empty instead of failing
+    pub lenient: bool,
+}
+
+impl TemplateBackend for TemplateEngine {
+    fn add_template(&mut self, name: &str, source: &str) -> Result<()> {
+        self.add_raw_template(name, source)
+    }
+
+    fn render_template(
+        &self,
+        name: &str,
+        context: &HashMap<String, Value>,
+        lenient: bool,
+    ) -> Result<String> {
+        self.render_with(name, context, lenient)
+    }
+
+    fn list_templates(&self) -> Vec<&str> {
+        self.get_template_names()
+    }
+}
+
+/// Handlebars backend
+///
+/// Templates never HTML-escape values, and front-matter defaults apply as with
+/// Tera. Strict mode reports missing variables unless rendering is lenient.
+#[cfg(feature = "handlebars")]
+pub struct HandlebarsEngine {
+    registry: handlebars::Handlebars<'static>,
+    front_matter: HashMap<String, FrontMatter>,
+}
+
+#[cfg(feature = "handlebars")]
+impl HandlebarsEngine {
+    /// Create an empty Handlebars backend
+    pub fn new() -> Self {
+        let mut registry = handlebars::Handlebars::new();
+        registry.register_escape_fn(handlebars::no_escape);
+
+        HandlebarsEngine {
+            registry,
+            front_matter: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "handlebars")]
+impl Default for HandlebarsEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "handlebars")]
+impl TemplateBackend for HandlebarsEngine {
+    fn add_template(&mut self, name: &str, source: &str) -> Result<()> {
+        let body = match crate::front_matter::parse_front_matter(source)? {
+            Some((front_matter, _, body)) => {
+                self.front_matter.insert(name.to_string(), front_matter);
+                body
+            }
+            None => source,
+        };
+
+        self.registry
+            .register_template_string(name, body)
+            .context("Failed to add Handlebars template")
+    }
+
+    fn render_template(
+        &self,
+        name: &str,
+        context: &HashMap<String, Value>,
+        lenient: bool,
+    ) -> Result<String> {
+        let front_matter = self.front_matter.get(name).cloned().unwrap_or_default();
+        let data = front_matter.apply_defaults(context);
+
+        let mut registry = self.registry.clone();
+        registry.set_strict_mode(!lenient);
+        let code = registry
+            .render(name, &data)
+            .context("Failed to render Handlebars template")?;
+        front_matter.add_imports(code, context)
+    }
+
+    fn list_templates(&self) -> Vec<&str> {
+        self.registry
+            .get_templates()
+            .keys()
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::create_context;
+    use serde_json::json;
+
+    #[test]
+    fn test_tera_backend() {
+        let backend = Engine::Tera
+            .from_string("t", "pub struct {{ name }};")
+            .unwrap();
+        let context = create_context(vec![("name", json!("User"))]);
+
+        assert_eq!(backend.list_templates(), vec!["t"]);
+        assert_eq!(
+            backend.render_template("t", &context, false).unwrap(),
+            "pub struct User;"
+        );
+    }
+
+    #[cfg(feature = "handlebars")]
+    #[test]
+    fn test_handlebars_backend() {
+        let template = "+++\n[defaults]\nvis = \"pub\"\n+++\n\
+            {{vis}} struct {{name}} {\n{{#each fields}}    {{name}}: {{ty}},\n{{/each}}}\n";
+        let backend = Engine::parse("handlebars")
+            .unwrap()
+            .from_string("t", template)
+            .unwrap();
+
+        let context = create_context(vec![
+            ("name", json!("User")),
+            ("fields", json!([{ "name": "id", "ty": "Vec<u8>" }])),
+        ]);
+        assert_eq!(
+            backend.render_template("t", &context, false).unwrap(),
+            "pub struct User {\n    id: Vec<u8>,\n}\n"
+        );
+
+        let context = create_context(vec![("fields", json!([]))]);
+        assert!(backend.render_template("t", &context, false).is_err());
+        assert!(backend.render_template("t", &context, true).is_ok());
+    }
+}
@@ -0,0 +1,59 @@
+//! `cargo rpt` subcommand entry point
+//!
+//! Cargo invokes this binary as `cargo-rpt rpt <args>`. Paths are resolved
+//! relative to the workspace root, and configuration is read from
+//! `[package.metadata.rpt]` in its Cargo.toml when no `rpt.toml` is given.
+
+use anyhow::{Context, Result};
+use clap::{CommandFactory, FromArgMatches};
+use std::path::PathBuf;
+use std::process::Command;
+
+use rpt::cli::Cli;
+use rpt::config::Config;
+
+fn main() -> Result<()> {
+    // Drop the `rpt` argument cargo inserts before ours
+    let mut args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("rpt") {
+        args.remove(1);
+    }
+
+    let matches = Cli::command().bin_name("cargo rpt").get_matches_from(args);
+    let cli = Cli::from_arg_matches(&matches)?;
+
+    let manifest = workspace_manifest()?;
+    let root = manifest
+        .parent()
+        .context("Workspace manifest has no parent directory")?;
+    std::env::set_current_dir(root)
+        .with_context(|| format!("Failed to enter workspace root {}", root.display()))?;
+
+    let config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => match Config::from_cargo_manifest(&manifest)? {
+            Some(config) => config,
+            None => Config::discover(root)?,
+        },
+    };
+
+    rpt::app::run(cli, config)
+}
+
+/// Locate the workspace root Cargo.toml with `cargo locate-project`
+fn workspace_manifest() -> Result<PathBuf> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .output()
+        .context("Failed to run cargo locate-project")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Not inside a cargo workspace:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(PathBuf::from(String::from_utf8(output.stdout)?.trim()))
+}
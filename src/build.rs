@@ -0,0 +1,90 @@
+//! Build script support
+//!
+//! This module exposes the generator in a form suited to `build.rs`: outputs go
+//! to `OUT_DIR`, cargo is told which inputs to watch, and nothing is ever
+//! prompted or printed besides `cargo:` directives.
+//!
+//! ```no_run
+//! // in build.rs `main`
+//! rpt::build::generate_in_out_dir("templates/model.tera", "contexts/model.json", "model.rs")?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::app::load_context_data;
+use crate::generator::{generate, GenerateOptions};
+use crate::merger::MergeStrategy;
+
+/// Render `template` with `context` into `$OUT_DIR/<file_name>`
+///
+/// Emits `cargo:rerun-if-changed` for both inputs and returns the output path,
+/// ready for `include!(concat!(env!("OUT_DIR"), "/<file_name>"))`.
+pub fn generate_in_out_dir(
+    template: impl AsRef<Path>,
+    context: impl AsRef<Path>,
+    file_name: &str,
+) -> Result<PathBuf> {
+    let out_dir =
+        std::env::var_os("OUT_DIR").context("OUT_DIR is not set; call this from build.rs")?;
+
+    generate_into(template, context, Path::new(&out_dir), file_name)
+}
+
+/// Same as [`generate_in_out_dir`] with an explicit output directory
+pub fn generate_into(
+    template: impl AsRef<Path>,
+    context: impl AsRef<Path>,
+    out_dir: &Path,
+    file_name: &str,
+) -> Result<PathBuf> {
+    let template = template.as_ref();
+    let context = context.as_ref();
+
+    println!("cargo:rerun-if-changed={}", template.display());
+    println!("cargo:rerun-if-changed={}", context.display());
+
+    let output = out_dir.join(file_name);
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Files in OUT_DIR are never edited by hand, so the template always wins
+    let context_data = load_context_data(context)?;
+    let options = GenerateOptions {
+        strategy: MergeStrategy::PreferTemplate,
+        ..GenerateOptions::default()
+    };
+    let result = generate(template, context_data, &output, options)?;
+
+    // Leave the file untouched when nothing changed to avoid needless rebuilds
+    if fs::read_to_string(&output).ok().as_deref() != Some(result.as_str()) {
+        fs::write(&output, result)?;
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_into() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("model.tera");
+        let context = dir.path().join("model.json");
+        fs::write(&template, "pub struct {{ name }};\n").unwrap();
+        fs::write(&context, r#"{"name": "User"}"#).unwrap();
+
+        let out_dir = dir.path().join("out");
+        let output = generate_into(&template, &context, &out_dir, "gen/model.rs").unwrap();
+
+        assert_eq!(output, out_dir.join("gen/model.rs"));
+        assert!(fs::read_to_string(output)
+            .unwrap()
+            .contains("pub struct User;"));
+    }
+}
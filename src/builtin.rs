@@ -0,0 +1,328 @@
+//! Built-in templates module
+//!
+//! This module bundles starter templates for common patterns, selected with
+//! `--template builtin:<name>` or a target's `template = "builtin:<name>"`, so
+//! the tool is useful before a project writes its own:
+//!
+//! - `builder`: a struct with a builder, from `name` and `fields`,
+//! - `display-fromstr`: an enum with `Display` and `FromStr` impls, from `name`
+//!   and `variants`,
+//! - `serde-dto`: a serde data transfer object, from `name` and `fields`,
+//! - `axum-crud`: axum CRUD handlers and routes for `name`,
+//! - `sea-orm-entity`: a SeaORM entity, from `table` and `fields`.
+//!
+//! Fields are objects with `name`, `type` and an optional `doc`. A
+//! `builtin/<name>.tera` file under the template directories overrides the
+//! bundled template; otherwise it is written to the template cache (see
+//! [`crate::remote::cache_dir`]) and rendered from there.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+/// Prefix of built-in template arguments
+pub const PREFIX: &str = "builtin:";
+
+/// Directory, under a template directory, of the templates overriding the built-in ones
+pub const OVERRIDE_DIR: &str = "builtin";
+
+/// Bundled templates, by name
+const TEMPLATES: &[(&str, &str)] = &[
+    (
+        "builder",
+        r#"+++
+description = "Struct with a builder, from `name` and `fields`"
+
+[defaults]
+doc = ""
++++
+{% import "rpt::macros" as rpt -%}
+{% if doc %}{{ rpt::doc(text=doc) }}
+{% endif %}#[derive(Debug, Clone, PartialEq)]
+pub struct {{ name }} {
+    {{ rpt::fields(fields=fields) }}
+}
+
+impl {{ name }} {
+    /// Start building a {{ name }}
+    pub fn builder() -> {{ name }}Builder {
+        {{ name }}Builder::default()
+    }
+}
+
+/// Builder of [`{{ name }}`]
+#[derive(Debug, Clone, Default)]
+pub struct {{ name }}Builder {
+{%- for field in fields %}
+    {{ field.name }}: Option<{{ field.type }}>,
+{%- endfor %}
+}
+
+impl {{ name }}Builder {
+{%- for field in fields %}
+    pub fn {{ field.name }}(mut self, {{ field.name }}: {{ field.type }}) -> Self {
+        self.{{ field.name }} = Some({{ field.name }});
+        self
+    }
+{% endfor %}
+    /// Build the {{ name }}, failing on the first missing field
+    pub fn build(self) -> Result<{{ name }}, String> {
+        Ok({{ name }} {
+{%- for field in fields %}
+            {{ field.name }}: self.{{ field.name }}.ok_or("missing field `{{ field.name }}`")?,
+{%- endfor %}
+        })
+    }
+}
+"#,
+    ),
+    (
+        "display-fromstr",
+        r#"+++
+description = "Enum with Display and FromStr impls, from `name` and `variants` with `name` and an optional `text`"
+
+[defaults]
+doc = ""
++++
+{% import "rpt::macros" as rpt -%}
+{% if doc %}{{ rpt::doc(text=doc) }}
+{% endif %}#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum {{ name }} {
+{%- for variant in variants %}
+    {{ variant.name }},
+{%- endfor %}
+}
+
+impl std::fmt::Display for {{ name }} {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+{%- for variant in variants %}
+            {{ name }}::{{ variant.name }} => "{{ variant.text | default(value=variant.name) }}",
+{%- endfor %}
+        })
+    }
+}
+
+impl std::str::FromStr for {{ name }} {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+{%- for variant in variants %}
+            "{{ variant.text | default(value=variant.name) }}" => Ok({{ name }}::{{ variant.name }}),
+{%- endfor %}
+            _ => Err(format!("unknown {{ name }} '{}'", s)),
+        }
+    }
+}
+"#,
+    ),
+    (
+        "serde-dto",
+        r#"+++
+description = "Serde data transfer object, from `name` and `fields`, optional when `optional` is set"
+
+[defaults]
+doc = ""
+rename_all = "camelCase"
++++
+{% import "rpt::macros" as rpt -%}
+use serde::{Deserialize, Serialize};
+
+{% if doc %}{{ rpt::doc(text=doc) }}
+{% endif %}#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "{{ rename_all }}")]
+pub struct {{ name }} {
+{%- for field in fields %}
+{%- if field.doc %}
+    {{ rpt::doc(text=field.doc, indent="    ") }}
+{%- endif %}
+{%- if field.optional %}
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub {{ field.name }}: Option<{{ field.type }}>,
+{%- else %}
+    pub {{ field.name }}: {{ field.type }},
+{%- endif %}
+{%- endfor %}
+}
+"#,
+    ),
+    (
+        "axum-crud",
+        r#"+++
+description = "axum CRUD handlers and routes for the `name` model of `module`"
+
+[defaults]
+module = "crate::models"
+id_type = "u64"
++++
+{%- set lower = name | lower -%}
+{%- set resource = "/" ~ lower ~ "s" -%}
+{%- set path = path | default(value=resource) -%}
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+
+use {{ module }}::{{ name }};
+
+/// Routes of the {{ name }} resource
+pub fn routes() -> Router {
+    Router::new()
+        .route("{{ path }}", get(list).post(create))
+        .route("{{ path }}/:id", get(read).put(update).delete(delete))
+}
+
+/// List every {{ name }}
+pub async fn list() -> Json<Vec<{{ name }}>> {
+    todo!()
+}
+
+/// Create a {{ name }}
+pub async fn create(Json(input): Json<{{ name }}>) -> Result<(StatusCode, Json<{{ name }}>), StatusCode> {
+    todo!()
+}
+
+/// Get a {{ name }} by id
+pub async fn read(Path(id): Path<{{ id_type }}>) -> Result<Json<{{ name }}>, StatusCode> {
+    todo!()
+}
+
+/// Replace a {{ name }}
+pub async fn update(Path(id): Path<{{ id_type }}>, Json(input): Json<{{ name }}>) -> Result<Json<{{ name }}>, StatusCode> {
+    todo!()
+}
+
+/// Delete a {{ name }}
+pub async fn delete(Path(id): Path<{{ id_type }}>) -> StatusCode {
+    todo!()
+}
+"#,
+    ),
+    (
+        "sea-orm-entity",
+        r#"+++
+description = "SeaORM entity, from `table` and `fields`, keyed by those with `primary_key`"
++++
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "{{ table }}")]
+pub struct Model {
+{%- for field in fields %}
+{%- if field.primary_key %}
+    #[sea_orm(primary_key)]
+{%- endif %}
+    pub {{ field.name }}: {{ field.type }},
+{%- endfor %}
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+"#,
+    ),
+];
+
+/// Names of the built-in templates
+pub fn names() -> impl Iterator<Item = &'static str> {
+    TEMPLATES.iter().map(|(name, _)| *name)
+}
+
+/// Source of a built-in template
+pub fn source(name: &str) -> Option<&'static str> {
+    TEMPLATES
+        .iter()
+        .find(|(template, _)| *template == name)
+        .map(|(_, source)| *source)
+}
+
+/// Resolve a `builtin:<name>` template argument to a file, `None` for other templates
+///
+/// An override under the config's template directories wins over the bundled template.
+pub fn resolve(config: &Config, template: &Path) -> Result<Option<PathBuf>> {
+    let Some(name) = template.to_str().and_then(|t| t.strip_prefix(PREFIX)) else {
+        return Ok(None);
+    };
+
+    let file_name = format!("{}.tera", name);
+    let overridden = config
+        .template_search_dirs()
+        .into_iter()
+        .map(|dir| dir.join(OVERRIDE_DIR).join(&file_name))
+        .find(|path| path.is_file());
+    if overridden.is_some() {
+        return Ok(overridden);
+    }
+
+    let source = source(name).with_context(|| {
+        format!(
+            "Unknown built-in template '{}', available: {}",
+            name,
+            names().collect::<Vec<_>>().join(", ")
+        )
+    })?;
+    let path = crate::remote::cache_dir()?
+        .join(OVERRIDE_DIR)
+        .join(&file_name);
+    if fs::read_to_string(&path).ok().as_deref() != Some(source) {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, source).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{create_context, TemplateEngine};
+    use serde_json::json;
+
+    #[test]
+    fn test_builtin_templates_render_rust() {
+        let fields = json!([
+            { "name": "id", "type": "i64", "doc": "Identifier", "primary_key": true },
+            { "name": "email", "type": "String", "optional": true },
+        ]);
+        let variants = json!([{ "name": "Active", "text": "active" }, { "name": "Banned" }]);
+        let context = create_context(vec![
+            ("name", json!("User")),
+            ("table", json!("users")),
+            ("fields", fields),
+            ("variants", variants),
+        ]);
+
+        for name in names() {
+            let engine = TemplateEngine::from_string(name, source(name).unwrap()).unwrap();
+            let code = engine.render(name, &context).unwrap();
+            syn::parse_file(&code).unwrap_or_else(|e| panic!("{}: {}\n{}", name, e, code));
+        }
+        let engine = TemplateEngine::from_string("t", source("display-fromstr").unwrap()).unwrap();
+        let code = engine.render("t", &context).unwrap();
+        assert!(code.contains(r#"User::Banned => "Banned","#));
+        assert!(code.contains(r#""active" => Ok(User::Active),"#));
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("templates/builtin")).unwrap();
+        fs::write(dir.path().join("templates/builtin/builder.tera"), "").unwrap();
+        let config = Config {
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        assert_eq!(
+            resolve(&config, Path::new("builtin:builder")).unwrap(),
+            Some(dir.path().join("templates/builtin/builder.tera"))
+        );
+        assert_eq!(resolve(&config, Path::new("model.tera")).unwrap(), None);
+        assert!(resolve(&config, Path::new("builtin:missing"))
+            .unwrap_err()
+            .to_string()
+            .contains("available: builder, display-fromstr"));
+    }
+}
@@ -0,0 +1,281 @@
+//! Cargo.toml module
+//!
+//! This module merges generated TOML into an existing file key by key, when
+//! generating with `--language toml`, so templates can add the dependencies
+//! their generated code needs without clobbering those added by hand. The
+//! existing file is edited in place, keeping its comments and layout:
+//!
+//! - keys and tables only the template has are added, and keys only the
+//!   existing file has are kept,
+//! - `[features]` lists and the `features` of dependencies are merged as sets,
+//! - a dependency given as a version string is merged with its table form,
+//!   such as `serde = "1"` and `serde = { version = "1", features = ["derive"] }`,
+//! - any other value both sides set differently is resolved by the merge
+//!   strategy.
+
+use anyhow::{Context, Result};
+use toml_edit::{DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+use crate::diff::compute_text_diff;
+use crate::generator::MergedOutput;
+use crate::merger::{MergeStrategy, Warning};
+
+/// Tables whose entries are dependencies
+const DEPENDENCY_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Merge `generated` TOML into `existing`
+pub fn merge_toml(
+    generated: &str,
+    existing: &str,
+    strategy: MergeStrategy,
+) -> Result<MergedOutput> {
+    let generated: DocumentMut = generated
+        .parse()
+        .context("Failed to parse generated TOML")?;
+    let mut merged: DocumentMut = existing.parse().context("Failed to parse existing TOML")?;
+
+    let mut merge = TomlMerge {
+        strategy,
+        conflicts: Vec::new(),
+        position: last_position(merged.as_table()),
+    };
+    merge.merge_table(&mut Vec::new(), merged.as_table_mut(), generated.as_table());
+    let conflicts = merge.conflicts;
+
+    if !conflicts.is_empty() && strategy == MergeStrategy::FailOnConflict {
+        anyhow::bail!("Merge conflicts detected:\n{}", conflicts.join("\n"));
+    }
+    Ok(MergedOutput {
+        code: merged.to_string(),
+        conflicts: conflicts.into_iter().map(Warning::conflict).collect(),
+    })
+}
+
+/// Line diff between the existing TOML and the generated TOML merged into it
+pub fn diff_toml(generated: &str, existing: &str) -> Result<String> {
+    let merged = merge_toml(generated, existing, MergeStrategy::PreferTemplate)?;
+    if merged.code == existing {
+        return Ok("No differences found.".to_string());
+    }
+    Ok(compute_text_diff(existing, &merged.code))
+}
+
+/// State of a merge into an existing document
+struct TomlMerge {
+    strategy: MergeStrategy,
+    conflicts: Vec<String>,
+    /// Position of the last table header, new tables are printed after it
+    position: usize,
+}
+
+impl TomlMerge {
+    /// Merge the keys of a generated table into an existing one, `path` leading to both
+    fn merge_table(
+        &mut self,
+        path: &mut Vec<String>,
+        existing: &mut dyn TableLike,
+        generated: &dyn TableLike,
+    ) {
+        for (key, generated_item) in generated.iter() {
+            path.push(key.to_string());
+            match existing.get_mut(key) {
+                None => {
+                    let mut item = generated_item.clone();
+                    self.place_last(&mut item);
+                    existing.insert(key, item);
+                }
+                Some(existing_item) => self.merge_item(path, existing_item, generated_item),
+            }
+            path.pop();
+        }
+    }
+
+    /// Merge a generated value into the existing value at `path`
+    fn merge_item(&mut self, path: &mut Vec<String>, existing: &mut Item, generated: &Item) {
+        if is_dependency(path) {
+            // `name = "1"` is short for `name = { version = "1" }`
+            if let (Some(version), Some(_)) = (existing.as_str(), generated.as_table_like()) {
+                let mut table = InlineTable::new();
+                table.insert("version", Value::from(version));
+                *existing = Item::Value(Value::InlineTable(table));
+            }
+            if let (Some(_), Some(version)) = (existing.as_table_like(), generated.as_str()) {
+                let mut table = InlineTable::new();
+                table.insert("version", Value::from(version));
+                return self.merge_item(path, existing, &Item::Value(Value::InlineTable(table)));
+            }
+        }
+
+        if let (Some(existing), Some(generated)) =
+            (existing.as_table_like_mut(), generated.as_table_like())
+        {
+            return self.merge_table(path, existing, generated);
+        }
+
+        if is_feature_list(path) {
+            if let (Some(existing), Some(generated)) =
+                (existing.as_array_mut(), generated.as_array())
+            {
+                for value in generated {
+                    if !existing.iter().any(|other| same_value(other, value)) {
+                        existing.push_formatted(value.clone().decorated("", ""));
+                    }
+                }
+                existing.fmt();
+                return;
+            }
+        }
+
+        let (manual, template) = (text(existing), text(generated));
+        if manual == template {
+            return;
+        }
+        let key = path.join(".");
+        match self.strategy {
+            MergeStrategy::PreferTemplate => {
+                self.conflicts.push(format!(
+                    "Key '{}' was set to {} by hand, overridden by template value {}",
+                    key, manual, template
+                ));
+                let decor = existing.as_value().map(|value| value.decor().clone());
+                *existing = generated.clone();
+                if let (Some(decor), Some(value)) = (decor, existing.as_value_mut()) {
+                    *value.decor_mut() = decor;
+                }
+            }
+            MergeStrategy::PreferManual | MergeStrategy::Smart => self.conflicts.push(format!(
+                "Key '{}' was set to {} by hand, template value {} skipped",
+                key, manual, template
+            )),
+            MergeStrategy::FailOnConflict => self.conflicts.push(format!(
+                "Conflict: Key '{}' was set to {} by hand, template sets {}",
+                key, manual, template
+            )),
+        }
+    }
+
+    /// Print the tables of an inserted item after the existing ones, apart from them
+    fn place_last(&mut self, item: &mut Item) {
+        let tables: Vec<&mut Table> = match item {
+            Item::Table(table) => vec![table],
+            Item::ArrayOfTables(array) => array.iter_mut().collect(),
+            _ => return,
+        };
+        for table in tables {
+            self.position += 1;
+            table.set_position(self.position);
+            if table
+                .decor()
+                .prefix()
+                .is_none_or(|prefix| prefix.as_str() == Some(""))
+            {
+                table.decor_mut().set_prefix("\n");
+            }
+            for (_, child) in table.iter_mut() {
+                self.place_last(child);
+            }
+        }
+    }
+}
+
+/// Position of the last table header of a document
+fn last_position(table: &Table) -> usize {
+    table
+        .iter()
+        .flat_map(|(_, item)| match item {
+            Item::Table(table) => vec![table],
+            Item::ArrayOfTables(array) => array.iter().collect(),
+            _ => Vec::new(),
+        })
+        .map(|table| table.position().unwrap_or(0).max(last_position(table)))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Whether `path` leads to a dependency, such as `dependencies.serde` or
+/// `target.'cfg(unix)'.dev-dependencies.libc`
+fn is_dependency(path: &[String]) -> bool {
+    matches!(path, [.., table, _] if DEPENDENCY_TABLES.contains(&table.as_str()))
+}
+
+/// Whether `path` leads to a list of features, merged as a set
+fn is_feature_list(path: &[String]) -> bool {
+    match path {
+        [features, _] => features == "features",
+        [.., _, last] => last == "features" && is_dependency(&path[..path.len() - 1]),
+        _ => false,
+    }
+}
+
+/// Source text of a value, without its surrounding whitespace and comments
+fn text(item: &Item) -> String {
+    match item.as_value() {
+        Some(value) => value.clone().decorated("", "").to_string(),
+        None => item.to_string().trim().to_string(),
+    }
+}
+
+/// Whether two values are the same, whatever their formatting
+fn same_value(a: &Value, b: &Value) -> bool {
+    a.clone().decorated("", "").to_string() == b.clone().decorated("", "").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_toml() {
+        let existing = r#"[package]
+name = "app"
+version = "0.2.0" # bumped by hand
+
+[dependencies]
+# Logging
+log = "0.4"
+serde = "1.0"
+
+[features]
+default = ["cli"]
+cli = []
+"#;
+        let generated = r#"[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+serde = { version = "1.0", features = ["derive"] }
+serde_json = "1.0"
+
+[features]
+default = ["json"]
+"#;
+
+        let merged = merge_toml(generated, existing, MergeStrategy::PreferManual).unwrap();
+        assert_eq!(
+            merged.code,
+            r#"[package]
+name = "app"
+version = "0.2.0" # bumped by hand
+
+[dependencies]
+# Logging
+log = "0.4"
+serde = { version = "1.0", features = ["derive"] }
+serde_json = "1.0"
+
+[features]
+default = ["cli", "json"]
+cli = []
+"#
+        );
+        assert_eq!(
+            merged.conflicts,
+            vec!["Key 'package.version' was set to \"0.2.0\" by hand, template value \"0.1.0\" skipped"]
+        );
+
+        let merged = merge_toml(generated, existing, MergeStrategy::PreferTemplate).unwrap();
+        assert!(merged.code.contains("version = \"0.1.0\" # bumped by hand"));
+        assert!(merge_toml(generated, existing, MergeStrategy::FailOnConflict).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! Manifest check module
+//!
+//! This module checks every manifest target at once for `check --all`, in
+//! parallel, and rates each pending change by severity:
+//!
+//! - `error`: a change to a file edited by hand since its last generation,
+//!   where the template and the manual edits diverge,
+//! - `warning`: an item of the file the template doesn't produce, which the
+//!   template deleted or which was added by hand,
+//! - `info`: a pending template update on a file nobody edited, a new item or
+//!   a new import.
+//!
+//! Like `status`, hashes recorded in `.rpt/cache.json` tell the two apart;
+//! targets that were never generated count as edited by hand.
+
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use syn::spanned::Spanned;
+use syn::Item;
+
+use crate::ast_parser::ParsedFile;
+use crate::backend::RenderOptions;
+use crate::config::{Config, Target};
+use crate::diff::{compute_patch, extract_item_name, PatchOp};
+use crate::edition::Edition;
+use crate::generator::render_template_file;
+use crate::imports::import_paths;
+use crate::incremental::{hash_target, RegenerationCache};
+use crate::matching::{with_comparison, with_generics, Comparison, GenericsMatching};
+use crate::summary::{describe_file_header, describe_op};
+use crate::target_refs::target_context;
+
+/// How serious a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// Parse a severity: info, warning or error
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => anyhow::bail!(
+                "Unknown severity '{}', expected info, warning or error",
+                value
+            ),
+        }
+    }
+
+    /// Name used in reports
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// One pending change of a target
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    /// First and last line of the item in the existing file, 1-based
+    pub lines: Option<(usize, usize)>,
+}
+
+/// Findings of one manifest target
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetCheck {
+    pub output: PathBuf,
+    pub findings: Vec<Finding>,
+}
+
+impl TargetCheck {
+    /// Highest severity among the findings
+    pub fn severity(&self) -> Option<Severity> {
+        self.findings.iter().map(|finding| finding.severity).max()
+    }
+}
+
+/// Check a manifest target against its regenerated output, in memory
+pub fn check_target(
+    config: &Config,
+    target: &Target,
+    cache: &RegenerationCache,
+    render: RenderOptions,
+) -> Result<TargetCheck> {
+    let output = config.resolve_path(&target.output);
+    let mut findings = Vec::new();
+    let Ok(existing) = fs::read_to_string(&output) else {
+        findings.push(Finding {
+            severity: Severity::Info,
+            message: "Not generated yet".to_string(),
+            lines: None,
+        });
+        return Ok(TargetCheck {
+            output: target.output.clone(),
+            findings,
+        });
+    };
+
+    let context_data = target_context(config, target)?;
+    let generated = render_template_file(
+        &config.resolve_path(&target.template),
+        &context_data,
+        render,
+    )?;
+
+    let hashes = hash_target(config, target, render)?;
+    let edited = cache
+        .recorded(target)
+        .is_none_or(|recorded| recorded.output != hashes.output);
+    // Workers run on their own threads, without the caller's matching settings
+    let (generics, comparison) = (
+        GenericsMatching::from_config(config)?,
+        Comparison::from_config(config)?,
+    );
+    let edition = Edition::resolve(Edition::from_config(config)?, output.parent());
+    findings.extend(with_generics(generics, || {
+        with_comparison(comparison, || {
+            find_changes(&existing, &generated, edited, edition)
+        })
+    })?);
+
+    Ok(TargetCheck {
+        output: target.output.clone(),
+        findings,
+    })
+}
+
+/// Rate the changes regenerating `existing` as `generated` would make
+///
+/// `edited` tells whether the existing file was edited by hand since it was
+/// generated. Both versions are parsed as `edition`. Findings are sorted by decreasing severity and located in the
+/// existing file when they concern one of its items.
+pub fn find_changes(
+    existing: &str,
+    generated: &str,
+    edited: bool,
+    edition: Edition,
+) -> Result<Vec<Finding>> {
+    let existing_ast = ParsedFile::parse_edition(existing, edition)?;
+    let generated_ast = ParsedFile::parse_edition(generated, edition)?;
+    let lines = |item: &Item| {
+        let span = item.span();
+        Some((span.start().line, span.end().line))
+    };
+
+    let mut findings = Vec::new();
+    let changed = if edited {
+        Severity::Error
+    } else {
+        Severity::Info
+    };
+
+    if let Some(header) =
+        describe_file_header(&existing_ast.syntax_tree, &generated_ast.syntax_tree)
+    {
+        findings.push(Finding {
+            severity: changed,
+            message: header,
+            lines: None,
+        });
+    }
+
+    let patch = compute_patch(existing_ast.items(), generated_ast.items())?;
+    for item in &patch.imports {
+        if let Item::Use(item) = item {
+            for path in import_paths(item) {
+                findings.push(Finding {
+                    severity: Severity::Info,
+                    message: format!("Import `{}`", path),
+                    lines: None,
+                });
+            }
+        }
+    }
+    for op in &patch.operations {
+        let (severity, lines) = match op {
+            PatchOp::Modify { old_item, .. } | PatchOp::Rename { old_item, .. } => {
+                (changed, lines(old_item))
+            }
+            PatchOp::Delete { name } => (
+                Severity::Warning,
+                existing_ast
+                    .items()
+                    .iter()
+                    .find(|item| extract_item_name(item).as_ref() == Some(name))
+                    .and_then(lines),
+            ),
+            PatchOp::Move { from, .. } => (Severity::Info, lines(&existing_ast.items()[*from])),
+            _ => (Severity::Info, None),
+        };
+        if let Some(message) = describe_op(op) {
+            findings.push(Finding {
+                severity,
+                message,
+                lines,
+            });
+        }
+    }
+
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    Ok(findings)
+}
+
+/// Check every manifest target, spread over the available cores
+///
+/// Results follow the order of the manifest.
+pub fn check_manifest(config: &Config, render: RenderOptions) -> Result<Vec<TargetCheck>> {
+    let cache = RegenerationCache::load(config);
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = config.targets.len().div_ceil(workers).max(1);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = config
+            .targets
+            .chunks(chunk)
+            .map(|targets| {
+                let cache = &cache;
+                scope.spawn(move || {
+                    targets
+                        .iter()
+                        .map(|target| check_target(config, target, cache, render))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("check worker panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = |name: &str| Target {
+            name: None,
+            depends_on: Vec::new(),
+            template: "t.tera".into(),
+            context: "c.json".into(),
+            output: format!("{}.rs", name).into(),
+            strategy: "manual".to_string(),
+            language: "rust".to_string(),
+        };
+        let config = Config {
+            root: dir.path().to_path_buf(),
+            targets: vec![target("a"), target("b"), target("c")],
+            ..Config::default()
+        };
+        fs::write(
+            dir.path().join("t.tera"),
+            "pub fn a() -> u8 { 1 }\npub fn b() {}",
+        )
+        .unwrap();
+        fs::write(dir.path().join("c.json"), "{}").unwrap();
+        fs::write(
+            dir.path().join("a.rs"),
+            "pub fn a() -> u8 { 1 }\npub fn b() {}",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("b.rs"),
+            "pub fn a() -> u8 { 2 }\npub fn c() {}",
+        )
+        .unwrap();
+
+        let checks = check_manifest(&config, RenderOptions::default()).unwrap();
+        assert_eq!(checks[0].findings, vec![]);
+        assert_eq!(checks[1].severity(), Some(Severity::Error));
+        let severities: Vec<Severity> = checks[1].findings.iter().map(|f| f.severity).collect();
+        assert_eq!(
+            severities,
+            vec![Severity::Error, Severity::Warning, Severity::Info]
+        );
+        assert_eq!(checks[1].findings[1].lines, Some((2, 2)));
+        assert_eq!(checks[2].severity(), Some(Severity::Info));
+        assert!(Severity::parse("fatal").is_err());
+    }
+}
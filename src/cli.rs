@@ -3,8 +3,13 @@
 //! This module defines the command-line interface with generate, diff, and check commands.
 
 use clap::{Parser, Subcommand};
+use clap_complete::engine::ArgValueCandidates;
 use std::path::PathBuf;
 
+use crate::completions::{
+    engine_candidates, formatter_candidates, strategy_candidates, template_candidates,
+};
+
 /// Rust code generator with AST-based intelligent merging
 #[derive(Parser, Debug)]
 #[command(name = "rust-patchs-templates")]
@@ -16,74 +21,579 @@ changes with existing files using AST-based Patch Theory. Preserves manual edits
 template updates. No text markers - pure structural diffing."
 )]
 pub struct Cli {
+    /// Path to the project config file (defaults to rpt.toml in the current directory or above)
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Render missing or null context variables as empty instead of failing
+    #[arg(long, global = true)]
+    pub lenient: bool,
+
+    /// Template engine: tera or handlebars
+    #[arg(long, global = true, default_value = "tera", add = ArgValueCandidates::new(engine_candidates))]
+    pub engine: String,
+
+    /// Branch, tag or commit to use for `--template <git url>#<path>`
+    #[arg(long, global = true)]
+    pub template_ref: Option<String>,
+
+    /// Pair items the template dropped and added as renames when at least this similar (0 to 1)
+    #[arg(long, global = true)]
+    pub match_threshold: Option<f64>,
+
+    /// Treat items whose doc comments alone differ as unchanged
+    #[arg(long, global = true)]
+    pub ignore_docs: bool,
+
+    /// Treat items that only differ by these attributes as unchanged, e.g. `allow,inline`
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub ignore_attrs: Vec<String>,
+
+    /// Fix `now()`, disable `get_random()` and check two renders of each template are identical
+    #[arg(long, global = true)]
+    pub deterministic: bool,
+
+    /// Print the time spent in each stage of generation, per file
+    #[arg(long, global = true)]
+    pub timings: bool,
+
+    /// Where to write rendered output that fails to parse (defaults to .rpt/last-render.rs)
+    #[arg(long, global = true)]
+    pub debug_render: Option<PathBuf>,
+
+    /// Print diffs directly instead of through `$PAGER` when they don't fit the terminal
+    #[arg(long, global = true)]
+    pub no_pager: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
+    /// Create starter templates, a sample context and an rpt.toml manifest
+    Init {
+        /// Directory to scaffold the project in
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Replace files that already exist
+        #[arg(long, default_value = "false")]
+        force: bool,
+    },
+
     /// Generate code from templates
     Generate {
-        /// Path to template file or directory
+        /// Path to template file or directory, `-` for stdin, or `<git url>#<path>`
+        #[arg(short, long, add = ArgValueCandidates::new(template_candidates))]
+        template: PathBuf,
+
+        /// Path to context data file (JSON)
+        #[arg(short, long)]
+        context: PathBuf,
+
+        /// Output file path, `-` for stdout, or the base directory for template directories and
+        /// `{% file %}` blocks [default: the template's front-matter `output`]
         #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Existing code to merge into, `-` for stdin [default: the output file]
+        #[arg(short, long)]
+        existing: Option<PathBuf>,
+
+        /// Previously generated version of the output, for a three-way merge that applies template
+        /// changes to items not edited by hand; a directory mirroring the output for several files
+        #[arg(long)]
+        base: Option<PathBuf>,
+
+        /// Merge strategy: template, manual, fail, or smart [default: the template's front-matter
+        /// `strategy`, else manual]
+        #[arg(short, long, add = ArgValueCandidates::new(strategy_candidates))]
+        strategy: Option<String>,
+
+        /// Apply item reorders from the template instead of keeping the existing order
+        #[arg(long, default_value = "false")]
+        respect_order: bool,
+
+        /// Formatter for merged output: prettyplease or rustfmt
+        #[arg(long, default_value = "prettyplease", value_parser = ["prettyplease", "rustfmt"], add = ArgValueCandidates::new(formatter_candidates))]
+        formatter: String,
+
+        /// Verify the written output and roll back on failure: check (cargo check, the default),
+        /// clippy to also fail on clippy warnings in generated items, or clippy-report to print them
+        #[arg(long, num_args = 0..=1, default_missing_value = "check", value_parser = ["check", "clippy", "clippy-report"])]
+        verify: Option<String>,
+
+        /// Only check that the output is up to date, without writing it
+        #[arg(long, default_value = "false")]
+        check: bool,
+
+        /// Output language: rust, markdown (merged by section), toml (merged by key), or text to
+        /// merge any other file by `<rpt:keep>` regions
+        #[arg(long, default_value = "rust", value_parser = ["rust", "markdown", "toml", "text"])]
+        language: String,
+
+        /// When the existing Rust file doesn't parse: fail, skip it, text-merge it line by line,
+        /// or overwrite-with-backup to replace it and keep a copy in `<file>.bak`
+        #[arg(long, default_value = "fail", value_parser = ["fail", "skip", "text-merge", "overwrite-with-backup"])]
+        on_parse_error: String,
+
+        /// Write a header recording the template, its hash, the context hash and the tool version
+        #[arg(long, default_value = "false")]
+        provenance: bool,
+
+        /// Output format: text, or json to print the changes, conflicts and verification status of
+        /// every written file as one JSON object
+        #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+        format: String,
+
+        /// Write a report of the items each written file gained, lost or changed, with their diffs
+        /// and conflicts, in the given format (html) to the given path
+        #[arg(long, num_args = 2, value_names = ["FORMAT", "PATH"])]
+        report: Option<Vec<String>>,
+
+        /// Abort before writing anything when the merge meets more than this many conflicts
+        #[arg(long, value_name = "N")]
+        max_conflicts: Option<usize>,
+
+        /// Abort before writing anything when the merge reports any warning, conflicts included
+        #[arg(long, default_value = "false")]
+        fail_on_warnings: bool,
+    },
+
+    /// Render a template as is, without parsing or merging the output
+    Render {
+        /// Path to template file, `-` for stdin, or `<git url>#<path>`
+        #[arg(short, long, add = ArgValueCandidates::new(template_candidates))]
         template: PathBuf,
 
         /// Path to context data file (JSON)
         #[arg(short, long)]
         context: PathBuf,
 
-        /// Output file path
+        /// File to write the rendered template to, `-` for stdout [default: stdout]
         #[arg(short, long)]
-        output: PathBuf,
+        output: Option<PathBuf>,
+    },
 
-        /// Merge strategy: template, manual, or fail
-        #[arg(short, long, default_value = "manual")]
-        strategy: String,
+    /// Generate every target declared in the config file
+    GenerateAll {
+        /// Regenerate targets even if their inputs and output are unchanged
+        #[arg(long, default_value = "false")]
+        force: bool,
+
+        /// Write a report of the items each written file gained, lost or changed, with their diffs
+        /// and conflicts, in the given format (html) to the given path
+        #[arg(long, num_args = 2, value_names = ["FORMAT", "PATH"])]
+        report: Option<Vec<String>>,
+
+        /// Abort before writing anything when the merge meets more than this many conflicts
+        #[arg(long, value_name = "N")]
+        max_conflicts: Option<usize>,
+
+        /// Abort before writing anything when the merge reports any warning, conflicts included
+        #[arg(long, default_value = "false")]
+        fail_on_warnings: bool,
+    },
+
+    /// Report which config targets are up to date, have template updates, manual edits or conflicts
+    Status,
+
+    /// Classify the items of every config target as generated, modified or manual
+    Report {
+        /// Output format: text, json or html
+        #[arg(long, default_value = "text")]
+        format: String,
     },
 
     /// Show diff between generated code and existing file
     Diff {
-        /// Path to template file or directory
-        #[arg(short, long)]
+        /// Path to template file or directory, `-` for stdin, or `<git url>#<path>`
+        #[arg(short, long, add = ArgValueCandidates::new(template_candidates))]
         template: PathBuf,
 
         /// Path to context data file (JSON)
         #[arg(short, long)]
         context: PathBuf,
 
-        /// Existing file to compare against
+        /// Existing file to compare against, `-` for stdin
         #[arg(short, long)]
         existing: PathBuf,
 
         /// Show detailed AST-level diff
         #[arg(short, long, default_value = "false")]
         verbose: bool,
+
+        /// Output format: text, sarif, github (Actions annotations), or patch for a unified diff of
+        /// the merged output that `git apply` takes
+        #[arg(long, default_value = "text")]
+        format: String,
+
+        /// Output language: rust, markdown (diffed by section), toml (diffed by key), or text for
+        /// a line diff that keeps `<rpt:keep>` regions
+        #[arg(long, default_value = "rust", value_parser = ["rust", "markdown", "toml", "text"])]
+        language: String,
+    },
+
+    /// Structurally diff two Rust files, without a template
+    DiffFiles {
+        /// Original file
+        old: PathBuf,
+
+        /// Changed file
+        new: PathBuf,
+
+        /// Show detailed AST-level diff
+        #[arg(short, long, default_value = "false")]
+        verbose: bool,
+
+        /// Output format: text, or json for the patch in the stored patch schema
+        #[arg(long, default_value = "text", value_parser = ["text", "json"])]
+        format: String,
     },
 
     /// Check for conflicts without applying changes
     Check {
-        /// Path to template file or directory
-        #[arg(short, long)]
+        /// Path to template file or directory, `-` for stdin, or `<git url>#<path>`
+        #[arg(short, long, required_unless_present = "all", add = ArgValueCandidates::new(template_candidates))]
+        template: Option<PathBuf>,
+
+        /// Path to context data file (JSON)
+        #[arg(short, long, required_unless_present = "all")]
+        context: Option<PathBuf>,
+
+        /// Existing file to check, `-` for stdin
+        #[arg(short, long, required_unless_present = "all")]
+        existing: Option<PathBuf>,
+
+        /// Check every target declared in the config file, with a report by severity
+        #[arg(long, conflicts_with_all = ["template", "context", "existing"])]
+        all: bool,
+
+        /// Lowest severity that makes `--all` fail: info, warning or error
+        #[arg(long, default_value = "error", requires = "all")]
+        fail_on: String,
+
+        /// Output format: text, sarif or github (Actions annotations)
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
+    /// Explain what the merge would do to each item of an existing file, and why
+    Explain {
+        /// Path to template file or directory, `-` for stdin, or `<git url>#<path>`
+        #[arg(short, long, add = ArgValueCandidates::new(template_candidates))]
         template: PathBuf,
 
         /// Path to context data file (JSON)
         #[arg(short, long)]
         context: PathBuf,
 
-        /// Existing file to check
+        /// Existing file the template would be merged into, `-` for stdin
         #[arg(short, long)]
         existing: PathBuf,
+
+        /// Merge strategy: template, manual, fail, or smart
+        #[arg(short, long, default_value = "manual", add = ArgValueCandidates::new(strategy_candidates))]
+        strategy: String,
+
+        /// Apply item reorders from the template instead of keeping the existing order
+        #[arg(long, default_value = "false")]
+        respect_order: bool,
     },
+
+    /// Review template changes item by item before writing them
+    #[cfg(feature = "tui")]
+    Review {
+        /// Path to template file or directory, or `<git url>#<path>`
+        #[arg(short, long, add = ArgValueCandidates::new(template_candidates))]
+        template: PathBuf,
+
+        /// Path to context data file (JSON)
+        #[arg(short, long)]
+        context: PathBuf,
+
+        /// Output file path
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// Formatter for merged output: prettyplease or rustfmt
+        #[arg(long, default_value = "prettyplease", value_parser = ["prettyplease", "rustfmt"], add = ArgValueCandidates::new(formatter_candidates))]
+        formatter: String,
+    },
+
+    /// Lint a template by rendering it with a sample context
+    Lint {
+        /// Path to template file, or `<git url>#<path>`
+        #[arg(short, long, add = ArgValueCandidates::new(template_candidates))]
+        template: PathBuf,
+
+        /// Path to sample context data file (JSON)
+        #[arg(short, long)]
+        context: PathBuf,
+    },
+
+    /// Run golden tests: render each case and compare it with its snapshot
+    Test {
+        /// Directory of cases, one subdirectory per case with template.tera,
+        /// context.json and expected.rs
+        dir: PathBuf,
+
+        /// Write rendered output as the new expected snapshot
+        #[arg(long, default_value = "false")]
+        update: bool,
+    },
+
+    /// List the templates in a template directory with their required variables
+    ListTemplates {
+        /// Path to template file or directory, or `<git url>#<path>`
+        #[arg(short, long, add = ArgValueCandidates::new(template_candidates))]
+        template: PathBuf,
+    },
+
+    /// Show the variables, filters and loops a template uses from its context
+    Vars {
+        /// Path to template file, or `<git url>#<path>`
+        #[arg(short, long, add = ArgValueCandidates::new(template_candidates))]
+        template: PathBuf,
+    },
+
+    /// Write Rust structs matching the context a template reads, to build it with serde
+    ContextTypes {
+        /// Path to template file, or `<git url>#<path>`
+        #[arg(short, long, add = ArgValueCandidates::new(template_candidates))]
+        template: PathBuf,
+
+        /// File to write the structs to [default: stdout]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Name of the root struct [default: the template name followed by `Context`]
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Turn an existing Rust file into a template and the context that renders it
+    Templatize {
+        /// Rust file to templatize
+        file: PathBuf,
+
+        /// Variables to extract: name, fields, or key=text for any other word
+        #[arg(long, value_delimiter = ',', required = true)]
+        vars: Vec<String>,
+
+        /// Template file to write [default: templates/<file stem>.rs.tera]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Context file to write [default: contexts/<file stem>.json]
+        #[arg(short, long)]
+        context: Option<PathBuf>,
+
+        /// Replace files that already exist
+        #[arg(long, default_value = "false")]
+        force: bool,
+    },
+
+    /// List the top-level items of a Rust file as the merger sees them
+    Parse {
+        /// Rust file to parse
+        file: PathBuf,
+
+        /// Print the items as JSON
+        #[arg(long, default_value = "false")]
+        json: bool,
+    },
+
+    /// Re-resolve the remote templates in rpt.lock to the latest revision of their ref
+    UpdateLock,
+
+    /// Check that every target output was generated from its current template and context, as recorded in rpt.lock
+    Verify,
+
+    /// Record the current content of generated files as the base of later three-way merges
+    Snapshot {
+        /// Generated files to record
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        paths: Vec<PathBuf>,
+
+        /// Record the output of every target
+        #[arg(long, default_value = "false")]
+        all: bool,
+    },
+
+    /// Serve render, diff, check and merge as JSON-RPC for editor integrations
+    Serve {
+        /// Communicate over stdin/stdout with Content-Length framed messages
+        #[arg(long, default_value = "false")]
+        stdio: bool,
+    },
+
+    /// Print the shell completion script, e.g. `source <(rust-patchs-templates completions bash)`
+    Completions {
+        /// Shell to generate completions for
+        shell: clap_complete::Shell,
+    },
+
+    /// AST merge of two Rust files, three-way when `--base` is given
+    MergeFiles {
+        /// Common ancestor version, for a three-way merge
+        #[arg(long)]
+        base: Option<PathBuf>,
+
+        /// Edited version, kept where it conflicts in a two-way merge
+        #[arg(long)]
+        ours: PathBuf,
+
+        /// Version to merge in, such as regenerated code
+        #[arg(long)]
+        theirs: PathBuf,
+
+        /// File to write the result to [default: stdout]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Two-way merge strategy: template (theirs), manual (ours), or fail
+        #[arg(short, long, default_value = "manual", add = ArgValueCandidates::new(strategy_candidates))]
+        strategy: String,
+
+        /// Apply item reorders from theirs instead of keeping our order (two-way)
+        #[arg(long, default_value = "false")]
+        respect_order: bool,
+
+        /// Formatter for merged output (two-way): prettyplease or rustfmt
+        #[arg(long, default_value = "prettyplease", value_parser = ["prettyplease", "rustfmt"], add = ArgValueCandidates::new(formatter_candidates))]
+        formatter: String,
+    },
+
+    /// Git merge driver: three-way AST merge of `%O %A %B`, result written to `%A`
+    MergeDriver {
+        /// Common ancestor version (%O)
+        base: PathBuf,
+
+        /// Our version, overwritten with the merge result (%A)
+        ours: PathBuf,
+
+        /// Their version (%B)
+        theirs: PathBuf,
+    },
+
+    /// Manage the git pre-commit hook that blocks stale generated code
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HookAction {
+    /// Install the pre-commit hook into the current git repository
+    Install {
+        /// Replace an existing pre-commit hook
+        #[arg(long, default_value = "false")]
+        force: bool,
+
+        /// Print configuration for the pre-commit framework instead of installing
+        #[arg(long, default_value = "false")]
+        pre_commit_framework: bool,
+    },
+
+    /// Check staged manifest targets (run by the installed hook)
+    Run,
 }
 
 impl Commands {
+    /// Get the `--template` argument of commands that take one
+    pub fn template_mut(&mut self) -> Option<&mut PathBuf> {
+        match self {
+            Commands::Generate { template, .. }
+            | Commands::Render { template, .. }
+            | Commands::Diff { template, .. }
+            | Commands::Explain { template, .. }
+            | Commands::Lint { template, .. }
+            | Commands::ListTemplates { template }
+            | Commands::Vars { template }
+            | Commands::ContextTypes { template, .. } => Some(template),
+            Commands::Check { template, .. } => template.as_mut(),
+            #[cfg(feature = "tui")]
+            Commands::Review { template, .. } => Some(template),
+            _ => None,
+        }
+    }
+
+    /// Whether the command renders the templates of the manifest targets
+    pub fn uses_targets(&self) -> bool {
+        matches!(
+            self,
+            Commands::GenerateAll { .. }
+                | Commands::Status
+                | Commands::Report { .. }
+                | Commands::Check { all: true, .. }
+                | Commands::Verify
+                | Commands::Hook { .. }
+        )
+    }
+
+    /// Number of `-` arguments the command reads from stdin
+    pub fn stdin_inputs(&self) -> usize {
+        let inputs: Vec<&PathBuf> = match self {
+            Commands::Generate {
+                template, existing, ..
+            } => std::iter::once(template).chain(existing).collect(),
+            Commands::Diff {
+                template, existing, ..
+            }
+            | Commands::Explain {
+                template, existing, ..
+            } => vec![template, existing],
+            Commands::Check {
+                template, existing, ..
+            } => template.iter().chain(existing).collect(),
+            _ => Vec::new(),
+        };
+
+        inputs
+            .into_iter()
+            .filter(|path| crate::input::is_stdio(path))
+            .count()
+    }
+
     /// Get the merge strategy from string
     pub fn parse_strategy(strategy: &str) -> crate::merger::MergeStrategy {
         match strategy.to_lowercase().as_str() {
             "template" => crate::merger::MergeStrategy::PreferTemplate,
             "manual" => crate::merger::MergeStrategy::PreferManual,
             "fail" => crate::merger::MergeStrategy::FailOnConflict,
+            "smart" => crate::merger::MergeStrategy::Smart,
             _ => crate::merger::MergeStrategy::PreferManual,
         }
     }
+
+    /// Get the output language from string
+    pub fn parse_language(language: &str) -> crate::generator::Language {
+        match language.to_lowercase().as_str() {
+            "text" => crate::generator::Language::Text,
+            "markdown" => crate::generator::Language::Markdown,
+            "toml" => crate::generator::Language::Toml,
+            _ => crate::generator::Language::Rust,
+        }
+    }
+
+    /// Get the handling of unparsable existing files from string
+    pub fn parse_on_parse_error(action: &str) -> crate::generator::OnParseError {
+        match action.to_lowercase().as_str() {
+            "skip" => crate::generator::OnParseError::Skip,
+            "text-merge" => crate::generator::OnParseError::TextMerge,
+            "overwrite-with-backup" => crate::generator::OnParseError::OverwriteWithBackup,
+            _ => crate::generator::OnParseError::Fail,
+        }
+    }
+
+    /// Get the formatter from string
+    pub fn parse_formatter(formatter: &str) -> crate::merger::Formatter {
+        match formatter.to_lowercase().as_str() {
+            "rustfmt" => crate::merger::Formatter::Rustfmt,
+            _ => crate::merger::Formatter::Prettyplease,
+        }
+    }
 }
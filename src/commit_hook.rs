@@ -0,0 +1,96 @@
+//! Git pre-commit hook module
+//!
+//! This module installs a pre-commit hook that blocks commits when staged
+//! manifest targets are out of date with their templates.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{Config, Target};
+
+/// Script written to `.git/hooks/pre-commit`
+const HOOK_SCRIPT: &str = "#!/bin/sh\n\
+# Installed by rust-patchs-templates: block commits with stale generated code\n\
+exec rust-patchs-templates hook run\n";
+
+/// Snippet for `.pre-commit-config.yaml` when using the pre-commit framework
+pub const PRE_COMMIT_CONFIG: &str = "repos:
+  - repo: local
+    hooks:
+      - id: rust-patchs-templates
+        name: Check generated code is up to date
+        entry: rust-patchs-templates hook run
+        language: system
+        pass_filenames: false
+";
+
+/// Write the pre-commit hook into the current repository's hooks directory
+///
+/// An existing hook is only replaced when `force` is set.
+pub fn install(force: bool) -> Result<PathBuf> {
+    let hooks_dir = PathBuf::from(git(&["rev-parse", "--git-path", "hooks"])?);
+    let hook_path = hooks_dir.join("pre-commit");
+
+    if hook_path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists, use --force to replace it",
+            hook_path.display()
+        );
+    }
+
+    fs::create_dir_all(&hooks_dir)?;
+    fs::write(&hook_path, HOOK_SCRIPT)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(hook_path)
+}
+
+/// List manifest targets whose template, context or output is staged
+pub fn staged_targets(config: &Config) -> Result<Vec<&Target>> {
+    let top_level = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?);
+    let staged: Vec<PathBuf> = git(&["diff", "--cached", "--name-only", "--diff-filter=ACMR"])?
+        .lines()
+        .filter_map(|line| top_level.join(line).canonicalize().ok())
+        .collect();
+
+    let is_staged = |path: &Path| {
+        config
+            .resolve_path(path)
+            .canonicalize()
+            .map(|path| staged.contains(&path))
+            .unwrap_or(false)
+    };
+
+    Ok(config
+        .targets
+        .iter()
+        .filter(|target| {
+            is_staged(&target.template) || is_staged(&target.context) || is_staged(&target.output)
+        })
+        .collect())
+}
+
+/// Run a git command and return its trimmed stdout
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed:\n{}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
@@ -0,0 +1,134 @@
+//! Shell completion module
+//!
+//! This module provides the dynamic completion candidates and writes the
+//! shell registration scripts. Completion requests are answered by the binary
+//! itself through `COMPLETE=<shell>`.
+
+use anyhow::{Context, Result};
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+use std::io::Write;
+use std::path::PathBuf;
+use walkdir::WalkDir;
+
+use crate::config::Config;
+
+/// Environment variable that switches the binary into completion mode
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Write the completion registration script for `shell` to `out`
+pub fn write_registration(shell: &str, bin: &str, out: &mut dyn Write) -> Result<()> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell)
+        .with_context(|| format!("Unsupported shell '{}'", shell))?;
+
+    // Call back into this exact binary, so completions work from target/ too
+    let exe = std::env::current_exe()
+        .ok()
+        .map(|exe| exe.to_string_lossy().into_owned())
+        .unwrap_or_else(|| bin.to_string());
+
+    completer.write_registration(COMPLETE_VAR, bin, bin, &exe, out)?;
+    Ok(())
+}
+
+/// Merge strategy values
+pub fn strategy_candidates() -> Vec<CompletionCandidate> {
+    [
+        ("manual", "Keep manual edits when they conflict"),
+        ("template", "Overwrite manual edits with the template"),
+        ("fail", "Fail on any conflict"),
+        ("smart", "Combine template signatures with manual bodies"),
+    ]
+    .into_iter()
+    .map(|(value, help)| CompletionCandidate::new(value).help(Some(help.into())))
+    .collect()
+}
+
+/// Formatter values
+pub fn formatter_candidates() -> Vec<CompletionCandidate> {
+    ["prettyplease", "rustfmt"]
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Template engine values
+pub fn engine_candidates() -> Vec<CompletionCandidate> {
+    [
+        "tera",
+        #[cfg(feature = "handlebars")]
+        "handlebars",
+    ]
+    .into_iter()
+    .map(CompletionCandidate::new)
+    .collect()
+}
+
+/// Templates found in the configured template directories
+pub fn template_candidates() -> Vec<CompletionCandidate> {
+    let Ok(current_dir) = std::env::current_dir() else {
+        return Vec::new();
+    };
+    let config = Config::discover(&current_dir).unwrap_or_default();
+
+    template_files(&config)
+        .into_iter()
+        .map(|path| {
+            let display = path.strip_prefix(&current_dir).unwrap_or(&path);
+            CompletionCandidate::new(display.as_os_str())
+        })
+        .collect()
+}
+
+/// Every `.tera` file under the config's template directories, sorted
+pub fn template_files(config: &Config) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = config
+        .template_search_dirs()
+        .iter()
+        .flat_map(|dir| WalkDir::new(dir).into_iter().filter_map(|entry| entry.ok()))
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tera"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_template_files_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("tpl/nested")).unwrap();
+        fs::write(dir.path().join("tpl/a.tera"), "").unwrap();
+        fs::write(dir.path().join("tpl/nested/b.tera"), "").unwrap();
+        fs::write(dir.path().join("tpl/readme.md"), "").unwrap();
+
+        let config = Config {
+            template_dirs: vec![PathBuf::from("tpl")],
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+
+        let files = template_files(&config);
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("tpl/a.tera"),
+                dir.path().join("tpl/nested/b.tera"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_registration() {
+        let mut script = Vec::new();
+        write_registration("bash", "rust-patchs-templates", &mut script).unwrap();
+        assert!(String::from_utf8(script).unwrap().contains("COMPLETE"));
+        assert!(write_registration("tcsh", "rust-patchs-templates", &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,373 @@
+//! Project configuration module
+//!
+//! This module loads the optional `rpt.toml` file that configures the tool for
+//! a project.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::merger::MergeStrategy;
+
+/// Name of the configuration file looked up in the working directory and its ancestors
+pub const CONFIG_FILE_NAME: &str = "rpt.toml";
+
+/// Template directory used when the config doesn't list any
+pub const DEFAULT_TEMPLATE_DIR: &str = "templates";
+
+/// Directory of template overrides used when the config doesn't set one
+pub const DEFAULT_OVERRIDES_DIR: &str = "templates-overrides";
+
+/// Project configuration loaded from `rpt.toml`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Commands run around generation
+    pub hooks: Hooks,
+    /// Generation targets (the project manifest)
+    pub targets: Vec<Target>,
+    /// Directories holding the project's templates, relative to the config file
+    pub template_dirs: Vec<PathBuf>,
+    /// Templates replacing shared ones of the same name, see [`crate::overrides`]
+    pub overrides_dir: Option<PathBuf>,
+    /// Scripts providing custom Tera filters and functions
+    pub plugins: Plugins,
+    /// Per-item conflict resolution, see [`crate::policy`]
+    pub merge_policy: MergePolicyConfig,
+    /// Item names whose existing version always wins, see [`crate::strategy`]
+    pub preserve: Vec<String>,
+    /// Item names whose template version always wins
+    pub always_replace: Vec<String>,
+    /// Conflict strategy by item kind
+    pub strategies: BTreeMap<String, String>,
+    /// Where new items are inserted, by item kind, see [`crate::placement`]
+    pub placement: BTreeMap<String, String>,
+    /// Order of the items in merged files, see [`crate::ordering`]
+    pub ordering: OrderingConfig,
+    /// How items are matched, see [`crate::matching`]
+    pub matching: MatchingConfig,
+    /// Append a record of every generation to `.rpt/audit.jsonl`, see [`crate::audit`]
+    pub audit: bool,
+    /// Render deterministically, as `--deterministic`, see [`crate::deterministic`]
+    pub deterministic: bool,
+    /// Write a provenance header on top of generated Rust files, see [`crate::provenance`]
+    pub provenance: bool,
+    /// Rust edition of the generated code, see [`crate::edition`] [default: the output crate's]
+    pub edition: Option<String>,
+    /// Directory containing the config file, used to resolve relative paths
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+/// Shell commands run before and after generation
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Run before generation; a failing command aborts it
+    pub pre: Vec<String>,
+    /// Run after the output has been written successfully
+    pub post: Vec<String>,
+}
+
+/// Rhai scripts whose public functions are registered into Tera
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Plugins {
+    /// Scripts of filters, `fn name(value)` or `fn name(value, args)`
+    pub filters: Vec<PathBuf>,
+    /// Scripts of functions, `fn name()` or `fn name(args)`
+    pub functions: Vec<PathBuf>,
+}
+
+impl Plugins {
+    /// Whether no plugin script is declared
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty() && self.functions.is_empty()
+    }
+}
+
+/// Command or script deciding merge conflicts
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MergePolicyConfig {
+    /// Shell command reading the conflict as JSON on stdin
+    pub command: Option<String>,
+    /// Rhai script defining `fn resolve(conflict)`, relative to the config file
+    pub script: Option<PathBuf>,
+}
+
+/// Item order of merged files
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OrderingConfig {
+    /// Item kinds in output order, empty to keep the existing order
+    pub groups: Vec<String>,
+    /// Sort items by name within each group
+    pub alphabetical: bool,
+}
+
+/// How items of the existing file are matched with template items
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchingConfig {
+    /// Generics compared: name, params or strict [default: name]
+    pub generics: Option<String>,
+    /// Similarity from 0 to 1 above which dropped and added items are paired as renames
+    pub threshold: Option<f64>,
+    /// Compare items without their doc comments
+    pub ignore_docs: bool,
+    /// Compare items without these attributes, by path
+    pub ignore_attrs: Vec<String>,
+}
+
+/// One generated output declared in the manifest
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    /// Name other templates read this target's outputs by, as `targets.<name>`;
+    /// targets sharing a name are read together
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Names of the targets to generate before this one
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Template file, relative to the config file
+    pub template: PathBuf,
+    /// Context data file (JSON), relative to the config file
+    pub context: PathBuf,
+    /// Output file, relative to the config file
+    pub output: PathBuf,
+    /// Merge strategy: template, manual, fail, or smart
+    #[serde(default = "default_strategy")]
+    pub strategy: String,
+    /// Output language: rust, markdown, toml, or text to merge by keep regions
+    #[serde(default = "default_language")]
+    pub language: String,
+}
+
+fn default_strategy() -> String {
+    "manual".to_string()
+}
+
+fn default_language() -> String {
+    "rust".to_string()
+}
+
+impl Config {
+    /// Resolve a path from the config file against its directory
+    pub fn resolve_path(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// The template directories, resolved, or [`DEFAULT_TEMPLATE_DIR`] when none is listed
+    pub fn template_search_dirs(&self) -> Vec<PathBuf> {
+        if self.template_dirs.is_empty() {
+            return vec![self.resolve_path(Path::new(DEFAULT_TEMPLATE_DIR))];
+        }
+        self.template_dirs
+            .iter()
+            .map(|dir| self.resolve_path(dir))
+            .collect()
+    }
+
+    /// The overrides directory, resolved, see [`DEFAULT_OVERRIDES_DIR`]
+    pub fn overrides_dir(&self) -> PathBuf {
+        self.resolve_path(
+            self.overrides_dir
+                .as_deref()
+                .unwrap_or(Path::new(DEFAULT_OVERRIDES_DIR)),
+        )
+    }
+
+    /// Check the values deserializing doesn't, such as target strategies
+    fn validate(&self) -> Result<()> {
+        for target in &self.targets {
+            MergeStrategy::parse(&target.strategy)
+                .with_context(|| format!("Target {}", target.output.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Load the configuration from an explicit file
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+        let mut config: Config = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        config.root = directory_of(path);
+        config
+            .validate()
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+        Ok(config)
+    }
+
+    /// Load an explicit config file, or discover one from the current directory
+    pub fn resolve(explicit: Option<&Path>) -> Result<Self> {
+        match explicit {
+            Some(path) => Self::load(path),
+            None => Self::discover(&std::env::current_dir()?),
+        }
+    }
+
+    /// Read configuration from `[package.metadata.rpt]` (or `[workspace.metadata.rpt]`)
+    ///
+    /// Returns `None` when the manifest has no such table.
+    pub fn from_cargo_manifest(manifest: &Path) -> Result<Option<Self>> {
+        let content = fs::read_to_string(manifest)
+            .with_context(|| format!("Failed to read {}", manifest.display()))?;
+        let value: toml::Value = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", manifest.display()))?;
+
+        let table = ["package", "workspace"].iter().find_map(|section| {
+            value
+                .get(section)
+                .and_then(|s| s.get("metadata"))
+                .and_then(|m| m.get("rpt"))
+        });
+
+        let Some(table) = table else {
+            return Ok(None);
+        };
+
+        let mut config: Config = table
+            .clone()
+            .try_into()
+            .context("Invalid [package.metadata.rpt] configuration")?;
+        config.root = directory_of(manifest);
+        config
+            .validate()
+            .context("Invalid [package.metadata.rpt] configuration")?;
+
+        Ok(Some(config))
+    }
+
+    /// Find `rpt.toml` in `start` or its ancestors, falling back to defaults
+    pub fn discover(start: &Path) -> Result<Self> {
+        let found = start
+            .ancestors()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|candidate| candidate.is_file());
+
+        match found {
+            Some(path) => Self::load(&path),
+            None => Ok(Config {
+                root: start.to_path_buf(),
+                ..Config::default()
+            }),
+        }
+    }
+}
+
+/// Directory holding a config file, `.` for a bare file name
+fn directory_of(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_from_subdirectory() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(CONFIG_FILE_NAME),
+            "[hooks]\npre = [\"true\"]\npost = [\"cargo fmt\", \"cargo check\"]\n",
+        )
+        .unwrap();
+        let nested = dir.path().join("src/models");
+        fs::create_dir_all(&nested).unwrap();
+
+        let config = Config::discover(&nested).unwrap();
+
+        assert_eq!(config.hooks.pre, vec!["true"]);
+        assert_eq!(config.hooks.post, vec!["cargo fmt", "cargo check"]);
+        assert_eq!(config.root, dir.path());
+    }
+
+    #[test]
+    fn test_targets_resolve_against_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(
+            &path,
+            "[[targets]]\ntemplate = \"t/user.tera\"\ncontext = \"c/user.json\"\noutput = \"src/user.rs\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+
+        assert_eq!(config.targets.len(), 1);
+        assert_eq!(config.targets[0].strategy, "manual");
+        assert_eq!(
+            config.resolve_path(&config.targets[0].output),
+            dir.path().join("src/user.rs")
+        );
+    }
+
+    #[test]
+    fn test_unknown_target_strategy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(
+            &path,
+            "[[targets]]\ntemplate = \"t.tera\"\ncontext = \"c.json\"\noutput = \"user.rs\"\nstrategy = \"tempalte\"\n",
+        )
+        .unwrap();
+
+        let error = format!("{:#}", Config::load(&path).unwrap_err());
+        assert!(error.contains("Target user.rs: Unknown strategy 'tempalte'"));
+    }
+
+    #[test]
+    fn test_from_cargo_manifest_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("Cargo.toml");
+        fs::write(
+            &manifest,
+            "[package]\nname = \"demo\"\n\n[package.metadata.rpt.hooks]\npost = [\"cargo fmt\"]\n",
+        )
+        .unwrap();
+
+        let config = Config::from_cargo_manifest(&manifest).unwrap().unwrap();
+
+        assert_eq!(config.hooks.post, vec!["cargo fmt"]);
+        assert_eq!(config.root, dir.path());
+    }
+
+    #[test]
+    fn test_from_cargo_manifest_without_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("Cargo.toml");
+        fs::write(&manifest, "[package]\nname = \"demo\"\n").unwrap();
+
+        assert!(Config::from_cargo_manifest(&manifest).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_load_bare_file_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("rpt.toml");
+        fs::write(&config_file, "[hooks]\npre = [\"true\"]\n").unwrap();
+        let manifest = dir.path().join("Cargo.toml");
+        fs::write(&manifest, "[package.metadata.rpt]\n").unwrap();
+
+        let config = Config::load(&config_file).unwrap();
+        assert_eq!(config.root, dir.path());
+        let from_manifest = Config::from_cargo_manifest(&manifest).unwrap().unwrap();
+        assert_eq!(from_manifest.root, dir.path());
+
+        // Files in the current directory, named without any directory
+        let root = directory_of(Path::new("rpt.toml"));
+        assert_eq!(root, PathBuf::from("."));
+        crate::hooks::run_hooks("pre", &config.hooks.pre, &root, None).unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+//! Context types module
+//!
+//! This module writes Rust structs matching the context a template reads,
+//! from its static analysis (see [`crate::analysis`]), so library users can
+//! build contexts with serde instead of assembling a `HashMap<String, Value>`
+//! by hand. The shape of each variable is inferred from how it is used:
+//!
+//! - `{{ name }}` is a `String`,
+//! - `{{ entity.name }}` makes `entity` a struct with a `name` field,
+//! - `{% for field in fields %}` makes `fields` a `Vec` of structs holding
+//!   the attributes read from `field`, and `{% for key, value in extra %}` a
+//!   `BTreeMap<String, _>`,
+//! - a variable passed to an array filter, such as `join`, is a `Vec<String>`,
+//! - a variable only tested by `{% if public %}` is a `bool`,
+//! - variables read behind `default(...)`, `is defined` or with a front-matter
+//!   default are `Option`s.
+//!
+//! Values are printed as text, so scalars are strings unless they are flags;
+//! numbers need their type changed by hand.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::analysis::TemplateUsage;
+
+/// Keywords that can't be raw identifiers
+const RESERVED: &[&str] = &["crate", "self", "Self", "super"];
+
+/// Inferred shape of a context value
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    Text,
+    Flag,
+    List(Box<Shape>),
+    Map(Box<Shape>),
+    Record(BTreeMap<String, Shape>),
+}
+
+/// Rust source of the structs matching the context of `usage`, the root one named `name`
+pub fn context_types(usage: &TemplateUsage, name: &str) -> String {
+    let mut root = Shape::Record(BTreeMap::new());
+    for variable in usage.variables.iter().chain(&usage.optional) {
+        entry(&mut root, &[variable.as_str()]);
+    }
+    for path in &usage.paths {
+        entry(&mut root, &path.split('.').collect::<Vec<_>>());
+    }
+    for path in &usage.lists {
+        let shape = entry(&mut root, &path.split('.').collect::<Vec<_>>());
+        if *shape == Shape::Text {
+            *shape = Shape::List(Box::new(Shape::Text));
+        }
+    }
+    for path in &usage.conditions {
+        let shape = entry(&mut root, &path.split('.').collect::<Vec<_>>());
+        if *shape == Shape::Text {
+            *shape = Shape::Flag;
+        }
+    }
+
+    // Path of the elements each loop variable stands for
+    let mut elements: HashMap<&str, Vec<&str>> = HashMap::new();
+    for forloop in &usage.loops {
+        let mut segments = forloop.container.split('.');
+        let Some(first) = segments.next().filter(|first| *first != "<expression>") else {
+            continue;
+        };
+        let mut path = elements.get(first).cloned().unwrap_or_else(|| vec![first]);
+        path.extend(segments);
+
+        let (keyed, value) = match forloop.binding.split_once(", ") {
+            Some((_, value)) => (true, value),
+            None => (false, forloop.binding.as_str()),
+        };
+        let container = entry(&mut root, &path);
+        match (keyed, &*container) {
+            (true, Shape::Map(_)) | (false, Shape::List(_)) => {}
+            (true, _) => *container = Shape::Map(Box::new(Shape::Text)),
+            (false, _) => *container = Shape::List(Box::new(Shape::Text)),
+        }
+
+        path.push("[]");
+        for attribute in &forloop.attributes {
+            let mut attribute_path = path.clone();
+            attribute_path.push(attribute);
+            entry(&mut root, &attribute_path);
+        }
+        elements.insert(value, path);
+    }
+
+    let Shape::Record(fields) = root else {
+        unreachable!("the root is a record")
+    };
+    let mut printer = Printer::default();
+    printer.record(name, &fields, |field| usage.optional.contains(field));
+    let uses_map = printer
+        .structs
+        .iter()
+        .any(|code| code.contains("BTreeMap<"));
+
+    let mut code = String::from("use serde::{Deserialize, Serialize};\n");
+    if uses_map {
+        code.push_str("use std::collections::BTreeMap;\n");
+    }
+    for definition in &printer.structs {
+        code.push('\n');
+        code.push_str(definition);
+    }
+    code
+}
+
+/// Shape at `path` below `shape`, created as text where missing
+///
+/// `[]` in `path` stands for the elements of a list or map.
+fn entry<'a>(shape: &'a mut Shape, path: &[&str]) -> &'a mut Shape {
+    let Some((first, rest)) = path.split_first() else {
+        return shape;
+    };
+    if *first == "[]" {
+        if !matches!(shape, Shape::List(_) | Shape::Map(_)) {
+            *shape = Shape::List(Box::new(Shape::Text));
+        }
+        return match shape {
+            Shape::List(element) | Shape::Map(element) => entry(element, rest),
+            _ => unreachable!("the shape was made a list"),
+        };
+    }
+
+    if !matches!(shape, Shape::Record(_)) {
+        *shape = Shape::Record(BTreeMap::new());
+    }
+    match shape {
+        Shape::Record(fields) => {
+            entry(fields.entry(first.to_string()).or_insert(Shape::Text), rest)
+        }
+        _ => unreachable!("the shape was made a record"),
+    }
+}
+
+/// Struct definitions, root first
+#[derive(Default)]
+struct Printer {
+    structs: Vec<String>,
+    names: Vec<String>,
+}
+
+impl Printer {
+    /// Print a struct, and the structs of its fields after it
+    fn record(
+        &mut self,
+        name: &str,
+        fields: &BTreeMap<String, Shape>,
+        optional: impl Fn(&str) -> bool,
+    ) {
+        self.names.push(name.to_string());
+        let index = self.structs.len();
+        self.structs.push(String::new());
+
+        let mut definition = format!(
+            "#[derive(Debug, Clone, Default, Serialize, Deserialize)]\npub struct {} {{\n",
+            name
+        );
+        for (field, shape) in fields {
+            let mut ty = self.type_of(name, field, shape);
+            let (ident, rename) = field_ident(field);
+            if let Some(rename) = rename {
+                definition.push_str(&format!("    #[serde(rename = \"{}\")]\n", rename));
+            }
+            if optional(field) && *shape == Shape::Flag {
+                // A missing flag tests as false
+                definition.push_str("    #[serde(default)]\n");
+            } else if optional(field) {
+                definition
+                    .push_str("    #[serde(default, skip_serializing_if = \"Option::is_none\")]\n");
+                ty = format!("Option<{}>", ty);
+            }
+            definition.push_str(&format!("    pub {}: {},\n", ident, ty));
+        }
+        definition.push_str("}\n");
+        self.structs[index] = definition;
+    }
+
+    /// Rust type of the field `field` of the struct `parent`
+    fn type_of(&mut self, parent: &str, field: &str, shape: &Shape) -> String {
+        match shape {
+            Shape::Text => "String".to_string(),
+            Shape::Flag => "bool".to_string(),
+            Shape::List(element) => {
+                format!("Vec<{}>", self.type_of(parent, &singular(field), element))
+            }
+            Shape::Map(element) => format!(
+                "BTreeMap<String, {}>",
+                self.type_of(parent, &singular(field), element)
+            ),
+            Shape::Record(fields) => {
+                let mut name = pascal_case(field);
+                if self.names.contains(&name) {
+                    name = format!("{}{}", parent, name);
+                }
+                self.record(&name, fields, |_| false);
+                name
+            }
+        }
+    }
+}
+
+/// Rust identifier of a context key, and the key to rename it from when they differ
+fn field_ident(key: &str) -> (String, Option<&str>) {
+    let snake = snake_case(key);
+    if RESERVED.contains(&snake.as_str()) {
+        return (format!("{}_", snake), Some(key));
+    }
+    let ident = if syn::parse_str::<syn::Ident>(&snake).is_err() {
+        format!("r#{}", snake)
+    } else {
+        snake.clone()
+    };
+    (ident, (snake != key).then_some(key))
+}
+
+/// `userId` or `UserId` as `user_id`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if index > 0 && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// `user_id` as `UserId`
+pub fn pascal_case(name: &str) -> String {
+    name.split(['_', '-', '.', ' '])
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Name of one element of a list named `name`, such as `Field` for `fields`
+fn singular(name: &str) -> String {
+    if let Some(stem) = name.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if let Some(stem) = name.strip_suffix('s').filter(|stem| !stem.ends_with('s')) {
+        stem.to_string()
+    } else {
+        format!("{}_item", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::TemplateEngine;
+
+    #[test]
+    fn test_context_types() {
+        let template = "{% if public %}pub {% endif %}struct {{ entity.name }} {\n\
+            {% for field in fields %}    {{ field.name }}: {{ field.type }},\n\
+            {% for attr in field.attributes %}{{ attr }}{% endfor %}{% endfor %}}\n\
+            {% for key, value in extra %}{{ key }} = {{ value }}{% endfor %}\
+            {{ derive | default(value=\"Debug\") }}{{ userId }}{{ tags | join(sep=\",\") }}";
+        let engine = TemplateEngine::from_string("model.rs.tera", template).unwrap();
+        let usage = engine.analyze("model.rs.tera").unwrap();
+
+        assert_eq!(
+            context_types(&usage, "ModelContext"),
+            "use serde::{Deserialize, Serialize};\n\
+             use std::collections::BTreeMap;\n\
+             \n\
+             #[derive(Debug, Clone, Default, Serialize, Deserialize)]\n\
+             pub struct ModelContext {\n    \
+                 #[serde(default, skip_serializing_if = \"Option::is_none\")]\n    \
+                 pub derive: Option<String>,\n    \
+                 pub entity: Entity,\n    \
+                 pub extra: BTreeMap<String, String>,\n    \
+                 pub fields: Vec<Field>,\n    \
+                 #[serde(default)]\n    \
+                 pub public: bool,\n    \
+                 pub tags: Vec<String>,\n    \
+                 #[serde(rename = \"userId\")]\n    \
+                 pub user_id: String,\n\
+             }\n\
+             \n\
+             #[derive(Debug, Clone, Default, Serialize, Deserialize)]\n\
+             pub struct Entity {\n    \
+                 pub name: String,\n\
+             }\n\
+             \n\
+             #[derive(Debug, Clone, Default, Serialize, Deserialize)]\n\
+             pub struct Field {\n    \
+                 pub attributes: Vec<String>,\n    \
+                 pub name: String,\n    \
+                 pub r#type: String,\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Deterministic output module
+//!
+//! This module backs `--deterministic` (or `deterministic = true` in
+//! `rpt.toml`), for templates whose output must not change between runs with
+//! unchanged inputs, so regeneration doesn't dirty git. Once [`enable`] was
+//! called:
+//!
+//! - Tera's `now()` returns a fixed time, `$SOURCE_DATE_EPOCH` when set and
+//!   the Unix epoch otherwise,
+//! - `get_random()` fails, since no fixed value would stay random,
+//! - every template is rendered twice and the renders must be byte-identical,
+//!   which catches plugins and other sources of varying output.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tera::Tera;
+
+/// Environment variable fixing the time `now()` returns, in seconds since the Unix epoch
+pub const EPOCH_VAR: &str = "SOURCE_DATE_EPOCH";
+
+/// Whether output must be deterministic
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Make output deterministic, for the rest of the process
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether [`enable`] was called
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Replace the Tera functions whose result varies between runs
+pub fn register(tera: &mut Tera) {
+    tera.register_function("now", fixed_now);
+    tera.register_function("get_random", |_: &HashMap<String, Value>| {
+        Err(tera::Error::msg(
+            "get_random() is disabled by --deterministic",
+        ))
+    });
+}
+
+/// Check that two renders of `template_name` are identical
+pub fn verify(template_name: &str, first: &str, second: &str) -> Result<()> {
+    if first == second {
+        return Ok(());
+    }
+
+    // Texts only differing by a final newline run out of lines together
+    let (mut first_lines, mut second_lines) = (first.lines(), second.lines());
+    let (line, a, b) = (1..)
+        .map(|line| (line, first_lines.next(), second_lines.next()))
+        .find(|(_, a, b)| a != b || a.is_none())
+        .expect("lines run out");
+    anyhow::bail!(
+        "Output of '{}' is not deterministic, two renders differ at line {}:\n- {}\n+ {}",
+        template_name,
+        line,
+        a.unwrap_or_default(),
+        b.unwrap_or_default()
+    )
+}
+
+/// `now(timestamp=false, utc=false)` at the fixed time
+fn fixed_now(args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let seconds = match std::env::var(EPOCH_VAR) {
+        Ok(epoch) => epoch
+            .trim()
+            .parse::<i64>()
+            .map_err(|_| tera::Error::msg(format!("{} must be a number of seconds", EPOCH_VAR)))?,
+        Err(_) => 0,
+    };
+    if args
+        .get("timestamp")
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+    {
+        return Ok(seconds.into());
+    }
+
+    let time = chrono::DateTime::from_timestamp(seconds, 0)
+        .ok_or_else(|| tera::Error::msg(format!("{} is out of range", EPOCH_VAR)))?;
+    Ok(time.to_rfc3339().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic_functions() {
+        let mut tera = Tera::default();
+        register(&mut tera);
+        let context = tera::Context::new();
+
+        assert_eq!(
+            tera.render_str("{{ now() }} {{ now(timestamp=true) }}", &context)
+                .unwrap(),
+            "1970-01-01T00:00:00+00:00 0"
+        );
+        assert!(tera
+            .render_str("{{ get_random(end=9) }}", &context)
+            .is_err());
+
+        assert!(verify("t", "a\nb\n", "a\nb\n").is_ok());
+        assert_eq!(
+            verify("t", "a\nb\n", "a\nc\n").unwrap_err().to_string(),
+            "Output of 't' is not deterministic, two renders differ at line 2:\n- b\n+ c"
+        );
+        assert!(verify("t", "a\n", "a\nb\n").is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Diagnostics module
+//!
+//! This module turns a `syn` parse error into a diagnostic showing the
+//! offending source lines, with the error span underlined:
+//!
+//! ```text
+//! error: expected `;`
+//!  --> generated code:3:5
+//!   |
+//! 2 |     let x = 1
+//! 3 |     x
+//!   |     ^
+//!   = note: rendered from template line 2: `    let x = {{ value }}`
+//! ```
+//!
+//! For generated code, the failing line is mapped back to the template line
+//! whose literal text, outside `{{ }}`, `{% %}` and `{# #}` tags, best matches
+//! it. The mapping is a heuristic and is left out when no line matches.
+//!
+//! The command line also writes generated code that fails to parse to
+//! [`LAST_RENDER_FILE`], or to `--debug-render <path>`, to inspect it whole.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Origin of diagnostics for rendered template output
+pub const GENERATED_CODE: &str = "generated code";
+
+/// Default file generated code that fails to parse is written to
+pub const LAST_RENDER_FILE: &str = ".rpt/last-render.rs";
+
+/// A parse error located in its source
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseDiagnostic {
+    /// What was parsed, such as `generated code`
+    pub origin: String,
+    pub message: String,
+    /// Line of the error, from 1
+    pub line: usize,
+    /// Columns of the error on its line, from 0
+    pub columns: (usize, usize),
+    /// Source lines shown, with their numbers
+    pub lines: Vec<(usize, String)>,
+    /// Number and text of the template line the failing line was rendered from
+    pub template_line: Option<(usize, String)>,
+    /// Notes printed after the snippet
+    pub notes: Vec<String>,
+    /// Whole source that failed to parse
+    pub source: String,
+}
+
+impl ParseDiagnostic {
+    /// Locate `error` in `source`
+    pub fn new(origin: &str, source: &str, error: &syn::Error) -> Self {
+        let error = error
+            .clone()
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| error.clone());
+        let (start, end) = (error.span().start(), error.span().end());
+        let source_lines: Vec<&str> = source.lines().collect();
+        // An error at the end of input points past the last line
+        let line = start.line.clamp(1, source_lines.len().max(1));
+        let text = source_lines.get(line - 1).copied().unwrap_or_default();
+        let width = text.chars().count();
+        let columns = if start.line != line {
+            (width, width + 1)
+        } else if end.line == start.line && end.column > start.column {
+            (start.column, end.column)
+        } else {
+            (start.column, start.column.max(width).max(start.column + 1))
+        };
+
+        ParseDiagnostic {
+            origin: origin.to_string(),
+            message: error.to_string(),
+            line,
+            columns,
+            lines: (line.saturating_sub(1).max(1)..=line)
+                .filter_map(|number| Some((number, source_lines.get(number - 1)?.to_string())))
+                .collect(),
+            template_line: None,
+            notes: Vec::new(),
+            source: source.to_string(),
+        }
+    }
+
+    /// Map the failing line back to the line of `template` it was most likely rendered from
+    pub fn locate_in_template(&mut self, template: &str) {
+        let Some((_, rendered)) = self.lines.iter().find(|(number, _)| *number == self.line) else {
+            return;
+        };
+        self.template_line = template
+            .lines()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let fragments = literal_fragments(line);
+                let matched: usize = fragments.iter().map(|fragment| fragment.len()).sum();
+                (matched > 0 && contains_in_order(rendered, &fragments)).then_some((matched, index))
+            })
+            .max_by_key(|&(matched, index)| (matched, std::cmp::Reverse(index)))
+            .map(|(_, index)| {
+                (
+                    index + 1,
+                    template.lines().nth(index).unwrap_or_default().to_string(),
+                )
+            });
+    }
+}
+
+impl fmt::Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = self.line.to_string().len();
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(
+            f,
+            "{:gutter$}--> {}:{}:{}",
+            "",
+            self.origin,
+            self.line,
+            self.columns.0 + 1,
+            gutter = gutter
+        )?;
+        writeln!(f, "{:gutter$} |", "", gutter = gutter)?;
+        for (number, text) in &self.lines {
+            writeln!(f, "{:>gutter$} | {}", number, text, gutter = gutter)?;
+        }
+        write!(
+            f,
+            "{:gutter$} | {}{}",
+            "",
+            " ".repeat(self.columns.0),
+            "^".repeat(self.columns.1 - self.columns.0),
+            gutter = gutter
+        )?;
+        if let Some((number, text)) = &self.template_line {
+            write!(
+                f,
+                "\n{:gutter$} = note: rendered from template line {}: `{}`",
+                "",
+                number,
+                text,
+                gutter = gutter
+            )?;
+        }
+        for note in &self.notes {
+            write!(f, "\n{:gutter$} = note: {}", "", note, gutter = gutter)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseDiagnostic {}
+
+/// Name what was parsed in `error`, if it is a parse error
+pub fn with_origin(mut error: anyhow::Error, origin: &str) -> anyhow::Error {
+    if let Some(diagnostic) = error.downcast_mut::<ParseDiagnostic>() {
+        diagnostic.origin = origin.to_string();
+    }
+    error
+}
+
+/// Map a parse error in generated code back to the `template` it was rendered from
+pub fn with_template(mut error: anyhow::Error, template: &str) -> anyhow::Error {
+    if let Some(diagnostic) = error.downcast_mut::<ParseDiagnostic>() {
+        if diagnostic.origin == GENERATED_CODE {
+            diagnostic.locate_in_template(template);
+        }
+    }
+    error
+}
+
+/// Write the generated code of a parse error to `path`, noting it in the error
+///
+/// Failing to write is noted instead, the parse error is what matters.
+pub fn dump_render(mut error: anyhow::Error, path: &Path) -> anyhow::Error {
+    if let Some(diagnostic) = error.downcast_mut::<ParseDiagnostic>() {
+        if diagnostic.origin == GENERATED_CODE {
+            let written = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir),
+                _ => Ok(()),
+            }
+            .and_then(|()| fs::write(path, &diagnostic.source));
+            diagnostic.notes.push(match written {
+                Ok(()) => format!("rendered output written to {}", path.display()),
+                Err(err) => format!(
+                    "failed to write rendered output to {}: {}",
+                    path.display(),
+                    err
+                ),
+            });
+        }
+    }
+    error
+}
+
+/// Literal text of a template line, split around its tags
+fn literal_fragments(line: &str) -> Vec<&str> {
+    let mut fragments = Vec::new();
+    let mut rest = line;
+    while let Some(open) = ["{{", "{%", "{#"]
+        .iter()
+        .filter_map(|tag| rest.find(tag))
+        .min()
+    {
+        fragments.push(&rest[..open]);
+        let close = match &rest[open..open + 2] {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+        rest = match rest[open..].find(close) {
+            Some(end) => &rest[open + end + 2..],
+            None => "",
+        };
+    }
+    fragments.push(rest);
+    fragments
+        .into_iter()
+        .map(str::trim)
+        .filter(|fragment| !fragment.is_empty())
+        .collect()
+}
+
+/// Whether `fragments` appear in `text` in order
+fn contains_in_order(text: &str, fragments: &[&str]) -> bool {
+    let mut rest = text;
+    fragments.iter().all(|fragment| match rest.find(fragment) {
+        Some(at) => {
+            rest = &rest[at + fragment.len()..];
+            true
+        }
+        None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diagnostic() {
+        let template = "pub fn {{ name }}() -> u32 {\n    let x = {{ value }}\n    x\n}\n";
+        let source = "pub fn answer() -> u32 {\n    let x = 42\n    x\n}\n";
+        let error = syn::parse_file(source).unwrap_err();
+
+        let diagnostic = ParseDiagnostic::new("source", source, &error);
+        let error = with_template(with_origin(diagnostic.into(), GENERATED_CODE), template);
+        assert_eq!(
+            error.to_string(),
+            "error: expected `;`\n \
+             --> generated code:3:5\n  \
+             |\n\
+             2 |     let x = 42\n\
+             3 |     x\n  \
+             | \x20   ^\n  \
+             = note: rendered from template line 3: `    x`"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LAST_RENDER_FILE);
+        let error = dump_render(error, &path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), source);
+        assert!(error.to_string().ends_with(&format!(
+            "= note: rendered output written to {}",
+            path.display()
+        )));
+    }
+}
@@ -4,107 +4,206 @@
 //! changes between two versions of code without relying on text markers.
 
 use anyhow::Result;
+use proc_macro2::{Delimiter, Group, TokenStream, TokenTree};
+use quote::ToTokens;
 use similar::{ChangeTag, TextDiff};
+use std::collections::{HashMap, HashSet};
 use syn::Item;
 
+use crate::ast_parser::{impl_name, item_kind};
+use crate::hash::sha256_hex;
+use crate::imports::missing_imports;
+use crate::matching::{Comparison, GenericsMatching};
+
 /// Represents a change operation in the patch
+///
+/// Operations borrow the items they refer to from the compared ASTs.
 #[derive(Debug, Clone, PartialEq)]
-pub enum PatchOp {
+pub enum PatchOp<'a> {
     /// Insert a new item
-    Insert { name: String, item: Item },
+    Insert { name: String, item: &'a Item },
     /// Delete an existing item
     Delete { name: String },
     /// Modify an existing item
     Modify {
         name: String,
-        old_item: Item,
-        new_item: Item,
+        old_item: &'a Item,
+        new_item: &'a Item,
+    },
+    /// Replace an item with a similar one under another name, see [`crate::matching`]
+    Rename {
+        from: String,
+        name: String,
+        old_item: &'a Item,
+        new_item: &'a Item,
     },
     /// Keep an item unchanged
     Keep { name: String },
+    /// Keep an item unchanged but at a different position
+    Move {
+        name: String,
+        from: usize,
+        to: usize,
+    },
 }
 
 /// A patch is a sequence of operations
 #[derive(Debug, Clone)]
-pub struct Patch {
-    pub operations: Vec<PatchOp>,
+pub struct Patch<'a> {
+    pub operations: Vec<PatchOp<'a>>,
+    /// Imports the new side adds, see [`crate::imports`]
+    pub imports: Vec<Item>,
 }
 
-impl Patch {
+impl<'a> Patch<'a> {
     /// Create a new empty patch
     pub fn new() -> Self {
         Patch {
             operations: Vec::new(),
+            imports: Vec::new(),
         }
     }
 
     /// Add an operation to the patch
-    pub fn add_operation(&mut self, op: PatchOp) {
+    pub fn add_operation(&mut self, op: PatchOp<'a>) {
         self.operations.push(op);
     }
 
     /// Check if the patch is empty (no changes)
     pub fn is_empty(&self) -> bool {
-        self.operations
-            .iter()
-            .all(|op| matches!(op, PatchOp::Keep { .. }))
+        self.imports.is_empty()
+            && self
+                .operations
+                .iter()
+                .all(|op| matches!(op, PatchOp::Keep { .. }))
     }
 }
 
-impl Default for Patch {
+impl Default for Patch<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// `extern "C" {a, b}`: the ABI and the sorted names an extern block declares
+fn extern_block_key(block: &syn::ItemForeignMod) -> String {
+    let mut symbols: Vec<String> = block
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::ForeignItem::Fn(f) => Some(f.sig.ident.to_string()),
+            syn::ForeignItem::Static(s) => Some(s.ident.to_string()),
+            syn::ForeignItem::Type(t) => Some(t.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+    symbols.sort();
+
+    let abi = match &block.abi.name {
+        Some(name) => format!("extern {}", name.token()),
+        None => "extern".to_string(),
+    };
+    format!("{} {{{}}}", abi, symbols.join(", "))
+}
+
 /// Compute a structural diff between two ASTs
-pub fn compute_patch(old_items: &[Item], new_items: &[Item]) -> Result<Patch> {
+///
+/// Items are compared as the current merge says, see
+/// [`crate::matching::with_comparison`].
+pub fn compute_patch<'a>(old_items: &'a [Item], new_items: &'a [Item]) -> Result<Patch<'a>> {
+    compute_patch_with(old_items, new_items, &crate::matching::comparison())
+}
+
+/// Same as [`compute_patch`], with explicit comparison settings
+pub fn compute_patch_with<'a>(
+    old_items: &'a [Item],
+    new_items: &'a [Item],
+    comparison: &Comparison,
+) -> Result<Patch<'a>> {
     let mut patch = Patch::new();
+    patch.imports = missing_imports(old_items, new_items);
 
-    // Extract item names for comparison
-    let old_names: Vec<String> = old_items
-        .iter()
-        .filter_map(|item| extract_item_name(item))
-        .collect();
+    // Index old items by name, keeping the first occurrence of duplicates
+    let mut old_index: HashMap<String, usize> = HashMap::with_capacity(old_items.len());
+    for (idx, item) in old_items.iter().enumerate() {
+        if let Some(name) = extract_item_name(item) {
+            old_index.entry(name).or_insert(idx);
+        }
+    }
 
-    let _new_names: Vec<String> = new_items
+    // Pair every named new item with its old counterpart, if any
+    let mut processed_old = vec![false; old_items.len()];
+    let pairs: Vec<(usize, &'a Item, String, Option<usize>)> = new_items
         .iter()
-        .filter_map(|item| extract_item_name(item))
+        .enumerate()
+        .filter_map(|(new_idx, new_item)| {
+            let name = extract_item_name(new_item)?;
+            let old_idx = old_index.get(&name).copied();
+            if let Some(idx) = old_idx {
+                processed_old[idx] = true;
+            }
+            Some((new_idx, new_item, name, old_idx))
+        })
         .collect();
 
-    // Track which old items have been processed
-    let mut processed_old = vec![false; old_items.len()];
+    // Matched items outside the longest in-order run have been reordered
+    let matched_old: Vec<usize> = pairs.iter().filter_map(|(_, _, _, idx)| *idx).collect();
+    let in_order = longest_increasing_run(&matched_old);
+
+    // Pair items added and dropped under different names when similar enough
+    let renames = match comparison.threshold {
+        Some(threshold) => {
+            let added: Vec<usize> = pairs
+                .iter()
+                .filter(|(_, _, _, old_idx)| old_idx.is_none())
+                .map(|(new_idx, _, _, _)| *new_idx)
+                .collect();
+            let dropped: Vec<usize> = (0..old_items.len())
+                .filter(|&idx| !processed_old[idx] && extract_item_name(&old_items[idx]).is_some())
+                .collect();
+            pair_renames(old_items, new_items, &dropped, &added, threshold)
+        }
+        None => HashMap::new(),
+    };
+    for &old_idx in renames.values() {
+        processed_old[old_idx] = true;
+    }
 
     // Process new items
-    for (_new_idx, new_item) in new_items.iter().enumerate() {
-        let new_name = extract_item_name(new_item);
-
-        if let Some(name) = new_name {
-            // Find matching item in old items
-            if let Some(old_idx) = old_names.iter().position(|n| n == &name) {
-                processed_old[old_idx] = true;
-
-                // Compare items to see if they've changed
-                let old_item = &old_items[old_idx];
-                let old_code = quote::quote!(#old_item).to_string();
-                let new_code = quote::quote!(#new_item).to_string();
-
-                if old_code != new_code {
-                    patch.add_operation(PatchOp::Modify {
-                        name: name.clone(),
-                        old_item: old_item.clone(),
-                        new_item: new_item.clone(),
-                    });
-                } else {
-                    patch.add_operation(PatchOp::Keep { name: name.clone() });
-                }
+    for (new_idx, new_item, name, old_idx) in pairs {
+        if let Some(old_idx) = old_idx {
+            // Compare items structurally to see if they've changed
+            let old_item = &old_items[old_idx];
+
+            if !same_item(old_item, new_item, &comparison.ignored_attrs) {
+                patch.add_operation(PatchOp::Modify {
+                    name,
+                    old_item,
+                    new_item,
+                });
+            } else if in_order.contains(&old_idx) {
+                patch.add_operation(PatchOp::Keep { name });
             } else {
-                // New item - insert
-                patch.add_operation(PatchOp::Insert {
-                    name: name.clone(),
-                    item: new_item.clone(),
+                patch.add_operation(PatchOp::Move {
+                    name,
+                    from: old_idx,
+                    to: new_idx,
                 });
             }
+        } else if let Some(&old_idx) = renames.get(&new_idx) {
+            let old_item = &old_items[old_idx];
+            patch.add_operation(PatchOp::Rename {
+                from: extract_item_name(old_item).unwrap_or_default(),
+                name,
+                old_item,
+                new_item,
+            });
+        } else {
+            // New item - insert
+            patch.add_operation(PatchOp::Insert {
+                name,
+                item: new_item,
+            });
         }
     }
 
@@ -120,17 +219,247 @@ pub fn compute_patch(old_items: &[Item], new_items: &[Item]) -> Result<Patch> {
     Ok(patch)
 }
 
+/// Pair dropped old items with added new items of the same kind, most similar first
+///
+/// Returns old indices keyed by new index, for pairs at least `threshold` similar.
+fn pair_renames(
+    old_items: &[Item],
+    new_items: &[Item],
+    dropped: &[usize],
+    added: &[usize],
+    threshold: f64,
+) -> HashMap<usize, usize> {
+    let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+    for &new_idx in added {
+        for &old_idx in dropped {
+            let (old_item, new_item) = (&old_items[old_idx], &new_items[new_idx]);
+            if item_kind(old_item) != item_kind(new_item) {
+                continue;
+            }
+            let score = similarity(old_item, new_item);
+            if score >= threshold {
+                candidates.push((score, new_idx, old_idx));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut renames = HashMap::new();
+    let mut taken = HashSet::new();
+    for (_, new_idx, old_idx) in candidates {
+        if !renames.contains_key(&new_idx) && taken.insert(old_idx) {
+            renames.insert(new_idx, old_idx);
+        }
+    }
+    renames
+}
+
+/// Whether two items are equal once the `ignored` attributes are left out
+///
+/// Formatting doesn't count, trailing commas included.
+pub fn same_item(a: &Item, b: &Item, ignored: &[String]) -> bool {
+    if ignored.is_empty() && a == b {
+        return true;
+    }
+    let a = without_trailing_commas(strip_attrs(a.to_token_stream(), ignored));
+    let b = without_trailing_commas(strip_attrs(b.to_token_stream(), ignored));
+    a.to_string() == b.to_string()
+}
+
+/// Remove the comma ending each delimited group, at any depth
+///
+/// Parentheses keep the comma of their only element, which makes `(x,)` a
+/// one-element tuple rather than `x`.
+fn without_trailing_commas(tokens: TokenStream) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|tree| match tree {
+            TokenTree::Group(group) => {
+                let mut trees: Vec<TokenTree> = without_trailing_commas(group.stream())
+                    .into_iter()
+                    .collect();
+                let single = group.delimiter() == Delimiter::Parenthesis && separators(&trees) < 2;
+                if is_punct(trees.last(), ',') && !single {
+                    trees.pop();
+                }
+                TokenTree::Group(Group::new(group.delimiter(), trees.into_iter().collect()))
+            }
+            tree => tree,
+        })
+        .collect()
+}
+
+/// Commas separating the elements of a group, leaving out those between `<` and `>`
+fn separators(trees: &[TokenTree]) -> usize {
+    let mut depth = 0usize;
+    let mut commas = 0;
+    for (idx, tree) in trees.iter().enumerate() {
+        let arrow =
+            idx > 0 && (is_punct(trees.get(idx - 1), '-') || is_punct(trees.get(idx - 1), '='));
+        match tree {
+            TokenTree::Punct(punct) if punct.as_char() == '<' => depth += 1,
+            TokenTree::Punct(punct) if punct.as_char() == '>' && !arrow => {
+                depth = depth.saturating_sub(1)
+            }
+            TokenTree::Punct(punct) if punct.as_char() == ',' && depth == 0 => commas += 1,
+            _ => {}
+        }
+    }
+    commas
+}
+
+/// Whether `tree` is the punctuation `ch`
+fn is_punct(tree: Option<&TokenTree>, ch: char) -> bool {
+    matches!(tree, Some(TokenTree::Punct(punct)) if punct.as_char() == ch)
+}
+
+/// Remove outer and inner attributes whose path is in `ignored`, at any depth
+fn strip_attrs(tokens: TokenStream, ignored: &[String]) -> TokenStream {
+    let trees: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut stripped = Vec::with_capacity(trees.len());
+    let mut idx = 0;
+
+    while idx < trees.len() {
+        if let TokenTree::Punct(punct) = &trees[idx] {
+            if punct.as_char() == '#' {
+                // `#[attr]` or `#![attr]`
+                let bang =
+                    matches!(&trees.get(idx + 1), Some(TokenTree::Punct(p)) if p.as_char() == '!');
+                let body = idx + 1 + usize::from(bang);
+                if let Some(TokenTree::Group(group)) = trees.get(body) {
+                    if group.delimiter() == Delimiter::Bracket
+                        && ignored.contains(&attr_path(group.stream()))
+                    {
+                        idx = body + 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        stripped.push(match &trees[idx] {
+            TokenTree::Group(group) => TokenTree::Group(Group::new(
+                group.delimiter(),
+                strip_attrs(group.stream(), ignored),
+            )),
+            tree => tree.clone(),
+        });
+        idx += 1;
+    }
+    stripped.into_iter().collect()
+}
+
+/// Path of an attribute from the tokens inside its brackets, e.g. `tracing::instrument`
+fn attr_path(tokens: TokenStream) -> String {
+    tokens
+        .into_iter()
+        .take_while(|tree| match tree {
+            TokenTree::Ident(_) => true,
+            TokenTree::Punct(punct) => punct.as_char() == ':',
+            _ => false,
+        })
+        .map(|tree| tree.to_string())
+        .collect()
+}
+
+/// Share of tokens two items have in common, from 0 to 1
+pub fn similarity(a: &Item, b: &Item) -> f64 {
+    let a = a.to_token_stream().to_string();
+    let b = b.to_token_stream().to_string();
+    let a: Vec<&str> = a.split_whitespace().collect();
+    let b: Vec<&str> = b.split_whitespace().collect();
+    f64::from(TextDiff::from_slices(&a, &b).ratio())
+}
+
+/// Find the longest subsequence of `indices` that is already in increasing order
+///
+/// Items in this subsequence kept their relative order; every other item moved.
+/// Uses patience sorting, so this runs in O(n log n).
+fn longest_increasing_run(indices: &[usize]) -> HashSet<usize> {
+    // tails[k] = position of the smallest tail of an increasing run of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; indices.len()];
+
+    for (i, &value) in indices.iter().enumerate() {
+        let k = tails.partition_point(|&t| indices[t] < value);
+        if k > 0 {
+            previous[i] = Some(tails[k - 1]);
+        }
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut result = HashSet::new();
+    let mut current = tails.last().copied();
+    while let Some(i) = current {
+        result.insert(indices[i]);
+        current = previous[i];
+    }
+
+    result
+}
+
 /// Extract the name/identifier from an AST item
-fn extract_item_name(item: &Item) -> Option<String> {
+///
+/// Items are keyed by the [`crate::matching::ItemMatcher`] of the current
+/// merge, by [`name_key`] unless it sets one.
+pub fn extract_item_name(item: &Item) -> Option<String> {
+    crate::matching::matched_key(item)
+}
+
+/// Name of an item, with generics as the current [`GenericsMatching`] says, see
+/// [`crate::matching::with_generics`]
+///
+/// Items without a name, such as macro invocations, are keyed by a hash of
+/// their tokens, so only identical blocks match; `use` declarations have no
+/// key, see [`crate::imports`].
+pub fn name_key(item: &Item) -> Option<String> {
+    item_key(item, crate::matching::generics()).or_else(|| fallback_key(item))
+}
+
+/// `lazy_static! #1f2e3d4c`: the macro path or item kind, and a hash of the item's tokens
+fn fallback_key(item: &Item) -> Option<String> {
+    let label = match item {
+        Item::Use(_) => return None,
+        Item::Macro(m) => format!("{}!", m.mac.path.to_token_stream()).replace(' ', ""),
+        item => item_kind(item).to_string(),
+    };
+    let hash = sha256_hex(item.to_token_stream().to_string());
+    Some(format!("{} #{}", label, &hash[..8]))
+}
+
+/// Key an item is matched by, with its generics as `mode` says
+pub fn item_key(item: &Item, mode: GenericsMatching) -> Option<String> {
+    let named = |ident: &syn::Ident, generics| {
+        format!(
+            "{}{}{}",
+            ident,
+            mode.params(generics),
+            mode.where_clause(generics)
+        )
+    };
+
     match item {
-        Item::Fn(func) => Some(func.sig.ident.to_string()),
-        Item::Struct(s) => Some(s.ident.to_string()),
-        Item::Enum(e) => Some(e.ident.to_string()),
-        Item::Trait(t) => Some(t.ident.to_string()),
-        Item::Type(t) => Some(t.ident.to_string()),
+        Item::Fn(func) => Some(named(&func.sig.ident, &func.sig.generics)),
+        Item::Struct(s) => Some(named(&s.ident, &s.generics)),
+        Item::Enum(e) => Some(named(&e.ident, &e.generics)),
+        Item::Trait(t) => Some(named(&t.ident, &t.generics)),
+        Item::Type(t) => Some(named(&t.ident, &t.generics)),
         Item::Const(c) => Some(c.ident.to_string()),
         Item::Static(s) => Some(s.ident.to_string()),
-        Item::Mod(m) => Some(m.ident.to_string()),
+        Item::Mod(m) => Some(format!("mod {}", m.ident)),
+        Item::Union(u) => Some(named(&u.ident, &u.generics)),
+        Item::TraitAlias(t) => Some(named(&t.ident, &t.generics)),
+        Item::ForeignMod(f) => Some(extern_block_key(f)),
+        Item::Macro(m) => m.ident.as_ref().map(|ident| format!("{}!", ident)),
+        Item::ExternCrate(c) => Some(format!("extern crate {}", c.ident)),
+        Item::Impl(i) => {
+            let (params, where_clause) = (mode.params(&i.generics), mode.where_clause(&i.generics));
+            impl_name(item).map(|name| format!("impl{} {}{}", params, name, where_clause))
+        }
         _ => None,
     }
 }
@@ -152,11 +481,48 @@ pub fn compute_text_diff(old_text: &str, new_text: &str) -> String {
     result
 }
 
+/// Unified diff of the file at `path` from `old_text` to `new_text`, as `git apply` reads it
+///
+/// Empty when nothing changed.
+pub fn unified_patch(path: &str, old_text: &str, new_text: &str) -> String {
+    if old_text == new_text {
+        return String::new();
+    }
+
+    let diff = TextDiff::from_lines(old_text, new_text);
+    format!(
+        "diff --git a/{0} b/{0}\n{1}",
+        path,
+        diff.unified_diff()
+            .context_radius(3)
+            .header(&format!("a/{}", path), &format!("b/{}", path))
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use syn::parse_quote;
 
+    #[test]
+    fn test_unified_patch() {
+        assert_eq!(unified_patch("src/a.rs", "fn a() {}\n", "fn a() {}\n"), "");
+        assert_eq!(
+            unified_patch(
+                "src/a.rs",
+                "fn a() {}\nfn b() {}\n",
+                "fn a() {}\nfn c() {}\n"
+            ),
+            "diff --git a/src/a.rs b/src/a.rs\n\
+             --- a/src/a.rs\n\
+             +++ b/src/a.rs\n\
+             @@ -1,2 +1,2 @@\n \
+             fn a() {}\n\
+             -fn b() {}\n\
+             +fn c() {}\n"
+        );
+    }
+
     #[test]
     fn test_compute_patch_insert() {
         let old_items: Vec<Item> = vec![];
@@ -186,4 +552,236 @@ mod tests {
         assert_eq!(patch.operations.len(), 1);
         assert!(matches!(patch.operations[0], PatchOp::Keep { .. }));
     }
+
+    #[test]
+    fn test_compute_patch_ignores_formatting() {
+        let old_items = syn::parse_file("fn hello( a: u32 ) -> u32 {\n    a\n}")
+            .unwrap()
+            .items;
+        let new_items = syn::parse_file("fn hello(a: u32) -> u32 { a }")
+            .unwrap()
+            .items;
+
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+        assert!(patch.is_empty());
+    }
+
+    #[test]
+    fn test_compute_patch_move() {
+        let old_items: Vec<Item> = vec![
+            parse_quote! { fn a() {} },
+            parse_quote! { fn b() {} },
+            parse_quote! { fn c() {} },
+        ];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { fn c() {} },
+            parse_quote! { fn a() {} },
+            parse_quote! { fn b() {} },
+        ];
+
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+        assert_eq!(
+            patch.operations[0],
+            PatchOp::Move {
+                name: "c".to_string(),
+                from: 2,
+                to: 0
+            }
+        );
+        assert!(matches!(patch.operations[1], PatchOp::Keep { .. }));
+        assert!(matches!(patch.operations[2], PatchOp::Keep { .. }));
+    }
+
+    #[test]
+    fn test_item_key_generics() {
+        let item: Item = parse_quote! { struct Foo<'a, T: Clone, const N: usize>(&'a [T; N]); };
+        let imp: Item = parse_quote! { impl<T> Show for Foo<T> where T: Display {} };
+
+        assert_eq!(item_key(&item, GenericsMatching::Name).unwrap(), "Foo");
+        assert_eq!(
+            item_key(&item, GenericsMatching::Params).unwrap(),
+            "Foo<'a, T, N>"
+        );
+        assert_eq!(
+            item_key(&item, GenericsMatching::Strict).unwrap(),
+            "Foo<'a, T : Clone, const N : usize>"
+        );
+        assert_eq!(
+            item_key(&imp, GenericsMatching::Name).unwrap(),
+            "impl Show for Foo < T >"
+        );
+        assert_eq!(
+            item_key(&imp, GenericsMatching::Strict).unwrap(),
+            "impl<T> Show for Foo < T > where T : Display"
+        );
+    }
+
+    #[test]
+    fn test_item_key_ffi() {
+        let items: Vec<Item> = syn::parse_file(
+            "union Bits { i: u32, f: f32 }\n\
+             trait Shared = Send + Sync;\n\
+             extern \"C\" { fn strlen(s: *const u8) -> usize; static errno: i32; }",
+        )
+        .unwrap()
+        .items;
+        let names: Vec<String> = items.iter().filter_map(extract_item_name).collect();
+        assert_eq!(
+            names,
+            vec!["Bits", "Shared", "extern \"C\" {errno, strlen}"]
+        );
+
+        let patch = compute_patch(&items, &items).unwrap();
+        assert!(patch.is_empty());
+    }
+
+    #[test]
+    fn test_fallback_key() {
+        let old_items: Vec<Item> = syn::parse_file(
+            "macro_rules! square { ($x:expr) => { $x * $x }; }\n\
+             lazy_static! { static ref A: u8 = 1; }\n\
+             thread_local! { static B: u8 = 2; }",
+        )
+        .unwrap()
+        .items;
+        let new_items: Vec<Item> = syn::parse_file(
+            "macro_rules! square { ($x:expr) => { ($x) * ($x) }; }\n\
+             lazy_static! { static ref A: u8 = 1; }",
+        )
+        .unwrap()
+        .items;
+
+        assert_eq!(extract_item_name(&old_items[0]).unwrap(), "square!");
+        assert!(extract_item_name(&old_items[1])
+            .unwrap()
+            .starts_with("lazy_static! #"));
+
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+        let ops: Vec<&str> = patch
+            .operations
+            .iter()
+            .map(|op| match op {
+                PatchOp::Modify { .. } => "modify",
+                PatchOp::Keep { .. } => "keep",
+                PatchOp::Delete { .. } => "delete",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(ops, vec!["modify", "keep", "delete"]);
+    }
+
+    #[test]
+    fn test_compute_patch_rename() {
+        let old_items = syn::parse_file(
+            "fn load_user(id: u64) -> Option<User> { db().find(id) }\nfn helper() {}",
+        )
+        .unwrap()
+        .items;
+        let new_items = syn::parse_file(
+            "fn fetch_user(id: u64) -> Option<User> { db().find(id) }\nfn other(a: u8) -> u8 { a }",
+        )
+        .unwrap()
+        .items;
+
+        let comparison = Comparison {
+            threshold: Some(0.8),
+            ..Comparison::default()
+        };
+        let patch = compute_patch_with(&old_items, &new_items, &comparison).unwrap();
+        assert!(matches!(
+            &patch.operations[0],
+            PatchOp::Rename { from, name, .. } if from == "load_user" && name == "fetch_user"
+        ));
+        assert!(matches!(&patch.operations[1], PatchOp::Insert { name, .. } if name == "other"));
+        assert!(matches!(&patch.operations[2], PatchOp::Delete { name } if name == "helper"));
+        assert_eq!(patch.operations.len(), 3);
+
+        let patch = compute_patch_with(&old_items, &new_items, &Comparison::default()).unwrap();
+        assert!(patch
+            .operations
+            .iter()
+            .all(|op| !matches!(op, PatchOp::Rename { .. })));
+    }
+
+    #[test]
+    fn test_compute_patch_ignore_docs() {
+        let old_items = syn::parse_file(
+            "/// Old docs\nfn a() {}\nimpl S {\n    /// Old\n    fn b() {}\n}\n/// Docs\nfn c() {}",
+        )
+        .unwrap()
+        .items;
+        let new_items = syn::parse_file(
+            "/// New docs\nfn a() {}\nimpl S {\n    /// New\n    fn b() {}\n}\n/// Docs\nfn c() -> u8 { 1 }",
+        )
+        .unwrap()
+        .items;
+        let ops = |comparison: &Comparison| -> Vec<bool> {
+            compute_patch_with(&old_items, &new_items, comparison)
+                .unwrap()
+                .operations
+                .iter()
+                .map(|op| matches!(op, PatchOp::Keep { .. }))
+                .collect()
+        };
+
+        assert_eq!(ops(&Comparison::default()), vec![false, false, false]);
+        let ignore_docs = Comparison {
+            ignored_attrs: vec!["doc".to_string()],
+            ..Comparison::default()
+        };
+        assert_eq!(ops(&ignore_docs), vec![true, true, false]);
+    }
+
+    #[test]
+    fn test_same_item_trailing_commas() {
+        let same = |a: &str, b: &str| {
+            same_item(
+                &syn::parse_str(a).unwrap(),
+                &syn::parse_str(b).unwrap(),
+                &[],
+            )
+        };
+
+        assert!(same("struct S { id: u64, }", "struct S { id: u64 }"));
+        assert!(same("fn f(a: u8, b: u8,) {}", "fn f(a: u8, b: u8) {}"));
+        assert!(same(
+            "const P: (u8, u8) = (1, 2,);",
+            "const P: (u8, u8) = (1, 2);"
+        ));
+        // A one-element tuple isn't its element
+        assert!(!same("const P: (u8,) = (1,);", "const P: (u8) = (1);"));
+        assert!(!same(
+            "type T = (HashMap<K, V>,);",
+            "type T = (HashMap<K, V>);"
+        ));
+    }
+
+    #[test]
+    fn test_compute_patch_ignore_attrs() {
+        let old_items = syn::parse_file(
+            "#[inline]\nfn a() {}\n#[tracing::instrument(skip(x))]\nfn b(x: u8) {}\n#[derive(Debug)]\nstruct S;",
+        )
+        .unwrap()
+        .items;
+        let new_items = syn::parse_file(
+            "#[allow(dead_code)]\nfn a() {}\nfn b(x: u8) {}\n#[derive(Clone)]\nstruct S;",
+        )
+        .unwrap()
+        .items;
+        let comparison = Comparison {
+            ignored_attrs: crate::matching::ignored_attrs(
+                false,
+                &["allow", "#[inline]", "tracing :: instrument"].map(String::from),
+            ),
+            ..Comparison::default()
+        };
+        let kept: Vec<bool> = compute_patch_with(&old_items, &new_items, &comparison)
+            .unwrap()
+            .operations
+            .iter()
+            .map(|op| matches!(op, PatchOp::Keep { .. }))
+            .collect();
+
+        assert_eq!(kept, vec![true, true, false]);
+    }
 }
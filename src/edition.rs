@@ -0,0 +1,232 @@
+//! Edition module
+//!
+//! This module handles the Rust edition of generated code, which changes what
+//! parses: `async`, `await` and `try` are plain identifiers in 2015, and `gen`
+//! is reserved from 2024. The edition is the `edition` of rpt.toml, else the
+//! `package.edition` of the Cargo.toml nearest to the output, else 2021.
+//!
+//! `syn` parses the 2018 and 2021 editions. 2015 sources have those keywords
+//! turned into raw identifiers before parsing and back after printing, and
+//! 2024 sources using `gen` as an identifier are rejected. `rustfmt` is run
+//! with the edition.
+
+use anyhow::Result;
+use proc_macro2::{LineColumn, TokenStream, TokenTree};
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+
+/// Identifiers that are keywords from 2018 on, and can't be used unraw by `syn`
+const KEYWORDS_2018: &[&str] = &["async", "await", "try"];
+
+/// A Rust edition
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edition {
+    E2015,
+    E2018,
+    #[default]
+    E2021,
+    E2024,
+}
+
+impl Edition {
+    /// Parse an edition such as `2021`
+    pub fn parse(edition: &str) -> Result<Self> {
+        match edition {
+            "2015" => Ok(Edition::E2015),
+            "2018" => Ok(Edition::E2018),
+            "2021" => Ok(Edition::E2021),
+            "2024" => Ok(Edition::E2024),
+            _ => anyhow::bail!(
+                "Unknown edition '{}', expected 2015, 2018, 2021 or 2024",
+                edition
+            ),
+        }
+    }
+
+    /// The edition as cargo and rustfmt spell it
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Edition::E2015 => "2015",
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+            Edition::E2024 => "2024",
+        }
+    }
+
+    /// Edition set in rpt.toml, if any
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        config.edition.as_deref().map(Edition::parse).transpose()
+    }
+
+    /// Edition of the crate containing `dir`, from its Cargo.toml
+    ///
+    /// A package without an `edition` is 2015, as for cargo. `edition.workspace
+    /// = true` is looked up in the workspace manifest above.
+    pub fn of_crate(dir: &Path) -> Option<Self> {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let mut inherited = false;
+        for ancestor in dir.ancestors() {
+            let Ok(content) = fs::read_to_string(ancestor.join("Cargo.toml")) else {
+                continue;
+            };
+            let manifest: toml::Value = toml::from_str(&content).ok()?;
+            let edition = if inherited {
+                manifest.get("workspace")?.get("package")?.get("edition")
+            } else {
+                match manifest.get("package")?.get("edition") {
+                    None => return Some(Edition::E2015),
+                    edition => edition,
+                }
+            };
+            match edition {
+                Some(toml::Value::String(edition)) => return Edition::parse(edition).ok(),
+                Some(toml::Value::Table(table)) if table.get("workspace").is_some() => {
+                    inherited = true;
+                }
+                _ if inherited => continue,
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// The configured edition, else that of the crate containing `dir`, else 2021
+    pub fn resolve(configured: Option<Self>, dir: Option<&Path>) -> Self {
+        configured
+            .or_else(|| dir.and_then(Edition::of_crate))
+            .unwrap_or_default()
+    }
+
+    /// Rewrite `source` so that `syn` parses it as this edition
+    pub(crate) fn prepare(self, source: &str) -> Result<Cow<'_, str>, syn::Error> {
+        match self {
+            Edition::E2015 => Ok(rewrite_idents(source, |ident| {
+                KEYWORDS_2018
+                    .contains(&ident)
+                    .then(|| format!("r#{}", ident))
+            })),
+            Edition::E2018 | Edition::E2021 => Ok(Cow::Borrowed(source)),
+            Edition::E2024 => match find_ident(source, "gen") {
+                Some(span) => Err(syn::Error::new(
+                    span,
+                    "`gen` is a reserved keyword in edition 2024, use `r#gen`",
+                )),
+                None => Ok(Cow::Borrowed(source)),
+            },
+        }
+    }
+
+    /// Undo in printed code what [`Edition::prepare`] did to parse it
+    pub fn restore(self, code: String) -> String {
+        if self != Edition::E2015 {
+            return code;
+        }
+        rewrite_idents(&code, |ident| {
+            let ident = ident.strip_prefix("r#")?;
+            KEYWORDS_2018.contains(&ident).then(|| ident.to_string())
+        })
+        .into_owned()
+    }
+}
+
+/// Replace the identifiers of `source` that `replace` returns a replacement for
+///
+/// Sources that don't tokenize are returned as they are, for the parser to
+/// report the error.
+fn rewrite_idents(source: &str, replace: impl Fn(&str) -> Option<String>) -> Cow<'_, str> {
+    let Ok(tokens) = source.parse::<TokenStream>() else {
+        return Cow::Borrowed(source);
+    };
+    let mut replacements = Vec::new();
+    visit_idents(tokens, &mut |ident| {
+        if let Some(replacement) = replace(&ident.to_string()) {
+            replacements.push((ident.span().start(), ident.span().end(), replacement));
+        }
+    });
+    if replacements.is_empty() {
+        return Cow::Borrowed(source);
+    }
+
+    let lines: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(at, _)| at + 1))
+        .collect();
+    let offset = |position: LineColumn| {
+        let start = lines[position.line - 1];
+        source[start..]
+            .char_indices()
+            .nth(position.column)
+            .map_or(source.len(), |(at, _)| start + at)
+    };
+    let mut rewritten = source.to_string();
+    for (start, end, replacement) in replacements.into_iter().rev() {
+        rewritten.replace_range(offset(start)..offset(end), &replacement);
+    }
+    Cow::Owned(rewritten)
+}
+
+/// Span of the first identifier `name` in `source`, if it tokenizes
+fn find_ident(source: &str, name: &str) -> Option<proc_macro2::Span> {
+    let tokens = source.parse::<TokenStream>().ok()?;
+    let mut found = None;
+    visit_idents(tokens, &mut |ident| {
+        if found.is_none() && ident == name {
+            found = Some(ident.span());
+        }
+    });
+    found
+}
+
+/// Call `f` on every identifier of `tokens`, in order
+fn visit_idents(tokens: TokenStream, f: &mut impl FnMut(&proc_macro2::Ident)) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => f(&ident),
+            TokenTree::Group(group) => visit_idents(group.stream(), f),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast_parser::ParsedFile;
+
+    #[test]
+    fn test_edition() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"app\"]\n\n[workspace.package]\nedition = \"2024\"\n",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.path().join("app/src")).unwrap();
+        fs::write(
+            dir.path().join("app/Cargo.toml"),
+            "[package]\nname = \"app\"\nedition.workspace = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Edition::of_crate(&dir.path().join("app/src")),
+            Some(Edition::E2024)
+        );
+        assert_eq!(
+            Edition::resolve(Some(Edition::E2018), Some(&dir.path().join("app/src"))),
+            Edition::E2018
+        );
+
+        let source = "fn r#gen() {}\nfn main() {\n    let gen = 1;\n}\n";
+        assert!(ParsedFile::parse_edition(source, Edition::E2021).is_ok());
+        let error = ParsedFile::parse_edition(source, Edition::E2024).unwrap_err();
+        assert!(format!("{:#}", error).contains("--> source:3:9"));
+
+        let source = "fn async() -> u32 { try!(Ok(1)) }\n";
+        assert!(ParsedFile::parse(source).is_err());
+        let parsed = ParsedFile::parse_edition(source, Edition::E2015).unwrap();
+        let printed = Edition::E2015.restore(prettyplease::unparse(&parsed.syntax_tree));
+        assert_eq!(printed, "fn async() -> u32 {\n    try!(Ok(1))\n}\n");
+    }
+}
@@ -0,0 +1,71 @@
+//! Events module
+//!
+//! This module reports what generation is doing as it goes, for library users
+//! showing progress and for the progress bar of `generate-all`. The sink set in
+//! [`GenerateOptions::events`](crate::generator::GenerateOptions::events)
+//! receives, for each file:
+//!
+//! - [`Event::RenderStarted`] before the template renders,
+//! - [`Event::Parsed`] once the generated and existing Rust code parse,
+//! - [`Event::DiffComputed`] with the number of changes the template makes,
+//! - [`Event::ConflictFound`] for each conflict met while merging,
+//! - [`Event::FileWritten`] once the output is written, by callers that write.
+//!
+//! Closures taking an [`Event`] and `mpsc::Sender<Event>` are sinks:
+//!
+//! ```
+//! use std::sync::{mpsc, Arc};
+//!
+//! let (sender, receiver) = mpsc::channel();
+//! let options = rpt::generator::GenerateOptions {
+//!     events: Some(Arc::new(sender)),
+//!     ..Default::default()
+//! };
+//! # drop((options, receiver));
+//! ```
+
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+
+/// A step of generation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A template started rendering
+    RenderStarted { template: PathBuf },
+    /// Generated and existing code were parsed, with their number of items
+    Parsed { generated: usize, existing: usize },
+    /// The patch from existing to generated code was computed, with `ops` changes
+    DiffComputed { ops: usize },
+    /// A conflict was met while merging, and resolved as the message says
+    ConflictFound { message: String },
+    /// An output file was written
+    FileWritten { path: PathBuf },
+}
+
+/// Receiver of generation events
+pub trait EventSink: Send + Sync {
+    /// Handle `event`; generation doesn't wait on anything but this call
+    fn emit(&self, event: Event);
+}
+
+impl std::fmt::Debug for dyn EventSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EventSink")
+    }
+}
+
+impl<F> EventSink for F
+where
+    F: Fn(Event) + Send + Sync,
+{
+    fn emit(&self, event: Event) {
+        self(event)
+    }
+}
+
+impl EventSink for Sender<Event> {
+    fn emit(&self, event: Event) {
+        // A dropped receiver stopped listening, which doesn't stop generation
+        let _ = self.send(event);
+    }
+}
@@ -0,0 +1,429 @@
+//! Merge explanation module
+//!
+//! This module describes, item by item, what a merge would do to the target
+//! file and why, without applying anything.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+use syn::Item;
+
+use crate::ast_parser::item_kind;
+use crate::diff::{extract_item_name, Patch, PatchOp};
+use crate::merger::{fills_placeholders, merge_patch_with_policy, MergeStrategy, WarningKind};
+use crate::placement::PlacementRules;
+use crate::policy::MergePolicy;
+use crate::strategy::{ItemStrategy, StrategyRules};
+use crate::summary::{describe_changes, describe_rename};
+
+/// What the merge does with an item
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeAction {
+    /// Existing item is identical to the template
+    Kept,
+    /// Item only exists in the template and will be added
+    Added,
+    /// Existing item is replaced by the template version
+    Updated,
+    /// Existing item is kept despite differing from the template
+    Preserved,
+    /// Existing item is removed because the template dropped it
+    Removed,
+    /// Item differs and the strategy refuses to pick a side
+    Conflict,
+    /// Item can't be matched by name and is left out of the merge
+    Dropped,
+}
+
+impl fmt::Display for MergeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            MergeAction::Kept => "kept",
+            MergeAction::Added => "added",
+            MergeAction::Updated => "updated",
+            MergeAction::Preserved => "preserved",
+            MergeAction::Removed => "removed",
+            MergeAction::Conflict => "conflict",
+            MergeAction::Dropped => "dropped",
+        };
+        f.pad(label)
+    }
+}
+
+/// Planned merge action for one item, with the reason behind it
+#[derive(Debug, Clone)]
+pub struct ItemExplanation {
+    pub kind: &'static str,
+    /// Item name, `None` for items that can't be named (impls, uses, ...)
+    pub name: Option<String>,
+    pub action: MergeAction,
+    pub reason: String,
+}
+
+impl fmt::Display for ItemExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(
+                f,
+                "{:<9}  {} `{}`: {}",
+                self.action, self.kind, name, self.reason
+            ),
+            None => write!(f, "{:<9}  {}: {}", self.action, self.kind, self.reason),
+        }
+    }
+}
+
+/// Explain how `patch` would be merged into `base_items`
+///
+/// Existing items come first in file order, followed by items that only
+/// exist in the template. The patch is merged with
+/// [`crate::merger::merge_patch_with_policy`], so each action is the one the
+/// merge takes, with the `preserve`/`always_replace` lists, per-kind
+/// `[strategies]`, recorded resolutions and `policy` applied; the reason names
+/// the rule that decided it.
+pub fn explain_patch(
+    base_items: &[Item],
+    patch: &Patch,
+    strategy: MergeStrategy,
+    respect_order: bool,
+    policy: Option<&dyn MergePolicy>,
+    strategies: &StrategyRules,
+) -> Result<Vec<ItemExplanation>> {
+    let result = merge_patch_with_policy(
+        base_items,
+        patch,
+        strategy,
+        respect_order,
+        policy,
+        strategies,
+        &PlacementRules::default(),
+    )?;
+    let merged: HashMap<String, &Item> = result
+        .merged_items
+        .iter()
+        .filter_map(|item| extract_item_name(item).map(|name| (name, item)))
+        .collect();
+    let conflicted = |name: &str| {
+        result.conflicts.iter().any(|conflict| {
+            conflict.kind == WarningKind::Unresolved
+                && conflict.message.contains(&format!("'{}'", name))
+        })
+    };
+    let base_map: HashMap<String, &Item> = base_items
+        .iter()
+        .filter_map(|item| extract_item_name(item).map(|name| (name, item)))
+        .collect();
+
+    let mut planned: HashMap<&str, ItemExplanation> = HashMap::new();
+    let mut added = Vec::new();
+
+    for op in &patch.operations {
+        match op {
+            PatchOp::Insert { name, item } => match base_map.get(name) {
+                Some(&base_item) => {
+                    let changes = describe_changes(base_item, item).join("; ");
+                    let outcome = merged.get(name).copied();
+                    let (action, reason) = explain_change(
+                        &changes,
+                        base_item,
+                        item,
+                        outcome,
+                        conflicted(name),
+                        &rule_note(strategies, name, Some(base_item), Some(item), strategy),
+                    );
+                    planned.insert(name, planned_item(action, &reason));
+                }
+                None => added.push(ItemExplanation {
+                    kind: item_kind(item),
+                    name: Some(name.clone()),
+                    action: MergeAction::Added,
+                    reason: "new in template".to_string(),
+                }),
+            },
+            PatchOp::Keep { name } => {
+                planned.insert(name, planned_item(MergeAction::Kept, "unchanged"));
+            }
+            PatchOp::Move { name, from, to } => {
+                let reason = if respect_order {
+                    format!(
+                        "unchanged, moved from position {} to {} to follow the template",
+                        from, to
+                    )
+                } else {
+                    "unchanged, template order differs but position is kept".to_string()
+                };
+                planned.insert(name, planned_item(MergeAction::Kept, &reason));
+            }
+            PatchOp::Modify {
+                name,
+                old_item,
+                new_item,
+            }
+            | PatchOp::Rename {
+                from: name,
+                old_item,
+                new_item,
+                ..
+            } => {
+                let (changes, outcome) = match op {
+                    PatchOp::Rename { from, name, .. } => {
+                        let mut changes = vec![format!("renamed to `{}`", name)];
+                        changes.extend(describe_rename(from, name, old_item, new_item));
+                        (changes, merged.get(name).or(merged.get(from)).copied())
+                    }
+                    _ => (
+                        describe_changes(old_item, new_item),
+                        merged.get(name).copied(),
+                    ),
+                };
+                let base_item = base_map.get(name).copied().unwrap_or(old_item);
+                let (action, reason) = explain_change(
+                    &changes.join("; "),
+                    base_item,
+                    new_item,
+                    outcome,
+                    conflicted(name),
+                    &rule_note(strategies, name, Some(base_item), Some(new_item), strategy),
+                );
+                planned.insert(name, planned_item(action, &reason));
+            }
+            PatchOp::Delete { name } => {
+                let Some(&base_item) = base_map.get(name) else {
+                    continue;
+                };
+                let note = rule_note(strategies, name, Some(base_item), None, strategy);
+                let (action, reason) = match merged.get(name) {
+                    _ if conflicted(name) => (
+                        MergeAction::Conflict,
+                        "exists in file but not in template".to_string(),
+                    ),
+                    None => (
+                        MergeAction::Removed,
+                        format!("no longer in template{}", note),
+                    ),
+                    Some(&kept) if kept == base_item => (
+                        MergeAction::Preserved,
+                        format!("not in template, kept as a manual addition{}", note),
+                    ),
+                    Some(_) => (
+                        MergeAction::Updated,
+                        format!("not in template, replaced{}", note),
+                    ),
+                };
+                planned.insert(name, planned_item(action, &reason));
+            }
+        }
+    }
+
+    let mut explanations: Vec<ItemExplanation> = base_items
+        .iter()
+        .map(|item| {
+            let name = extract_item_name(item);
+            let planned = name.as_deref().and_then(|name| planned.remove(name));
+            match planned {
+                Some(planned) => ItemExplanation {
+                    kind: item_kind(item),
+                    name,
+                    ..planned
+                },
+                None => ItemExplanation {
+                    kind: item_kind(item),
+                    name,
+                    action: MergeAction::Dropped,
+                    reason: "can't be matched by name".to_string(),
+                },
+            }
+        })
+        .collect();
+
+    explanations.extend(added);
+    Ok(explanations)
+}
+
+/// Action and reason for an item both the file and the template define, from what the merge made of it
+fn explain_change(
+    changes: &str,
+    manual: &Item,
+    template: &Item,
+    outcome: Option<&Item>,
+    conflicted: bool,
+    note: &str,
+) -> (MergeAction, String) {
+    match outcome {
+        _ if conflicted => (
+            MergeAction::Conflict,
+            format!("both template and file define it differently ({})", changes),
+        ),
+        None => (
+            MergeAction::Conflict,
+            format!("left out of the merge ({}){}", changes, note),
+        ),
+        Some(outcome) if outcome == manual => (
+            MergeAction::Preserved,
+            format!(
+                "manual edits detected ({}), template update skipped{}",
+                changes, note
+            ),
+        ),
+        Some(outcome) if outcome == template && fills_placeholders(manual, template) => (
+            MergeAction::Updated,
+            format!(
+                "template changed ({}), implementing placeholder bodies{}",
+                changes, note
+            ),
+        ),
+        Some(outcome) if outcome == template => (
+            MergeAction::Updated,
+            format!(
+                "template changed ({}), manual edits overridden{}",
+                changes, note
+            ),
+        ),
+        Some(_) => (
+            MergeAction::Updated,
+            format!(
+                "template changed ({}), combined with the manual version{}",
+                changes, note
+            ),
+        ),
+    }
+}
+
+/// `, by <rule>` for conflicts a rule other than `--strategy` decides
+fn rule_note(
+    strategies: &StrategyRules,
+    name: &str,
+    manual: Option<&Item>,
+    template: Option<&Item>,
+    strategy: MergeStrategy,
+) -> String {
+    let kind = manual.or(template).map(item_kind).unwrap_or("item");
+    let by_name = strategies.resolve(name, kind, strategy);
+    let rule = match strategies.resolve_conflict(name, manual, template, strategy) {
+        ItemStrategy::Preserve if by_name == ItemStrategy::Preserve => {
+            "the `preserve` list".to_string()
+        }
+        ItemStrategy::AlwaysReplace if by_name == ItemStrategy::AlwaysReplace => {
+            "the `always_replace` list".to_string()
+        }
+        ItemStrategy::Preserve | ItemStrategy::AlwaysReplace => "a recorded resolution".to_string(),
+        ItemStrategy::MergeMethods => format!("the `{}` rule merge-methods", kind),
+        ItemStrategy::MergeFields => format!("the `{}` rule merge-fields", kind),
+        ItemStrategy::Hybrid => format!("the `{}` rule hybrid", kind),
+        ItemStrategy::Strategy(rule) if rule != strategy => format!("the `{}` rule", kind),
+        ItemStrategy::Strategy(_) => return String::new(),
+    };
+    format!(", by {}", rule)
+}
+
+/// Explanation whose kind and name are filled in from the base item
+fn planned_item(action: MergeAction, reason: &str) -> ItemExplanation {
+    ItemExplanation {
+        kind: "item",
+        name: None,
+        action,
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::compute_patch;
+    use syn::parse_quote;
+
+    #[test]
+    fn test_explain_patch_actions() {
+        let base_items: Vec<Item> = vec![
+            parse_quote! { fn same() {} },
+            parse_quote! { fn edited() -> u32 { 1 } },
+            parse_quote! { fn manual() {} },
+        ];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { fn same() {} },
+            parse_quote! { fn edited() -> u64 { 1 } },
+            parse_quote! { fn added() {} },
+        ];
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let actions: Vec<_> = explain_patch(
+            &base_items,
+            &patch,
+            MergeStrategy::PreferManual,
+            false,
+            None,
+            &StrategyRules::default(),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|explanation| (explanation.name.unwrap(), explanation.action))
+        .collect();
+
+        assert_eq!(
+            actions,
+            vec![
+                ("same".to_string(), MergeAction::Kept),
+                ("edited".to_string(), MergeAction::Preserved),
+                ("manual".to_string(), MergeAction::Preserved),
+                ("added".to_string(), MergeAction::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_patch_template_strategy() {
+        let base_items: Vec<Item> = vec![parse_quote! { fn edited() -> u32 { 1 } }];
+        let new_items: Vec<Item> = vec![parse_quote! { fn edited() -> u64 { 1 } }];
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let explanations = explain_patch(
+            &base_items,
+            &patch,
+            MergeStrategy::PreferTemplate,
+            false,
+            None,
+            &StrategyRules::default(),
+        )
+        .unwrap();
+
+        assert_eq!(explanations[0].action, MergeAction::Updated);
+        assert!(explanations[0]
+            .reason
+            .contains("return type changed from u32 to u64"));
+    }
+
+    #[test]
+    fn test_explain_patch_follows_rules() {
+        let base_items: Vec<Item> = vec![
+            parse_quote! { fn foo() -> u32 { 1 } },
+            parse_quote! { fn load(id: u32) -> u32 { id } },
+        ];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { fn foo() -> u64 { 0 } },
+            parse_quote! { fn load(id: u32, db: &Db) -> u32 { todo!() } },
+        ];
+        let config = crate::config::Config {
+            preserve: vec!["foo".to_string()],
+            strategies: [("fn".to_string(), "hybrid".to_string())].into(),
+            ..crate::config::Config::default()
+        };
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let explanations = explain_patch(
+            &base_items,
+            &patch,
+            MergeStrategy::PreferTemplate,
+            false,
+            None,
+            &StrategyRules::from_config(&config).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(explanations[0].action, MergeAction::Preserved);
+        assert!(explanations[0].reason.ends_with("by the `preserve` list"));
+        assert_eq!(explanations[1].action, MergeAction::Updated);
+        assert!(explanations[1]
+            .reason
+            .ends_with("combined with the manual version, by the `fn` rule hybrid"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Multi-file template module
+//!
+//! This module lets one template emit several files with
+//! `{% file "src/models/user.rs" %} … {% endfile %}` blocks. Tera has no custom
+//! tags, so the blocks are rewritten into marker comments before compiling and
+//! the rendered output is split on those markers. Paths may use Tera
+//! expressions, e.g. `{% file "src/models/{{ entity.name }}.rs" %}` inside a loop.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::path::{Component, PathBuf};
+
+use crate::merger::Warning;
+
+/// Marker line opening a file block in rendered output
+const FILE_MARKER: &str = "// @rpt:file ";
+/// Marker line closing a file block in rendered output
+const END_MARKER: &str = "// @rpt:endfile";
+
+/// One file emitted by a multi-file template
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedFile {
+    /// Output path, relative to the output directory
+    pub path: PathBuf,
+    /// Rendered code for this file
+    pub code: String,
+    /// Conflicts resolved while merging this file, and notes
+    pub conflicts: Vec<Warning>,
+}
+
+/// Rewrite `{% file "…" %}` and `{% endfile %}` tags into marker lines
+pub fn expand_file_tags(template: &str) -> Result<String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{%") {
+        // Leave unterminated tags for Tera to report
+        let Some(end) = rest[start..].find("%}").map(|end| start + end + 2) else {
+            break;
+        };
+        let tag = &rest[start..end];
+
+        expanded.push_str(&rest[..start]);
+        match parse_tag(tag)? {
+            Some(Tag::File(path)) => {
+                expanded.push('\n');
+                expanded.push_str(FILE_MARKER);
+                expanded.push_str(path);
+                expanded.push('\n');
+            }
+            Some(Tag::EndFile) => {
+                expanded.push('\n');
+                expanded.push_str(END_MARKER);
+                expanded.push('\n');
+            }
+            None => expanded.push_str(tag),
+        }
+        rest = &rest[end..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+enum Tag<'a> {
+    File(&'a str),
+    EndFile,
+}
+
+/// Recognise the file tags, leaving every other Tera tag untouched
+fn parse_tag(tag: &str) -> Result<Option<Tag<'_>>> {
+    let inner = tag[2..tag.len() - 2]
+        .trim_start_matches('-')
+        .trim_end_matches('-')
+        .trim();
+
+    if inner == "endfile" {
+        return Ok(Some(Tag::EndFile));
+    }
+
+    let Some(arg) = inner.strip_prefix("file") else {
+        return Ok(None);
+    };
+    if !arg.starts_with(char::is_whitespace) {
+        return Ok(None);
+    }
+
+    let arg = arg.trim();
+    let path = arg
+        .strip_prefix('"')
+        .and_then(|arg| arg.strip_suffix('"'))
+        .or_else(|| {
+            arg.strip_prefix('\'')
+                .and_then(|arg| arg.strip_suffix('\''))
+        })
+        .with_context(|| format!("Expected a quoted path in `{}`", tag))?;
+
+    Ok(Some(Tag::File(path)))
+}
+
+/// Split rendered output into its file blocks
+///
+/// Returns `None` when the template declares no file blocks. Outside of file
+/// blocks only whitespace is allowed.
+pub fn split_files(rendered: &str) -> Result<Option<Vec<RenderedFile>>> {
+    let mut files = Vec::new();
+    let mut current: Option<RenderedFile> = None;
+    let mut seen = HashSet::new();
+    let mut stray_line = None;
+
+    for (index, line) in rendered.lines().enumerate() {
+        let trimmed = line.trim();
+
+        if let Some(path) = trimmed.strip_prefix(FILE_MARKER) {
+            if let Some(open) = &current {
+                anyhow::bail!(
+                    "File block '{}' opened inside '{}' (line {})",
+                    path,
+                    open.path.display(),
+                    index + 1
+                );
+            }
+            let path = validate_path(path.trim())?;
+            if !seen.insert(path.clone()) {
+                anyhow::bail!("File '{}' is emitted more than once", path.display());
+            }
+            current = Some(RenderedFile {
+                path,
+                code: String::new(),
+                conflicts: Vec::new(),
+            });
+        } else if trimmed == END_MARKER {
+            let file = current
+                .take()
+                .with_context(|| format!("`endfile` without a file block (line {})", index + 1))?;
+            files.push(file);
+        } else if let Some(file) = current.as_mut() {
+            file.code.push_str(line);
+            file.code.push('\n');
+        } else if !trimmed.is_empty() {
+            stray_line.get_or_insert(index + 1);
+        }
+    }
+
+    if let Some(open) = current {
+        anyhow::bail!("File block '{}' is never closed", open.path.display());
+    }
+
+    if files.is_empty() {
+        return Ok(None);
+    }
+    if let Some(line) = stray_line {
+        anyhow::bail!("Output outside of a file block (line {})", line);
+    }
+
+    Ok(Some(files))
+}
+
+/// Reject paths that would escape the output directory
+pub(crate) fn validate_path(path: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(path);
+    let escapes = path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+
+    if path.as_os_str().is_empty() || escapes {
+        anyhow::bail!(
+            "File path '{}' must be relative to the output directory",
+            path.display()
+        );
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{create_context, TemplateEngine};
+    use serde_json::json;
+
+    #[test]
+    fn test_render_multiple_files() {
+        let template = r#"
+{% for entity in entities %}
+{% file "models/{{ entity | lower }}.rs" %}
+pub struct {{ entity }};
+{% endfile %}
+{% endfor %}
+"#;
+        let engine = TemplateEngine::from_string("t", template).unwrap();
+        let context = create_context(vec![("entities", json!(["User", "Post"]))]);
+        let rendered = engine.render("t", &context).unwrap();
+
+        let files = split_files(&rendered).unwrap().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, PathBuf::from("models/user.rs"));
+        assert!(files[0].code.contains("pub struct User;"));
+        assert_eq!(files[1].path, PathBuf::from("models/post.rs"));
+        assert!(!files[1].code.contains("User"));
+    }
+
+    #[test]
+    fn test_split_without_blocks() {
+        assert_eq!(split_files("fn main() {}\n").unwrap(), None);
+    }
+
+    #[test]
+    fn test_split_rejects_invalid_blocks() {
+        let stray = format!("{}a.rs\n{}\nfn stray() {{}}\n", FILE_MARKER, END_MARKER);
+        assert!(split_files(&stray).is_err());
+
+        let unclosed = format!("{}a.rs\nfn a() {{}}\n", FILE_MARKER);
+        assert!(split_files(&unclosed).is_err());
+
+        let escaping = format!("{}../a.rs\n{}\n", FILE_MARKER, END_MARKER);
+        assert!(split_files(&escaping).is_err());
+
+        let duplicate = format!(
+            "{m}a.rs\n{e}\n{m}a.rs\n{e}\n",
+            m = FILE_MARKER,
+            e = END_MARKER
+        );
+        assert!(split_files(&duplicate).is_err());
+    }
+
+    #[test]
+    fn test_expand_keeps_other_tags() {
+        let template = "{% if x %}{% file 'a.rs' %}{% endfile %}{%- endif -%}";
+        let expanded = expand_file_tags(template).unwrap();
+        assert!(expanded.starts_with("{% if x %}"));
+        assert!(expanded.contains("// @rpt:file a.rs\n"));
+        assert!(expanded.ends_with("{%- endif -%}"));
+    }
+}
@@ -0,0 +1,274 @@
+//! Template front-matter module
+//!
+//! This module reads the optional TOML front matter at the top of a template,
+//! between `+++` lines:
+//!
+//! ```text
+//! +++
+//! description = "Entity model"
+//! output = "src/models/{{ name | lower }}.rs"
+//! strategy = "template"
+//!
+//! [defaults]
+//! derives = ["Debug", "Clone"]
+//!
+//! [imports]
+//! "{{ name }}" = ["serde::Serialize"]
+//! +++
+//! pub struct {{ name }} { … }
+//! ```
+//!
+//! Defaults fill context keys the context file leaves out, while `output` and
+//! `strategy` apply when the matching CLI flags are absent. `imports` lists the
+//! paths each item needs, keyed by item name, and is imported when the
+//! rendered code has the item.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use syn::spanned::Spanned;
+use syn::Item;
+
+use crate::diff::extract_item_name;
+use crate::imports::import_paths;
+use crate::input::read_source;
+use crate::merger::MergeStrategy;
+use crate::modules::insert_line;
+use crate::template::render_str;
+
+/// Delimiter line around the front matter
+const DELIMITER: &str = "+++";
+
+/// Metadata declared at the top of a template
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrontMatter {
+    /// Short description shown by `list-templates`
+    pub description: Option<String>,
+    /// Output path pattern, rendered with the context
+    pub output: Option<String>,
+    /// Recommended merge strategy: template, manual, fail, or smart
+    pub strategy: Option<String>,
+    /// Default context values
+    pub defaults: HashMap<String, Value>,
+    /// Paths to import by item name, the names are rendered with the context
+    pub imports: BTreeMap<String, Vec<String>>,
+}
+
+impl FrontMatter {
+    /// Read the front matter of a template file
+    pub fn from_template_file(path: &Path) -> Result<Self> {
+        let content = read_source(path).context("Failed to read template file")?;
+        let (front_matter, _) = split_front_matter(&content)
+            .with_context(|| format!("In template {}", path.display()))?;
+        Ok(front_matter)
+    }
+
+    /// Context with the defaults filled in for keys it doesn't set
+    pub fn apply_defaults(&self, context: &HashMap<String, Value>) -> HashMap<String, Value> {
+        let mut merged = self.defaults.clone();
+        merged.extend(context.iter().map(|(k, v)| (k.clone(), v.clone())));
+        merged
+    }
+
+    /// Add a `use` for the declared imports of the items in rendered code
+    ///
+    /// Paths the code already imports are skipped. Code that doesn't parse is
+    /// returned as is, for the merge to report.
+    pub fn add_imports(&self, code: String, context: &HashMap<String, Value>) -> Result<String> {
+        if self.imports.is_empty() {
+            return Ok(code);
+        }
+        let Ok(file) = syn::parse_file(&code) else {
+            return Ok(code);
+        };
+
+        let context = self.apply_defaults(context);
+        let names: BTreeSet<String> = file.items.iter().filter_map(extract_item_name).collect();
+        let mut known: BTreeSet<String> = file
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Use(item) => Some(import_paths(item)),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+
+        let mut lines = String::new();
+        for (name, paths) in &self.imports {
+            if !names.contains(&render_str(name, &context)?) {
+                continue;
+            }
+            for path in paths {
+                if known.insert(path.clone()) {
+                    lines.push_str(&format!("use {};\n", path));
+                }
+            }
+        }
+
+        // Imports go after the inner attributes and docs
+        let line = match (file.attrs.last(), &file.shebang) {
+            (Some(attr), _) => attr.span().end().line,
+            (None, Some(_)) => 1,
+            (None, None) => 0,
+        };
+        Ok(insert_line(&code, line, &lines))
+    }
+
+    /// Render the output path pattern, if the template declares one
+    pub fn output_path(&self, context: &HashMap<String, Value>) -> Result<Option<PathBuf>> {
+        self.output
+            .as_deref()
+            .map(|pattern| {
+                Ok(PathBuf::from(render_str(
+                    pattern,
+                    &self.apply_defaults(context),
+                )?))
+            })
+            .transpose()
+    }
+}
+
+/// Split a template into its front matter and body
+///
+/// The front matter is replaced by a Tera comment spanning the same lines, so
+/// errors in the body keep their line numbers. Templates without front matter
+/// are returned unchanged.
+pub fn split_front_matter(template: &str) -> Result<(FrontMatter, String)> {
+    let Some((front_matter, lines, body)) = parse_front_matter(template)? else {
+        return Ok((FrontMatter::default(), template.to_string()));
+    };
+
+    // Tera comments have no whitespace control, the empty expression
+    // swallows the newline after the closing delimiter instead. `import` and
+    // `extends` must come before any content and swallow it themselves.
+    let top_level = body
+        .trim_start()
+        .strip_prefix("{%")
+        .map(|tag| tag.trim_start_matches('-').trim_start())
+        .is_some_and(|tag| tag.starts_with("import") || tag.starts_with("extends"));
+    let swallow = if top_level { "" } else { "{{- \"\" -}}" };
+    let body = format!("{{#{}#}}{}\n{}", "\n".repeat(lines - 1), swallow, body);
+    Ok((front_matter, body))
+}
+
+/// Parse the front matter of a template, if it has one
+///
+/// Returns the front matter, the number of lines it spans including both
+/// delimiters, and the body after it.
+pub fn parse_front_matter(template: &str) -> Result<Option<(FrontMatter, usize, &str)>> {
+    let Some(rest) = template.strip_prefix(DELIMITER).and_then(|rest| {
+        rest.strip_prefix('\n')
+            .or_else(|| rest.strip_prefix("\r\n"))
+    }) else {
+        return Ok(None);
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == DELIMITER {
+            let toml_source = &rest[..offset];
+            let front_matter: FrontMatter =
+                toml::from_str(toml_source).context("Failed to parse template front matter")?;
+            if let Some(strategy) = &front_matter.strategy {
+                MergeStrategy::parse(strategy).context("Invalid template front matter")?;
+            }
+
+            let lines = toml_source.matches('\n').count() + 2;
+            return Ok(Some((front_matter, lines, &rest[offset + line.len()..])));
+        }
+        offset += line.len();
+    }
+
+    anyhow::bail!(
+        "Template front matter is missing its closing `{}`",
+        DELIMITER
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{create_context, TemplateEngine};
+    use serde_json::json;
+
+    const TEMPLATE: &str = "+++\n\
+        output = \"src/{{ name | lower }}.rs\"\n\
+        strategy = \"template\"\n\
+        [defaults]\n\
+        vis = \"pub\"\n\
+        +++\n\
+        {{ vis }} struct {{ name }};\n";
+
+    #[test]
+    fn test_split_front_matter() {
+        let (front_matter, body) = split_front_matter(TEMPLATE).unwrap();
+
+        assert_eq!(front_matter.strategy.as_deref(), Some("template"));
+        assert_eq!(front_matter.defaults.get("vis"), Some(&json!("pub")));
+        assert_eq!(body.lines().count(), TEMPLATE.lines().count());
+
+        let context = create_context(vec![("name", json!("User"))]);
+        assert_eq!(
+            front_matter.output_path(&context).unwrap(),
+            Some(PathBuf::from("src/user.rs"))
+        );
+    }
+
+    #[test]
+    fn test_render_with_defaults() {
+        let engine = TemplateEngine::from_string("t", TEMPLATE).unwrap();
+
+        let context = create_context(vec![("name", json!("User"))]);
+        assert_eq!(engine.render("t", &context).unwrap(), "pub struct User;\n");
+
+        let context = create_context(vec![("name", json!("User")), ("vis", json!("pub(crate)"))]);
+        assert_eq!(
+            engine.render("t", &context).unwrap(),
+            "pub(crate) struct User;\n"
+        );
+    }
+
+    #[test]
+    fn test_add_imports() {
+        let template = "+++\n\
+            [imports]\n\
+            \"{{ name }}\" = [\"serde::Serialize\", \"std::fmt\"]\n\
+            helper = [\"std::io\"]\n\
+            +++\n\
+            //! Models\n\
+            use std::fmt;\n\
+            #[derive(Serialize)]\n\
+            pub struct {{ name }};\n";
+        let engine = TemplateEngine::from_string("t", template).unwrap();
+
+        let context = create_context(vec![("name", json!("User"))]);
+        assert_eq!(
+            engine.render("t", &context).unwrap(),
+            "//! Models\nuse serde::Serialize;\nuse std::fmt;\n#[derive(Serialize)]\npub struct User;\n"
+        );
+    }
+
+    #[test]
+    fn test_template_without_front_matter() {
+        let (front_matter, body) = split_front_matter("fn main() {}\n").unwrap();
+        assert_eq!(front_matter, FrontMatter::default());
+        assert_eq!(body, "fn main() {}\n");
+
+        assert!(split_front_matter("+++\nstrategy = \"manual\"\n").is_err());
+    }
+
+    #[test]
+    fn test_unknown_front_matter_strategy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user.rs.tera");
+        std::fs::write(&path, "+++\nstrategy = \"manaul\"\n+++\nfn f() {}\n").unwrap();
+
+        let error = format!("{:#}", FrontMatter::from_template_file(&path).unwrap_err());
+        assert!(error.contains("user.rs.tera"));
+        assert!(error.contains("Unknown strategy 'manaul'"));
+    }
+}
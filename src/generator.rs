@@ -7,70 +7,683 @@ use anyhow::{Context, Result};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::ast_parser::ParsedFile;
-use crate::diff::compute_patch;
-use crate::merger::{format_merged_code, merge_patch, MergeStrategy};
-use crate::template::TemplateEngine;
+use crate::backend::{Engine, RenderOptions};
+use crate::cargo_toml::merge_toml;
+use crate::diagnostics::{with_origin, with_template, ParseDiagnostic, GENERATED_CODE};
+use crate::diff::{compute_patch, PatchOp};
+use crate::edition::Edition;
+use crate::events::{Event, EventSink};
+use crate::explain::{explain_patch, ItemExplanation};
+use crate::file_blocks::{split_files, RenderedFile};
+use crate::input::{is_stdio, read_source, source_exists};
+use crate::line_merge::merge_lines;
+use crate::markdown::merge_sections;
+use crate::matching::{
+    with_comparison, with_generics, with_matcher, Comparison, GenericsMatching, ItemMatcher,
+};
+use crate::merger::{
+    format_merged_code, merge_file_header, merge_patch, merge_patch_with_policy, messages,
+    restore_kept_items, Formatter, MergeStrategy, Warning, WarningKind,
+};
+use crate::ordering::Ordering;
+use crate::placement::PlacementRules;
+use crate::policy::MergePolicy;
+use crate::provenance::{strip_header, Provenance};
+use crate::regions::merge_regions;
+use crate::strategy::StrategyRules;
+use crate::summary::{describe_file_header, field_type_changes, FieldTypeChange};
+use crate::template::TemplateCache;
+use crate::three_way::{apply_unedited, narrow_patch};
+use crate::timings::{time, Stage};
+use crate::tree::render_tree;
+
+/// Options controlling how generated code is rendered, merged and printed
+#[derive(Debug, Clone)]
+pub struct GenerateOptions {
+    /// Template engine and missing-variable handling
+    pub render: RenderOptions,
+    /// How conflicts with manual edits are resolved
+    pub strategy: MergeStrategy,
+    /// Apply item reorders from the template
+    pub respect_order: bool,
+    /// Printer used for merged output
+    pub formatter: Formatter,
+    /// Per-item conflict resolution, overriding `strategy` where it decides
+    pub policy: Option<Arc<dyn MergePolicy>>,
+    /// Conflict strategy by item kind, overriding `strategy`
+    pub strategies: StrategyRules,
+    /// Where new items are inserted, by item kind
+    pub placement: PlacementRules,
+    /// Order of the items in the merged file
+    pub ordering: Ordering,
+    /// Language of the output, which decides how it is merged
+    pub language: Language,
+    /// What to do when the existing code isn't valid Rust
+    pub on_parse_error: OnParseError,
+    /// Rust edition of the output [default: that of the crate it is in]
+    pub edition: Option<Edition>,
+    /// Previous generated version of a Rust output, for a three-way merge, see
+    /// [`crate::three_way`]; a directory mirroring the output for several files
+    pub base: Option<PathBuf>,
+    /// Receiver of progress events, see [`crate::events`]
+    pub events: Option<Arc<dyn EventSink>>,
+    /// How existing items are paired with template items [default: by name], see [`crate::matching`]
+    pub matcher: Option<Arc<dyn ItemMatcher>>,
+    /// How generics take part in matching items, see [`crate::matching`]
+    pub generics: GenericsMatching,
+    /// How paired items are compared and renames detected, see [`crate::matching`]
+    pub comparison: Comparison,
+    /// Write a provenance header on top of Rust outputs, see [`crate::provenance`]
+    pub provenance: bool,
+}
+
+/// What to do when the existing code of a Rust output doesn't parse
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnParseError {
+    /// Abort generation
+    Fail,
+    /// Leave the existing code as it is
+    Skip,
+    /// Merge line by line, see [`crate::line_merge`]
+    TextMerge,
+    /// Replace the existing code with the generated code, the caller backs it up
+    OverwriteWithBackup,
+}
+
+/// Language of the generated output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    /// Rust, merged item by item
+    Rust,
+    /// Any other text, merged by keep regions, see [`crate::regions`]
+    Text,
+    /// Markdown, merged section by section, see [`crate::markdown`]
+    Markdown,
+    /// TOML, merged key by key, see [`crate::cargo_toml`]
+    Toml,
+}
+
+impl Language {
+    /// Language a generated file is merged as, from its extension
+    pub fn of_path(path: &Path) -> Option<Language> {
+        match path.extension()?.to_str()? {
+            "rs" => Some(Language::Rust),
+            "md" | "markdown" => Some(Language::Markdown),
+            "toml" => Some(Language::Toml),
+            _ => None,
+        }
+    }
+}
+
+impl GenerateOptions {
+    /// Send `event` to the event sink, if any
+    pub fn emit(&self, event: impl FnOnce() -> Event) {
+        if let Some(events) = &self.events {
+            events.emit(event());
+        }
+    }
+
+    /// Run `f` with items matched as these options say, see [`crate::matching`]
+    pub fn matching<T>(&self, f: impl FnOnce() -> T) -> T {
+        with_matcher(self.matcher.clone(), || {
+            with_generics(self.generics, || {
+                with_comparison(self.comparison.clone(), f)
+            })
+        })
+    }
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        GenerateOptions {
+            render: RenderOptions::default(),
+            strategy: MergeStrategy::PreferManual,
+            respect_order: false,
+            formatter: Formatter::Prettyplease,
+            policy: None,
+            strategies: StrategyRules::default(),
+            placement: PlacementRules::default(),
+            ordering: Ordering::default(),
+            language: Language::Rust,
+            on_parse_error: OnParseError::Fail,
+            edition: None,
+            base: None,
+            events: None,
+            matcher: None,
+            generics: GenericsMatching::Name,
+            comparison: Comparison::default(),
+            provenance: false,
+        }
+    }
+}
+
+/// Result of rendering a template and merging it in memory
+#[derive(Debug)]
+pub struct MergedOutput {
+    /// Final source code
+    pub code: String,
+    /// Conflicts that were resolved by the merge strategy, and notes
+    pub conflicts: Vec<Warning>,
+}
 
 /// Generate code from template and merge with existing file if present
 pub fn generate(
     template_path: &Path,
     context_data: HashMap<String, Value>,
     output_path: &Path,
-    strategy: MergeStrategy,
+    options: GenerateOptions,
+) -> Result<String> {
+    let output = generate_cached(
+        &mut TemplateCache::new(),
+        template_path,
+        context_data,
+        output_path,
+        options,
+    )?;
+    Ok(output.code)
+}
+
+/// Same as [`generate`], reusing compiled templates from `cache`
+pub fn generate_cached(
+    cache: &mut TemplateCache,
+    template_path: &Path,
+    context_data: HashMap<String, Value>,
+    output_path: &Path,
+    options: GenerateOptions,
+) -> Result<MergedOutput> {
+    options.emit(|| Event::RenderStarted {
+        template: template_path.to_path_buf(),
+    });
+    let generated_code = render_cached(cache, template_path, &context_data, options.render)?;
+
+    if split_files(&generated_code)?.is_some() {
+        anyhow::bail!(
+            "Template {} emits several files, use `generate` with an output directory",
+            template_path.display()
+        );
+    }
+
+    let provenance = provenance_of(template_path, &context_data, &options)?;
+    merge_into_file(
+        generated_code,
+        output_path,
+        Some(output_path),
+        &options,
+        provenance.as_ref(),
+    )
+    .map_err(|error| locate_in_template(error, template_path))
+}
+
+/// Render a template file as is, reusing its compiled version from `cache`
+///
+/// Only Tera templates are cached, other engines render from scratch.
+pub fn render_cached(
+    cache: &mut TemplateCache,
+    template_path: &Path,
+    context_data: &HashMap<String, Value>,
+    render: RenderOptions,
 ) -> Result<String> {
-    // Load and render template
+    if render.engine != Engine::Tera {
+        return render_template_file(template_path, context_data, render);
+    }
+
     let template_name = template_path
         .file_name()
         .and_then(|n| n.to_str())
         .context("Invalid template file name")?;
+    let template_content = read_source(template_path)?;
 
-    let template_content =
-        fs::read_to_string(template_path).context("Failed to read template file")?;
+    time(Stage::Render, || {
+        let engine = cache.get_or_compile_file(template_path, &template_content)?;
+        engine.render_with(template_name, context_data, render.lenient)
+    })
+}
 
-    let engine = TemplateEngine::from_string(template_name, &template_content)?;
-    let generated_code = engine.render(template_name, &context_data)?;
+/// Generate every file of a template and merge each with its existing counterpart
+///
+/// Template directories (see [`crate::tree`]) and templates with `{% file %}`
+/// blocks resolve their paths against `output_path` as a directory; other
+/// templates produce `output_path` itself. Only Rust files are merged, other
+/// files are created when missing and otherwise left untouched.
+///
+/// `existing_path` merges a single-file template into another file than
+/// `output_path`, such as `-` for stdin.
+pub fn generate_files(
+    template_path: &Path,
+    context_data: HashMap<String, Value>,
+    output_path: &Path,
+    existing_path: Option<&Path>,
+    options: GenerateOptions,
+) -> Result<Vec<RenderedFile>> {
+    let several_files = || {
+        anyhow::anyhow!(
+            "Template {} generates several files, it can't merge into --existing",
+            template_path.display()
+        )
+    };
 
-    // Parse generated code
-    let generated_ast =
-        ParsedFile::parse(&generated_code).context("Failed to parse generated code")?;
+    options.emit(|| Event::RenderStarted {
+        template: template_path.to_path_buf(),
+    });
+    let provenance = provenance_of(template_path, &context_data, &options)?;
+    let files = if template_path.is_dir() {
+        if existing_path.is_some() {
+            return Err(several_files());
+        }
+        if options.render.engine != Engine::Tera {
+            anyhow::bail!("Template directories are only supported with the tera engine");
+        }
+        time(Stage::Render, || {
+            render_tree(template_path, &context_data, options.render.lenient)
+        })?
+    } else {
+        let generated_code = render_template_file(template_path, &context_data, options.render)?;
 
-    // Check if output file exists
-    let merged_code = if output_path.exists() {
-        // Read and parse existing file
-        let existing_code =
-            fs::read_to_string(output_path).context("Failed to read existing file")?;
+        let Some(files) = split_files(&generated_code)? else {
+            // Output to stdout has no existing file unless one is given
+            let existing_path = existing_path.or((!is_stdio(output_path)).then_some(output_path));
+            let output = merge_into_file(
+                generated_code,
+                output_path,
+                existing_path,
+                &options,
+                provenance.as_ref(),
+            )
+            .map_err(|error| locate_in_template(error, template_path))?;
+            return Ok(vec![RenderedFile {
+                path: output_path.to_path_buf(),
+                code: output.code,
+                conflicts: output.conflicts,
+            }]);
+        };
+        if existing_path.is_some() {
+            return Err(several_files());
+        }
+        files
+    };
 
-        let existing_ast =
-            ParsedFile::parse(&existing_code).context("Failed to parse existing file")?;
+    files
+        .into_iter()
+        .map(|file| {
+            let path = output_path.join(&file.path);
+            // Other files are merged by keep regions in text mode, else left alone
+            let language = Language::of_path(&path)
+                .or((options.language == Language::Text).then_some(Language::Text));
+            let output = match language {
+                Some(language) => {
+                    let options = GenerateOptions {
+                        language,
+                        base: options
+                            .base
+                            .as_ref()
+                            .map(|base| base.join(&file.path))
+                            .filter(|base| base.exists()),
+                        ..options.clone()
+                    };
+                    merge_into_file(file.code, &path, Some(&path), &options, provenance.as_ref())
+                        .with_context(|| format!("Failed to generate {}", path.display()))?
+                }
+                None if path.exists() => MergedOutput {
+                    code: fs::read_to_string(&path).context("Failed to read existing file")?,
+                    conflicts: Vec::new(),
+                },
+                None => MergedOutput {
+                    code: file.code,
+                    conflicts: Vec::new(),
+                },
+            };
+            Ok(RenderedFile {
+                path,
+                code: output.code,
+                conflicts: output.conflicts,
+            })
+        })
+        .collect()
+}
 
-        // Compute patch
-        let patch = compute_patch(&existing_ast.items, &generated_ast.items)?;
+/// Merge generated code into the file at `existing_path`, if it exists
+///
+/// The rustfmt config is looked up from `output_path`. Rust outputs get the
+/// `provenance` header in place of the one the existing file has.
+fn merge_into_file(
+    generated_code: String,
+    output_path: &Path,
+    existing_path: Option<&Path>,
+    options: &GenerateOptions,
+    provenance: Option<&Provenance>,
+) -> Result<MergedOutput> {
+    let provenance = provenance.filter(|_| options.language == Language::Rust);
 
-        // Merge changes
-        let merge_result = merge_patch(&existing_ast.items, &patch, strategy)?;
+    // Read existing file if present
+    let existing_code = match existing_path {
+        Some(path) if source_exists(path) => {
+            Some(read_source(path).context("Failed to read existing file")?)
+        }
+        _ => None,
+    };
+    let existing_code = match provenance {
+        Some(_) => existing_code.map(|code| strip_header(&code).to_string()),
+        None => existing_code,
+    };
 
-        if !merge_result.conflicts.is_empty() && strategy == MergeStrategy::FailOnConflict {
-            anyhow::bail!(
-                "Merge conflicts detected:\n{}",
-                merge_result.conflicts.join("\n")
-            );
+    let mut output = merge_generated(
+        generated_code,
+        existing_code.as_deref(),
+        options.clone(),
+        output_path.parent(),
+    )?;
+    if let Some(provenance) = provenance {
+        output.code = provenance.apply(&output.code);
+    }
+
+    // Print warnings for conflicts
+    for conflict in &output.conflicts {
+        eprintln!("Warning: {}", conflict);
+    }
+
+    Ok(output)
+}
+
+/// Provenance of the outputs of a template, when enabled and the template is a file on disk
+fn provenance_of(
+    template_path: &Path,
+    context_data: &HashMap<String, Value>,
+    options: &GenerateOptions,
+) -> Result<Option<Provenance>> {
+    if !options.provenance || is_stdio(template_path) {
+        return Ok(None);
+    }
+    Provenance::new(template_path, context_data).map(Some)
+}
+
+/// Map a parse error in generated code back to the template file it was rendered from
+fn locate_in_template(error: anyhow::Error, template_path: &Path) -> anyhow::Error {
+    // Stdin was consumed by rendering
+    if is_stdio(template_path) {
+        return error;
+    }
+    match fs::read_to_string(template_path) {
+        Ok(template) => with_template(error, &template),
+        Err(_) => error,
+    }
+}
+
+/// Render a template file with the given context, without merging
+pub fn render_template_file(
+    template_path: &Path,
+    context_data: &HashMap<String, Value>,
+    render: RenderOptions,
+) -> Result<String> {
+    let template_name = template_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("Invalid template file name")?;
+
+    let template_content = read_source(template_path)?;
+
+    time(Stage::Render, || {
+        let backend = render.engine.from_file(template_path, &template_content)?;
+        backend.render_template(template_name, context_data, render.lenient)
+    })
+}
+
+/// Render a template and merge the result into existing code, without touching the filesystem
+///
+/// `project_dir` is only used to locate the rustfmt config.
+pub fn render_and_merge(
+    template_name: &str,
+    template_content: &str,
+    context_data: &HashMap<String, Value>,
+    existing_code: Option<&str>,
+    options: GenerateOptions,
+    project_dir: Option<&Path>,
+) -> Result<MergedOutput> {
+    // Render template
+    options.emit(|| Event::RenderStarted {
+        template: PathBuf::from(template_name),
+    });
+    let generated_code = time(Stage::Render, || {
+        let backend = options
+            .render
+            .engine
+            .from_string(template_name, template_content)?;
+        backend.render_template(template_name, context_data, options.render.lenient)
+    })?;
+
+    merge_generated(generated_code, existing_code, options, project_dir)
+        .map_err(|error| with_template(error, template_content))
+}
+
+/// Merge rendered template output into existing code
+pub fn merge_generated(
+    generated_code: String,
+    existing_code: Option<&str>,
+    options: GenerateOptions,
+    project_dir: Option<&Path>,
+) -> Result<MergedOutput> {
+    let events = options.events.clone();
+    let output =
+        options.matching(|| merge_output(generated_code, existing_code, &options, project_dir))?;
+    if let Some(events) = events {
+        for conflict in &output.conflicts {
+            events.emit(Event::ConflictFound {
+                message: conflict.to_string(),
+            });
         }
+    }
+    Ok(output)
+}
 
-        // Print warnings for conflicts
-        for conflict in &merge_result.conflicts {
-            eprintln!("Warning: {}", conflict);
+/// [`merge_generated`], without reporting conflicts
+fn merge_output(
+    generated_code: String,
+    existing_code: Option<&str>,
+    options: &GenerateOptions,
+    project_dir: Option<&Path>,
+) -> Result<MergedOutput> {
+    match (options.language, existing_code) {
+        (Language::Rust, _) => {}
+        (Language::Text, Some(existing_code)) => {
+            return time(Stage::Merge, || {
+                merge_regions(&generated_code, existing_code, options.strategy)
+            })
+        }
+        (Language::Markdown, Some(existing_code)) => {
+            return time(Stage::Merge, || {
+                merge_sections(&generated_code, existing_code, options.strategy)
+            })
+        }
+        (Language::Toml, Some(existing_code)) => {
+            return time(Stage::Merge, || {
+                merge_toml(&generated_code, existing_code, options.strategy)
+            })
+        }
+        (_, None) => {
+            return Ok(MergedOutput {
+                code: generated_code,
+                conflicts: Vec::new(),
+            })
         }
+    }
 
-        format_merged_code(merge_result.merged_items)?
-    } else {
+    // Parse generated code
+    let edition = Edition::resolve(options.edition, project_dir);
+    let generated_ast = time(Stage::ParseGenerated, || {
+        ParsedFile::parse_edition(&generated_code, edition)
+    })
+    .map_err(|error| with_origin(error, GENERATED_CODE))
+    .context("Failed to parse generated code")?;
+
+    let Some(existing_code) = existing_code else {
         // No existing file, use generated code as-is
-        generated_code
+        options.emit(|| Event::Parsed {
+            generated: generated_ast.items().len(),
+            existing: 0,
+        });
+        return Ok(MergedOutput {
+            code: generated_code,
+            conflicts: Vec::new(),
+        });
     };
 
-    Ok(merged_code)
+    // Parse existing code
+    let existing_ast = match time(Stage::ParseExisting, || {
+        ParsedFile::parse_edition(existing_code, edition)
+    }) {
+        Ok(existing_ast) => existing_ast,
+        Err(error) => {
+            let error = with_origin(error, "existing file");
+            let Some(diagnostic) = error.downcast_ref::<ParseDiagnostic>() else {
+                return Err(error.context("Failed to parse existing file"));
+            };
+            let problem = format!(
+                "Existing file doesn't parse ({} at line {})",
+                diagnostic.message, diagnostic.line
+            );
+            return match options.on_parse_error {
+                OnParseError::Fail => Err(error.context("Failed to parse existing file")),
+                OnParseError::Skip => Ok(MergedOutput {
+                    code: existing_code.to_string(),
+                    conflicts: vec![Warning::note(format!("{}, left unchanged", problem))],
+                }),
+                OnParseError::TextMerge => {
+                    let mut output = time(Stage::Merge, || {
+                        merge_lines(&generated_code, existing_code, options.strategy)
+                    })?;
+                    output.conflicts.insert(
+                        0,
+                        Warning::note(format!("{}, merged line by line", problem)),
+                    );
+                    Ok(output)
+                }
+                OnParseError::OverwriteWithBackup => Ok(MergedOutput {
+                    code: generated_code,
+                    conflicts: vec![Warning::note(format!(
+                        "{}, overwritten by template",
+                        problem
+                    ))],
+                }),
+            };
+        }
+    };
+
+    options.emit(|| Event::Parsed {
+        generated: generated_ast.items().len(),
+        existing: existing_ast.items().len(),
+    });
+
+    // Take the template's changes since the base where nothing was edited by hand
+    let base_ast = match &options.base {
+        Some(base) => {
+            let source = fs::read_to_string(base)
+                .with_context(|| format!("Failed to read base file {}", base.display()))?;
+            let parsed = time(Stage::ParseExisting, || {
+                ParsedFile::parse_edition(&source, edition)
+            })
+            .map_err(|error| with_origin(error, &base.display().to_string()))
+            .context("Failed to parse base file")?;
+            Some(parsed)
+        }
+        None => None,
+    };
+    let rebased = base_ast.as_ref().map(|base_ast| {
+        apply_unedited(
+            base_ast.items(),
+            existing_ast.items(),
+            generated_ast.items(),
+        )
+    });
+    let existing_items = rebased.as_deref().unwrap_or(existing_ast.items());
+
+    // Compute patch
+    let patch = time(Stage::Diff, || {
+        let mut patch = compute_patch(existing_items, generated_ast.items())?;
+        if let Some(base_ast) = &base_ast {
+            narrow_patch(&mut patch, base_ast.items());
+        }
+        Ok::<_, anyhow::Error>(patch)
+    })?;
+    options.emit(|| Event::DiffComputed {
+        ops: patch
+            .operations
+            .iter()
+            .filter(|op| !matches!(op, PatchOp::Keep { .. }))
+            .count(),
+    });
+
+    // Merge changes
+    let (header, mut conflicts) = time(Stage::Merge, || {
+        merge_file_header(
+            &existing_ast.syntax_tree,
+            &generated_ast.syntax_tree,
+            options.strategy,
+        )
+    });
+    let merge_result = time(Stage::Merge, || {
+        merge_patch_with_policy(
+            existing_items,
+            &patch,
+            options.strategy,
+            options.respect_order,
+            options.policy.as_deref(),
+            &options.strategies,
+            &options.placement,
+        )
+    })?;
+
+    conflicts.extend(merge_result.conflicts);
+
+    // `fail`, as the strategy or the rule of an item's kind, leaves conflicts unresolved
+    let unresolved: Vec<String> = conflicts
+        .iter()
+        .filter(|conflict| conflict.kind == WarningKind::Unresolved)
+        .map(ToString::to_string)
+        .collect();
+    if !unresolved.is_empty() {
+        anyhow::bail!("Merge conflicts detected:\n{}", unresolved.join("\n"));
+    }
+
+    let code = time(Stage::Format, || {
+        format_merged_code(
+            header,
+            merge_result.merged_items,
+            &options.ordering,
+            options.formatter,
+            edition,
+            project_dir,
+        )
+    })?;
+    let code = restore_kept_items(&code, &existing_ast.syntax_tree, existing_code);
+
+    Ok(MergedOutput { code, conflicts })
+}
+
+/// Check whether the file at `output_path` already matches `generated`
+///
+/// Formatting differences are ignored when both versions parse as `edition`.
+pub fn is_up_to_date(output_path: &Path, generated: &str, edition: Edition) -> Result<bool> {
+    if !output_path.exists() {
+        return Ok(false);
+    }
+
+    let current = fs::read_to_string(output_path).context("Failed to read existing file")?;
+
+    Ok(same_code(&current, generated, edition))
+}
+
+/// Compare two versions of a file, ignoring formatting when both parse as `edition`
+pub fn same_code(a: &str, b: &str, edition: Edition) -> bool {
+    match (
+        ParsedFile::parse_edition(a, edition),
+        ParsedFile::parse_edition(b, edition),
+    ) {
+        (Ok(a), Ok(b)) => a.syntax_tree == b.syntax_tree,
+        _ => a == b,
+    }
 }
 
 /// Show diff between generated code and existing file
@@ -79,32 +692,42 @@ pub fn show_diff(
     context_data: HashMap<String, Value>,
     existing_path: &Path,
     verbose: bool,
+    render: RenderOptions,
+    edition: Edition,
 ) -> Result<String> {
-    // Load and render template
-    let template_name = template_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .context("Invalid template file name")?;
-
-    let template_content =
-        fs::read_to_string(template_path).context("Failed to read template file")?;
+    // Render template
+    let generated_code = render_template_file(template_path, &context_data, render)?;
 
-    let engine = TemplateEngine::from_string(template_name, &template_content)?;
-    let generated_code = engine.render(template_name, &context_data)?;
+    // Read existing file, without the tool-owned provenance header
+    let existing_code = read_source(existing_path).context("Failed to read existing file")?;
 
-    // Parse generated code
-    let generated_ast = ParsedFile::parse(&generated_code)?;
-
-    // Read and parse existing file
-    let existing_code =
-        fs::read_to_string(existing_path).context("Failed to read existing file")?;
+    diff_sources(
+        strip_header(&existing_code),
+        &generated_code,
+        verbose,
+        edition,
+    )
+}
 
-    let existing_ast = ParsedFile::parse(&existing_code)?;
+/// Structurally diff two versions of a Rust file
+///
+/// Prints the change summary and a text diff, or the patch operations when
+/// `verbose`. Both versions are parsed as `edition`.
+pub fn diff_sources(
+    old_code: &str,
+    new_code: &str,
+    verbose: bool,
+    edition: Edition,
+) -> Result<String> {
+    // Parse both versions
+    let old_ast = ParsedFile::parse_edition(old_code, edition)?;
+    let new_ast = ParsedFile::parse_edition(new_code, edition)?;
 
     // Compute patch
-    let patch = compute_patch(&existing_ast.items, &generated_ast.items)?;
+    let patch = compute_patch(old_ast.items(), new_ast.items())?;
+    let header = describe_file_header(&old_ast.syntax_tree, &new_ast.syntax_tree);
 
-    if patch.is_empty() {
+    if patch.is_empty() && header.is_none() {
         return Ok("No differences found.".to_string());
     }
 
@@ -117,47 +740,118 @@ pub fn show_diff(
             diff_output.push_str(&format!("{:?}\n", op));
         }
     } else {
-        // Show text-based diff
-        diff_output = crate::diff::compute_text_diff(&existing_code, &generated_code);
+        // Show a change summary followed by the text-based diff
+        for line in header.iter().cloned().chain(patch.summary()) {
+            diff_output.push_str(&format!("{}\n", line));
+        }
+
+        let type_changes = field_type_changes(&patch, old_ast.items());
+        if !type_changes.is_empty() {
+            diff_output.push_str("\n⚠ Field types changed, code using these fields may break:\n");
+            for change in &type_changes {
+                diff_output.push_str(&format!("  - {}\n", change));
+            }
+        }
+        diff_output.push('\n');
+        diff_output.push_str(&crate::diff::compute_text_diff(old_code, new_code));
     }
 
     Ok(diff_output)
 }
 
-/// Check for conflicts without applying changes
+/// Outcome of a conflict check
+#[derive(Debug)]
+pub struct CheckReport {
+    /// Human-readable summary of the pending template changes
+    pub changes: Vec<String>,
+    /// Struct fields the template retypes, see [`field_type_changes`]
+    pub type_changes: Vec<FieldTypeChange>,
+    /// Conflicts that would block the merge
+    pub conflicts: Vec<String>,
+}
+
+/// Check for conflicts without applying changes, parsing both versions as `edition`
 pub fn check_conflicts(
     template_path: &Path,
     context_data: HashMap<String, Value>,
     existing_path: &Path,
-) -> Result<Vec<String>> {
-    // Load and render template
-    let template_name = template_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .context("Invalid template file name")?;
-
-    let template_content =
-        fs::read_to_string(template_path).context("Failed to read template file")?;
-
-    let engine = TemplateEngine::from_string(template_name, &template_content)?;
-    let generated_code = engine.render(template_name, &context_data)?;
+    render: RenderOptions,
+    edition: Edition,
+) -> Result<CheckReport> {
+    // Render template
+    let generated_code = render_template_file(template_path, &context_data, render)?;
 
     // Parse generated code
-    let generated_ast = ParsedFile::parse(&generated_code)?;
+    let generated_ast = ParsedFile::parse_edition(&generated_code, edition)?;
 
     // Read and parse existing file
-    let existing_code =
-        fs::read_to_string(existing_path).context("Failed to read existing file")?;
+    let existing_code = read_source(existing_path).context("Failed to read existing file")?;
 
-    let existing_ast = ParsedFile::parse(&existing_code)?;
+    let existing_ast = ParsedFile::parse_edition(&existing_code, edition)?;
 
     // Compute patch
-    let patch = compute_patch(&existing_ast.items, &generated_ast.items)?;
+    let patch = compute_patch(existing_ast.items(), generated_ast.items())?;
 
     // Try merge with FailOnConflict strategy
-    let merge_result = merge_patch(&existing_ast.items, &patch, MergeStrategy::FailOnConflict)?;
+    let (_, mut conflicts) = merge_file_header(
+        &existing_ast.syntax_tree,
+        &generated_ast.syntax_tree,
+        MergeStrategy::FailOnConflict,
+    );
+    let merge_result = merge_patch(
+        existing_ast.items(),
+        &patch,
+        MergeStrategy::FailOnConflict,
+        false,
+    )?;
+    conflicts.extend(merge_result.conflicts);
 
-    Ok(merge_result.conflicts)
+    let header = describe_file_header(&existing_ast.syntax_tree, &generated_ast.syntax_tree);
+    Ok(CheckReport {
+        changes: header.into_iter().chain(patch.summary()).collect(),
+        type_changes: field_type_changes(&patch, existing_ast.items()),
+        conflicts: messages(&conflicts),
+    })
+}
+
+/// Explain, per item, what merging the template into an existing file would do
+///
+/// A missing file is treated as empty.
+pub fn explain(
+    template_path: &Path,
+    context_data: HashMap<String, Value>,
+    existing_path: &Path,
+    options: GenerateOptions,
+) -> Result<Vec<ItemExplanation>> {
+    // Render template
+    let generated_code = render_template_file(template_path, &context_data, options.render)?;
+    let edition = Edition::resolve(options.edition, existing_path.parent());
+
+    // Parse generated code
+    let generated_ast = ParsedFile::parse_edition(&generated_code, edition)?;
+
+    // Read and parse existing file
+    let existing_code = if source_exists(existing_path) {
+        read_source(existing_path).context("Failed to read existing file")?
+    } else {
+        String::new()
+    };
+
+    let existing_ast = ParsedFile::parse_edition(&existing_code, edition)?;
+
+    options.matching(|| {
+        // Compute patch
+        let patch = compute_patch(existing_ast.items(), generated_ast.items())?;
+
+        explain_patch(
+            existing_ast.items(),
+            &patch,
+            options.strategy,
+            options.respect_order,
+            options.policy.as_deref(),
+            &options.strategies,
+        )
+    })
 }
 
 #[cfg(test)]
@@ -182,10 +876,398 @@ mod tests {
             template_file.path(),
             context,
             output_path,
-            MergeStrategy::PreferManual,
+            GenerateOptions::default(),
         )?;
 
         assert!(result.contains("fn test_fn"));
         Ok(())
     }
+
+    #[test]
+    fn test_render_and_merge_in_memory() -> Result<()> {
+        let context = [("name".to_string(), json!("generated"))]
+            .into_iter()
+            .collect();
+
+        let output = render_and_merge(
+            "t",
+            "fn {{ name }}() {}",
+            &context,
+            Some("fn manual() {}"),
+            GenerateOptions::default(),
+            None,
+        )?;
+
+        assert!(output.code.contains("fn manual()"));
+        assert!(output.code.contains("fn generated()"));
+        assert_eq!(output.conflicts.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_existing_text() -> Result<()> {
+        let existing = "pub struct User { pub id: u64, pub name: String }\n\n\
+                        fn check(user: &User) -> bool {\n    \
+                            // ids start at 1\n    \
+                            user.id > 0\n\
+                        }\n\n\
+                        fn old() -> u8 { 1 }\n";
+        let generated = "pub struct User {\n    pub id: u64,\n    pub name: String,\n}\n\
+                         fn check(user: &User) -> bool { user.id > 0 }\n\
+                         fn old() -> u8 { 2 }\n";
+
+        let output = merge_generated(
+            generated.to_string(),
+            Some(existing),
+            GenerateOptions {
+                strategy: MergeStrategy::PreferTemplate,
+                ..GenerateOptions::default()
+            },
+            None,
+        )?;
+
+        assert_eq!(
+            output.code,
+            "pub struct User { pub id: u64, pub name: String }\n\
+             fn check(user: &User) -> bool {\n    \
+                 // ids start at 1\n    \
+                 user.id > 0\n\
+             }\n\
+             fn old() -> u8 {\n    2\n}\n"
+        );
+        // Only `old` differs, the others only by layout and trailing commas
+        assert_eq!(
+            output.conflicts,
+            ["Item 'old' has manual changes, overridden by template"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_unparsable_existing() -> Result<()> {
+        let existing = "fn manual() {\n    let x = 1\n}\n";
+        let merge = |on_parse_error| {
+            merge_generated(
+                "fn generated() {}\n".to_string(),
+                Some(existing),
+                GenerateOptions {
+                    on_parse_error,
+                    ..GenerateOptions::default()
+                },
+                None,
+            )
+        };
+
+        assert!(merge(OnParseError::Fail).is_err());
+        let output = merge(OnParseError::Skip)?;
+        assert_eq!(output.code, existing);
+        assert_eq!(
+            output.conflicts,
+            vec!["Existing file doesn't parse (expected `;` at line 3), left unchanged"]
+        );
+        let output = merge(OnParseError::TextMerge)?;
+        assert_eq!(output.code, existing);
+        assert_eq!(
+            output.conflicts,
+            vec![
+                "Existing file doesn't parse (expected `;` at line 3), merged line by line",
+                "Lines 1-3 have manual changes, template update skipped"
+            ]
+        );
+        assert_eq!(
+            merge(OnParseError::OverwriteWithBackup)?.code,
+            "fn generated() {}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_with_base() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let base = dir.path().join("user.rs");
+        fs::write(
+            &base,
+            "pub struct User {\n    pub id: u64,\n}\nfn edited() -> u8 {\n    1\n}\nfn dropped() {}\nfn both() -> u8 {\n    1\n}\n",
+        )?;
+        let existing = "pub struct User {\n    pub id: u64,\n}\nfn edited() -> u8 {\n    2\n}\nfn dropped() {}\nfn both() -> u8 {\n    2\n}\nfn manual() {}\n";
+        let generated = "pub struct User {\n    pub id: u64,\n    pub name: String,\n}\nfn edited() -> u8 {\n    1\n}\nfn both() -> u8 {\n    3\n}\n";
+
+        let output = merge_generated(
+            generated.to_string(),
+            Some(existing),
+            GenerateOptions {
+                base: Some(base),
+                ..GenerateOptions::default()
+            },
+            None,
+        )?;
+        assert_eq!(
+            output.code,
+            "pub struct User {\n    pub id: u64,\n    pub name: String,\n}\nfn edited() -> u8 {\n    2\n}\nfn both() -> u8 {\n    2\n}\nfn manual() {}\n"
+        );
+        assert_eq!(
+            output.conflicts,
+            vec!["Item 'both' has manual changes, template update skipped"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generics_matching_per_run() -> Result<()> {
+        let merge = |generics| {
+            merge_generated(
+                "pub struct Id(u64);\n".to_string(),
+                Some("pub struct Id<T>(T);\n"),
+                GenerateOptions {
+                    generics,
+                    ..GenerateOptions::default()
+                },
+                None,
+            )
+        };
+
+        assert_eq!(
+            merge(GenericsMatching::Name)?.code,
+            "pub struct Id<T>(T);\n"
+        );
+        assert_eq!(
+            merge(GenericsMatching::Params)?.code,
+            "pub struct Id(u64);\npub struct Id<T>(T);\n"
+        );
+        assert_eq!(
+            merge(GenericsMatching::Name)?.code,
+            "pub struct Id<T>(T);\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_comparison_per_run() -> Result<()> {
+        let merge = |threshold| {
+            merge_generated(
+                "fn fetch_user(id: u64) -> Option<u64> {\n    Some(id)\n}\n".to_string(),
+                Some("fn load_user(id: u64) -> Option<u64> {\n    Some(id)\n}\n"),
+                GenerateOptions {
+                    comparison: Comparison {
+                        threshold,
+                        ..Comparison::default()
+                    },
+                    ..GenerateOptions::default()
+                },
+                None,
+            )
+        };
+
+        // Without a threshold the dropped function is kept next to the added one,
+        // with one it is a rename, which the manual version wins
+        assert!(merge(None)?.code.contains("fn fetch_user"));
+        let renamed = merge(Some(0.8))?;
+        assert!(!renamed.code.contains("fn fetch_user"));
+        assert_eq!(
+            renamed.conflicts,
+            vec!["Item 'load_user' was renamed to 'fetch_user' by template, manual version kept"]
+        );
+        assert!(merge(None)?.code.contains("fn fetch_user"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_docs_per_run() -> Result<()> {
+        let merge = |ignore_docs| {
+            merge_generated(
+                "/// Generated\nfn check() {}\n".to_string(),
+                Some("/// Written by hand\nfn check() {}\n"),
+                GenerateOptions {
+                    strategy: MergeStrategy::PreferTemplate,
+                    comparison: Comparison {
+                        ignored_attrs: crate::matching::ignored_attrs(ignore_docs, &[]),
+                        ..Comparison::default()
+                    },
+                    ..GenerateOptions::default()
+                },
+                None,
+            )
+        };
+
+        assert_eq!(merge(true)?.code, "/// Written by hand\nfn check() {}\n");
+        assert_eq!(merge(false)?.code, "/// Generated\nfn check() {}\n");
+        assert_eq!(merge(true)?.code, "/// Written by hand\nfn check() {}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fail_rule_under_manual_strategy() -> Result<()> {
+        let config = crate::config::Config {
+            strategies: [("struct".to_string(), "fail".to_string())].into(),
+            ..crate::config::Config::default()
+        };
+        let options = || -> Result<GenerateOptions> {
+            Ok(GenerateOptions {
+                strategy: MergeStrategy::PreferManual,
+                strategies: StrategyRules::from_config(&config)?,
+                ..GenerateOptions::default()
+            })
+        };
+        let existing = "pub struct User {\n    pub id: u64,\n}\n";
+
+        let error = merge_generated(
+            "pub struct User {\n    pub name: String,\n}\n".to_string(),
+            Some(existing),
+            options()?,
+            None,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("Item 'User'"));
+
+        let error = merge_generated(
+            "pub fn other() {}\n".to_string(),
+            Some(existing),
+            options()?,
+            None,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("Item 'User' was deleted"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_files_with_existing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let template_path = dir.path().join("model.tera");
+        fs::write(&template_path, "pub struct {{ name }};")?;
+        let existing_path = dir.path().join("buffer.rs");
+        fs::write(&existing_path, "fn manual() {}\n")?;
+
+        let context: HashMap<String, Value> =
+            [("name".to_string(), json!("User"))].into_iter().collect();
+        let files = generate_files(
+            &template_path,
+            context.clone(),
+            Path::new("-"),
+            Some(&existing_path),
+            GenerateOptions::default(),
+        )?;
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, Path::new("-"));
+        assert!(files[0].code.contains("fn manual()"));
+        assert!(files[0].code.contains("pub struct User"));
+
+        let output_dir = dir.path().join("out");
+        assert!(generate_files(
+            dir.path(),
+            context,
+            &output_dir,
+            Some(&existing_path),
+            GenerateOptions::default(),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_files_merges_each_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let template_path = dir.path().join("models.tera");
+        fs::write(
+            &template_path,
+            "{% for name in names %}{% file \"{{ name }}.rs\" %}fn {{ name }}() {}{% endfile %}{% endfor %}",
+        )?;
+        let output_dir = dir.path().join("out");
+        fs::create_dir_all(&output_dir)?;
+        fs::write(output_dir.join("a.rs"), "fn manual() {}\n")?;
+
+        let context = [("names".to_string(), json!(["a", "b"]))]
+            .into_iter()
+            .collect();
+        let files = generate_files(
+            &template_path,
+            context,
+            &output_dir,
+            None,
+            GenerateOptions::default(),
+        )?;
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, output_dir.join("a.rs"));
+        assert!(files[0].code.contains("fn manual()"));
+        assert!(files[0].code.contains("fn a()"));
+        assert_eq!(files[1].path, output_dir.join("b.rs"));
+        assert!(files[1].code.contains("fn b()"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_up_to_date_ignores_formatting() -> Result<()> {
+        let mut output_file = NamedTempFile::new()?;
+        writeln!(output_file, "fn   test_fn( ) {{ }}")?;
+
+        let edition = Edition::default();
+        assert!(is_up_to_date(
+            output_file.path(),
+            "fn test_fn() {}\n",
+            edition
+        )?);
+        assert!(!is_up_to_date(
+            output_file.path(),
+            "fn other_fn() {}\n",
+            edition
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_sources() -> Result<()> {
+        let diff = diff_sources(
+            "fn f() -> u8 { 1 }\n",
+            "fn f() -> u16 { 1 }\nfn g() {}\n",
+            false,
+            Edition::default(),
+        )?;
+        assert!(
+            diff.starts_with("Modify fn `f`: return type changed from u8 to u16\nInsert fn `g`\n")
+        );
+
+        let diff = diff_sources("fn   f() {}", "fn f() {}\n", false, Edition::default())?;
+        assert_eq!(diff, "No differences found.");
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_in_edition_2015() -> Result<()> {
+        let old_code = "fn async() -> u8 { 1 }\n";
+        let new_code = "fn   async() -> u8 { 1 }\nfn g() {}\n";
+
+        assert!(same_code(old_code, "fn async() -> u8 {1}", Edition::E2015));
+        assert!(!same_code(old_code, "fn async() -> u8 {1}", Edition::E2021));
+
+        let diff = diff_sources(old_code, new_code, false, Edition::E2015)?;
+        assert!(diff.starts_with("Insert fn `g`\n"));
+        assert!(diff_sources(old_code, new_code, false, Edition::E2021).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_diff_ignores_provenance_header() -> Result<()> {
+        let mut template_file = NamedTempFile::new()?;
+        writeln!(template_file, "fn f() -> u16 {{ 1 }}")?;
+        let mut existing_file = NamedTempFile::new()?;
+        write!(
+            existing_file,
+            "{} 0.1.0\n// template: t.tera sha256:0\n// context: sha256:0\n\nfn f() -> u8 {{ 1 }}\n",
+            crate::provenance::MARKER
+        )?;
+
+        let diff = show_diff(
+            template_file.path(),
+            HashMap::new(),
+            existing_file.path(),
+            false,
+            RenderOptions::default(),
+            Edition::default(),
+        )?;
+        assert!(diff.contains("return type changed from u8 to u16"));
+        assert!(!diff.contains("@generated"));
+        Ok(())
+    }
 }
@@ -0,0 +1,82 @@
+//! GitHub annotations module
+//!
+//! This module prints check and diff findings as GitHub Actions workflow
+//! commands, such as `::error file=src/user.rs,line=3,endLine=5::…`, which
+//! Actions turns into inline annotations on pull requests without any upload
+//! step. Severities map to `error`, `warning` and `notice`.
+
+use std::fmt::Write;
+
+use crate::check::{Severity, TargetCheck};
+use crate::sarif::rule;
+
+/// Render the findings of the given files as workflow commands, one per line
+pub fn to_annotations(checks: &[TargetCheck]) -> String {
+    let mut output = String::new();
+    for check in checks {
+        let file = escape_property(&check.output.to_string_lossy().replace('\\', "/"));
+        for finding in &check.findings {
+            let command = match finding.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Info => "notice",
+            };
+            let (start, end) = finding.lines.unwrap_or((1, 1));
+            let (_, _, title) = rule(finding.severity);
+            let _ = writeln!(
+                output,
+                "::{} file={},line={},endLine={},title={}::{}",
+                command,
+                file,
+                start,
+                end,
+                escape_property(title),
+                escape_data(&finding.message)
+            );
+        }
+    }
+    output
+}
+
+/// Escape a message for a workflow command
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property value for a workflow command
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::Finding;
+
+    #[test]
+    fn test_to_annotations() {
+        let check = TargetCheck {
+            output: "src/a,b.rs".into(),
+            findings: vec![
+                Finding {
+                    severity: Severity::Error,
+                    message: "Modify fn `a`: 100% changed\nbody".to_string(),
+                    lines: Some((3, 5)),
+                },
+                Finding {
+                    severity: Severity::Info,
+                    message: "Import `std::fmt`".to_string(),
+                    lines: None,
+                },
+            ],
+        };
+
+        assert_eq!(
+            to_annotations(&[check]),
+            "::error file=src/a%2Cb.rs,line=3,endLine=5,title=Template change to code edited by hand::Modify fn `a`: 100%25 changed%0Abody\n\
+             ::notice file=src/a%2Cb.rs,line=1,endLine=1,title=Pending template update::Import `std::fmt`\n"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Golden test module
+//!
+//! This module renders a directory of template cases and compares each one
+//! against its blessed snapshot. Every case is a subdirectory holding
+//! `template.tera`, `context.json` and `expected.rs`.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::diff::compute_text_diff;
+use crate::template::{parse_context, TemplateEngine};
+
+/// Template file of a case
+pub const CASE_TEMPLATE: &str = "template.tera";
+/// Context file of a case
+pub const CASE_CONTEXT: &str = "context.json";
+/// Snapshot file of a case
+pub const CASE_EXPECTED: &str = "expected.rs";
+
+/// Outcome of a single golden case
+#[derive(Debug, PartialEq)]
+pub enum CaseOutcome {
+    /// Rendered output matches the snapshot
+    Passed,
+    /// Rendered output differs from the snapshot
+    Failed { diff: String },
+    /// No snapshot exists yet
+    Missing,
+    /// Snapshot was written with `--update`
+    Updated,
+}
+
+/// Result of running one case directory
+#[derive(Debug)]
+pub struct CaseResult {
+    pub name: String,
+    pub outcome: CaseOutcome,
+}
+
+/// Run every case under `dir`, in name order
+///
+/// With `update`, missing or outdated snapshots are overwritten with the
+/// rendered output.
+pub fn run_cases(dir: &Path, update: bool) -> Result<Vec<CaseResult>> {
+    let mut case_dirs: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read test directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.join(CASE_TEMPLATE).is_file())
+        .collect();
+    case_dirs.sort();
+
+    case_dirs
+        .iter()
+        .map(|case_dir| run_case(case_dir, update))
+        .collect()
+}
+
+/// Render a single case and compare it against its snapshot
+fn run_case(case_dir: &Path, update: bool) -> Result<CaseResult> {
+    let name = case_dir
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("Invalid case directory name")?
+        .to_string();
+
+    let template = fs::read_to_string(case_dir.join(CASE_TEMPLATE))
+        .with_context(|| format!("Failed to read template for case '{}'", name))?;
+    let context_path = case_dir.join(CASE_CONTEXT);
+    let context_data = if context_path.exists() {
+        parse_context(&fs::read_to_string(&context_path)?)
+            .with_context(|| format!("Invalid context for case '{}'", name))?
+    } else {
+        Default::default()
+    };
+
+    let engine = TemplateEngine::from_string(&name, &template)?;
+    let rendered = engine
+        .render(&name, &context_data)
+        .with_context(|| format!("Failed to render case '{}'", name))?;
+
+    let expected_path = case_dir.join(CASE_EXPECTED);
+    let expected = if expected_path.exists() {
+        Some(fs::read_to_string(&expected_path)?)
+    } else {
+        None
+    };
+
+    let outcome = match expected {
+        Some(expected) if expected == rendered => CaseOutcome::Passed,
+        _ if update => {
+            fs::write(&expected_path, &rendered)?;
+            CaseOutcome::Updated
+        }
+        Some(expected) => CaseOutcome::Failed {
+            diff: compute_text_diff(&expected, &rendered),
+        },
+        None => CaseOutcome::Missing,
+    };
+
+    Ok(CaseResult { name, outcome })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_case(dir: &Path, name: &str, expected: Option<&str>) {
+        let case = dir.join(name);
+        fs::create_dir(&case).unwrap();
+        fs::write(case.join(CASE_TEMPLATE), "fn {{ name }}() {}\n").unwrap();
+        fs::write(case.join(CASE_CONTEXT), r#"{"name": "hello"}"#).unwrap();
+        if let Some(expected) = expected {
+            fs::write(case.join(CASE_EXPECTED), expected).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_cases_outcomes() {
+        let dir = tempfile::tempdir().unwrap();
+        write_case(dir.path(), "a_pass", Some("fn hello() {}\n"));
+        write_case(dir.path(), "b_fail", Some("fn other() {}\n"));
+        write_case(dir.path(), "c_missing", None);
+
+        let results = run_cases(dir.path(), false).unwrap();
+        assert_eq!(results[0].outcome, CaseOutcome::Passed);
+        assert!(matches!(results[1].outcome, CaseOutcome::Failed { .. }));
+        assert_eq!(results[2].outcome, CaseOutcome::Missing);
+    }
+
+    #[test]
+    fn test_run_cases_update() {
+        let dir = tempfile::tempdir().unwrap();
+        write_case(dir.path(), "case", Some("fn other() {}\n"));
+
+        let results = run_cases(dir.path(), true).unwrap();
+        assert_eq!(results[0].outcome, CaseOutcome::Updated);
+
+        let results = run_cases(dir.path(), false).unwrap();
+        assert_eq!(results[0].outcome, CaseOutcome::Passed);
+    }
+}
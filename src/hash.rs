@@ -0,0 +1,81 @@
+//! Content hashing helpers
+//!
+//! Hashes written to disk use SHA-256 so they stay stable across tool versions
+//! and machines.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Hex-encoded SHA-256 of `data`
+pub fn sha256_hex(data: impl AsRef<[u8]>) -> String {
+    Sha256::digest(data.as_ref())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Hex-encoded SHA-256 of a file, or of every file under a directory
+///
+/// Directory hashes cover each file's relative path and contents, in sorted
+/// order, skipping `.git`.
+pub fn sha256_path(path: &Path) -> Result<String> {
+    if !path.is_dir() {
+        let content =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        return Ok(sha256_hex(content));
+    }
+
+    let mut hasher = Sha256::new();
+    let entries = WalkDir::new(path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git");
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read {}", path.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(path)?;
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(entry.path())?);
+        hasher.update([0]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_sha256_path_of_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.tera"), "a").unwrap();
+        let before = sha256_path(dir.path()).unwrap();
+
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".git/HEAD"), "ref").unwrap();
+        assert_eq!(sha256_path(dir.path()).unwrap(), before);
+
+        fs::write(dir.path().join("a.tera"), "b").unwrap();
+        assert_ne!(sha256_path(dir.path()).unwrap(), before);
+    }
+}
@@ -0,0 +1,87 @@
+//! Generation hooks module
+//!
+//! This module runs the pre- and post-generation shell commands declared in
+//! the project configuration.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Run hook commands in order, failing on the first non-zero exit
+///
+/// Commands run through the platform shell from `working_dir`. For a single
+/// target, its absolute template and output paths are exported as
+/// `RPT_TEMPLATE` and `RPT_OUTPUT`; batch runs pass `None`.
+pub fn run_hooks(
+    stage: &str,
+    commands: &[String],
+    working_dir: &Path,
+    target: Option<(&Path, &Path)>,
+) -> Result<()> {
+    let target = match target {
+        Some((template, output)) => {
+            Some((std::path::absolute(template)?, std::path::absolute(output)?))
+        }
+        None => None,
+    };
+
+    for command in commands {
+        let mut cmd = shell(command);
+        cmd.current_dir(working_dir);
+        if let Some((template, output)) = &target {
+            cmd.env("RPT_TEMPLATE", template).env("RPT_OUTPUT", output);
+        }
+
+        let status = cmd
+            .status()
+            .with_context(|| format!("Failed to run {} hook `{}`", stage, command))?;
+
+        if !status.success() {
+            anyhow::bail!("{} hook `{}` failed with {}", stage, command, status);
+        }
+    }
+
+    Ok(())
+}
+
+/// Command running `command` through the platform shell
+#[cfg(windows)]
+pub(crate) fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.args(["/C", command]);
+    cmd
+}
+
+/// Command running `command` through the platform shell
+#[cfg(not(windows))]
+pub(crate) fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", command]);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_failing_hook_aborts() {
+        let dir = tempfile::tempdir().unwrap();
+        let commands = vec![
+            "test -n \"$RPT_OUTPUT\"".to_string(),
+            "exit 3".to_string(),
+            "touch never".to_string(),
+        ];
+
+        let result = run_hooks(
+            "pre",
+            &commands,
+            dir.path(),
+            Some((Path::new("t.tera"), Path::new("out.rs"))),
+        );
+
+        assert!(result.is_err());
+        assert!(!dir.path().join("never").exists());
+    }
+}
@@ -0,0 +1,180 @@
+//! Imports module
+//!
+//! This module merges `use` declarations, which are not matched by name like
+//! other items. Imports are compared path by path, so `use std::fmt;` is
+//! already covered by `use std::{fmt, io};`:
+//!
+//! - existing imports are always kept where they are, even when the template
+//!   no longer has them,
+//! - template imports the existing file doesn't cover are added after the
+//!   imports at its top, split into single paths when the file covers some
+//!   of them.
+
+use quote::ToTokens;
+use std::collections::{BTreeSet, HashMap};
+use syn::{Item, ItemUse, UseTree};
+
+use crate::diff::extract_item_name;
+
+/// Imported paths of a `use` declaration, such as `std::fmt`, `std::io::Read as _` or `std::*`
+pub fn import_paths(item: &ItemUse) -> Vec<String> {
+    let mut paths = Vec::new();
+    collect_paths(&item.tree, String::new(), &mut paths);
+    paths
+}
+
+fn collect_paths(tree: &UseTree, prefix: String, paths: &mut Vec<String>) {
+    let join = |name: &dyn std::fmt::Display| match prefix.as_str() {
+        "" => name.to_string(),
+        prefix => format!("{}::{}", prefix, name),
+    };
+
+    match tree {
+        UseTree::Path(path) => collect_paths(&path.tree, join(&path.ident), paths),
+        // `use a::{self}` imports `a`
+        UseTree::Name(name) if name.ident == "self" && !prefix.is_empty() => {
+            paths.push(prefix.clone())
+        }
+        UseTree::Name(name) => paths.push(join(&name.ident)),
+        UseTree::Rename(rename) => {
+            paths.push(format!("{} as {}", join(&rename.ident), rename.rename))
+        }
+        UseTree::Glob(_) => paths.push(join(&"*")),
+        UseTree::Group(group) => {
+            for tree in &group.items {
+                collect_paths(tree, prefix.clone(), paths);
+            }
+        }
+    }
+}
+
+/// Imports of `new_items` that `old_items` don't have
+///
+/// Declarations the old items partly cover are split into one `use` per
+/// missing path.
+pub fn missing_imports(old_items: &[Item], new_items: &[Item]) -> Vec<Item> {
+    let mut known: BTreeSet<String> = uses(old_items).flat_map(import_paths).collect();
+
+    let mut missing = Vec::new();
+    for item in uses(new_items) {
+        let paths = import_paths(item);
+        let new_paths: Vec<&String> = paths.iter().filter(|path| !known.contains(*path)).collect();
+
+        if new_paths.len() == paths.len() {
+            missing.push(Item::Use(item.clone()));
+        } else {
+            let vis = item.vis.to_token_stream();
+            for path in new_paths {
+                if let Ok(item) = syn::parse_str(&format!("{} use {};", vis, path)) {
+                    missing.push(item);
+                }
+            }
+        }
+        known.extend(paths);
+    }
+    missing
+}
+
+/// Put the existing file's imports back among the merged items, then add `new_imports`
+///
+/// Imports that open the existing file stay first; the others go before the
+/// next item of the existing file that is still merged, or first when none
+/// is. New imports go after the imports that open the file.
+pub fn place_imports(merged: Vec<Item>, base_items: &[Item], new_imports: &[Item]) -> Vec<Item> {
+    let merged_names: BTreeSet<String> = merged.iter().filter_map(extract_item_name).collect();
+
+    let leading = base_items
+        .iter()
+        .take_while(|item| extract_item_name(item).is_none())
+        .count();
+
+    let mut anchored: HashMap<String, Vec<&Item>> = HashMap::new();
+    let mut pending: Vec<&Item> = base_items[..leading]
+        .iter()
+        .filter(|item| matches!(item, Item::Use(_)))
+        .collect();
+    let mut placed: Vec<Item> = pending.drain(..).cloned().collect();
+    for item in &base_items[leading..] {
+        match (item, extract_item_name(item)) {
+            (Item::Use(_), _) => pending.push(item),
+            (_, Some(name)) if merged_names.contains(&name) && !pending.is_empty() => {
+                anchored.entry(name).or_default().append(&mut pending);
+            }
+            _ => {}
+        }
+    }
+
+    placed.extend(pending.into_iter().cloned());
+    for item in merged {
+        if let Some(imports) = extract_item_name(&item).and_then(|name| anchored.remove(&name)) {
+            placed.extend(imports.into_iter().cloned());
+        }
+        placed.push(item);
+    }
+
+    insert_imports(&mut placed, new_imports);
+    placed
+}
+
+/// Insert imports after the `use` declarations that open the file, or first
+pub fn insert_imports(items: &mut Vec<Item>, imports: &[Item]) {
+    let leading = items
+        .iter()
+        .take_while(|item| extract_item_name(item).is_none())
+        .count();
+    let position = items[..leading]
+        .iter()
+        .rposition(|item| matches!(item, Item::Use(_)))
+        .map_or(0, |idx| idx + 1);
+    items.splice(position..position, imports.iter().cloned());
+}
+
+/// `use` declarations among the items
+fn uses(items: &[Item]) -> impl Iterator<Item = &ItemUse> {
+    items.iter().filter_map(|item| match item {
+        Item::Use(item) => Some(item),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(code: &str) -> Vec<Item> {
+        syn::parse_file(code).unwrap().items
+    }
+
+    #[test]
+    fn test_missing_imports() {
+        let item: ItemUse =
+            syn::parse_str("use std::{fmt, io::{self, Read as _}, sync::*};").unwrap();
+        assert_eq!(
+            import_paths(&item),
+            vec!["std::fmt", "std::io", "std::io::Read as _", "std::sync::*"]
+        );
+
+        let old = items("use std::{fmt, io};\nuse serde::Serialize;");
+        let new = items(
+            "use std::fmt;\nuse std::collections::{HashMap, HashSet};\npub use serde::{Deserialize, Serialize};",
+        );
+        assert_eq!(
+            missing_imports(&old, &new),
+            items("use std::collections::{HashMap, HashSet};\npub use serde::Deserialize;")
+        );
+    }
+
+    #[test]
+    fn test_place_imports() {
+        let base = items("use std::fmt;\nstruct A;\nuse std::io;\nfn gone() {}\nfn b() {}");
+        let merged = items("fn c() {}\nstruct A;\nfn b() {}");
+        let new = items("use std::collections::HashMap;");
+
+        assert_eq!(
+            place_imports(merged, &base, &new),
+            items(
+                "use std::fmt;\nuse std::collections::HashMap;\nfn c() {}\nstruct A;\nuse std::io;\nfn b() {}"
+            )
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Incremental regeneration module
+//!
+//! This module records the hashes of each manifest target's inputs and output
+//! in `.rpt/cache.json`, so batch runs can skip targets where nothing changed.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backend::RenderOptions;
+use crate::config::{Config, Target};
+use crate::hash::sha256_hex;
+use crate::target_refs::dependencies;
+use crate::template::included_files;
+
+/// Location of the cache file, relative to the config root
+pub const CACHE_FILE: &str = ".rpt/cache.json";
+
+/// Hashes recorded for every target after it was last generated
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RegenerationCache {
+    /// Tool version that wrote the cache; other versions start from scratch
+    version: String,
+    /// Entries keyed by the target's output path as declared in the manifest
+    targets: BTreeMap<String, TargetHashes>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+/// Fingerprint of a target's inputs and output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TargetHashes {
+    pub template: String,
+    pub context: String,
+    pub strategy: String,
+    /// Hash of the settings that change merged output: `rpt.toml`, command-line
+    /// flags and plugin scripts
+    #[serde(default)]
+    pub settings: String,
+    pub output: Option<String>,
+}
+
+impl RegenerationCache {
+    /// Load the cache for a project, starting empty if it is missing or stale
+    pub fn load(config: &Config) -> Self {
+        let path = config.resolve_path(Path::new(CACHE_FILE));
+
+        let cache = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<RegenerationCache>(&content).ok())
+            .filter(|cache| cache.version == env!("CARGO_PKG_VERSION"));
+
+        RegenerationCache {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            targets: cache.map(|cache| cache.targets).unwrap_or_default(),
+            path,
+        }
+    }
+
+    /// Check whether the target's inputs and output match the last generation
+    pub fn is_fresh(&self, target: &Target, hashes: &TargetHashes) -> bool {
+        hashes.output.is_some() && self.targets.get(&key(target)) == Some(hashes)
+    }
+
+    /// Hashes recorded when the target was last generated
+    pub fn recorded(&self, target: &Target) -> Option<&TargetHashes> {
+        self.targets.get(&key(target))
+    }
+
+    /// Record the hashes of a freshly generated target
+    pub fn record(&mut self, target: &Target, hashes: TargetHashes) {
+        self.targets.insert(key(target), hashes);
+    }
+
+    /// Forget targets that are no longer in the manifest, returning their outputs
+    pub fn prune(&mut self, config: &Config) -> Vec<PathBuf> {
+        let declared: Vec<String> = config.targets.iter().map(key).collect();
+        let removed: Vec<String> = self
+            .targets
+            .keys()
+            .filter(|output| !declared.contains(output))
+            .cloned()
+            .collect();
+
+        for output in &removed {
+            self.targets.remove(output);
+        }
+        removed.into_iter().map(PathBuf::from).collect()
+    }
+
+    /// Write the cache back to disk
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// Hash a target's template, context, merge settings and current output
+///
+/// The template hash covers the templates it includes, imports or extends, as
+/// resolved through the overrides and template directories, see
+/// [`included_files`]. Command-line flags count through `render` and the
+/// `config` they were applied to.
+pub fn hash_target(
+    config: &Config,
+    target: &Target,
+    render: RenderOptions,
+) -> Result<TargetHashes> {
+    let read = |path: &Path| {
+        fs::read(config.resolve_path(path))
+            .with_context(|| format!("Failed to read {}", path.display()))
+    };
+
+    let template_path = config.resolve_path(&target.template);
+    let mut template = read(&target.template)?;
+    let included = included_files(&template_path, &String::from_utf8_lossy(&template))?;
+    for (name, file) in included {
+        template.extend(name.as_bytes());
+        template.push(0);
+        template
+            .extend(fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))?);
+        template.push(0);
+    }
+    // Outputs of referenced targets are part of the context
+    let mut context = read(&target.context)?;
+    for dependency in dependencies(config, target)? {
+        if let Ok(output) = fs::read(config.resolve_path(&dependency.output)) {
+            context.extend(output);
+        }
+    }
+
+    Ok(TargetHashes {
+        template: sha256_hex(template),
+        context: sha256_hex(context),
+        strategy: target.strategy.clone(),
+        settings: sha256_hex(merge_settings(config, render)?),
+        output: fs::read(config.resolve_path(&target.output))
+            .ok()
+            .map(sha256_hex),
+    })
+}
+
+/// The settings that change merged output, in a stable form, followed by the
+/// scripts that extend rendering and merging
+fn merge_settings(config: &Config, render: RenderOptions) -> Result<Vec<u8>> {
+    let mut settings = format!(
+        "{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{:?}\n{}\n{}\n{:?}\n{}",
+        config.preserve,
+        config.always_replace,
+        config.strategies,
+        config.placement,
+        config.ordering,
+        config.matching,
+        config.merge_policy,
+        config.overrides_dir,
+        config.edition,
+        config.provenance,
+        config.deterministic,
+        render.engine,
+        render.lenient
+    )
+    .into_bytes();
+
+    let plugins = &config.plugins;
+    let scripts = plugins.filters.iter().chain(&plugins.functions);
+    for script in scripts.chain(&config.merge_policy.script) {
+        let path = config.resolve_path(script);
+        settings.push(0);
+        settings
+            .extend(fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?);
+    }
+    Ok(settings)
+}
+
+fn key(target: &Target) -> String {
+    target.output.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let target = Target {
+            name: None,
+            depends_on: Vec::new(),
+            template: "t.tera".into(),
+            context: "c.json".into(),
+            output: "out.rs".into(),
+            strategy: "manual".to_string(),
+            language: "rust".to_string(),
+        };
+        fs::write(dir.path().join("t.tera"), "fn f() {}").unwrap();
+        fs::write(dir.path().join("c.json"), "{}").unwrap();
+        fs::write(dir.path().join("out.rs"), "fn f() {}").unwrap();
+
+        let mut cache = RegenerationCache::load(&config);
+        let hashes = hash_target(&config, &target, RenderOptions::default()).unwrap();
+        assert!(!cache.is_fresh(&target, &hashes));
+
+        cache.record(&target, hashes);
+        cache.save().unwrap();
+
+        let cache = RegenerationCache::load(&config);
+        assert!(cache.is_fresh(
+            &target,
+            &hash_target(&config, &target, RenderOptions::default()).unwrap()
+        ));
+
+        fs::write(dir.path().join("c.json"), r#"{"changed": true}"#).unwrap();
+        assert!(!cache.is_fresh(
+            &target,
+            &hash_target(&config, &target, RenderOptions::default()).unwrap()
+        ));
+
+        // Partials the template includes and merge settings count as inputs
+        fs::write(dir.path().join("t.tera"), r#"{% include "f.tera" %}"#).unwrap();
+        fs::write(dir.path().join("f.tera"), "fn f() {}").unwrap();
+        let hashes = hash_target(&config, &target, RenderOptions::default()).unwrap();
+        fs::write(dir.path().join("f.tera"), "fn g() {}").unwrap();
+        assert_ne!(
+            hash_target(&config, &target, RenderOptions::default()).unwrap(),
+            hashes
+        );
+        let preserving = Config {
+            preserve: vec!["f".to_string()],
+            ..config.clone()
+        };
+        assert_ne!(
+            hash_target(&preserving, &target, RenderOptions::default())
+                .unwrap()
+                .settings,
+            hash_target(&config, &target, RenderOptions::default())
+                .unwrap()
+                .settings
+        );
+    }
+
+    #[test]
+    fn test_flags_and_scripts_invalidate_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config {
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config.plugins.filters.push("shout.rhai".into());
+        let target = Target {
+            name: None,
+            depends_on: Vec::new(),
+            template: "t.tera".into(),
+            context: "c.json".into(),
+            output: "out.rs".into(),
+            strategy: "manual".to_string(),
+            language: "rust".to_string(),
+        };
+        fs::write(dir.path().join("t.tera"), "fn f() {}").unwrap();
+        fs::write(dir.path().join("c.json"), "{}").unwrap();
+        fs::write(dir.path().join("out.rs"), "fn f() {}").unwrap();
+        fs::write(dir.path().join("shout.rhai"), "fn shout(s) { s }").unwrap();
+
+        let render = RenderOptions::default();
+        let settings =
+            |config: &Config, render| hash_target(config, &target, render).unwrap().settings;
+        let recorded = settings(&config, render);
+        assert_eq!(settings(&config, render), recorded);
+
+        let lenient = RenderOptions {
+            lenient: true,
+            ..render
+        };
+        assert_ne!(settings(&config, lenient), recorded);
+        let mut flagged = config.clone();
+        flagged.deterministic = true;
+        assert_ne!(settings(&flagged, render), recorded);
+        let mut flagged = config.clone();
+        flagged.matching.ignore_docs = true;
+        assert_ne!(settings(&flagged, render), recorded);
+
+        fs::write(dir.path().join("shout.rhai"), "fn shout(s) { s + \"!\" }").unwrap();
+        assert_ne!(settings(&config, render), recorded);
+    }
+}
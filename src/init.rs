@@ -0,0 +1,152 @@
+//! Project scaffolding module
+//!
+//! This module writes the starter layout `init` creates: a struct template and
+//! an impl template under `templates/`, a sample context under `contexts/`, and
+//! an `rpt.toml` whose manifest generates both into `src/`, so
+//! `generate-all` works right away and shows the whole workflow.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files of the starter layout, relative to the project directory
+const FILES: &[(&str, &str)] = &[
+    (
+        "rpt.toml",
+        r#"# Directories searched for templates
+template_dirs = ["templates"]
+
+# Commands run around generation, e.g. "cargo fmt"
+[hooks]
+pre = []
+post = []
+
+# Manifest: run `rust-patchs-templates generate-all` to generate every target
+[[targets]]
+template = "templates/struct.rs.tera"
+context = "contexts/user.json"
+output = "src/user.rs"
+strategy = "manual"
+
+[[targets]]
+template = "templates/impl.rs.tera"
+context = "contexts/user.json"
+output = "src/user_impl.rs"
+strategy = "manual"
+"#,
+    ),
+    (
+        "templates/struct.rs.tera",
+        r#"+++
+description = "Struct with documented fields"
+
+[defaults]
+derives = ["Debug", "Clone", "PartialEq"]
++++
+//! {{ doc }}
+
+#[derive({{ derives | join(sep=", ") }})]
+pub struct {{ name }} {
+{%- for field in fields %}
+    /// {{ field.doc }}
+    pub {{ field.name }}: {{ field.type }},
+{%- endfor %}
+}
+"#,
+    ),
+    (
+        "templates/impl.rs.tera",
+        r#"+++
+description = "Constructor and getters for a struct"
++++
+//! Methods of {{ name }}
+
+use crate::user::{{ name }};
+
+impl {{ name }} {
+    /// Create a new {{ name }}
+    pub fn new({% for field in fields %}{{ field.name }}: {{ field.type }}{% if not loop.last %}, {% endif %}{% endfor %}) -> Self {
+        Self { {% for field in fields %}{{ field.name }}{% if not loop.last %}, {% endif %}{% endfor %} }
+    }
+{%- for field in fields %}
+
+    /// {{ field.doc }}
+    pub fn {{ field.name }}(&self) -> &{{ field.type }} {
+        &self.{{ field.name }}
+    }
+{%- endfor %}
+}
+"#,
+    ),
+    (
+        "contexts/user.json",
+        r#"{
+  "name": "User",
+  "doc": "A registered user",
+  "fields": [
+    { "name": "id", "type": "u64", "doc": "Unique identifier" },
+    { "name": "name", "type": "String", "doc": "Display name" },
+    { "name": "email", "type": "String", "doc": "Contact address" }
+  ]
+}
+"#,
+    ),
+];
+
+/// Write the starter layout into `dir`, returning the files created
+///
+/// Existing files are only replaced when `force` is set; otherwise nothing is
+/// written.
+pub fn scaffold(dir: &Path, force: bool) -> Result<Vec<PathBuf>> {
+    if !force {
+        if let Some((path, _)) = FILES.iter().find(|(path, _)| dir.join(path).exists()) {
+            anyhow::bail!(
+                "{} already exists, use --force to replace it",
+                dir.join(path).display()
+            );
+        }
+    }
+
+    FILES
+        .iter()
+        .map(|(path, content)| {
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, content)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::load_context_data;
+    use crate::config::Config;
+    use crate::generator::{generate, GenerateOptions};
+
+    #[test]
+    fn test_scaffold() {
+        let dir = tempfile::tempdir().unwrap();
+        let created = scaffold(dir.path(), false).unwrap();
+        assert_eq!(created.len(), FILES.len());
+        assert!(scaffold(dir.path(), false).is_err());
+        assert!(scaffold(dir.path(), true).is_ok());
+
+        let config = Config::load(&dir.path().join("rpt.toml")).unwrap();
+        assert_eq!(config.targets.len(), 2);
+        for target in &config.targets {
+            let code = generate(
+                &config.resolve_path(&target.template),
+                load_context_data(&config.resolve_path(&target.context)).unwrap(),
+                &config.resolve_path(&target.output),
+                GenerateOptions::default(),
+            )
+            .unwrap();
+            syn::parse_file(&code).unwrap();
+        }
+    }
+}
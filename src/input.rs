@@ -0,0 +1,62 @@
+//! Input module
+//!
+//! This module reads template and source files where the path `-` stands for
+//! stdin, so editor integrations can pipe buffers through the tool. Stdin is
+//! read once and kept, since a template is read more than once per run.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Path that reads from stdin, or writes to stdout as an output
+pub const STDIO_PATH: &str = "-";
+
+/// Content read from stdin, on first use
+static STDIN: OnceLock<String> = OnceLock::new();
+
+/// Whether a path argument stands for stdin or stdout
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO_PATH)
+}
+
+/// Read a file, or stdin for `-`
+pub fn read_source(path: &Path) -> Result<String> {
+    if !is_stdio(path) {
+        return fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()));
+    }
+
+    if let Some(content) = STDIN.get() {
+        return Ok(content.clone());
+    }
+
+    let mut content = String::new();
+    std::io::stdin()
+        .read_to_string(&mut content)
+        .context("Failed to read stdin")?;
+    Ok(STDIN.get_or_init(|| content).clone())
+}
+
+/// Whether `path` can be read: stdin, or an existing file
+pub fn source_exists(path: &Path) -> bool {
+    is_stdio(path) || path.exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_source_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.rs");
+        fs::write(&path, "fn a() {}").unwrap();
+
+        assert!(is_stdio(Path::new("-")));
+        assert!(!is_stdio(&path));
+        assert_eq!(read_source(&path).unwrap(), "fn a() {}");
+        assert!(read_source(&dir.path().join("missing.rs")).is_err());
+    }
+}
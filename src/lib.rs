@@ -0,0 +1,86 @@
+//! Rust code generator with AST-based intelligent merging
+//!
+//! This tool generates Rust code from Tera templates and intelligently merges
+//! changes with existing files using AST-based patch theory, preserving manual
+//! edits while applying template updates.
+
+pub mod analysis;
+pub mod api;
+pub mod app;
+pub mod ast_parser;
+pub mod audit;
+pub mod backend;
+pub mod build;
+pub mod builtin;
+pub mod cargo_toml;
+pub mod check;
+pub mod cli;
+pub mod commit_hook;
+pub mod completions;
+pub mod config;
+pub mod context_types;
+pub mod deterministic;
+pub mod diagnostics;
+pub mod diff;
+pub mod edition;
+pub mod events;
+pub mod explain;
+pub mod file_blocks;
+pub mod front_matter;
+pub mod generator;
+pub mod github;
+pub mod golden;
+pub mod hash;
+pub mod hooks;
+pub mod imports;
+pub mod incremental;
+pub mod init;
+pub mod input;
+pub mod limits;
+pub mod line_merge;
+pub mod lint;
+pub mod lockfile;
+pub mod macros;
+pub mod markdown;
+pub mod matching;
+pub mod merger;
+pub mod modules;
+pub mod ordering;
+pub mod outcome;
+pub mod overrides;
+pub mod pager;
+pub mod pipeline;
+pub mod placement;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+pub mod policy;
+pub mod preview;
+pub mod progress;
+pub mod provenance;
+pub mod regions;
+pub mod remote;
+pub mod report;
+pub mod resolutions;
+pub mod review;
+pub mod sarif;
+pub mod server;
+pub mod smart;
+pub mod snapshot;
+pub mod status;
+pub mod stored_patch;
+pub mod strategy;
+pub mod summary;
+pub mod target_refs;
+pub mod template;
+pub mod templatize;
+pub mod three_way;
+pub mod timings;
+pub mod tree;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use api::{run, GenerateRequest, GenerateResponse};
+pub use pipeline::{Generator, GeneratorBuilder};
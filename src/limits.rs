@@ -0,0 +1,144 @@
+//! Conflict limits module
+//!
+//! This module backs `--max-conflicts <N>` and `--fail-on-warnings` on
+//! `generate` and `generate-all`, which abort the run before any file is
+//! written when the merge meets more than `N` conflicts, or any warning at all.
+//! They guard against a badly edited template overwriting manual changes in
+//! many files at once, as `--strategy template` otherwise does silently.
+//!
+//! Merges report conflicts, where manual and template changes to the same
+//! item met, along with notes that don't pit one change against another: items
+//! the template dropped but the file keeps, existing files that don't parse, and
+//! signature changes a manual body may need to follow (see [`WarningKind`](crate::merger::WarningKind)).
+//! `--max-conflicts` counts the former, `--fail-on-warnings` any of them.
+//! `generate-all` merges every stale target in memory first, in generation
+//! order, each against the merged outputs of the targets it depends on, and
+//! writes the outputs it checked.
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::merger::Warning;
+
+/// Thresholds above which generation aborts
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    /// Number of conflicts tolerated across the files of a run
+    pub max_conflicts: Option<usize>,
+    /// Abort on the first warning
+    pub fail_on_warnings: bool,
+}
+
+impl Limits {
+    /// Whether any limit is set
+    pub fn is_set(&self) -> bool {
+        self.max_conflicts.is_some() || self.fail_on_warnings
+    }
+
+    /// Fail when the warnings of a run, see [`warnings_of`], exceed the limits
+    pub fn check(&self, warnings: &[Warning]) -> Result<()> {
+        let conflicts: Vec<&Warning> = warnings
+            .iter()
+            .filter(|warning| warning.is_conflict())
+            .collect();
+        let (exceeded, listed) = if self.fail_on_warnings && !warnings.is_empty() {
+            (
+                format!("--fail-on-warnings and {} warning(s)", warnings.len()),
+                warnings.iter().collect(),
+            )
+        } else {
+            match self.max_conflicts {
+                Some(max) if conflicts.len() > max => (
+                    format!(
+                        "{} conflicts, more than --max-conflicts {}",
+                        conflicts.len(),
+                        max
+                    ),
+                    conflicts,
+                ),
+                _ => return Ok(()),
+            }
+        };
+
+        let listed: Vec<&str> = listed
+            .into_iter()
+            .map(|warning| warning.message.as_str())
+            .collect();
+        anyhow::bail!(
+            "Aborted before writing any file: {}\n  {}",
+            exceeded,
+            listed.join("\n  ")
+        )
+    }
+}
+
+/// Warnings of one output, their messages prefixed with its path
+pub fn warnings_of(path: &Path, conflicts: &[Warning]) -> Vec<Warning> {
+    conflicts
+        .iter()
+        .map(|conflict| Warning {
+            kind: conflict.kind,
+            message: format!("{}: {}", path.display(), conflict),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merger::WarningKind;
+
+    #[test]
+    fn test_conflict_limits() {
+        let warnings = warnings_of(
+            Path::new("src/user.rs"),
+            &[Warning::conflict(
+                "Item 'id' has manual changes, overridden by template",
+            )],
+        );
+        assert_eq!(
+            warnings,
+            vec!["src/user.rs: Item 'id' has manual changes, overridden by template"]
+        );
+
+        assert!(!Limits::default().is_set());
+        assert!(Limits::default().check(&warnings).is_ok());
+        let max_one = Limits {
+            max_conflicts: Some(1),
+            ..Limits::default()
+        };
+        assert!(max_one.check(&warnings).is_ok());
+        let twice = [warnings.clone(), warnings.clone()].concat();
+        assert_eq!(
+            max_one.check(&twice).unwrap_err().to_string(),
+            "Aborted before writing any file: 2 conflicts, more than --max-conflicts 1\n  \
+             src/user.rs: Item 'id' has manual changes, overridden by template\n  \
+             src/user.rs: Item 'id' has manual changes, overridden by template"
+        );
+
+        let strict = Limits {
+            fail_on_warnings: true,
+            ..max_one
+        };
+        let notes = vec![
+            Warning::note("src/a.rs: Item 'old' was deleted in template but exists in base"),
+            Warning::note(
+                "src/b.rs: Existing file doesn't parse (expected `;` at line 3), left unchanged",
+            ),
+        ];
+        assert_eq!(warnings[0].kind, WarningKind::Conflict);
+        assert!(Limits {
+            max_conflicts: Some(0),
+            ..Limits::default()
+        }
+        .check(&notes)
+        .is_ok());
+        assert!(strict.check(&[]).is_ok());
+        assert!(strict.check(&notes).is_err());
+        assert!(strict
+            .check(&warnings)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Aborted before writing any file: --fail-on-warnings and 1 warning(s)"));
+    }
+}
@@ -0,0 +1,122 @@
+//! Line merge module
+//!
+//! This module merges generated code into an existing file line by line, for
+//! existing files that don't parse, such as a file saved mid-edit, with
+//! `--on-parse-error text-merge`. Lines only the template has are inserted,
+//! lines only the existing file has are kept, and lines both sides changed
+//! are resolved by the merge strategy. The smart strategy leaves them between
+//! conflict markers, the way `git merge` does:
+//!
+//! ```text
+//! <<<<<<< existing
+//!     let x = 2
+//! =======
+//!     let x = 1;
+//! >>>>>>> template
+//! ```
+
+use anyhow::Result;
+use similar::{DiffTag, TextDiff};
+
+use crate::generator::MergedOutput;
+use crate::merger::{MergeStrategy, Warning};
+
+/// Merge `generated` into `existing` line by line
+pub fn merge_lines(
+    generated: &str,
+    existing: &str,
+    strategy: MergeStrategy,
+) -> Result<MergedOutput> {
+    let old: Vec<&str> = existing.split_inclusive('\n').collect();
+    let new: Vec<&str> = generated.split_inclusive('\n').collect();
+    let diff = TextDiff::from_lines(existing, generated);
+
+    let mut merged = String::new();
+    let mut conflicts = Vec::new();
+    for op in diff.ops() {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        let (manual, template) = (old[old_range.clone()].concat(), new[new_range].concat());
+        match tag {
+            DiffTag::Equal | DiffTag::Delete => merged.push_str(&manual),
+            DiffTag::Insert => merged.push_str(&template),
+            DiffTag::Replace => {
+                let lines = if old_range.len() == 1 {
+                    format!("Line {} has", old_range.start + 1)
+                } else {
+                    format!("Lines {}-{} have", old_range.start + 1, old_range.end)
+                };
+                match strategy {
+                    MergeStrategy::PreferTemplate => {
+                        conflicts.push(format!("{} manual changes, overridden by template", lines));
+                        merged.push_str(&template);
+                    }
+                    MergeStrategy::PreferManual => {
+                        conflicts
+                            .push(format!("{} manual changes, template update skipped", lines));
+                        merged.push_str(&manual);
+                    }
+                    MergeStrategy::Smart | MergeStrategy::FailOnConflict => {
+                        conflicts.push(format!(
+                            "Conflict: {} manual changes conflicting with template",
+                            lines
+                        ));
+                        merged.push_str(&format!(
+                            "<<<<<<< existing\n{}{}=======\n{}{}>>>>>>> template\n",
+                            manual,
+                            line_end(&manual),
+                            template,
+                            line_end(&template)
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    if !conflicts.is_empty() && strategy == MergeStrategy::FailOnConflict {
+        anyhow::bail!("Merge conflicts detected:\n{}", conflicts.join("\n"));
+    }
+    Ok(MergedOutput {
+        code: merged,
+        conflicts: conflicts.into_iter().map(Warning::conflict).collect(),
+    })
+}
+
+/// Newline ending `text` before a conflict marker, if it lacks one
+fn line_end(text: &str) -> &'static str {
+    if text.is_empty() || text.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_lines() {
+        let existing = "fn main() {\n    let x = 2\n    // manual note\n}\n";
+        let generated = "use std::fmt;\n\nfn main() {\n    let x = 1;\n}\n";
+
+        let merged = merge_lines(generated, existing, MergeStrategy::Smart).unwrap();
+        assert_eq!(
+            merged.code,
+            "use std::fmt;\n\nfn main() {\n\
+             <<<<<<< existing\n    let x = 2\n    // manual note\n=======\n    let x = 1;\n\
+             >>>>>>> template\n}\n"
+        );
+        assert_eq!(
+            merged.conflicts,
+            vec!["Conflict: Lines 2-3 have manual changes conflicting with template"]
+        );
+
+        let merged = merge_lines(generated, existing, MergeStrategy::PreferManual).unwrap();
+        assert_eq!(
+            merged.code,
+            "use std::fmt;\n\nfn main() {\n    let x = 2\n    // manual note\n}\n"
+        );
+        assert!(merge_lines(generated, existing, MergeStrategy::FailOnConflict).is_err());
+    }
+}
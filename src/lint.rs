@@ -0,0 +1,295 @@
+//! Template lint module
+//!
+//! This module renders a template with sample context and reports problems
+//! that originate in the template: Tera errors, unbalanced delimiters and
+//! output that doesn't parse as Rust, mapped back to template lines where
+//! possible.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::template::TemplateEngine;
+
+/// A problem found while linting a template
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintIssue {
+    /// Line in the template (1-based), when it could be determined
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Render and parse a template, collecting every issue found
+pub fn lint_template(
+    template_name: &str,
+    template: &str,
+    context: &HashMap<String, Value>,
+) -> Vec<LintIssue> {
+    let engine = match TemplateEngine::from_string(template_name, template) {
+        Ok(engine) => engine,
+        Err(err) => {
+            let message = format!("{:#}", err);
+            return vec![LintIssue {
+                line: tera_error_line(&message),
+                message,
+            }];
+        }
+    };
+
+    let rendered = match engine.render(template_name, context) {
+        Ok(rendered) => rendered,
+        Err(err) => {
+            let message = format!("{:#}", err);
+            let line = missing_variable(&message).and_then(|name| find_tag_line(template, &name));
+            return vec![LintIssue { line, message }];
+        }
+    };
+
+    if let Some((rendered_line, message)) = unbalanced_delimiter(&rendered) {
+        return vec![LintIssue {
+            line: map_rendered_line(template, &rendered, rendered_line),
+            message,
+        }];
+    }
+
+    if let Err(err) = syn::parse_file(&rendered) {
+        let rendered_line = err.span().start().line;
+        return vec![LintIssue {
+            line: map_rendered_line(template, &rendered, rendered_line),
+            message: format!(
+                "Rendered output is not valid Rust (output line {}): {}",
+                rendered_line, err
+            ),
+        }];
+    }
+
+    Vec::new()
+}
+
+/// Extract the line from Tera parse errors, which point at ` --> line:column`
+fn tera_error_line(message: &str) -> Option<usize> {
+    let location = message.split("--> ").nth(1)?;
+    location.split(':').next()?.trim().parse().ok()
+}
+
+/// Extract the variable name from Tera's "Variable `x` not found" errors
+fn missing_variable(message: &str) -> Option<String> {
+    let start = message.find("Variable `")? + "Variable `".len();
+    let end = message[start..].find('`')? + start;
+    Some(message[start..end].to_string())
+}
+
+/// Find the first template line using `name` inside a Tera tag
+fn find_tag_line(template: &str, name: &str) -> Option<usize> {
+    template
+        .lines()
+        .position(|line| {
+            tags(line).any(|tag| {
+                tag.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                    .any(|word| word == name)
+            })
+        })
+        .map(|idx| idx + 1)
+}
+
+/// Contents of the `{{ }}` / `{% %}` tags on a template line
+fn tags(line: &str) -> impl Iterator<Item = &str> {
+    line.split("{{")
+        .chain(line.split("{%"))
+        .skip(1)
+        .filter_map(|rest| rest.split(['}', '%']).next())
+}
+
+/// Map a line of rendered output back to the template line that produced it
+///
+/// Picks the first template line whose longest literal (non-tag) fragment
+/// appears on the rendered line.
+fn map_rendered_line(template: &str, rendered: &str, rendered_line: usize) -> Option<usize> {
+    let target = rendered.lines().nth(rendered_line.checked_sub(1)?)?.trim();
+    if target.is_empty() {
+        return None;
+    }
+
+    template
+        .lines()
+        .position(|line| {
+            literal_fragments(line)
+                .into_iter()
+                .max_by_key(|fragment| fragment.len())
+                .is_some_and(|fragment| fragment.len() >= 2 && target.contains(fragment))
+        })
+        .map(|idx| idx + 1)
+}
+
+/// Template line text with Tera tags removed, split into trimmed fragments
+fn literal_fragments(line: &str) -> Vec<&str> {
+    let mut fragments = Vec::new();
+    let mut rest = line;
+
+    while let Some(start) = rest
+        .find(['{'])
+        .filter(|&i| matches!(rest.get(i..i + 2), Some("{{") | Some("{%") | Some("{#")))
+    {
+        fragments.push(rest[..start].trim());
+        let close = match &rest[start..start + 2] {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+        match rest[start..].find(close) {
+            Some(end) => rest = &rest[start + end + 2..],
+            None => {
+                rest = "";
+                break;
+            }
+        }
+    }
+    fragments.push(rest.trim());
+
+    fragments.retain(|fragment| !fragment.is_empty());
+    fragments
+}
+
+/// Find the first unbalanced `()`, `[]` or `{}` in rendered code
+///
+/// Returns the output line of the offending delimiter and a description.
+/// String, char literals and comments are skipped.
+fn unbalanced_delimiter(code: &str) -> Option<(usize, String)> {
+    let mut stack: Vec<(char, usize)> = Vec::new();
+    let mut line = 1;
+    let mut chars = code.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '"' => {
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    match c {
+                        '\n' => line += 1,
+                        '\\' if !escaped => {
+                            escaped = true;
+                            continue;
+                        }
+                        '"' if !escaped => break,
+                        _ => {}
+                    }
+                    escaped = false;
+                }
+            }
+            '\'' => {
+                // Char literal like 'a' or '\n'; lifetimes have no closing quote
+                let mut lookahead = chars.clone();
+                let literal_len = match (lookahead.next(), lookahead.next(), lookahead.next()) {
+                    (Some('\\'), Some(_), Some('\'')) => 3,
+                    (Some(_), Some('\''), _) => 2,
+                    _ => 0,
+                };
+                for _ in 0..literal_len {
+                    chars.next();
+                }
+            }
+            '(' | '[' | '{' => stack.push((c, line)),
+            ')' | ']' | '}' => {
+                let expected = match c {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                match stack.pop() {
+                    Some((open, _)) if open == expected => {}
+                    Some((open, open_line)) => {
+                        return Some((
+                            line,
+                            format!(
+                                "Mismatched `{}` closes `{}` opened on output line {}",
+                                c, open, open_line
+                            ),
+                        ))
+                    }
+                    None => return Some((line, format!("Unmatched closing `{}`", c))),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    stack
+        .pop()
+        .map(|(open, open_line)| (open_line, format!("Unclosed `{}`", open)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::create_context;
+    use serde_json::json;
+
+    #[test]
+    fn test_lint_clean_template() {
+        let context = create_context(vec![("name", json!("Person"))]);
+        let issues = lint_template(
+            "t",
+            "pub struct {{ name }} {\n    pub id: u64,\n}\n",
+            &context,
+        );
+        assert!(issues.is_empty());
+    }
+
+    #[test]
+    fn test_lint_missing_variable() {
+        let context = create_context(vec![]);
+        let issues = lint_template("t", "// header\npub struct {{ name }};\n", &context);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_lint_unbalanced_braces() {
+        let context = create_context(vec![("name", json!("Person"))]);
+        let template = "pub struct {{ name }} {\n    pub id: u64,\n\nfn helper() {}\n";
+        let issues = lint_template("t", template, &context);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(1));
+        assert!(issues[0].message.contains("Unclosed `{`"));
+    }
+
+    #[test]
+    fn test_lint_invalid_rust() {
+        let context = create_context(vec![("ty", json!("u64"))]);
+        let template = "pub struct Person {\n    pub id {{ ty }},\n}\n";
+        let issues = lint_template("t", template, &context);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(2));
+    }
+}
@@ -0,0 +1,335 @@
+//! Template lockfile module
+//!
+//! This module records the resolved revision and content hash of every remote
+//! template in `rpt.lock`, next to the config file. Later runs check out the
+//! locked revision and verify its hash, so regeneration is reproducible across
+//! machines until `update-lock` refreshes the entries.
+//!
+//! `generate-all` also records the hashes of the template and context each
+//! target output was generated from, so `verify` can fail CI when a template or
+//! context was changed without regenerating, or an output was regenerated from
+//! another context than the committed one.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backend::RenderOptions;
+use crate::config::{Config, Target};
+use crate::hash::sha256_path;
+use crate::incremental::{hash_target, TargetHashes};
+use crate::remote::{fetch, RemoteTemplate};
+use crate::target_refs::dependencies;
+
+/// Name of the lockfile, relative to the config root
+pub const LOCK_FILE: &str = "rpt.lock";
+
+/// Resolved remote templates of a project
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// Entries keyed by the template argument, `<url>#<path>`
+    #[serde(default)]
+    templates: BTreeMap<String, LockedTemplate>,
+    /// Inputs of generated outputs, keyed by the target output
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    outputs: BTreeMap<String, LockedOutput>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    changed: bool,
+}
+
+/// Pinned state of one remote template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedTemplate {
+    /// Branch, tag or commit requested with `--template-ref`
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Commit the template was resolved to
+    pub revision: String,
+    /// SHA-256 of the template file or directory
+    pub hash: String,
+}
+
+/// Hashes of the inputs a target output was generated from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedOutput {
+    /// SHA-256 of the template
+    pub template: String,
+    /// SHA-256 of the context, with the outputs of the targets it depends on
+    pub context: String,
+}
+
+impl Lockfile {
+    /// Load the project's lockfile, starting empty if there is none
+    pub fn load(config: &Config) -> Result<Self> {
+        let path = config.resolve_path(Path::new(LOCK_FILE));
+
+        let mut lockfile = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            Lockfile::default()
+        };
+
+        lockfile.path = path;
+        Ok(lockfile)
+    }
+
+    /// Write the lockfile back to disk if any entry changed
+    pub fn save(&self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let content = toml::to_string_pretty(self)?;
+        fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Get the locked entry of a template argument
+    pub fn get(&self, template: &str) -> Option<&LockedTemplate> {
+        self.templates.get(template)
+    }
+
+    /// Resolve a template argument to a local path through the lock
+    ///
+    /// Locked remote templates are checked out at their locked revision and
+    /// must still match the locked hash. New remote templates are resolved and
+    /// added. Local paths are returned unchanged.
+    pub fn resolve(&mut self, template: &Path, git_ref: Option<&str>) -> Result<PathBuf> {
+        let Some(remote) = RemoteTemplate::parse(template) else {
+            return Ok(template.to_path_buf());
+        };
+        let key = template.to_string_lossy().into_owned();
+
+        let Some(locked) = self.templates.get(&key) else {
+            return self.lock(key, &remote, git_ref);
+        };
+
+        if git_ref.is_some() && git_ref != locked.git_ref.as_deref() {
+            anyhow::bail!(
+                "{} is locked to ref '{}' in {}, run `update-lock` to change it",
+                key,
+                locked.git_ref.as_deref().unwrap_or("default branch"),
+                LOCK_FILE
+            );
+        }
+
+        let checkout = fetch(&remote, Some(&locked.revision))?;
+        if sha256_path(&checkout.path)? != locked.hash {
+            anyhow::bail!(
+                "{} at {} does not match the hash in {}",
+                key,
+                locked.revision,
+                LOCK_FILE
+            );
+        }
+
+        Ok(checkout.path)
+    }
+
+    /// Re-resolve every locked template to the latest revision of its ref
+    ///
+    /// Returns the templates whose revision or hash changed.
+    pub fn update(&mut self) -> Result<Vec<String>> {
+        let mut updated = Vec::new();
+
+        for key in self.templates.keys().cloned().collect::<Vec<_>>() {
+            let remote = RemoteTemplate::parse(Path::new(&key))
+                .with_context(|| format!("Invalid template '{}' in {}", key, LOCK_FILE))?;
+            let before = self.templates[&key].clone();
+
+            self.lock(key.clone(), &remote, before.git_ref.as_deref())?;
+            if self.templates[&key] != before {
+                updated.push(key);
+            }
+        }
+
+        Ok(updated)
+    }
+
+    /// Record the inputs a target output was just generated from
+    pub fn record_output(&mut self, target: &Target, hashes: &TargetHashes) {
+        let entry = LockedOutput {
+            template: hashes.template.clone(),
+            context: hashes.context.clone(),
+        };
+        let key = target.output.to_string_lossy().into_owned();
+        if self.outputs.get(&key) != Some(&entry) {
+            self.outputs.insert(key, entry);
+            self.changed = true;
+        }
+    }
+
+    /// Forget the outputs of targets that are no longer in the manifest
+    pub fn prune_outputs(&mut self, config: &Config) {
+        let before = self.outputs.len();
+        self.outputs.retain(|output, _| {
+            config
+                .targets
+                .iter()
+                .any(|target| target.output.to_string_lossy() == output.as_str())
+        });
+        self.changed |= self.outputs.len() != before;
+    }
+
+    /// Targets whose template or context changed since their output was generated
+    ///
+    /// Returns one message per drifted target, naming the file that changed.
+    pub fn drift(&self, config: &Config) -> Result<Vec<String>> {
+        let mut drifted = Vec::new();
+        for target in &config.targets {
+            let output = target.output.display();
+            let Some(locked) = self.outputs.get(target.output.to_string_lossy().as_ref()) else {
+                drifted.push(format!(
+                    "{}: not recorded in {}, run `generate-all` and commit {}",
+                    output, LOCK_FILE, LOCK_FILE
+                ));
+                continue;
+            };
+
+            // Only the template and context are compared, not the settings
+            let hashes = hash_target(config, target, RenderOptions::default())?;
+            if hashes.template != locked.template {
+                drifted.push(format!(
+                    "{}: template {} changed since the output was generated, run `generate-all`",
+                    output,
+                    target.template.display()
+                ));
+            }
+            if hashes.context != locked.context {
+                let referenced = if dependencies(config, target)?.is_empty() {
+                    ""
+                } else {
+                    " or the outputs it depends on"
+                };
+                drifted.push(format!(
+                    "{}: generated from another context than {}{}, run `generate-all`",
+                    output,
+                    target.context.display(),
+                    referenced
+                ));
+            }
+        }
+        Ok(drifted)
+    }
+
+    /// Resolve a remote template at `git_ref` and record it
+    fn lock(
+        &mut self,
+        key: String,
+        remote: &RemoteTemplate,
+        git_ref: Option<&str>,
+    ) -> Result<PathBuf> {
+        let checkout = fetch(remote, git_ref)?;
+        let entry = LockedTemplate {
+            git_ref: git_ref.map(str::to_string),
+            revision: checkout.revision,
+            hash: sha256_path(&checkout.path)?,
+        };
+
+        if self.templates.get(&key) != Some(&entry) {
+            self.templates.insert(key, entry);
+            self.changed = true;
+        }
+        Ok(checkout.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockfile_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+
+        let mut lockfile = Lockfile::load(&config).unwrap();
+        let local = lockfile
+            .resolve(Path::new("templates/model.tera"), None)
+            .unwrap();
+        assert_eq!(local, PathBuf::from("templates/model.tera"));
+
+        let entry = LockedTemplate {
+            git_ref: Some("v1".to_string()),
+            revision: "0123abcd".to_string(),
+            hash: "ff".to_string(),
+        };
+        lockfile.templates.insert(
+            "https://example.com/t.git#m.tera".to_string(),
+            entry.clone(),
+        );
+        lockfile.changed = true;
+        lockfile.save().unwrap();
+
+        let lockfile = Lockfile::load(&config).unwrap();
+        assert_eq!(
+            lockfile.get("https://example.com/t.git#m.tera"),
+            Some(&entry)
+        );
+    }
+
+    #[test]
+    fn test_resolve_rejects_changed_ref() {
+        let mut lockfile = Lockfile::default();
+        lockfile.templates.insert(
+            "https://example.com/t.git#m.tera".to_string(),
+            LockedTemplate {
+                git_ref: Some("v1".to_string()),
+                revision: "0123abcd".to_string(),
+                hash: "ff".to_string(),
+            },
+        );
+
+        let err = lockfile
+            .resolve(Path::new("https://example.com/t.git#m.tera"), Some("v2"))
+            .unwrap_err();
+        assert!(err.to_string().contains("update-lock"));
+    }
+
+    #[test]
+    fn test_output_drift() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("rpt.toml"),
+            "[[targets]]\ntemplate = \"t.tera\"\ncontext = \"c.json\"\noutput = \"out.rs\"\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("t.tera"), "pub struct {{ name }};").unwrap();
+        fs::write(dir.path().join("c.json"), "{\"name\": \"User\"}").unwrap();
+        let config = Config::load(&dir.path().join("rpt.toml")).unwrap();
+        let target = &config.targets[0];
+
+        let mut lockfile = Lockfile::load(&config).unwrap();
+        assert_eq!(
+            lockfile.drift(&config).unwrap(),
+            ["out.rs: not recorded in rpt.lock, run `generate-all` and commit rpt.lock"]
+        );
+        lockfile.record_output(
+            target,
+            &hash_target(&config, target, RenderOptions::default()).unwrap(),
+        );
+        lockfile.save().unwrap();
+
+        let lockfile = Lockfile::load(&config).unwrap();
+        assert!(lockfile.drift(&config).unwrap().is_empty());
+        fs::write(dir.path().join("t.tera"), "pub struct {{ name }}Dto;").unwrap();
+        fs::write(dir.path().join("c.json"), "{\"name\": \"Post\"}").unwrap();
+        assert_eq!(
+            lockfile.drift(&config).unwrap(),
+            [
+                "out.rs: template t.tera changed since the output was generated, run `generate-all`",
+                "out.rs: generated from another context than c.json, run `generate-all`",
+            ]
+        );
+    }
+}
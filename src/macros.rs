@@ -0,0 +1,178 @@
+//! Built-in macros module
+//!
+//! This module ships a library of Tera macros for common Rust constructs, so
+//! templates don't copy them from one another. Every Tera template can import
+//! it as `rpt::macros`:
+//!
+//! ```text
+//! {% import "rpt::macros" as rpt %}
+//! {{ rpt::doc(text=description) }}
+//! {{ rpt::derive(traits=["Debug", "Clone"]) }}
+//! pub struct {{ name }} {
+//!     {{ rpt::fields(fields=fields) }}
+//! }
+//! ```
+//!
+//! - `derive(traits)`: a `#[derive(...)]` line, nothing for no traits,
+//! - `doc(text, width=100, indent="")`: `///` lines wrapped to `width` columns,
+//! - `fields(fields, vis="pub", indent="    ")`: struct fields from objects with
+//!   `name`, `type` and an optional `doc`,
+//! - `match_arms(arms, indent="        ")`: `pattern => body,` arms from objects
+//!   with `pattern` and `body`,
+//! - `builder_methods(fields, indent="    ")`: a `fn name(mut self, name: Type) -> Self`
+//!   setter for each field.
+//!
+//! Macros print no trailing newline, and `indent` starts the lines after the
+//! first, so a call sits where its first line goes. The `wrap(width)` filter
+//! they use, splitting text into lines, is available to templates too.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+use tera::Tera;
+
+/// Name the macros are imported by
+pub const MACROS_TEMPLATE: &str = "rpt::macros";
+
+const MACROS: &str = r#"
+{% macro derive(traits) -%}
+{% if traits %}#[derive({{ traits | join(sep=", ") }})]{% endif %}
+{%- endmacro derive %}
+
+{% macro doc(text, width=100, indent="") -%}
+{% set used = indent | length -%}
+{% for line in text | wrap(width=width - used - 4) %}{% if not loop.first %}
+{{ indent }}{% endif %}/// {{ line }}{% endfor %}
+{%- endmacro doc %}
+
+{% macro fields(fields, vis="pub", indent="    ") -%}
+{% for field in fields %}{% if not loop.first %}
+{{ indent }}{% endif %}{% if field.doc %}{{ self::doc(text=field.doc, indent=indent) }}
+{{ indent }}{% endif %}{% if vis %}{{ vis }} {% endif %}{{ field.name }}: {{ field.type }},{% endfor %}
+{%- endmacro fields %}
+
+{% macro match_arms(arms, indent="        ") -%}
+{% for arm in arms %}{% if not loop.first %}
+{{ indent }}{% endif %}{{ arm.pattern }} => {{ arm.body }},{% endfor %}
+{%- endmacro match_arms %}
+
+{% macro builder_methods(fields, indent="    ") -%}
+{% for field in fields %}{% if not loop.first %}
+
+{{ indent }}{% endif %}pub fn {{ field.name }}(mut self, {{ field.name }}: {{ field.type }}) -> Self {
+{{ indent }}    self.{{ field.name }} = {{ field.name }};
+{{ indent }}    self
+{{ indent }}}{% endfor %}
+{%- endmacro builder_methods %}
+"#;
+
+/// Add the macro library and the `wrap` filter to a Tera instance
+pub fn register(tera: &mut Tera) {
+    tera.register_filter("wrap", wrap);
+    tera.add_raw_template(MACROS_TEMPLATE, MACROS)
+        .expect("the built-in macros compile");
+}
+
+/// Split text into lines of at most `width` characters, breaking between words
+fn wrap(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = value
+        .as_str()
+        .ok_or_else(|| tera::Error::msg("Filter `wrap` expects a string"))?;
+    let width = match args.get("width") {
+        Some(width) => width
+            .as_i64()
+            .ok_or_else(|| tera::Error::msg("Filter `wrap` expects an integer `width`"))?
+            .max(1) as usize,
+        None => 80,
+    };
+
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+
+    Ok(lines.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template::{create_context, TemplateEngine};
+    use serde_json::json;
+
+    #[test]
+    fn test_builtin_macros() {
+        let template = r#"{% import "rpt::macros" as rpt %}{{ rpt::doc(text=description, width=20) }}
+{{ rpt::derive(traits=["Debug", "Clone"]) }}{{ rpt::derive(traits=[]) }}
+pub struct User {
+    {{ rpt::fields(fields=fields) }}
+}
+
+impl User {
+    {{ rpt::builder_methods(fields=fields) }}
+
+    fn label(&self) -> &str {
+        match self.id {
+            {{ rpt::match_arms(arms=arms, indent="            ") }}
+        }
+    }
+}
+"#;
+        let engine = TemplateEngine::from_string("user.tera", template).unwrap();
+        let context = create_context(vec![
+            ("description", json!("A user of the application")),
+            (
+                "fields",
+                json!([
+                    { "name": "id", "type": "u32", "doc": "Identifier" },
+                    { "name": "name", "type": "String" },
+                ]),
+            ),
+            (
+                "arms",
+                json!([
+                    { "pattern": "0", "body": "\"root\"" },
+                    { "pattern": "_", "body": "\"user\"" },
+                ]),
+            ),
+        ]);
+
+        assert_eq!(
+            engine.render("user.tera", &context).unwrap(),
+            r#"/// A user of the
+/// application
+#[derive(Debug, Clone)]
+pub struct User {
+    /// Identifier
+    pub id: u32,
+    pub name: String,
+}
+
+impl User {
+    pub fn id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    fn label(&self) -> &str {
+        match self.id {
+            0 => "root",
+            _ => "user",
+        }
+    }
+}
+"#
+        );
+        assert_eq!(engine.get_template_names(), vec!["user.tera"]);
+    }
+}
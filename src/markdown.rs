@@ -0,0 +1,259 @@
+//! Markdown module
+//!
+//! This module merges generated Markdown, such as API docs rendered from the
+//! same context as the code, when generating with `--language markdown`.
+//! Sections play the part of items: each heading starts one, running to the
+//! next heading, and is keyed by its path, such as `API > User`, so that
+//! repeated titles like `Example` stay apart. Text before the first heading
+//! is a section of its own.
+//!
+//! Sections are merged like items: new template sections are inserted after
+//! the section that precedes them in the template, sections written by hand
+//! are kept, and sections both sides changed are resolved by the merge
+//! strategy. Headings inside fenced code blocks don't count.
+
+use anyhow::Result;
+
+use crate::diff::compute_text_diff;
+use crate::generator::MergedOutput;
+use crate::merger::{MergeStrategy, Warning};
+
+/// A heading and the text under it, up to the next heading
+#[derive(Debug, Clone, PartialEq)]
+struct Section {
+    /// Titles of the heading and its parents, joined with ` > `
+    key: String,
+    /// Source text, heading line included
+    text: String,
+}
+
+/// Merge `generated` Markdown into `existing` section by section
+pub fn merge_sections(
+    generated: &str,
+    existing: &str,
+    strategy: MergeStrategy,
+) -> Result<MergedOutput> {
+    let (merged, conflicts, _) = merge(generated, existing, strategy);
+
+    if !conflicts.is_empty() && strategy == MergeStrategy::FailOnConflict {
+        anyhow::bail!("Merge conflicts detected:\n{}", conflicts.join("\n"));
+    }
+    Ok(MergedOutput {
+        code: merged,
+        conflicts: conflicts.into_iter().map(Warning::conflict).collect(),
+    })
+}
+
+/// Sections the template adds or changes, followed by the resulting line diff
+pub fn diff_sections(generated: &str, existing: &str) -> String {
+    let (merged, _, changes) = merge(generated, existing, MergeStrategy::PreferTemplate);
+    if merged == existing {
+        return "No differences found.".to_string();
+    }
+    format!(
+        "{}\n\n{}",
+        changes.join("\n"),
+        compute_text_diff(existing, &merged)
+    )
+}
+
+/// Merged text, conflicts, and the changes the template made
+fn merge(
+    generated: &str,
+    existing: &str,
+    strategy: MergeStrategy,
+) -> (String, Vec<String>, Vec<String>) {
+    let mut merged = sections(existing);
+    let mut conflicts = Vec::new();
+    let mut changes = Vec::new();
+    let mut previous: Option<String> = None;
+
+    for section in sections(generated) {
+        match merged.iter().position(|other| other.key == section.key) {
+            Some(index) if same_text(&merged[index].text, &section.text) => {}
+            Some(index) => match strategy {
+                MergeStrategy::PreferTemplate => {
+                    changes.push(format!("Update section `{}`", label(&section.key)));
+                    conflicts.push(format!(
+                        "Section '{}' has manual changes, overridden by template",
+                        label(&section.key)
+                    ));
+                    merged[index].text = section.text.clone();
+                }
+                MergeStrategy::PreferManual | MergeStrategy::Smart => {
+                    conflicts.push(format!(
+                        "Section '{}' has manual changes, template update skipped",
+                        label(&section.key)
+                    ));
+                }
+                MergeStrategy::FailOnConflict => {
+                    conflicts.push(format!(
+                        "Conflict: Section '{}' has manual changes conflicting with template",
+                        label(&section.key)
+                    ));
+                }
+            },
+            None => {
+                // Right after the section that precedes it in the template
+                let at = previous
+                    .as_ref()
+                    .and_then(|key| merged.iter().position(|other| other.key == *key))
+                    .map_or(0, |index| index + 1);
+                changes.push(format!("Add section `{}`", label(&section.key)));
+                merged.insert(at, section.clone());
+                if at > 0 {
+                    separate(&mut merged[at - 1].text);
+                }
+                if at + 1 < merged.len() {
+                    separate(&mut merged[at].text);
+                }
+            }
+        }
+        previous = Some(section.key);
+    }
+
+    let mut text = String::new();
+    for section in &merged {
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&section.text);
+    }
+    (text, conflicts, changes)
+}
+
+/// Sections of a Markdown document, in order
+fn sections(source: &str) -> Vec<Section> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut parents: Vec<(usize, String)> = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut current = Section {
+        key: String::new(),
+        text: String::new(),
+    };
+
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| trimmed.starts_with(marker));
+        match (fence, marker) {
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            _ => {}
+        }
+
+        let heading = if fence.is_none() && marker.is_none() {
+            heading(line)
+        } else {
+            None
+        };
+        if let Some((level, title)) = heading {
+            if !current.text.is_empty() {
+                sections.push(current);
+            }
+            parents.retain(|(parent, _)| *parent < level);
+            parents.push((level, title));
+
+            let mut key = parents
+                .iter()
+                .map(|(_, title)| title.as_str())
+                .collect::<Vec<_>>()
+                .join(" > ");
+            let repeats = sections
+                .iter()
+                .filter(|section| {
+                    section.key == key || section.key.starts_with(&format!("{} #", key))
+                })
+                .count();
+            if repeats > 0 {
+                key = format!("{} #{}", key, repeats + 1);
+            }
+            current = Section {
+                key,
+                text: String::new(),
+            };
+        }
+        current.text.push_str(line);
+    }
+    if !current.text.is_empty() {
+        sections.push(current);
+    }
+    sections
+}
+
+/// Level and title of an ATX heading line, such as `## Usage`
+fn heading(line: &str) -> Option<(usize, String)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let line = line.trim();
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let title = &line[level..];
+    if !(1..=6).contains(&level) || !(title.is_empty() || title.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((level, title.trim().trim_end_matches('#').trim().to_string()))
+}
+
+/// End a section with a blank line, so that the heading after it stands apart
+fn separate(text: &mut String) {
+    while !text.ends_with("\n\n") {
+        text.push('\n');
+    }
+}
+
+/// Whether two sections have the same text, ignoring trailing blank lines
+fn same_text(a: &str, b: &str) -> bool {
+    a.trim_end() == b.trim_end()
+}
+
+/// Name of a section in messages
+fn label(key: &str) -> &str {
+    if key.is_empty() {
+        "(preamble)"
+    } else {
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_sections() {
+        let existing = "# API\n\n\
+            ## User\n\nA registered user.\n\n\
+            ### Example\n\n```bash\n# not a heading\ncurl /users\n```\n\n\
+            ## Notes\n\nWritten by hand.\n";
+        let generated = "# API\n\n\
+            ## User\n\nA user of the service.\n\n\
+            ### Example\n\n```bash\n# not a heading\ncurl /users\n```\n\n\
+            ## Order\n\nAn order.\n\n\
+            ### Example\n\n```bash\ncurl /orders\n```\n";
+
+        let merged = merge_sections(generated, existing, MergeStrategy::PreferManual).unwrap();
+        assert_eq!(
+            merged.code,
+            "# API\n\n\
+            ## User\n\nA registered user.\n\n\
+            ### Example\n\n```bash\n# not a heading\ncurl /users\n```\n\n\
+            ## Order\n\nAn order.\n\n\
+            ### Example\n\n```bash\ncurl /orders\n```\n\n\
+            ## Notes\n\nWritten by hand.\n"
+        );
+        assert_eq!(
+            merged.conflicts,
+            vec!["Section 'API > User' has manual changes, template update skipped"]
+        );
+
+        let merged = merge_sections(generated, existing, MergeStrategy::PreferTemplate).unwrap();
+        assert!(merged.code.contains("A user of the service."));
+        assert!(merge_sections(generated, existing, MergeStrategy::FailOnConflict).is_err());
+        assert!(diff_sections(generated, existing).starts_with(
+            "Update section `API > User`\nAdd section `API > Order`\nAdd section `API > Order > Example`"
+        ));
+    }
+}
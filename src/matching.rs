@@ -0,0 +1,434 @@
+//! Item matching module
+//!
+//! This module decides how strictly generics take part in matching items of
+//! the existing file with template items, from the `[matching]` table of
+//! `rpt.toml`:
+//!
+//! ```toml
+//! [matching]
+//! generics = "params"   # name, params or strict
+//! threshold = 0.8       # pair renamed items at least 80% similar
+//! ignore_docs = true    # items differing only by doc comments are unchanged
+//! ignore_attrs = ["allow", "inline", "tracing::instrument"]
+//! ```
+//!
+//! - `name` matches items by name alone, impls by their trait and type,
+//! - `params` adds the names of the generic parameters, so `Foo<T>` and `Foo`
+//!   are different items,
+//! - `strict` adds the parameters with their bounds and the where clause, so
+//!   impls that only differ by bounds are told apart.
+//!
+//! Item names in messages and in the `preserve`/`always_replace` lists carry
+//! the generics the mode adds.
+//!
+//! Items matched by name are never compared by content. With a `threshold`,
+//! or `--match-threshold`, an item the template drops and an item of the same
+//! kind it adds are paired as a rename when their tokens are at least that
+//! similar, instead of a deletion and an insertion. Lower values pair more
+//! aggressively; without a threshold renames are never detected.
+//!
+//! With `ignore_docs`, or `--ignore-docs`, items paired by name are compared
+//! without their doc comments, at any depth, so items whose docs alone differ
+//! are kept as they are instead of being modified. `ignore_attrs`, or
+//! `--ignore-attrs`, does the same for attributes with the given paths.
+//!
+//! These settings belong to a run, not to the process: commands read them from
+//! the config and the flags, and library users set them in
+//! [`GenerateOptions::generics`](crate::generator::GenerateOptions::generics)
+//! and [`GenerateOptions::comparison`](crate::generator::GenerateOptions::comparison),
+//! which apply to the thread the merge runs on, see [`with_generics`] and
+//! [`with_comparison`].
+//!
+//! Library users can replace how items are identified with an
+//! [`ItemMatcher`], set per generator with
+//! [`GeneratorBuilder::matcher`](crate::GeneratorBuilder::matcher) or in
+//! [`GenerateOptions::matcher`](crate::generator::GenerateOptions::matcher).
+//! The default, [`NameMatcher`], keys items by name as above;
+//! [`AttributeMatcher`] keys items by an id attribute, so they still match
+//! after being renamed:
+//!
+//! ```
+//! use rpt::matching::AttributeMatcher;
+//!
+//! // #[codegen(id = "user.create")] fn create_user() {}
+//! let generator = rpt::Generator::builder()
+//!     .matcher(AttributeMatcher::new("codegen", "id"))
+//!     .build();
+//! ```
+//!
+//! Items of inline modules merged item by item are keyed by their path from
+//! the file, such as `tests::handler`, in messages and name lists too, so they
+//! don't collide with items of the same name elsewhere; see [`in_module`].
+
+use anyhow::Result;
+use quote::ToTokens;
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use syn::{Expr, GenericParam, Generics, Item, Lit, Meta};
+
+use crate::ast_parser::attrs_and_vis;
+
+use crate::config::Config;
+
+/// Generics compared when matching items
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum GenericsMatching {
+    /// Names only
+    #[default]
+    Name,
+    /// Names and generic parameter names
+    Params,
+    /// Names, generic parameters with their bounds and where clauses
+    Strict,
+}
+
+impl GenericsMatching {
+    /// Parse a mode: name, params or strict
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "name" => Ok(GenericsMatching::Name),
+            "params" => Ok(GenericsMatching::Params),
+            "strict" => Ok(GenericsMatching::Strict),
+            _ => anyhow::bail!(
+                "Unknown generics matching '{}', expected name, params or strict",
+                value
+            ),
+        }
+    }
+
+    /// Read the mode declared in the config
+    pub fn from_config(config: &Config) -> Result<Self> {
+        config
+            .matching
+            .generics
+            .as_deref()
+            .map_or(Ok(GenericsMatching::Name), Self::parse)
+    }
+
+    /// Generic parameters as they appear in an item key, `<T>` or empty
+    pub fn params(self, generics: &Generics) -> String {
+        let params: Vec<String> = match self {
+            GenericsMatching::Name => return String::new(),
+            GenericsMatching::Params => generics
+                .params
+                .iter()
+                .map(|param| match param {
+                    GenericParam::Lifetime(param) => param.lifetime.to_string(),
+                    GenericParam::Type(param) => param.ident.to_string(),
+                    GenericParam::Const(param) => param.ident.to_string(),
+                })
+                .collect(),
+            GenericsMatching::Strict => generics
+                .params
+                .iter()
+                .map(|param| param.to_token_stream().to_string())
+                .collect(),
+        };
+
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("<{}>", params.join(", "))
+        }
+    }
+
+    /// Where clause as it appears at the end of an item key, ` where …` or empty
+    pub fn where_clause(self, generics: &Generics) -> String {
+        match (self, &generics.where_clause) {
+            (GenericsMatching::Strict, Some(clause)) => {
+                format!(" {}", clause.to_token_stream())
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+/// Check that a rename threshold lies within (0, 1]
+pub fn parse_threshold(threshold: f64) -> Result<f64> {
+    if threshold > 0.0 && threshold <= 1.0 {
+        Ok(threshold)
+    } else {
+        anyhow::bail!(
+            "Match threshold {} is out of range, expected a value in (0, 1]",
+            threshold
+        )
+    }
+}
+
+/// Attribute paths to leave out of comparisons, from docs and attribute names
+///
+/// Names may be written as paths, `allow`, or as attributes, `#[allow]`.
+pub fn ignored_attrs(ignore_docs: bool, names: &[String]) -> Vec<String> {
+    let names = names.iter().map(|name| {
+        let name = name.trim();
+        let name = name
+            .strip_prefix("#[")
+            .and_then(|name| name.strip_suffix(']'))
+            .unwrap_or(name);
+        name.split_whitespace().collect::<String>()
+    });
+
+    let mut ignored: Vec<String> = ignore_docs
+        .then(|| "doc".to_string())
+        .into_iter()
+        .chain(names)
+        .filter(|name| !name.is_empty())
+        .collect();
+    ignored.sort();
+    ignored.dedup();
+    ignored
+}
+
+/// How items are compared once paired, and how unpaired items are paired
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Comparison {
+    /// Similarity above which dropped and added items are paired as renames
+    pub threshold: Option<f64>,
+    /// Attributes left out when comparing items, by path, such as `doc`
+    pub ignored_attrs: Vec<String>,
+}
+
+impl Comparison {
+    /// Read the comparison declared in the config
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Comparison {
+            threshold: config.matching.threshold.map(parse_threshold).transpose()?,
+            ignored_attrs: ignored_attrs(
+                config.matching.ignore_docs,
+                &config.matching.ignore_attrs,
+            ),
+        })
+    }
+}
+
+/// How items of the existing file are paired with template items
+///
+/// Items with the same key are the same item; the key is also the name used in
+/// messages and in the `preserve`/`always_replace` lists.
+pub trait ItemMatcher: Send + Sync {
+    /// Key `item` is matched by, `None` for items never matched, such as `use` declarations
+    fn key(&self, item: &Item) -> Option<String>;
+}
+
+impl std::fmt::Debug for dyn ItemMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ItemMatcher")
+    }
+}
+
+/// Matches items by name, with generics as the current [`GenericsMatching`] says
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NameMatcher;
+
+impl ItemMatcher for NameMatcher {
+    fn key(&self, item: &Item) -> Option<String> {
+        crate::diff::name_key(item)
+    }
+}
+
+/// Matches items by a string argument of an attribute, other items by name
+///
+/// `AttributeMatcher::new("codegen", "id")` keys `#[codegen(id = "user.create")]`
+/// items as `user.create`.
+#[derive(Debug, Clone)]
+pub struct AttributeMatcher {
+    attribute: String,
+    argument: String,
+}
+
+impl AttributeMatcher {
+    /// Match by the `argument = "..."` argument of `#[attribute(...)]`
+    pub fn new(attribute: impl Into<String>, argument: impl Into<String>) -> Self {
+        AttributeMatcher {
+            attribute: attribute.into(),
+            argument: argument.into(),
+        }
+    }
+
+    /// Value of the id argument on `item`, if it has one
+    fn id(&self, item: &Item) -> Option<String> {
+        let (attrs, _) = attrs_and_vis(item);
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident(&self.attribute))
+            .filter_map(|attr| {
+                attr.parse_args_with(
+                    syn::punctuated::Punctuated::<Meta, syn::Token![,]>::parse_terminated,
+                )
+                .ok()
+            })
+            .flatten()
+            .find_map(|meta| match meta {
+                Meta::NameValue(pair) if pair.path.is_ident(&self.argument) => match pair.value {
+                    Expr::Lit(expr) => match expr.lit {
+                        Lit::Str(id) => Some(id.value()),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            })
+    }
+}
+
+impl ItemMatcher for AttributeMatcher {
+    fn key(&self, item: &Item) -> Option<String> {
+        self.id(item).or_else(|| NameMatcher.key(item))
+    }
+}
+
+thread_local! {
+    /// Matcher of the merge running on this thread, [`NameMatcher`] when `None`
+    static MATCHER: RefCell<Option<Arc<dyn ItemMatcher>>> = const { RefCell::new(None) };
+    /// Generics matching of the merge running on this thread
+    static GENERICS: Cell<GenericsMatching> = const { Cell::new(GenericsMatching::Name) };
+    /// Comparison of the diffs made on this thread
+    static COMPARISON: RefCell<Comparison> = const {
+        RefCell::new(Comparison {
+            threshold: None,
+            ignored_attrs: Vec::new(),
+        })
+    };
+    /// Inline modules the items matched on this thread are in, outermost first
+    static MODULE_PATH: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `f` with items keyed as items of the inline module `name`, as in `name::handler`
+///
+/// Calls nest, for modules in modules.
+pub fn in_module<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    /// Leaves the module when dropped, even when `f` panics
+    struct Leave;
+    impl Drop for Leave {
+        fn drop(&mut self) {
+            MODULE_PATH.with(|path| path.borrow_mut().pop());
+        }
+    }
+
+    MODULE_PATH.with(|path| path.borrow_mut().push(name.to_string()));
+    let _leave = Leave;
+    f()
+}
+
+/// Run `f` with items keyed by `matcher`, [`NameMatcher`] when `None`
+pub fn with_matcher<T>(matcher: Option<Arc<dyn ItemMatcher>>, f: impl FnOnce() -> T) -> T {
+    /// Restores the previous matcher when dropped, even when `f` panics
+    struct Restore(Option<Arc<dyn ItemMatcher>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            MATCHER.with(|current| *current.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _restore = Restore(MATCHER.with(|current| current.replace(matcher)));
+    f()
+}
+
+/// Run `f` with generics taking part in matching as `mode` says
+pub fn with_generics<T>(mode: GenericsMatching, f: impl FnOnce() -> T) -> T {
+    /// Restores the previous mode when dropped, even when `f` panics
+    struct Restore(GenericsMatching);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            GENERICS.with(|current| current.set(self.0));
+        }
+    }
+
+    let _restore = Restore(GENERICS.with(|current| current.replace(mode)));
+    f()
+}
+
+/// Generics matching of the merge running on this thread, `name` by default
+pub fn generics() -> GenericsMatching {
+    GENERICS.with(Cell::get)
+}
+
+/// Run `f` with items compared as `comparison` says
+pub fn with_comparison<T>(comparison: Comparison, f: impl FnOnce() -> T) -> T {
+    /// Restores the previous comparison when dropped, even when `f` panics
+    struct Restore(Comparison);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            COMPARISON.with(|current| *current.borrow_mut() = std::mem::take(&mut self.0));
+        }
+    }
+
+    let _restore = Restore(COMPARISON.with(|current| current.replace(comparison)));
+    f()
+}
+
+/// Comparison of the diffs made on this thread: exact, without rename detection by default
+pub fn comparison() -> Comparison {
+    COMPARISON.with(|current| current.borrow().clone())
+}
+
+/// Key of `item` by the current matcher, qualified by the module it is in
+pub fn matched_key(item: &Item) -> Option<String> {
+    let key = MATCHER.with(|matcher| match matcher.borrow().as_ref() {
+        Some(matcher) => matcher.key(item),
+        None => NameMatcher.key(item),
+    })?;
+    Some(MODULE_PATH.with(|path| {
+        path.borrow()
+            .iter()
+            .map(|module| format!("{}::", module))
+            .chain([key])
+            .collect()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attribute_matcher() {
+        let file: syn::File = syn::parse_str(
+            "#[codegen(skip, id = \"user.create\")] fn create_user() {}\n\
+             #[codegen(id = 1)] struct User;\n\
+             fn helper() {}",
+        )
+        .unwrap();
+        let matcher = AttributeMatcher::new("codegen", "id");
+        let keys: Vec<_> = file.items.iter().map(|item| matcher.key(item)).collect();
+
+        assert_eq!(
+            keys,
+            [
+                Some("user.create".to_string()),
+                Some("User".to_string()),
+                Some("helper".to_string()),
+            ]
+        );
+        assert_eq!(NameMatcher.key(&file.items[0]).unwrap(), "create_user");
+    }
+
+    #[test]
+    fn test_keys_in_module() {
+        let item: Item = syn::parse_str("fn handler() {}").unwrap();
+
+        assert_eq!(
+            in_module("v1", || matched_key(&item)).unwrap(),
+            "v1::handler"
+        );
+        assert_eq!(
+            in_module("api", || in_module("v2", || matched_key(&item))).unwrap(),
+            "api::v2::handler"
+        );
+        assert_eq!(matched_key(&item).unwrap(), "handler");
+    }
+
+    #[test]
+    fn test_comparison_from_config() {
+        let mut config = Config::default();
+        config.matching.ignore_docs = true;
+        config.matching.ignore_attrs = vec!["#[inline]".to_string(), "allow".to_string()];
+        let from_config = Comparison::from_config(&config).unwrap();
+        assert_eq!(from_config.ignored_attrs, ["allow", "doc", "inline"]);
+
+        // Scoped to the run, the previous comparison is back afterwards
+        let inner = with_comparison(from_config.clone(), comparison);
+        assert_eq!(inner, from_config);
+        assert_eq!(comparison(), Comparison::default());
+    }
+}
@@ -3,9 +3,26 @@
 //! This module implements intelligent merging of AST changes, preserving
 //! manual edits while applying template-generated updates.
 
-use crate::diff::{Patch, PatchOp};
-use anyhow::Result;
-use syn::{File, Item};
+use crate::ast_parser::{doc_line, item_kind};
+use crate::diff::{compute_patch, extract_item_name, same_item, Patch, PatchOp};
+use crate::edition::Edition;
+use crate::imports::place_imports;
+use crate::matching::in_module;
+use crate::ordering::Ordering;
+use crate::placement::{Placement, PlacementRules};
+use crate::policy::{Conflict, ConflictChange, MergePolicy, Resolution};
+use crate::smart::smart_merge;
+use crate::strategy::{merge_fields, merge_methods, merge_signatures, ItemStrategy, StrategyRules};
+use crate::summary::tokens;
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use syn::spanned::Spanned;
+use syn::{Attribute, Block, Expr, File, ImplItem, Item, ItemMod, Stmt};
 
 /// Merge strategy for handling conflicts
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -16,81 +33,317 @@ pub enum MergeStrategy {
     PreferManual,
     /// Fail on conflicts
     FailOnConflict,
+    /// Combine both versions with heuristics, see [`crate::smart`]
+    Smart,
+}
+
+impl MergeStrategy {
+    /// Parse a strategy: template, manual, fail or smart
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_lowercase().as_str() {
+            "template" => MergeStrategy::PreferTemplate,
+            "manual" => MergeStrategy::PreferManual,
+            "fail" => MergeStrategy::FailOnConflict,
+            "smart" => MergeStrategy::Smart,
+            _ => anyhow::bail!(
+                "Unknown strategy '{}', expected template, manual, fail or smart",
+                value
+            ),
+        })
+    }
+}
+
+/// Formatter used to print merged code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Formatter {
+    /// Built-in prettyplease printer
+    Prettyplease,
+    /// External `rustfmt`, honoring the project's rustfmt.toml
+    Rustfmt,
 }
 
 /// Result of a merge operation
 #[derive(Debug)]
 pub struct MergeResult {
     pub merged_items: Vec<Item>,
-    pub conflicts: Vec<String>,
+    pub conflicts: Vec<Warning>,
+}
+
+/// What a merge warning reports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarningKind {
+    /// Manual and template changes to the same item met and one was picked
+    Conflict,
+    /// Manual and template changes met where the strategy is `fail`
+    Unresolved,
+    /// Nothing was overridden: an item the template dropped but the file
+    /// keeps, a file that doesn't parse, a signature a manual body may need to follow
+    Note,
+}
+
+/// A message of a merge, see [`WarningKind`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl Warning {
+    /// A conflict resolved by the strategy
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Warning {
+            kind: WarningKind::Conflict,
+            message: message.into(),
+        }
+    }
+
+    /// A conflict left unresolved by the `fail` strategy
+    pub fn unresolved(message: impl Into<String>) -> Self {
+        Warning {
+            kind: WarningKind::Unresolved,
+            message: message.into(),
+        }
+    }
+
+    /// A warning that isn't a conflict
+    pub fn note(message: impl Into<String>) -> Self {
+        Warning {
+            kind: WarningKind::Note,
+            message: message.into(),
+        }
+    }
+
+    /// Whether it reports manual and template changes to the same item
+    pub fn is_conflict(&self) -> bool {
+        self.kind != WarningKind::Note
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl PartialEq<&str> for Warning {
+    fn eq(&self, other: &&str) -> bool {
+        self.message == *other
+    }
+}
+
+impl PartialEq<String> for Warning {
+    fn eq(&self, other: &String) -> bool {
+        self.message == *other
+    }
+}
+
+/// Messages of warnings, for reports that don't tell kinds apart
+pub fn messages(warnings: &[Warning]) -> Vec<String> {
+    warnings.iter().map(ToString::to_string).collect()
 }
 
 /// Merge changes from a patch into existing items
+///
+/// When `respect_order` is false, items keep their position from the base file
+/// and template reorders (`PatchOp::Move`) are ignored.
 pub fn merge_patch(
     base_items: &[Item],
     patch: &Patch,
     strategy: MergeStrategy,
+    respect_order: bool,
+) -> Result<MergeResult> {
+    merge_patch_with_policy(
+        base_items,
+        patch,
+        strategy,
+        respect_order,
+        None,
+        &StrategyRules::default(),
+        &PlacementRules::default(),
+    )
+}
+
+/// Merge changes from a patch, asking `policy` how to resolve each conflict
+///
+/// Items named in the `preserve` and `always_replace` lists of `strategies`
+/// bypass the policy. Conflicts the policy leaves undecided use the rule of
+/// the item's kind in `strategies`, then `strategy`, see [`crate::policy`] and
+/// [`crate::strategy`]. Without `respect_order`, new items are placed by
+/// `placement`, see [`crate::placement`].
+pub fn merge_patch_with_policy(
+    base_items: &[Item],
+    patch: &Patch,
+    strategy: MergeStrategy,
+    respect_order: bool,
+    policy: Option<&dyn MergePolicy>,
+    strategies: &StrategyRules,
+    placement: &PlacementRules,
 ) -> Result<MergeResult> {
-    let mut merged_items = Vec::new();
     let mut conflicts = Vec::new();
-    let mut base_map: std::collections::HashMap<String, Item> = base_items
+    let mut merged_items: Vec<(String, Cow<Item>)> = Vec::new();
+    let mut base_map: HashMap<String, &Item> = base_items
         .iter()
-        .filter_map(|item| extract_item_name(item).map(|name| (name, item.clone())))
+        .filter_map(|item| extract_item_name(item).map(|name| (name, item)))
         .collect();
 
+    // Per-item strategy, or the custom item the rules or the policy replaced it with;
+    // `base` is the template's previous version of the item, when known
+    let decide = |name: &str,
+                  change,
+                  manual: Option<&Item>,
+                  template: Option<&Item>,
+                  base: Option<&Item>|
+     -> Result<Decision> {
+        let rule = strategies.resolve_conflict(name, manual, template, strategy);
+
+        // Name lists and recorded resolutions win over the policy; custom items are
+        // taken without reporting a conflict
+        match (rule, manual, template) {
+            (ItemStrategy::Preserve, Some(manual), _) => {
+                return Ok(Decision::Custom(Box::new(manual.clone())))
+            }
+            (ItemStrategy::AlwaysReplace, _, Some(template)) => {
+                return Ok(Decision::Custom(Box::new(template.clone())))
+            }
+            (ItemStrategy::Preserve | ItemStrategy::AlwaysReplace, _, _) => {
+                return Ok(Decision::Strategy(MergeStrategy::PreferTemplate))
+            }
+            _ => {}
+        }
+
+        if let Some(policy) = policy {
+            let conflict = Conflict::new(name, change, manual, template);
+            match policy.resolve(&conflict)? {
+                None => {}
+                Some(Resolution::Manual) => {
+                    return Ok(Decision::Strategy(MergeStrategy::PreferManual))
+                }
+                Some(Resolution::Template) => {
+                    return Ok(Decision::Strategy(MergeStrategy::PreferTemplate))
+                }
+                Some(Resolution::Fail) => {
+                    anyhow::bail!("Merge policy rejected the conflict on item '{}'", name)
+                }
+                Some(Resolution::Custom(code)) => {
+                    return Ok(Decision::Custom(Box::new(Resolution::custom_item(&code)?)))
+                }
+            }
+        }
+
+        Ok(match (rule, manual, template) {
+            (ItemStrategy::MergeMethods, Some(Item::Impl(manual)), Some(Item::Impl(template))) => {
+                Decision::Custom(Box::new(merge_methods(manual, template)))
+            }
+            (
+                ItemStrategy::MergeFields,
+                Some(Item::Struct(manual)),
+                Some(Item::Struct(template)),
+            ) => {
+                let base = match base {
+                    Some(Item::Struct(base)) => Some(base),
+                    _ => None,
+                };
+                let (merged, conflicts) = merge_fields(manual, template, base);
+                Decision::Merged(
+                    Box::new(merged),
+                    conflicts.into_iter().map(Warning::conflict).collect(),
+                )
+            }
+            (ItemStrategy::Hybrid, Some(manual), Some(template)) => {
+                match merge_signatures(manual, template) {
+                    Some((merged, notes)) => Decision::Merged(
+                        Box::new(merged),
+                        notes.into_iter().map(Warning::note).collect(),
+                    ),
+                    None => Decision::Strategy(MergeStrategy::PreferManual),
+                }
+            }
+            (ItemStrategy::Strategy(strategy), _, _) => Decision::Strategy(strategy),
+            _ => Decision::Strategy(MergeStrategy::PreferManual),
+        })
+    };
+
     // Apply patch operations
     for op in &patch.operations {
         match op {
             PatchOp::Insert { name, item } => {
                 // Check if item already exists (manual addition)
-                if base_map.contains_key(name) {
-                    match strategy {
-                        MergeStrategy::PreferTemplate => {
-                            merged_items.push(item.clone());
+                if let Some(&base_item) = base_map.get(name) {
+                    match decide(
+                        name,
+                        ConflictChange::Insert,
+                        Some(base_item),
+                        Some(*item),
+                        None,
+                    )? {
+                        Decision::Custom(custom) => {
+                            merged_items.push((name.clone(), Cow::Owned(*custom)));
+                            base_map.remove(name);
+                        }
+                        Decision::Merged(merged, notes) => {
+                            merged_items.push((name.clone(), Cow::Owned(*merged)));
+                            conflicts.extend(notes);
+                            base_map.remove(name);
+                        }
+                        Decision::Strategy(MergeStrategy::PreferTemplate) => {
+                            merged_items.push((name.clone(), Cow::Borrowed(*item)));
                             base_map.remove(name);
                         }
-                        MergeStrategy::PreferManual => {
+                        Decision::Strategy(MergeStrategy::PreferManual) => {
                             if let Some(base_item) = base_map.remove(name) {
-                                merged_items.push(base_item);
+                                merged_items.push((name.clone(), Cow::Borrowed(base_item)));
                             }
-                            conflicts
-                                .push(format!("Item '{}' exists in both base and patch", name));
+                            conflicts.push(Warning::conflict(match value_conflict(base_item, item) {
+                                Some((kind, manual, template)) => format!(
+                                    "{} '{}' exists in both base and patch, manual value {} kept over template value {}",
+                                    kind, name, manual, template
+                                ),
+                                None => format!("Item '{}' exists in both base and patch", name),
+                            }));
                         }
-                        MergeStrategy::FailOnConflict => {
-                            conflicts.push(format!(
+                        Decision::Strategy(MergeStrategy::FailOnConflict) => {
+                            conflicts.push(Warning::unresolved(format!(
                                 "Conflict: Item '{}' exists in both base and patch",
                                 name
-                            ));
+                            )));
+                        }
+                        Decision::Strategy(MergeStrategy::Smart) => {
+                            base_map.remove(name);
+                            merged_items.push(merge_smart(name, base_item, item, &mut conflicts));
                         }
                     }
                 } else {
-                    merged_items.push(item.clone());
+                    merged_items.push((name.clone(), Cow::Borrowed(*item)));
                 }
             }
 
             PatchOp::Delete { name } => {
                 // Check if item still exists and has been modified
-                if let Some(base_item) = base_map.get(name) {
+                if let Some(&base_item) = base_map.get(name) {
                     // Compare with what patch expects to delete
-                    match strategy {
-                        MergeStrategy::PreferTemplate => {
+                    match decide(name, ConflictChange::Delete, Some(base_item), None, None)? {
+                        Decision::Custom(custom) | Decision::Merged(custom, _) => {
+                            merged_items.push((name.clone(), Cow::Owned(*custom)));
+                            base_map.remove(name);
+                        }
+                        Decision::Strategy(MergeStrategy::PreferTemplate) => {
                             // Remove the item
                             base_map.remove(name);
                         }
-                        MergeStrategy::PreferManual => {
+                        Decision::Strategy(MergeStrategy::PreferManual | MergeStrategy::Smart) => {
                             // Keep the item
-                            merged_items.push(base_item.clone());
+                            merged_items.push((name.clone(), Cow::Borrowed(base_item)));
                             base_map.remove(name);
-                            conflicts.push(format!(
+                            conflicts.push(Warning::note(format!(
                                 "Item '{}' was deleted in template but exists in base",
                                 name
-                            ));
+                            )));
                         }
-                        MergeStrategy::FailOnConflict => {
-                            conflicts.push(format!(
+                        Decision::Strategy(MergeStrategy::FailOnConflict) => {
+                            conflicts.push(Warning::unresolved(format!(
                                 "Conflict: Item '{}' was deleted in template but modified in base",
                                 name
-                            ));
+                            )));
                         }
                     }
                 } else {
@@ -100,92 +353,596 @@ pub fn merge_patch(
 
             PatchOp::Modify {
                 name,
-                old_item: _,
+                old_item,
                 new_item,
             } => {
                 // Check if base item differs from old_item (manual modification)
                 if let Some(base_item) = base_map.remove(name) {
-                    let base_code = quote::quote!(#base_item).to_string();
-                    let new_code = quote::quote!(#new_item).to_string();
-
-                    if base_code == new_code {
-                        // No manual changes, apply template update
-                        merged_items.push(new_item.clone());
+                    if base_item == *new_item || fills_placeholders(base_item, new_item) {
+                        // No manual changes, or only placeholder bodies the template
+                        // now implements: apply template update
+                        merged_items.push((name.clone(), Cow::Borrowed(*new_item)));
+                    } else if let (Some(manual_tests), Some(template_tests), Some(old_tests)) = (
+                        test_module_items(base_item),
+                        test_module_items(new_item),
+                        test_module_items(old_item),
+                    ) {
+                        // Generated tests are merged one by one next to hand-written ones,
+                        // which can't be told from removed tests without an ancestor, and are
+                        // keyed by their path, as `tests::it_works`
+                        let module_name = module_ident(base_item);
+                        let result = in_module(&module_name, || {
+                            let mut tests_patch = compute_patch(old_tests, template_tests)?;
+                            if *old_item == base_item {
+                                tests_patch
+                                    .operations
+                                    .retain(|op| !matches!(op, PatchOp::Delete { .. }));
+                            }
+                            merge_patch_with_policy(
+                                manual_tests,
+                                &tests_patch,
+                                strategy,
+                                respect_order,
+                                policy,
+                                strategies,
+                                placement,
+                            )
+                        })?;
+                        let mut module = base_item.clone();
+                        if let Item::Mod(ItemMod {
+                            content: Some((_, items)),
+                            ..
+                        }) = &mut module
+                        {
+                            *items = result.merged_items;
+                        }
+                        merged_items.push((name.clone(), Cow::Owned(module)));
+                        conflicts.extend(result.conflicts);
                     } else {
                         // Manual changes detected
-                        match strategy {
-                            MergeStrategy::PreferTemplate => {
-                                merged_items.push(new_item.clone());
-                                conflicts.push(format!(
-                                    "Item '{}' has manual changes, overridden by template",
-                                    name
-                                ));
+                        let values = value_conflict(base_item, new_item);
+                        match decide(
+                            name,
+                            ConflictChange::Modify,
+                            Some(base_item),
+                            Some(*new_item),
+                            // When diffed against the existing file, it is no ancestor
+                            Some(*old_item).filter(|old| *old != base_item),
+                        )? {
+                            Decision::Custom(custom) => {
+                                merged_items.push((name.clone(), Cow::Owned(*custom)));
                             }
-                            MergeStrategy::PreferManual => {
-                                merged_items.push(base_item);
-                                conflicts.push(format!(
-                                    "Item '{}' has manual changes, template update skipped",
-                                    name
-                                ));
+                            Decision::Merged(merged, notes) => {
+                                merged_items.push((name.clone(), Cow::Owned(*merged)));
+                                conflicts.extend(notes);
+                            }
+                            Decision::Strategy(MergeStrategy::PreferTemplate) => {
+                                merged_items.push((name.clone(), Cow::Borrowed(*new_item)));
+                                conflicts.push(Warning::conflict(match values {
+                                    Some((kind, manual, template)) => format!(
+                                        "{} '{}' was set to {} by hand, overridden by template value {}",
+                                        kind, name, manual, template
+                                    ),
+                                    None => format!(
+                                        "Item '{}' has manual changes, overridden by template",
+                                        name
+                                    ),
+                                }));
                             }
-                            MergeStrategy::FailOnConflict => {
-                                conflicts.push(format!("Conflict: Item '{}' has manual changes conflicting with template", name));
+                            Decision::Strategy(MergeStrategy::PreferManual) => {
+                                merged_items.push((name.clone(), Cow::Borrowed(base_item)));
+                                conflicts.push(Warning::conflict(match values {
+                                    Some((kind, manual, template)) => format!(
+                                        "{} '{}' was set to {} by hand, template value {} skipped",
+                                        kind, name, manual, template
+                                    ),
+                                    None => format!(
+                                        "Item '{}' has manual changes, template update skipped",
+                                        name
+                                    ),
+                                }));
+                            }
+                            Decision::Strategy(MergeStrategy::FailOnConflict) => {
+                                merged_items.push((name.clone(), Cow::Borrowed(base_item)));
+                                conflicts.push(Warning::unresolved(match values {
+                                    Some((kind, manual, template)) => format!(
+                                        "Conflict: {} '{}' was set to {} by hand, template sets {}",
+                                        kind, name, manual, template
+                                    ),
+                                    None => format!("Conflict: Item '{}' has manual changes conflicting with template", name),
+                                }));
+                            }
+                            Decision::Strategy(MergeStrategy::Smart) => {
+                                merged_items.push(merge_smart(
+                                    name,
+                                    base_item,
+                                    new_item,
+                                    &mut conflicts,
+                                ));
                             }
                         }
                     }
                 } else {
                     // Item doesn't exist in base - treat as insert
-                    merged_items.push(new_item.clone());
+                    merged_items.push((name.clone(), Cow::Borrowed(*new_item)));
                 }
             }
 
-            PatchOp::Keep { name } => {
-                // Keep existing item if it exists
+            PatchOp::Rename {
+                from,
+                name,
+                old_item,
+                new_item,
+            } => {
+                let Some(base_item) = base_map.remove(from) else {
+                    // Renamed item doesn't exist in base - treat as insert
+                    merged_items.push((name.clone(), Cow::Borrowed(*new_item)));
+                    continue;
+                };
+                // Keyed by the old name so the item keeps its place
+                match decide(
+                    from,
+                    ConflictChange::Modify,
+                    Some(base_item),
+                    Some(*new_item),
+                    Some(*old_item).filter(|old| *old != base_item),
+                )? {
+                    Decision::Custom(custom) => {
+                        merged_items.push((from.clone(), Cow::Owned(*custom)));
+                    }
+                    Decision::Merged(merged, notes) => {
+                        merged_items.push((from.clone(), Cow::Owned(*merged)));
+                        conflicts.extend(notes);
+                    }
+                    Decision::Strategy(MergeStrategy::PreferTemplate) => {
+                        merged_items.push((from.clone(), Cow::Borrowed(*new_item)));
+                        conflicts.push(Warning::conflict(format!(
+                            "Item '{}' was renamed to '{}' by template, manual version replaced",
+                            from, name
+                        )));
+                    }
+                    Decision::Strategy(MergeStrategy::PreferManual) => {
+                        merged_items.push((from.clone(), Cow::Borrowed(base_item)));
+                        conflicts.push(Warning::conflict(format!(
+                            "Item '{}' was renamed to '{}' by template, manual version kept",
+                            from, name
+                        )));
+                    }
+                    Decision::Strategy(MergeStrategy::FailOnConflict) => {
+                        merged_items.push((from.clone(), Cow::Borrowed(base_item)));
+                        conflicts.push(Warning::unresolved(format!(
+                            "Conflict: Item '{}' was renamed to '{}' by template",
+                            from, name
+                        )));
+                    }
+                    Decision::Strategy(MergeStrategy::Smart) => {
+                        merged_items.push(merge_smart(from, base_item, new_item, &mut conflicts));
+                    }
+                }
+            }
+
+            PatchOp::Keep { name } | PatchOp::Move { name, .. } => {
+                // Keep existing item if it exists; placement is decided below
                 if let Some(base_item) = base_map.remove(name) {
-                    merged_items.push(base_item);
+                    merged_items.push((name.clone(), Cow::Borrowed(base_item)));
                 }
             }
         }
     }
 
     // Add any remaining items from base (manual additions)
-    for (_name, item) in base_map {
-        merged_items.push(item);
+    for item in base_items {
+        if let Some(name) = extract_item_name(item) {
+            if let Some(item) = base_map.remove(&name) {
+                merged_items.push((name, Cow::Borrowed(item)));
+            }
+        }
+    }
+
+    if !respect_order {
+        restore_base_order(&mut merged_items, base_items, placement);
     }
 
+    // Items are only cloned here, when building the merged output
+    let merged_items = merged_items
+        .into_iter()
+        .map(|(_, item)| item.into_owned())
+        .collect();
+
     Ok(MergeResult {
-        merged_items,
+        merged_items: place_imports(merged_items, base_items, &patch.imports),
         conflicts,
     })
 }
 
-/// Extract the name/identifier from an AST item
-fn extract_item_name(item: &Item) -> Option<String> {
+/// Combine a manual and a template item, keeping the manual one when they can't be combined
+fn merge_smart<'a>(
+    name: &str,
+    manual: &'a Item,
+    template: &Item,
+    conflicts: &mut Vec<Warning>,
+) -> (String, Cow<'a, Item>) {
+    match smart_merge(manual, template) {
+        Some(merged) => (name.to_string(), Cow::Owned(merged)),
+        None => {
+            conflicts.push(Warning::conflict(format!(
+                "Item '{}' has manual and template changes that can't be combined, template update skipped",
+                name
+            )));
+            (name.to_string(), Cow::Borrowed(manual))
+        }
+    }
+}
+
+/// Kind and both values of a const or static whose versions only differ by value
+///
+/// Returns `None` for other items, and when the type or attributes differ too.
+fn value_conflict(manual: &Item, template: &Item) -> Option<(&'static str, String, String)> {
+    match (manual, template) {
+        (Item::Const(m), Item::Const(t)) if m.expr != t.expr => {
+            let mut retyped = m.clone();
+            retyped.expr = t.expr.clone();
+            (retyped == *t).then(|| ("Const", tokens(&m.expr), tokens(&t.expr)))
+        }
+        (Item::Static(m), Item::Static(t)) if m.expr != t.expr => {
+            let mut retyped = m.clone();
+            retyped.expr = t.expr.clone();
+            (retyped == *t).then(|| ("Static", tokens(&m.expr), tokens(&t.expr)))
+        }
+        _ => None,
+    }
+}
+
+/// Whether `template` only differs from `manual` by implementing its placeholder bodies
+///
+/// A placeholder body is a lone `todo!()` or `unimplemented!()`. Functions and
+/// impl methods are compared with those bodies swapped for the template's, so
+/// any other manual edit still goes through the conflict strategy.
+pub(crate) fn fills_placeholders(manual: &Item, template: &Item) -> bool {
+    let fill = |body: &mut Block, template: &Block| {
+        let filled = is_placeholder(body) && !is_placeholder(template);
+        if filled {
+            *body = template.clone();
+        }
+        filled
+    };
+
+    match (manual, template) {
+        (Item::Fn(manual), Item::Fn(template)) => {
+            let mut filled = manual.clone();
+            fill(&mut filled.block, &template.block) && filled == *template
+        }
+        (Item::Impl(manual), Item::Impl(template)) => {
+            let mut filled = manual.clone();
+            let mut any = false;
+            for item in &mut filled.items {
+                let ImplItem::Fn(method) = item else {
+                    continue;
+                };
+                let template_method = template.items.iter().find_map(|item| match item {
+                    ImplItem::Fn(f) if f.sig.ident == method.sig.ident => Some(f),
+                    _ => None,
+                });
+                if let Some(template_method) = template_method {
+                    any |= fill(&mut method.block, &template_method.block);
+                }
+            }
+            any && filled == *template
+        }
+        _ => false,
+    }
+}
+
+/// Whether a body is a lone `todo!()` or `unimplemented!()`, with or without a message
+fn is_placeholder(block: &Block) -> bool {
+    let mac = match block.stmts.as_slice() {
+        [Stmt::Macro(stmt)] => &stmt.mac,
+        [Stmt::Expr(Expr::Macro(expr), _)] => &expr.mac,
+        _ => return false,
+    };
+    mac.path.is_ident("todo") || mac.path.is_ident("unimplemented")
+}
+
+/// Name of a module item
+fn module_ident(item: &Item) -> String {
     match item {
-        Item::Fn(func) => Some(func.sig.ident.to_string()),
-        Item::Struct(s) => Some(s.ident.to_string()),
-        Item::Enum(e) => Some(e.ident.to_string()),
-        Item::Trait(t) => Some(t.ident.to_string()),
-        Item::Type(t) => Some(t.ident.to_string()),
-        Item::Const(c) => Some(c.ident.to_string()),
-        Item::Static(s) => Some(s.ident.to_string()),
-        Item::Mod(m) => Some(m.ident.to_string()),
+        Item::Mod(module) => module.ident.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Items of an inline `#[cfg(test)]` module
+fn test_module_items(item: &Item) -> Option<&[Item]> {
+    let Item::Mod(module) = item else {
+        return None;
+    };
+    let is_test = module.attrs.iter().any(|attr| {
+        attr.path().is_ident("cfg")
+            && attr
+                .parse_args::<syn::Ident>()
+                .is_ok_and(|arg| arg == "test")
+    });
+    match &module.content {
+        Some((_, items)) if is_test => Some(items),
         _ => None,
     }
 }
 
+/// How one conflicting item is merged
+enum Decision {
+    /// Resolve it with a merge strategy
+    Strategy(MergeStrategy),
+    /// Replace it with an item supplied by the merge policy
+    Custom(Box<Item>),
+    /// Replace it with both versions combined, and the conflicts left
+    Merged(Box<Item>, Vec<Warning>),
+}
+
+/// Reorder merged items to follow the base file instead of the template
+///
+/// Items that don't exist in the base are placed by their kind's rule, by
+/// default right after the item that precedes them in the template.
+fn restore_base_order(
+    merged_items: &mut Vec<(String, Cow<Item>)>,
+    base_items: &[Item],
+    placement: &PlacementRules,
+) {
+    let mut base_positions: HashMap<String, usize> = HashMap::new();
+    let mut last_of_kind: HashMap<&str, usize> = HashMap::new();
+    for (idx, item) in base_items.iter().enumerate() {
+        if let Some(name) = extract_item_name(item) {
+            base_positions.insert(name, idx);
+            last_of_kind.insert(item_kind(item), idx);
+        }
+    }
+
+    let mut anchor = None;
+    let mut keyed: Vec<_> = merged_items
+        .drain(..)
+        .enumerate()
+        .map(|(position, (name, item))| {
+            if let Some(&idx) = base_positions.get(&name) {
+                anchor = Some(idx);
+                return ((Some(idx), 0, position), (name, item));
+            }
+
+            let kind = item_kind(&item);
+            let placed = match placement.get(kind) {
+                Placement::Template => anchor,
+                Placement::Start => None,
+                Placement::End => Some(usize::MAX),
+                Placement::AfterLast => last_of_kind.get(kind).copied().or(anchor),
+                Placement::After(target) => base_positions.get(target).copied().or(anchor),
+            };
+            ((placed, 1, position), (name, item))
+        })
+        .collect();
+
+    keyed.sort_by_key(|(key, _)| *key);
+    merged_items.extend(keyed.into_iter().map(|(_, entry)| entry));
+}
+
+/// File-level parts of a Rust file: shebang, inner attributes and `//!` docs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileHeader {
+    pub shebang: Option<String>,
+    pub attrs: Vec<Attribute>,
+}
+
+impl FileHeader {
+    /// Header of a parsed file
+    pub fn of(file: &File) -> Self {
+        FileHeader {
+            shebang: file.shebang.clone(),
+            attrs: file.attrs.clone(),
+        }
+    }
+}
+
+/// Merge the file headers of the existing file and the generated code
+///
+/// Inner `//!` docs are one block: edited docs are a conflict resolved with
+/// `strategy`, like an edited item. Other inner attributes (`#![allow(...)]`)
+/// from both sides are kept, existing ones first. The existing shebang wins.
+pub fn merge_file_header(
+    base: &File,
+    generated: &File,
+    strategy: MergeStrategy,
+) -> (FileHeader, Vec<Warning>) {
+    let is_doc = |attr: &&Attribute| attr.path().is_ident("doc");
+    let base_docs: Vec<_> = base.attrs.iter().filter(is_doc).collect();
+    let generated_docs: Vec<_> = generated.attrs.iter().filter(is_doc).collect();
+
+    let mut conflicts = Vec::new();
+    let docs = if base_docs.is_empty() || base_docs == generated_docs {
+        generated_docs
+    } else if generated_docs.is_empty() {
+        base_docs
+    } else {
+        match strategy {
+            MergeStrategy::PreferTemplate => {
+                conflicts.push(Warning::conflict(
+                    "File docs have manual changes, overridden by template",
+                ));
+                generated_docs
+            }
+            MergeStrategy::PreferManual => {
+                conflicts.push(Warning::conflict(
+                    "File docs have manual changes, template update skipped",
+                ));
+                base_docs
+            }
+            MergeStrategy::FailOnConflict => {
+                conflicts.push(Warning::unresolved(
+                    "Conflict: File docs have manual changes conflicting with template",
+                ));
+                base_docs
+            }
+            MergeStrategy::Smart => {
+                let length = |docs: &[&Attribute]| -> usize {
+                    docs.iter()
+                        .filter_map(|attr| doc_line(attr))
+                        .map(|l| l.len())
+                        .sum()
+                };
+                if length(&base_docs) > length(&generated_docs) {
+                    base_docs
+                } else {
+                    generated_docs
+                }
+            }
+        }
+    };
+
+    let mut attrs: Vec<Attribute> = docs.into_iter().cloned().collect();
+    for attr in base.attrs.iter().chain(&generated.attrs) {
+        if !is_doc(&attr) && !attrs.contains(attr) {
+            attrs.push(attr.clone());
+        }
+    }
+
+    let header = FileHeader {
+        shebang: base.shebang.clone().or_else(|| generated.shebang.clone()),
+        attrs,
+    };
+    (header, conflicts)
+}
+
 /// Format merged items back into a complete Rust file
-pub fn format_merged_code(merged_items: Vec<Item>) -> Result<String> {
+///
+/// With `Formatter::Rustfmt`, the rustfmt config is looked up from `project_dir`
+/// upwards. `edition` is that of the items, see [`crate::edition`].
+pub fn format_merged_code(
+    header: FileHeader,
+    mut merged_items: Vec<Item>,
+    ordering: &Ordering,
+    formatter: Formatter,
+    edition: Edition,
+    project_dir: Option<&Path>,
+) -> Result<String> {
+    ordering.apply(&mut merged_items);
     let file = File {
-        shebang: None,
-        attrs: Vec::new(),
+        shebang: header.shebang,
+        attrs: header.attrs,
         items: merged_items,
     };
 
-    let syntax_tree = syn::parse_quote!(#file);
-    let formatted = prettyplease::unparse(&syntax_tree);
+    let formatted = edition.restore(prettyplease::unparse(&file));
+
+    match formatter {
+        Formatter::Prettyplease => Ok(formatted),
+        Formatter::Rustfmt => run_rustfmt(&formatted, edition, project_dir),
+    }
+}
+
+/// Put back the existing text of the items the merge left as they were
+///
+/// The formatter prints kept items again, dropping their comments and layout;
+/// the lines of each one are replaced with its lines in `existing_code`, unless
+/// it shares a line with another item on either side.
+pub fn restore_kept_items(formatted: &str, existing: &File, existing_code: &str) -> String {
+    let Ok(merged) = syn::parse_file(formatted) else {
+        return formatted.to_string();
+    };
+    let existing_by_name: HashMap<String, usize> = existing
+        .items
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, item)| extract_item_name(item).map(|name| (name, idx)))
+        .collect();
+    let existing_lines: Vec<&str> = existing_code.lines().collect();
+    let existing_ranges = line_ranges(&existing.items);
+    let merged_ranges = line_ranges(&merged.items);
+
+    let mut lines: Vec<&str> = formatted.lines().collect();
+    for (idx, item) in merged.items.iter().enumerate().rev() {
+        let Some(&original) = extract_item_name(item).and_then(|name| existing_by_name.get(&name))
+        else {
+            continue;
+        };
+        let (Some(range), Some(original_range)) = (
+            merged_ranges[idx].clone(),
+            existing_ranges[original].clone(),
+        ) else {
+            continue;
+        };
+        if same_item(item, &existing.items[original], &[]) {
+            lines.splice(range, existing_lines[original_range].iter().copied());
+        }
+    }
 
-    Ok(formatted)
+    let mut restored = lines.join("\n");
+    if formatted.ends_with('\n') {
+        restored.push('\n');
+    }
+    restored
+}
+
+/// Lines of each item, `None` for items sharing a line with another one
+fn line_ranges(items: &[Item]) -> Vec<Option<Range<usize>>> {
+    let lines: Vec<(usize, usize)> = items
+        .iter()
+        .map(|item| {
+            let span = item.span();
+            (span.start().line.saturating_sub(1), span.end().line)
+        })
+        .collect();
+    lines
+        .iter()
+        .enumerate()
+        .map(|(idx, &(start, end))| {
+            let shared = (idx > 0 && lines[idx - 1].1 > start)
+                || lines.get(idx + 1).is_some_and(|next| next.0 < end);
+            (!shared && start < end).then_some(start..end)
+        })
+        .collect()
+}
+
+/// Pipe source code through `rustfmt`
+fn run_rustfmt(source: &str, edition: Edition, project_dir: Option<&Path>) -> Result<String> {
+    let mut command = Command::new("rustfmt");
+    command.args(["--edition", edition.as_str(), "--emit", "stdout"]);
+
+    if let Some(config) = project_dir.and_then(find_rustfmt_config) {
+        command.arg("--config-path").arg(config);
+    }
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run rustfmt (is it installed?)")?;
+
+    child
+        .stdin
+        .take()
+        .context("Failed to open rustfmt stdin")?
+        .write_all(source.as_bytes())?;
+
+    let output = child
+        .wait_with_output()
+        .context("Failed to wait for rustfmt")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "rustfmt failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    String::from_utf8(output.stdout).context("rustfmt produced invalid UTF-8")
+}
+
+/// Find the nearest `rustfmt.toml` or `.rustfmt.toml` in `dir` or its ancestors
+fn find_rustfmt_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|ancestor| {
+            ["rustfmt.toml", ".rustfmt.toml"]
+                .into_iter()
+                .map(move |name| ancestor.join(name))
+        })
+        .find(|candidate| candidate.is_file())
 }
 
 #[cfg(test)]
@@ -200,7 +957,8 @@ mod tests {
         let new_items: Vec<Item> = vec![parse_quote! { fn hello() {} }];
 
         let patch = compute_patch(&base_items, &new_items).unwrap();
-        let result = merge_patch(&base_items, &patch, MergeStrategy::PreferTemplate).unwrap();
+        let result =
+            merge_patch(&base_items, &patch, MergeStrategy::PreferTemplate, false).unwrap();
 
         assert_eq!(result.merged_items.len(), 1);
         assert_eq!(result.conflicts.len(), 0);
@@ -212,9 +970,351 @@ mod tests {
         let new_items: Vec<Item> = vec![parse_quote! { fn template_fn() {} }];
 
         let patch = compute_patch(&[], &new_items).unwrap();
-        let result = merge_patch(&base_items, &patch, MergeStrategy::PreferManual).unwrap();
+        let result = merge_patch(&base_items, &patch, MergeStrategy::PreferManual, false).unwrap();
 
         // Should have both manual and template functions
         assert_eq!(result.merged_items.len(), 2);
     }
+
+    #[test]
+    fn test_merge_module_and_fn_of_same_name() {
+        let base_items: Vec<Item> = vec![
+            parse_quote! { pub mod user {} },
+            parse_quote! { pub fn user() {} },
+        ];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { pub mod user {} },
+            parse_quote! { pub fn user() -> u8 { 1 } },
+        ];
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let result =
+            merge_patch(&base_items, &patch, MergeStrategy::PreferTemplate, false).unwrap();
+        assert_eq!(result.merged_items, new_items);
+        assert_eq!(item_names(&result.merged_items), ["mod user", "user"]);
+    }
+
+    fn item_names(items: &[Item]) -> Vec<String> {
+        items.iter().filter_map(extract_item_name).collect()
+    }
+
+    #[test]
+    fn test_merge_move_ignored_by_default() {
+        let base_items: Vec<Item> = vec![parse_quote! { fn a() {} }, parse_quote! { fn b() {} }];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { fn b() {} },
+            parse_quote! { fn new_fn() {} },
+            parse_quote! { fn a() {} },
+        ];
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let result = merge_patch(&base_items, &patch, MergeStrategy::PreferManual, false).unwrap();
+
+        assert_eq!(item_names(&result.merged_items), vec!["a", "b", "new_fn"]);
+    }
+
+    #[test]
+    fn test_merge_move_respect_order() {
+        let base_items: Vec<Item> = vec![parse_quote! { fn a() {} }, parse_quote! { fn b() {} }];
+        let new_items: Vec<Item> = vec![parse_quote! { fn b() {} }, parse_quote! { fn a() {} }];
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let result = merge_patch(&base_items, &patch, MergeStrategy::PreferManual, true).unwrap();
+
+        assert_eq!(item_names(&result.merged_items), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_merge_placement_rules() {
+        let base_items: Vec<Item> = vec![
+            parse_quote! { fn a() {} },
+            parse_quote! { struct A; },
+            parse_quote! { fn b() {} },
+            parse_quote! { struct B; },
+        ];
+        let mut new_items = base_items.clone();
+        new_items.insert(0, parse_quote! { struct C; });
+        new_items.push(parse_quote! { fn c() {} });
+        let placement = PlacementRules::from_config(&crate::config::Config {
+            placement: [
+                ("fn".to_string(), "after-last".to_string()),
+                ("struct".to_string(), "after:A".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let result = merge_patch_with_policy(
+            &base_items,
+            &patch,
+            MergeStrategy::PreferManual,
+            false,
+            None,
+            &StrategyRules::default(),
+            &placement,
+        )
+        .unwrap();
+
+        assert_eq!(
+            item_names(&result.merged_items),
+            vec!["a", "A", "C", "b", "c", "B"]
+        );
+    }
+
+    #[test]
+    fn test_merge_value_conflict() {
+        let base_items: Vec<Item> = vec![
+            parse_quote! { pub const MAX_RETRIES: u32 = 5; },
+            parse_quote! { static NAME: &str = "app"; },
+        ];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { pub const MAX_RETRIES: u32 = 3; },
+            parse_quote! { static NAME: String = String::new(); },
+        ];
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let result = merge_patch(&base_items, &patch, MergeStrategy::PreferManual, false).unwrap();
+        assert_eq!(
+            result.conflicts,
+            vec![
+                "Const 'MAX_RETRIES' was set to 5 by hand, template value 3 skipped",
+                "Item 'NAME' has manual changes, template update skipped",
+            ]
+        );
+
+        // Dropped items are kept with a note, `fail` leaves conflicts unresolved
+        let dropped: Vec<Item> = vec![parse_quote! { fn legacy() {} }];
+        let patch = compute_patch(&dropped, &[]).unwrap();
+        let result = merge_patch(&dropped, &patch, MergeStrategy::PreferManual, false).unwrap();
+        assert_eq!(result.conflicts[0].kind, WarningKind::Note);
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let result =
+            merge_patch(&base_items, &patch, MergeStrategy::FailOnConflict, false).unwrap();
+        assert!(result
+            .conflicts
+            .iter()
+            .all(|conflict| conflict.kind == WarningKind::Unresolved));
+    }
+
+    #[test]
+    fn test_merge_placeholder_bodies() {
+        let base_items: Vec<Item> = vec![
+            parse_quote! { pub fn load(id: u64) -> User { todo!() } },
+            parse_quote! { impl User { fn a(&self) { unimplemented!("later") } fn b(&self) { todo!() } } },
+            parse_quote! { pub fn save(user: &User) { todo!() } },
+        ];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { pub fn load(id: u64) -> User { db::get(id) } },
+            parse_quote! { impl User { fn a(&self) { self.touch() } fn b(&self) { todo!() } } },
+            parse_quote! { pub fn save(user: &User, db: &Db) { db.put(user) } },
+        ];
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let result = merge_patch(&base_items, &patch, MergeStrategy::PreferManual, false).unwrap();
+        assert_eq!(result.merged_items[..2], new_items[..2]);
+        // The signatures differ too, so the strategy still applies
+        assert_eq!(result.merged_items[2], base_items[2]);
+        assert_eq!(
+            result.conflicts,
+            vec!["Item 'save' has manual changes, template update skipped"]
+        );
+    }
+
+    #[test]
+    fn test_merge_test_module() {
+        let base_items: Vec<Item> = vec![parse_quote! {
+            #[cfg(test)]
+            mod tests {
+                use super::*;
+
+                #[test]
+                fn test_new() { assert!(User::new().is_valid()); }
+
+                #[test]
+                fn test_by_hand() { assert_eq!(User::new().id, 0); }
+            }
+        }];
+        let new_items: Vec<Item> = vec![parse_quote! {
+            #[cfg(test)]
+            mod tests {
+                use super::*;
+
+                #[test]
+                fn test_new() { assert!(User::new().is_valid()); }
+
+                #[test]
+                fn test_email() { assert!(User::new().email.is_empty()); }
+            }
+        }];
+
+        let patch = compute_patch(&base_items, &new_items).unwrap();
+        let result = merge_patch(&base_items, &patch, MergeStrategy::PreferManual, false).unwrap();
+        let expected: Item = parse_quote! {
+            #[cfg(test)]
+            mod tests {
+                use super::*;
+
+                #[test]
+                fn test_new() { assert!(User::new().is_valid()); }
+
+                #[test]
+                fn test_email() { assert!(User::new().email.is_empty()); }
+
+                #[test]
+                fn test_by_hand() { assert_eq!(User::new().id, 0); }
+            }
+        };
+        assert_eq!(result.merged_items, vec![expected]);
+        assert!(result.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_test_module_qualified_names() {
+        let module = |body: &str| -> Vec<Item> {
+            syn::parse_file(&format!(
+                "fn handler() {{ {0} }}\n#[cfg(test)]\nmod tests {{ fn handler() {{ {0} }} }}",
+                body
+            ))
+            .unwrap()
+            .items
+        };
+        let (base_items, old_items, new_items) =
+            (module("manual();"), module(""), module("generated();"));
+
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+        let result = merge_patch(&base_items, &patch, MergeStrategy::PreferManual, false).unwrap();
+
+        assert_eq!(result.merged_items, base_items);
+        assert_eq!(
+            result.conflicts,
+            [
+                "Item 'handler' has manual changes, template update skipped",
+                "Item 'tests::handler' has manual changes, template update skipped",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_file_header() {
+        let base = syn::parse_file(
+            "#!/usr/bin/env run-cargo-script\n//! Edited docs\n#![allow(dead_code)]\nfn a() {}",
+        )
+        .unwrap();
+        let generated =
+            syn::parse_file("//! Generated docs\n#![deny(missing_docs)]\nfn a() {}").unwrap();
+
+        let (header, conflicts) = merge_file_header(&base, &generated, MergeStrategy::PreferManual);
+        let code = format_merged_code(
+            header,
+            base.items.clone(),
+            &Ordering::default(),
+            Formatter::Prettyplease,
+            Edition::default(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            code,
+            "#!/usr/bin/env run-cargo-script\n//! Edited docs\n#![allow(dead_code)]\n#![deny(missing_docs)]\nfn a() {}\n"
+        );
+        assert_eq!(conflicts.len(), 1);
+
+        let (header, _) = merge_file_header(&base, &generated, MergeStrategy::PreferTemplate);
+        assert_eq!(header.attrs[0], generated.attrs[0]);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_merge_with_policy() {
+        let base_items: Vec<Item> = vec![
+            parse_quote! { struct UserDto { id: u32 } },
+            parse_quote! { fn handler() { manual(); } },
+        ];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { struct UserDto { id: u64 } },
+            parse_quote! { fn handler() { generated(); } },
+        ];
+        let old_items: Vec<Item> = vec![
+            parse_quote! { struct UserDto { id: u8 } },
+            parse_quote! { fn handler() {} },
+        ];
+        let policy = crate::policy::ExternalPolicy::Command {
+            command: "grep -q UserDto && echo template || echo default".to_string(),
+            dir: std::env::temp_dir(),
+        };
+
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+        let result = merge_patch_with_policy(
+            &base_items,
+            &patch,
+            MergeStrategy::PreferManual,
+            false,
+            Some(&policy),
+            &StrategyRules::default(),
+            &PlacementRules::default(),
+        )
+        .unwrap();
+
+        assert_eq!(result.merged_items[0], new_items[0]);
+        assert_eq!(result.merged_items[1], base_items[1]);
+    }
+
+    #[test]
+    fn test_merge_with_closure_policy() {
+        let base_items: Vec<Item> = vec![
+            parse_quote! { struct UserDto { id: u32 } },
+            parse_quote! { struct User { id: u32 } },
+        ];
+        let old_items: Vec<Item> = vec![
+            parse_quote! { struct UserDto { id: u8 } },
+            parse_quote! { struct User { id: u8 } },
+        ];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { struct UserDto { id: u64 } },
+            parse_quote! { struct User { id: u64 } },
+        ];
+        let asked = std::sync::Mutex::new(Vec::new());
+        let policy = |conflict: &Conflict| {
+            asked.lock().unwrap().push(conflict.name.clone());
+            (conflict.name == "UserDto").then_some(Resolution::Template)
+        };
+
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+        let result = merge_patch_with_policy(
+            &base_items,
+            &patch,
+            MergeStrategy::PreferManual,
+            false,
+            Some(&policy),
+            &StrategyRules::default(),
+            &PlacementRules::default(),
+        )
+        .unwrap();
+
+        assert_eq!(result.merged_items[0], new_items[0]);
+        assert_eq!(result.merged_items[1], base_items[1]);
+        assert_eq!(asked.into_inner().unwrap(), ["UserDto", "User"]);
+    }
+
+    #[test]
+    fn test_format_with_rustfmt_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("rustfmt.toml"), "hard_tabs = true\n").unwrap();
+
+        let items: Vec<Item> = vec![parse_quote! { fn hello() { println!("hi"); } }];
+        let formatted = format_merged_code(
+            FileHeader::default(),
+            items,
+            &Ordering::default(),
+            Formatter::Rustfmt,
+            Edition::default(),
+            Some(dir.path()),
+        )
+        .unwrap();
+
+        assert!(formatted.contains("\tprintln!(\"hi\");"));
+    }
 }
@@ -0,0 +1,181 @@
+//! Module declaration module
+//!
+//! This module keeps `mod` declarations in step with generated files: a new
+//! `src/models/user.rs` is declared as `pub mod user;` in `src/models/mod.rs`,
+//! `src/models.rs`, or `src/lib.rs`/`src/main.rs` for files directly under
+//! `src`, whichever exists. Declarations are located on the parsed file and
+//! edited in place, so the rest of the parent file, comments included, is
+//! left as written.
+
+use anyhow::{Context, Result};
+use quote::ToTokens;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::spanned::Spanned;
+use syn::{Item, ItemMod, Visibility};
+
+/// Declare a Rust file in its parent module
+///
+/// Returns the parent file when a declaration was added.
+pub fn declare_module(path: &Path) -> Result<Option<PathBuf>> {
+    let Some((parent, name)) = parent_module(path) else {
+        return Ok(None);
+    };
+    let source = fs::read_to_string(&parent)?;
+    let file = syn::parse_file(&source)
+        .with_context(|| format!("Failed to parse {}", parent.display()))?;
+
+    let declarations: Vec<&ItemMod> = file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Mod(item) => Some(item),
+            _ => None,
+        })
+        .collect();
+    if declarations.iter().any(|item| item.ident == name) {
+        return Ok(None);
+    }
+
+    // Next to the other declarations, else after the imports, else before the first item
+    let (line, vis) = match declarations.last() {
+        Some(last) => (last.span().end().line, visibility(&last.vis)),
+        None => {
+            let last_import = file.items.iter().rfind(|item| matches!(item, Item::Use(_)));
+            let line = match (last_import, file.items.first()) {
+                (Some(last), _) => last.span().end().line,
+                (None, Some(first)) => first.span().start().line - 1,
+                (None, None) => source.lines().count(),
+            };
+            (line, "pub ".to_string())
+        }
+    };
+
+    let declaration = format!("{}mod {};\n", vis, name);
+    fs::write(&parent, insert_line(&source, line, &declaration))?;
+    Ok(Some(parent))
+}
+
+/// Remove the declaration of a Rust file from its parent module
+///
+/// Returns the parent file when a declaration was removed.
+pub fn undeclare_module(path: &Path) -> Result<Option<PathBuf>> {
+    let Some((parent, name)) = parent_module(path) else {
+        return Ok(None);
+    };
+    let source = fs::read_to_string(&parent)?;
+    let file = syn::parse_file(&source)
+        .with_context(|| format!("Failed to parse {}", parent.display()))?;
+
+    let Some(declaration) = file.items.iter().find_map(|item| match item {
+        Item::Mod(item) if item.ident == name && item.content.is_none() => Some(item),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+
+    let (start, end) = (
+        declaration.span().start().line,
+        declaration.span().end().line,
+    );
+    let kept: String = source
+        .split_inclusive('\n')
+        .enumerate()
+        .filter(|(index, _)| !(start..=end).contains(&(index + 1)))
+        .map(|(_, line)| line)
+        .collect();
+    fs::write(&parent, kept)?;
+    Ok(Some(parent))
+}
+
+/// Parent module file and module name of a Rust file, when the parent exists
+fn parent_module(path: &Path) -> Option<(PathBuf, String)> {
+    if path.extension()? != "rs" {
+        return None;
+    }
+    let dir = path.parent()?;
+    let (name, location) = match path.file_name()?.to_str()? {
+        "mod.rs" => (dir.file_name()?.to_str()?, dir.parent()?),
+        "lib.rs" | "main.rs" | "build.rs" => return None,
+        _ => (path.file_stem()?.to_str()?, dir),
+    };
+    if syn::parse_str::<syn::Ident>(name).is_err() {
+        return None;
+    }
+
+    let mut candidates = vec![location.join("mod.rs")];
+    if location.file_name()? == "src" {
+        candidates.push(location.join("lib.rs"));
+        candidates.push(location.join("main.rs"));
+    }
+    candidates.push(location.with_extension("rs"));
+
+    let parent = candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())?;
+    Some((parent, name.to_string()))
+}
+
+/// Visibility prefix as written in source, `pub ` or empty
+fn visibility(vis: &Visibility) -> String {
+    match vis {
+        Visibility::Inherited => String::new(),
+        vis => format!("{} ", vis.to_token_stream()).replace(" (", "("),
+    }
+}
+
+/// Insert `text` after the first `line` lines of `source`
+pub(crate) fn insert_line(source: &str, line: usize, text: &str) -> String {
+    let mut lines: Vec<&str> = source.split_inclusive('\n').collect();
+    let line = line.min(lines.len());
+    let mut result: String = lines.drain(..line).collect();
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+    result.push_str(text);
+    result.extend(lines);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declare_and_undeclare() {
+        let dir = tempfile::tempdir().unwrap();
+        let models = dir.path().join("src/models");
+        fs::create_dir_all(&models).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "//! My crate\n\nuse std::fmt;\n\n// Re-exported below\npub(crate) mod models;\n\npub fn run() {}\n",
+        )
+        .unwrap();
+        fs::write(models.join("mod.rs"), "").unwrap();
+
+        let user = models.join("user.rs");
+        assert_eq!(declare_module(&user).unwrap(), Some(models.join("mod.rs")));
+        assert_eq!(
+            fs::read_to_string(models.join("mod.rs")).unwrap(),
+            "pub mod user;\n"
+        );
+        assert_eq!(declare_module(&user).unwrap(), None);
+
+        let lib = dir.path().join("src/lib.rs");
+        declare_module(&dir.path().join("src/config.rs")).unwrap();
+        assert_eq!(
+            fs::read_to_string(&lib).unwrap(),
+            "//! My crate\n\nuse std::fmt;\n\n// Re-exported below\npub(crate) mod models;\npub(crate) mod config;\n\npub fn run() {}\n"
+        );
+
+        assert_eq!(
+            undeclare_module(&user).unwrap(),
+            Some(models.join("mod.rs"))
+        );
+        assert_eq!(fs::read_to_string(models.join("mod.rs")).unwrap(), "");
+        assert_eq!(
+            declare_module(&dir.path().join("other/user.rs")).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Item ordering module
+//!
+//! This module sorts the items of merged files from the `[ordering]` table of
+//! `rpt.toml`, so merged files stay tidy without reordering them by hand:
+//!
+//! ```toml
+//! [ordering]
+//! groups = ["use", "const", "struct", "enum", "trait", "impl", "fn"]
+//! alphabetical = true   # sort by name within each group
+//! ```
+//!
+//! Kinds missing from `groups` come last. Items keep their merged order
+//! within a group unless `alphabetical` is set, and without any setting the
+//! existing order is preserved.
+
+use anyhow::Result;
+use syn::Item;
+
+use crate::ast_parser::item_kind;
+use crate::config::Config;
+use crate::diff::extract_item_name;
+use crate::imports::import_paths;
+
+/// Item kinds a group can hold, as named by [`crate::ast_parser::item_kind`]
+const GROUP_KINDS: &[&str] = &[
+    "use",
+    "extern crate",
+    "mod",
+    "const",
+    "static",
+    "type",
+    "struct",
+    "enum",
+    "union",
+    "trait",
+    "trait alias",
+    "impl",
+    "fn",
+    "macro",
+    "extern block",
+];
+
+/// Order of the items in merged files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ordering {
+    groups: Vec<String>,
+    alphabetical: bool,
+}
+
+impl Ordering {
+    /// Read the ordering declared in the config
+    pub fn from_config(config: &Config) -> Result<Self> {
+        for kind in &config.ordering.groups {
+            if !GROUP_KINDS.contains(&kind.as_str()) {
+                anyhow::bail!(
+                    "Unknown item kind '{}' in [ordering] groups, expected one of {}",
+                    kind,
+                    GROUP_KINDS.join(", ")
+                );
+            }
+        }
+
+        Ok(Ordering {
+            groups: config.ordering.groups.clone(),
+            alphabetical: config.ordering.alphabetical,
+        })
+    }
+
+    /// Whether items keep their merged order
+    pub fn is_preserve(&self) -> bool {
+        self.groups.is_empty() && !self.alphabetical
+    }
+
+    /// Sort items by group, then by name when alphabetical
+    pub fn apply(&self, items: &mut [Item]) {
+        if self.is_preserve() {
+            return;
+        }
+
+        let group = |item: &Item| {
+            let kind = item_kind(item);
+            self.groups
+                .iter()
+                .position(|group| group == kind)
+                .unwrap_or(self.groups.len())
+        };
+        if self.alphabetical {
+            items.sort_by_cached_key(|item| (group(item), sort_name(item)));
+        } else {
+            items.sort_by_cached_key(group);
+        }
+    }
+}
+
+/// Name items are sorted by: their name, or the first path of an import
+fn sort_name(item: &Item) -> Option<String> {
+    match item {
+        Item::Use(item) => import_paths(item).into_iter().next(),
+        _ => extract_item_name(item),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OrderingConfig;
+
+    #[test]
+    fn test_ordering() {
+        let items = || {
+            syn::parse_file(
+                "fn b() {}\nstruct User;\nuse std::io;\nfn a() {}\nimpl User {}\nuse std::fmt;\nconst MAX: u8 = 1;",
+            )
+            .unwrap()
+            .items
+        };
+        let ordering = |groups: &[&str], alphabetical| {
+            Ordering::from_config(&Config {
+                ordering: OrderingConfig {
+                    groups: groups.iter().map(|kind| kind.to_string()).collect(),
+                    alphabetical,
+                },
+                ..Config::default()
+            })
+        };
+        let sorted = |ordering: Ordering| {
+            let mut items = items();
+            ordering.apply(&mut items);
+            items
+        };
+        let expected = |code| syn::parse_file(code).unwrap().items;
+
+        assert_eq!(sorted(ordering(&[], false).unwrap()), items());
+        assert_eq!(
+            sorted(ordering(&["use", "struct", "fn"], false).unwrap()),
+            expected("use std::io;\nuse std::fmt;\nstruct User;\nfn b() {}\nfn a() {}\nimpl User {}\nconst MAX: u8 = 1;")
+        );
+        assert_eq!(
+            sorted(ordering(&["use", "const", "struct", "impl", "fn"], true).unwrap()),
+            expected("use std::fmt;\nuse std::io;\nconst MAX: u8 = 1;\nstruct User;\nimpl User {}\nfn a() {}\nfn b() {}")
+        );
+        assert!(ordering(&["types"], false).is_err());
+    }
+}
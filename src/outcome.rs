@@ -0,0 +1,177 @@
+//! Generation outcome module
+//!
+//! This module builds the machine-readable result `generate --format json`
+//! prints instead of its messages: for each written file, whether it changed,
+//! how many items the merge inserted, deleted, modified, renamed or moved, and
+//! the conflicts met with how each was resolved, then the verification status.
+//!
+//! Resolutions are read from the conflict messages: `manual` when the manual
+//! version was kept, `template` when the template's won, `merged` when both
+//! were combined, and `unresolved` when the conflict was left for the user.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::ast_parser::ParsedFile;
+use crate::diff::{compute_patch, PatchOp};
+use crate::generator::Language;
+
+/// Result of a `generate` run
+#[derive(Debug, Default, Serialize)]
+pub struct GenerateOutcome {
+    pub files: Vec<FileOutcome>,
+    /// `None` without `--verify`
+    pub verification: Option<VerificationOutcome>,
+}
+
+/// What generation did to one output file
+#[derive(Debug, Serialize)]
+pub struct FileOutcome {
+    pub path: PathBuf,
+    pub created: bool,
+    pub changed: bool,
+    /// Items inserted, deleted, ... by operation; `None` for outputs that aren't valid Rust
+    pub operations: Option<BTreeMap<&'static str, usize>>,
+    pub conflicts: Vec<ConflictOutcome>,
+    /// Copy of an existing file that didn't parse
+    pub backup: Option<PathBuf>,
+    /// Module a created file was declared in
+    pub declared_in: Option<PathBuf>,
+}
+
+/// A conflict met while merging, and how it was resolved
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ConflictOutcome {
+    pub message: String,
+    pub resolution: &'static str,
+}
+
+/// Status of `--verify`
+#[derive(Debug, Serialize)]
+pub struct VerificationOutcome {
+    /// `check`, `clippy` or `clippy-report`
+    pub mode: String,
+    pub passed: bool,
+    /// Clippy warnings in generated items
+    pub warnings: Vec<String>,
+    /// Why verification failed
+    pub error: Option<String>,
+}
+
+impl FileOutcome {
+    /// Describe the change from `previous` to the written `code`
+    pub fn new(
+        path: PathBuf,
+        previous: Option<&str>,
+        code: &str,
+        language: Language,
+        conflicts: Vec<String>,
+    ) -> Self {
+        FileOutcome {
+            operations: (language == Language::Rust)
+                .then(|| count_operations(previous.unwrap_or_default(), code))
+                .flatten(),
+            created: previous.is_none(),
+            changed: previous != Some(code),
+            conflicts: conflicts.into_iter().map(ConflictOutcome::new).collect(),
+            backup: None,
+            declared_in: None,
+            path,
+        }
+    }
+}
+
+impl ConflictOutcome {
+    /// Classify a conflict message by its resolution
+    pub fn new(message: String) -> Self {
+        let resolution = if message.starts_with("Conflict") {
+            "unresolved"
+        } else if message.contains("kept") || message.contains("skipped") {
+            "manual"
+        } else if [
+            "by template",
+            "manual version replaced",
+            "content was dropped",
+        ]
+        .iter()
+        .any(|phrase| message.contains(phrase))
+        {
+            "template"
+        } else if message.contains("merged") || message.contains("combined") {
+            "merged"
+        } else {
+            "manual"
+        };
+        ConflictOutcome {
+            message,
+            resolution,
+        }
+    }
+}
+
+/// Patch operations from `previous` to `code` by name, when both parse
+fn count_operations(previous: &str, code: &str) -> Option<BTreeMap<&'static str, usize>> {
+    let previous = ParsedFile::parse(previous).ok()?;
+    let code = ParsedFile::parse(code).ok()?;
+    let patch = compute_patch(previous.items(), code.items()).ok()?;
+
+    let mut counts = BTreeMap::from([
+        ("insert", 0),
+        ("delete", 0),
+        ("modify", 0),
+        ("rename", 0),
+        ("move", 0),
+    ]);
+    for op in &patch.operations {
+        let name = match op {
+            PatchOp::Insert { .. } => "insert",
+            PatchOp::Delete { .. } => "delete",
+            PatchOp::Modify { .. } => "modify",
+            PatchOp::Rename { .. } => "rename",
+            PatchOp::Move { .. } => "move",
+            PatchOp::Keep { .. } => continue,
+        };
+        *counts.entry(name).or_default() += 1;
+    }
+    Some(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_outcome() {
+        let outcome = FileOutcome::new(
+            PathBuf::from("src/user.rs"),
+            Some("fn a() {}\nfn b() {}\n"),
+            "fn a() -> u8 { 1 }\nfn c() {}\n",
+            Language::Rust,
+            vec![
+                "Item 'b' was deleted in template but exists in base".to_string(),
+                "Item 'a' has manual changes, overridden by template".to_string(),
+                "Item 'd' was renamed to 'e' by template, manual version kept".to_string(),
+                "Conflict: Lines 2-3 have manual changes conflicting with template".to_string(),
+            ],
+        );
+
+        assert!(outcome.changed && !outcome.created);
+        assert_eq!(
+            outcome.operations,
+            Some(BTreeMap::from([
+                ("insert", 1),
+                ("delete", 1),
+                ("modify", 1),
+                ("rename", 0),
+                ("move", 0),
+            ]))
+        );
+        let resolutions: Vec<_> = outcome
+            .conflicts
+            .iter()
+            .map(|conflict| conflict.resolution)
+            .collect();
+        assert_eq!(resolutions, ["manual", "template", "manual", "unresolved"]);
+    }
+}
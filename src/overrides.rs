@@ -0,0 +1,100 @@
+//! Template overrides module
+//!
+//! This module lets a project tweak shared templates without forking them. A
+//! template in the overrides directory (`templates-overrides/` next to the
+//! config file, or `overrides_dir` in `rpt.toml`) replaces the shared template
+//! of the same name:
+//!
+//! - `builtin:<name>` templates (see [`crate::builtin`]) by `<name>.tera`,
+//! - git templates (see [`crate::remote`]) by their path in the repository,
+//! - templates under `template_dirs` by their path relative to the directory,
+//! - templates a template file includes, imports or extends by their name.
+
+use std::path::{Path, PathBuf};
+
+use crate::builtin;
+use crate::config::Config;
+use crate::remote::RemoteTemplate;
+
+/// Override of a shared template's name, when the project has one
+pub fn overriding(config: &Config, name: &Path) -> Option<PathBuf> {
+    Some(config.overrides_dir().join(name)).filter(|path| path.is_file())
+}
+
+/// The template to render for the `--template` argument, once `resolved` to a local path
+pub fn apply(config: &Config, argument: &Path, resolved: &Path) -> PathBuf {
+    shared_name(config, argument, resolved)
+        .and_then(|name| overriding(config, &name))
+        .unwrap_or_else(|| resolved.to_path_buf())
+}
+
+/// Name a shared template goes by, `None` for the project's own templates
+fn shared_name(config: &Config, argument: &Path, resolved: &Path) -> Option<PathBuf> {
+    if let Some(name) = argument
+        .to_str()
+        .and_then(|argument| argument.strip_prefix(builtin::PREFIX))
+    {
+        return Some(PathBuf::from(format!("{}.tera", name)));
+    }
+    if let Some(remote) = RemoteTemplate::parse(argument) {
+        return Some(remote.path);
+    }
+
+    let resolved = resolved.canonicalize().ok()?;
+    config
+        .template_search_dirs()
+        .iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .find_map(|dir| resolved.strip_prefix(dir).ok().map(Path::to_path_buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_override_shared_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "vendor/models/entity.tera",
+            "templates-overrides/models/entity.tera",
+            "templates-overrides/builder.tera",
+            "local.tera",
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let config = Config {
+            template_dirs: vec![PathBuf::from("vendor")],
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let overrides = dir.path().join("templates-overrides");
+
+        let entity = dir.path().join("vendor/models/entity.tera");
+        assert_eq!(
+            apply(&config, &entity, &entity),
+            overrides.join("models/entity.tera")
+        );
+        assert_eq!(
+            apply(
+                &config,
+                Path::new("builtin:builder"),
+                Path::new("cache/builder.tera")
+            ),
+            overrides.join("builder.tera")
+        );
+        assert_eq!(
+            apply(
+                &config,
+                Path::new("https://example.com/t.git#models/entity.tera"),
+                Path::new("checkout/models/entity.tera")
+            ),
+            overrides.join("models/entity.tera")
+        );
+        let local = dir.path().join("local.tera");
+        assert_eq!(apply(&config, &local, &local), local);
+    }
+}
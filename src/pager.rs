@@ -0,0 +1,100 @@
+//! Pager module
+//!
+//! This module shows long output through a pager, as git does: when stdout is
+//! a terminal and the text is taller than it, the text is piped to `$PAGER`,
+//! else `less -R`. An empty `$PAGER` or `cat`, a missing pager, and `--no-pager`
+//! print it directly.
+
+use anyhow::{Context, Result};
+use std::io::{ErrorKind, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Pager used when `$PAGER` isn't set
+const DEFAULT_PAGER: &str = "less -R";
+
+/// Rows assumed when the terminal size is unknown
+const DEFAULT_HEIGHT: usize = 24;
+
+/// Print `text` to stdout, through the pager when `enabled` and it doesn't fit the terminal
+pub fn print_paged(text: &str, enabled: bool) -> Result<()> {
+    let stdout = std::io::stdout();
+    if enabled && stdout.is_terminal() && text.lines().count() > terminal_height() {
+        if let Some(pager) = pager_command(std::env::var("PAGER").ok()) {
+            if page(&pager, text)? {
+                return Ok(());
+            }
+        }
+    }
+
+    print!("{}", text);
+    Ok(())
+}
+
+/// Program and arguments of the pager, `None` when paging is turned off
+fn pager_command(pager: Option<String>) -> Option<Vec<String>> {
+    let pager = pager.unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    let command: Vec<String> = pager.split_whitespace().map(str::to_string).collect();
+    match command.first().map(String::as_str) {
+        None | Some("cat") => None,
+        Some(_) => Some(command),
+    }
+}
+
+/// Pipe `text` to the pager, returning false when it can't be started
+fn page(pager: &[String], text: &str) -> Result<bool> {
+    let mut child = match Command::new(&pager[0])
+        .args(&pager[1..])
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(error) => {
+            return Err(error).with_context(|| format!("Failed to run pager {}", pager[0]))
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe, which is not an error
+        match stdin.write_all(text.as_bytes()) {
+            Err(error) if error.kind() != ErrorKind::BrokenPipe => {
+                return Err(error).context("Failed to write to the pager")
+            }
+            _ => {}
+        }
+    }
+    child.wait().context("Failed to wait for the pager")?;
+    Ok(true)
+}
+
+/// Rows of the terminal, from the terminal itself or `$LINES`
+fn terminal_height() -> usize {
+    #[cfg(feature = "tui")]
+    if let Ok((_, rows)) = ratatui::crossterm::terminal::size() {
+        return rows.into();
+    }
+
+    std::env::var("LINES")
+        .ok()
+        .and_then(|lines| lines.parse().ok())
+        .unwrap_or(DEFAULT_HEIGHT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pager_command() {
+        assert_eq!(
+            pager_command(None),
+            Some(vec!["less".to_string(), "-R".to_string()])
+        );
+        assert_eq!(
+            pager_command(Some("most -s".to_string())),
+            Some(vec!["most".to_string(), "-s".to_string()])
+        );
+        assert_eq!(pager_command(Some(String::new())), None);
+        assert_eq!(pager_command(Some("cat".to_string())), None);
+    }
+}
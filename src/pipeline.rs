@@ -0,0 +1,322 @@
+//! Generator pipeline module
+//!
+//! This module is the library entry point for generating files: a
+//! [`Generator`] is configured once and then run over many targets, compiling
+//! each template once.
+//!
+//! ```no_run
+//! use rpt::merger::{Formatter, MergeStrategy};
+//! use rpt::template::create_context;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut generator = rpt::Generator::builder()
+//!     .template_dir("templates")
+//!     .strategy(MergeStrategy::PreferManual)
+//!     .formatter(Formatter::Rustfmt)
+//!     .hook("cargo check")
+//!     .build();
+//!
+//! for name in ["User", "Post"] {
+//!     let context = create_context(vec![("name", name.into())]);
+//!     let output = format!("src/models/{}.rs", name.to_lowercase());
+//!     generator.generate_file("model.rs.tera", context, output)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::edition::Edition;
+use crate::events::{Event, EventSink};
+use crate::generator::{
+    diff_sources, generate_cached, is_up_to_date, render_cached, GenerateOptions, MergedOutput,
+};
+use crate::hooks::run_hooks;
+use crate::matching::ItemMatcher;
+use crate::merger::{Formatter, MergeStrategy};
+use crate::policy::MergePolicy;
+use crate::template::TemplateCache;
+
+/// Generation pipeline configured once, see [`Generator::builder`]
+pub struct Generator {
+    template_dir: PathBuf,
+    options: GenerateOptions,
+    hooks: Vec<String>,
+    cache: TemplateCache,
+}
+
+/// Settings of a [`Generator`]
+#[derive(Debug, Default)]
+pub struct GeneratorBuilder {
+    template_dir: PathBuf,
+    options: GenerateOptions,
+    hooks: Vec<String>,
+}
+
+impl GeneratorBuilder {
+    /// Directory template paths are relative to [default: the current directory]
+    pub fn template_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.template_dir = dir.into();
+        self
+    }
+
+    /// How conflicts with manual edits are resolved
+    pub fn strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.options.strategy = strategy;
+        self
+    }
+
+    /// Printer used for merged output
+    pub fn formatter(mut self, formatter: Formatter) -> Self {
+        self.options.formatter = formatter;
+        self
+    }
+
+    /// Per-item conflict resolution, consulted before the strategy
+    pub fn policy(mut self, policy: impl MergePolicy + 'static) -> Self {
+        self.options.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Receiver of progress events, see [`crate::events`]
+    pub fn events(mut self, sink: impl EventSink + 'static) -> Self {
+        self.options.events = Some(Arc::new(sink));
+        self
+    }
+
+    /// How existing items are paired with template items, see [`crate::matching`]
+    pub fn matcher(mut self, matcher: impl ItemMatcher + 'static) -> Self {
+        self.options.matcher = Some(Arc::new(matcher));
+        self
+    }
+
+    /// Shell command to run after each written file, as a post hook of rpt.toml
+    pub fn hook(mut self, command: impl Into<String>) -> Self {
+        self.hooks.push(command.into());
+        self
+    }
+
+    /// Every other option, keeping the strategy, formatter, policy, events and matcher set before
+    pub fn options(mut self, options: GenerateOptions) -> Self {
+        self.options = GenerateOptions {
+            strategy: self.options.strategy,
+            formatter: self.options.formatter,
+            policy: self.options.policy.take().or(options.policy),
+            events: self.options.events.take().or(options.events),
+            matcher: self.options.matcher.take().or(options.matcher),
+            ..options
+        };
+        self
+    }
+
+    /// The configured generator
+    pub fn build(self) -> Generator {
+        Generator {
+            template_dir: self.template_dir,
+            options: self.options,
+            hooks: self.hooks,
+            cache: TemplateCache::new(),
+        }
+    }
+}
+
+impl Generator {
+    /// Start configuring a generator
+    pub fn builder() -> GeneratorBuilder {
+        GeneratorBuilder::default()
+    }
+
+    /// Generate `output` from a template and merge it into the existing file, then run the hooks
+    pub fn generate_file(
+        &mut self,
+        template: impl AsRef<Path>,
+        context: HashMap<String, Value>,
+        output: impl AsRef<Path>,
+    ) -> Result<MergedOutput> {
+        let template = self.template_dir.join(template);
+        let output = output.as_ref();
+        let merged = self.merge(&template, context, output)?;
+
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(output, &merged.code)
+            .with_context(|| format!("Failed to write {}", output.display()))?;
+        self.options.emit(|| Event::FileWritten {
+            path: output.to_path_buf(),
+        });
+        run_hooks(
+            "post",
+            &self.hooks,
+            Path::new("."),
+            Some((&template, output)),
+        )?;
+
+        Ok(merged)
+    }
+
+    /// Structural diff from `existing` to the rendered template, as the `diff` command prints it
+    pub fn diff(
+        &mut self,
+        template: impl AsRef<Path>,
+        context: HashMap<String, Value>,
+        existing: impl AsRef<Path>,
+    ) -> Result<String> {
+        let template = self.template_dir.join(template);
+        let existing = existing.as_ref();
+        let generated = render_cached(&mut self.cache, &template, &context, self.options.render)?;
+        let existing_code = fs::read_to_string(existing)
+            .with_context(|| format!("Failed to read {}", existing.display()))?;
+
+        let edition = Edition::resolve(self.options.edition, existing.parent());
+
+        self.options
+            .matching(|| diff_sources(&existing_code, &generated, false, edition))
+    }
+
+    /// Whether `output` already holds what [`Generator::generate_file`] would write
+    pub fn check(
+        &mut self,
+        template: impl AsRef<Path>,
+        context: HashMap<String, Value>,
+        output: impl AsRef<Path>,
+    ) -> Result<bool> {
+        let template = self.template_dir.join(template);
+        let output = output.as_ref();
+        let merged = self.merge(&template, context, output)?;
+
+        is_up_to_date(
+            output,
+            &merged.code,
+            Edition::resolve(self.options.edition, output.parent()),
+        )
+    }
+
+    /// Render and merge in memory
+    fn merge(
+        &mut self,
+        template: &Path,
+        context: HashMap<String, Value>,
+        output: &Path,
+    ) -> Result<MergedOutput> {
+        generate_cached(
+            &mut self.cache,
+            template,
+            context,
+            output,
+            self.options.clone(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::create_context;
+    use serde_json::json;
+
+    #[test]
+    fn test_generator_builder() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("templates")).unwrap();
+        fs::write(
+            dir.path().join("templates/model.tera"),
+            "pub struct {{ name }};\n",
+        )
+        .unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut generator = Generator::builder()
+            .template_dir(dir.path().join("templates"))
+            .strategy(MergeStrategy::PreferTemplate)
+            .events(sender)
+            .build();
+
+        for name in ["User", "Post"] {
+            let output = dir.path().join(format!("{}.rs", name.to_lowercase()));
+            let context = create_context(vec![("name", json!(name))]);
+            assert!(!generator
+                .check("model.tera", context.clone(), &output)
+                .unwrap());
+            generator
+                .generate_file("model.tera", context.clone(), &output)
+                .unwrap();
+            assert!(generator.check("model.tera", context, &output).unwrap());
+        }
+        assert_eq!(
+            fs::read_to_string(dir.path().join("post.rs")).unwrap(),
+            "pub struct Post;\n"
+        );
+        let events: Vec<Event> = receiver.try_iter().collect();
+        assert_eq!(
+            events[..4],
+            [
+                Event::RenderStarted {
+                    template: dir.path().join("templates/model.tera"),
+                },
+                Event::Parsed {
+                    generated: 1,
+                    existing: 0,
+                },
+                Event::RenderStarted {
+                    template: dir.path().join("templates/model.tera"),
+                },
+                Event::Parsed {
+                    generated: 1,
+                    existing: 0,
+                },
+            ]
+        );
+        assert!(events.contains(&Event::FileWritten {
+            path: dir.path().join("post.rs"),
+        }));
+
+        fs::write(
+            dir.path().join("user.rs"),
+            "pub struct User;\nfn helper() {}\n",
+        )
+        .unwrap();
+        let diff = generator
+            .diff(
+                "model.tera",
+                create_context(vec![("name", json!("User"))]),
+                dir.path().join("user.rs"),
+            )
+            .unwrap();
+        assert!(diff.contains("Delete `helper`"));
+    }
+
+    #[test]
+    fn test_generators_keep_their_own_matcher() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("api.tera"),
+            "#[codegen(id = \"user.create\")]\npub fn create_user() {}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("api.rs"),
+            "#[codegen(id = \"user.create\")]\npub fn add_user() {}\n",
+        )
+        .unwrap();
+        let mut by_id = Generator::builder()
+            .template_dir(dir.path())
+            .matcher(crate::matching::AttributeMatcher::new("codegen", "id"))
+            .build();
+        let mut by_name = Generator::builder().template_dir(dir.path()).build();
+
+        let diff = |generator: &mut Generator| {
+            generator
+                .diff("api.tera", HashMap::new(), dir.path().join("api.rs"))
+                .unwrap()
+        };
+        assert!(!diff(&mut by_id).contains("Delete `add_user`"));
+        assert!(diff(&mut by_name).contains("Delete `add_user`"));
+        assert!(!diff(&mut by_id).contains("Delete `add_user`"));
+    }
+}
@@ -0,0 +1,139 @@
+//! Item placement module
+//!
+//! This module decides where items the template adds are inserted into an
+//! existing file, per item kind, from the `[placement]` table of `rpt.toml`:
+//!
+//! ```toml
+//! [placement]
+//! fn = "after-last"        # after the last existing fn
+//! struct = "after:mod models"  # after `mod models`
+//! default = "template"     # after the item preceding it in the template
+//! ```
+//!
+//! Rules only apply when existing items keep their order, i.e. without
+//! `--respect-order`.
+//!
+//! Anchors name items, not comments: plain `//` comments such as
+//! `// --- models ---` don't survive merging, so a marker comment would be gone
+//! after the first merge. A `mod models`, named `mod models`, or a marker
+//! `const` serves instead.
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+use crate::config::Config;
+
+/// Item kinds a rule can be declared for, as named by [`crate::ast_parser::item_kind`]
+pub(crate) const KINDS: &[&str] = &[
+    "fn",
+    "struct",
+    "enum",
+    "trait",
+    "type",
+    "const",
+    "static",
+    "mod",
+    "impl",
+    "union",
+    "trait alias",
+    "extern block",
+    "default",
+];
+
+/// Where a new item is inserted
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placement {
+    /// After the existing item that precedes it in the template
+    Template,
+    /// Before all existing items
+    Start,
+    /// After all existing items
+    End,
+    /// After the last existing item of the same kind
+    AfterLast,
+    /// After the existing item with this name
+    After(String),
+}
+
+impl Placement {
+    /// Parse a placement: template, start, end, after-last or after:<name>
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "template" => Ok(Placement::Template),
+            "start" => Ok(Placement::Start),
+            "end" => Ok(Placement::End),
+            "after-last" => Ok(Placement::AfterLast),
+            _ => match value.strip_prefix("after:") {
+                Some(name) if !name.is_empty() => Ok(Placement::After(name.to_string())),
+                _ => anyhow::bail!(
+                    "Unknown placement '{}', expected template, start, end, after-last or after:<name>",
+                    value
+                ),
+            },
+        }
+    }
+}
+
+/// Placement of new items by item kind
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlacementRules {
+    rules: HashMap<String, Placement>,
+}
+
+impl PlacementRules {
+    /// Read the rules declared in the config
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut rules = HashMap::new();
+        for (kind, value) in &config.placement {
+            if !KINDS.contains(&kind.as_str()) {
+                anyhow::bail!(
+                    "Unknown item kind '{}' in [placement], expected one of {}",
+                    kind,
+                    KINDS.join(", ")
+                );
+            }
+            rules.insert(kind.clone(), Placement::parse(value)?);
+        }
+
+        Ok(PlacementRules { rules })
+    }
+
+    /// Placement of a new item of `kind`, falling back to the `default` rule
+    pub fn get(&self, kind: &str) -> &Placement {
+        self.rules
+            .get(kind)
+            .or_else(|| self.rules.get("default"))
+            .unwrap_or(&Placement::Template)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_from_config() {
+        let config = Config {
+            placement: [
+                ("fn".to_string(), "after-last".to_string()),
+                ("struct".to_string(), "after:models".to_string()),
+                ("default".to_string(), "end".to_string()),
+            ]
+            .into(),
+            ..Config::default()
+        };
+
+        let rules = PlacementRules::from_config(&config).unwrap();
+        assert_eq!(rules.get("fn"), &Placement::AfterLast);
+        assert_eq!(rules.get("struct"), &Placement::After("models".to_string()));
+        assert_eq!(rules.get("enum"), &Placement::End);
+        assert_eq!(PlacementRules::default().get("fn"), &Placement::Template);
+
+        assert!(Placement::parse("after:").is_err());
+        let config = Config {
+            placement: [("use".to_string(), "end".to_string())].into(),
+            ..Config::default()
+        };
+        assert!(PlacementRules::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Template plugin module
+//!
+//! This module loads the Rhai scripts declared under `[plugins]` in `rpt.toml`
+//! and registers their public functions into every Tera instance, so projects
+//! can add domain-specific filters and functions without forking the tool:
+//!
+//! ```toml
+//! [plugins]
+//! filters = ["plugins/naming.rhai"]
+//! ```
+//!
+//! ```rhai
+//! fn table_name(value) { value.to_lower() + "s" }
+//! ```
+//!
+//! Filters take the piped value and optionally a map of the filter arguments;
+//! functions optionally take the map of their arguments. Values cross the
+//! boundary as JSON-like Rhai values. Private (`private fn`) script functions
+//! are helpers and are not registered.
+
+use anyhow::{Context, Result};
+use rhai::serde::{from_dynamic, to_dynamic};
+use rhai::{CallFnOptions, Dynamic, FnAccess, Scope, AST};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use tera::Tera;
+
+use crate::config::Config;
+
+/// Plugins registered into every Tera instance created by this process
+static INSTALLED: RwLock<Option<Plugins>> = RwLock::new(None);
+
+/// Filters and functions loaded from plugin scripts
+#[derive(Clone, Default)]
+pub struct Plugins {
+    filters: Vec<ScriptFn>,
+    functions: Vec<ScriptFn>,
+}
+
+/// A public function of a compiled script
+#[derive(Clone)]
+struct ScriptFn {
+    engine: Arc<rhai::Engine>,
+    ast: Arc<AST>,
+    name: String,
+    arity: usize,
+}
+
+impl Plugins {
+    /// Compile the plugin scripts declared in the config
+    pub fn load(config: &Config) -> Result<Self> {
+        let engine = Arc::new(rhai::Engine::new());
+        let mut plugins = Plugins::default();
+
+        for path in &config.plugins.filters {
+            let path = config.resolve_path(path);
+            plugins.filters.extend(compile(&engine, &path, 1..=2)?);
+        }
+        for path in &config.plugins.functions {
+            let path = config.resolve_path(path);
+            plugins.functions.extend(compile(&engine, &path, 0..=1)?);
+        }
+
+        Ok(plugins)
+    }
+
+    /// Names of the loaded filters
+    pub fn filter_names(&self) -> Vec<&str> {
+        self.filters.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    /// Names of the loaded functions
+    pub fn function_names(&self) -> Vec<&str> {
+        self.functions.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    /// Register the filters and functions into a Tera instance
+    pub fn register(&self, tera: &mut Tera) {
+        for filter in &self.filters {
+            tera.register_filter(&filter.name, filter.clone());
+        }
+        for function in &self.functions {
+            tera.register_function(&function.name, function.clone());
+        }
+    }
+}
+
+/// Make `plugins` available to every Tera instance created afterwards
+pub fn install(plugins: Plugins) {
+    *INSTALLED.write().unwrap_or_else(|e| e.into_inner()) = Some(plugins);
+}
+
+/// Register the installed plugins, if any, into a Tera instance
+pub fn register_installed(tera: &mut Tera) {
+    if let Some(plugins) = INSTALLED.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        plugins.register(tera);
+    }
+}
+
+/// Compile a script and collect its public functions, checking their arity
+fn compile(
+    engine: &Arc<rhai::Engine>,
+    path: &Path,
+    arity: std::ops::RangeInclusive<usize>,
+) -> Result<Vec<ScriptFn>> {
+    let ast = engine
+        .compile_file(path.to_path_buf())
+        .with_context(|| format!("Failed to compile plugin {}", path.display()))?;
+    let ast = Arc::new(ast);
+
+    let mut functions: Vec<_> = ast.iter_functions().collect();
+    functions.sort_by_key(|function| function.name);
+
+    functions
+        .into_iter()
+        .filter(|function| function.access != FnAccess::Private)
+        .map(|function| {
+            if !arity.contains(&function.params.len()) {
+                anyhow::bail!(
+                    "Plugin function `{}` in {} takes {} parameters, expected {} to {}",
+                    function.name,
+                    path.display(),
+                    function.params.len(),
+                    arity.start(),
+                    arity.end()
+                );
+            }
+
+            Ok(ScriptFn {
+                engine: Arc::clone(engine),
+                ast: Arc::clone(&ast),
+                name: function.name.to_string(),
+                arity: function.params.len(),
+            })
+        })
+        .collect()
+}
+
+impl ScriptFn {
+    /// Call the script function with its leading `arity` arguments
+    fn call(&self, mut args: Vec<Dynamic>) -> tera::Result<Value> {
+        args.truncate(self.arity);
+
+        let options = CallFnOptions::new().eval_ast(false);
+        let result: Dynamic = self
+            .engine
+            .call_fn_with_options(options, &mut Scope::new(), &self.ast, &self.name, args)
+            .map_err(|e| tera::Error::msg(format!("Plugin `{}` failed: {}", self.name, e)))?;
+
+        from_dynamic(&result).map_err(|e| {
+            tera::Error::msg(format!(
+                "Plugin `{}` returned an invalid value: {}",
+                self.name, e
+            ))
+        })
+    }
+}
+
+impl tera::Filter for ScriptFn {
+    fn filter(&self, value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        self.call(vec![script_value(value)?, script_value(args)?])
+    }
+}
+
+impl tera::Function for ScriptFn {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        ScriptFn::call(self, vec![script_value(args)?])
+    }
+}
+
+/// Convert a template value into a script value
+fn script_value<T: serde::Serialize>(value: &T) -> tera::Result<Dynamic> {
+    to_dynamic(value).map_err(|e| tera::Error::msg(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_script_filters_and_functions() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("naming.rhai"),
+            "fn table_name(value) { plural(value.to_lower()) }\n\
+             fn prefixed(value, args) { args.prefix + value }\n\
+             private fn plural(name) { name + \"s\" }\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("schema.rhai"),
+            "fn schema() { \"public\" }\n",
+        )
+        .unwrap();
+
+        let mut config = Config {
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config.plugins.filters = vec!["naming.rhai".into()];
+        config.plugins.functions = vec!["schema.rhai".into()];
+
+        let plugins = Plugins::load(&config).unwrap();
+        assert_eq!(plugins.filter_names(), vec!["prefixed", "table_name"]);
+        assert_eq!(plugins.function_names(), vec!["schema"]);
+
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "t",
+            "{{ schema() }}.{{ name | table_name }} {{ name | prefixed(prefix=\"Db\") }}",
+        )
+        .unwrap();
+        plugins.register(&mut tera);
+
+        let mut context = tera::Context::new();
+        context.insert("name", "User");
+        assert_eq!(tera.render("t", &context).unwrap(), "public.users DbUser");
+    }
+
+    #[test]
+    fn test_rejects_wrong_arity() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("bad.rhai"), "fn pair(a, b, c) { a }\n").unwrap();
+
+        let mut config = Config {
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config.plugins.filters = vec!["bad.rhai".into()];
+
+        let err = Plugins::load(&config).err().unwrap();
+        assert!(err.to_string().contains("takes 3 parameters"));
+    }
+}
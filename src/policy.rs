@@ -0,0 +1,331 @@
+//! Merge policy module
+//!
+//! This module lets a project decide conflicts item by item, instead of with
+//! one strategy for the whole file. The `[merge_policy]` table of `rpt.toml`
+//! names either a shell command or a Rhai script (`plugins` feature):
+//!
+//! ```toml
+//! [merge_policy]
+//! command = "./scripts/merge-policy"
+//! # or
+//! script = "policies/merge.rhai"
+//! ```
+//!
+//! Each conflict is handed over as a [`Conflict`]: on stdin as JSON for
+//! commands, as the argument of `fn resolve(conflict)` for scripts. The answer
+//! is `"manual"`, `"template"`, `"fail"`, `{ "custom": "<rust code>" }`, or
+//! nothing (empty output, `()`) to fall back to `--strategy`.
+//!
+//! Library users implement [`MergePolicy`] instead, to resolve conflicts in
+//! process, for instance by asking the user; closures taking a [`Conflict`]
+//! and returning an `Option<Resolution>` are policies:
+//!
+//! ```
+//! use rpt::policy::{Conflict, Resolution};
+//!
+//! let options = rpt::generator::GenerateOptions {
+//!     policy: Some(std::sync::Arc::new(|conflict: &Conflict| {
+//!         conflict.name.ends_with("Dto").then_some(Resolution::Template)
+//!     })),
+//!     ..Default::default()
+//! };
+//! ```
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use syn::Item;
+
+use crate::ast_parser::item_kind;
+use crate::config::Config;
+use crate::review::item_source;
+
+/// Source of per-item conflict resolutions, consulted before the merge strategy
+pub trait MergePolicy: Send + Sync {
+    /// How to resolve a conflict, `None` to use the merge strategy
+    fn resolve(&self, conflict: &Conflict) -> Result<Option<Resolution>>;
+}
+
+impl std::fmt::Debug for dyn MergePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MergePolicy")
+    }
+}
+
+impl<F> MergePolicy for F
+where
+    F: Fn(&Conflict) -> Option<Resolution> + Send + Sync,
+{
+    fn resolve(&self, conflict: &Conflict) -> Result<Option<Resolution>> {
+        Ok(self(conflict))
+    }
+}
+
+/// Policy declared in the config
+pub fn configured(config: &Config) -> Result<Option<Arc<dyn MergePolicy>>> {
+    Ok(ExternalPolicy::from_config(config)?.map(|policy| Arc::new(policy) as Arc<dyn MergePolicy>))
+}
+
+/// Policy run outside the process
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternalPolicy {
+    /// Shell command run from the config directory
+    Command { command: String, dir: PathBuf },
+    /// Rhai script defining `fn resolve(conflict)`
+    #[cfg(feature = "plugins")]
+    Script(PathBuf),
+}
+
+/// A conflict between the template and manual edits
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Conflict {
+    /// Item name
+    pub name: String,
+    /// Item kind, such as `struct` or `fn`
+    pub kind: String,
+    /// What the template does with the item
+    pub change: ConflictChange,
+    /// Current code of the item in the existing file
+    pub manual: Option<String>,
+    /// Code of the item generated by the template
+    pub template: Option<String>,
+}
+
+/// Template change that conflicts with the existing file
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictChange {
+    /// The template adds an item that already exists
+    Insert,
+    /// The template removes an item that still exists
+    Delete,
+    /// The template changes an item that was edited manually
+    Modify,
+}
+
+/// How a merge policy resolved a conflict
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// Keep the existing item
+    Manual,
+    /// Use the template's item, or drop it when the template deletes it
+    Template,
+    /// Abort the merge
+    Fail,
+    /// Replace the item with this code
+    Custom(String),
+}
+
+impl ExternalPolicy {
+    /// Get the policy declared in the config, if any
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let declared = &config.merge_policy;
+        match (&declared.command, &declared.script) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("[merge_policy] takes either `command` or `script`, not both")
+            }
+            (Some(command), None) => Ok(Some(ExternalPolicy::Command {
+                command: command.clone(),
+                dir: config.root.clone(),
+            })),
+            #[cfg(feature = "plugins")]
+            (None, Some(script)) => Ok(Some(ExternalPolicy::Script(config.resolve_path(script)))),
+            #[cfg(not(feature = "plugins"))]
+            (None, Some(_)) => {
+                anyhow::bail!("[merge_policy] scripts require building with the `plugins` feature")
+            }
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+impl MergePolicy for ExternalPolicy {
+    fn resolve(&self, conflict: &Conflict) -> Result<Option<Resolution>> {
+        let answer = match self {
+            ExternalPolicy::Command { command, dir } => run_command(command, dir, conflict)?,
+            #[cfg(feature = "plugins")]
+            ExternalPolicy::Script(path) => run_script(path, conflict)?,
+        };
+
+        Resolution::from_value(&answer)
+            .with_context(|| format!("Invalid merge policy answer for '{}'", conflict.name))
+    }
+}
+
+impl Conflict {
+    /// Describe a conflict on `name`, printing both versions of the item
+    pub fn new(
+        name: &str,
+        change: ConflictChange,
+        manual: Option<&Item>,
+        template: Option<&Item>,
+    ) -> Self {
+        let kind = manual.or(template).map(item_kind).unwrap_or("item");
+
+        Conflict {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            change,
+            manual: manual.map(item_source),
+            template: template.map(item_source),
+        }
+    }
+}
+
+impl Resolution {
+    /// Parse an answer, `null` meaning no decision
+    pub fn from_value(value: &Value) -> Result<Option<Self>> {
+        match value {
+            Value::Null => Ok(None),
+            Value::String(word) => match word.trim().to_lowercase().as_str() {
+                "" | "default" => Ok(None),
+                "manual" => Ok(Some(Resolution::Manual)),
+                "template" => Ok(Some(Resolution::Template)),
+                "fail" => Ok(Some(Resolution::Fail)),
+                other => anyhow::bail!(
+                    "Unknown resolution '{}', expected manual, template, fail or custom",
+                    other
+                ),
+            },
+            Value::Object(map) => match map.get("custom") {
+                Some(Value::String(code)) => Ok(Some(Resolution::Custom(code.clone()))),
+                _ => anyhow::bail!("Expected {{ \"custom\": \"<rust code>\" }}"),
+            },
+            other => anyhow::bail!("Unexpected resolution {}", other),
+        }
+    }
+
+    /// Parse the replacement item of a custom resolution
+    pub fn custom_item(code: &str) -> Result<Item> {
+        syn::parse_str(code).context("Custom merge resolution is not a single Rust item")
+    }
+}
+
+/// Run a policy command with the conflict as JSON on stdin
+///
+/// Stdout is read as JSON, or as a bare resolution word.
+fn run_command(command: &str, dir: &std::path::Path, conflict: &Conflict) -> Result<Value> {
+    let mut child = crate::hooks::shell(command)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run merge policy `{}`", command))?;
+
+    // Commands that answer without reading the conflict close stdin early
+    let written = child
+        .stdin
+        .take()
+        .context("Failed to open merge policy stdin")?
+        .write_all(&serde_json::to_vec(conflict)?);
+    if let Err(err) = written {
+        if err.kind() != std::io::ErrorKind::BrokenPipe {
+            return Err(err).context("Failed to write to merge policy");
+        }
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!("Merge policy `{}` failed with {}", command, output.status);
+    }
+
+    let stdout = String::from_utf8(output.stdout)?;
+    Ok(serde_json::from_str(&stdout).unwrap_or(Value::String(stdout)))
+}
+
+/// Call `resolve(conflict)` in a policy script
+#[cfg(feature = "plugins")]
+fn run_script(path: &std::path::Path, conflict: &Conflict) -> Result<Value> {
+    let engine = rhai::Engine::new();
+    let ast = engine
+        .compile_file(path.to_path_buf())
+        .with_context(|| format!("Failed to compile merge policy {}", path.display()))?;
+
+    let conflict = rhai::serde::to_dynamic(conflict).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let answer: rhai::Dynamic = engine
+        .call_fn(&mut rhai::Scope::new(), &ast, "resolve", (conflict,))
+        .map_err(|e| anyhow::anyhow!("Merge policy {} failed: {}", path.display(), e))?;
+
+    if answer.is_unit() {
+        return Ok(Value::Null);
+    }
+    rhai::serde::from_dynamic(&answer).map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolution_from_value() {
+        assert_eq!(Resolution::from_value(&json!(null)).unwrap(), None);
+        assert_eq!(Resolution::from_value(&json!("\n")).unwrap(), None);
+        assert_eq!(
+            Resolution::from_value(&json!("Template\n")).unwrap(),
+            Some(Resolution::Template)
+        );
+        assert_eq!(
+            Resolution::from_value(&json!({ "custom": "struct A;" })).unwrap(),
+            Some(Resolution::Custom("struct A;".to_string()))
+        );
+        assert!(Resolution::from_value(&json!("theirs")).is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_command_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = ExternalPolicy::Command {
+            command: "grep -q '\"kind\":\"struct\"' && echo template || echo manual".to_string(),
+            dir: dir.path().to_path_buf(),
+        };
+
+        let item: Item = syn::parse_str("struct UserDto;").unwrap();
+        let conflict = Conflict::new("UserDto", ConflictChange::Modify, Some(&item), Some(&item));
+        assert_eq!(
+            policy.resolve(&conflict).unwrap(),
+            Some(Resolution::Template)
+        );
+
+        let item: Item = syn::parse_str("fn handler() {}").unwrap();
+        let conflict = Conflict::new("handler", ConflictChange::Delete, Some(&item), None);
+        assert_eq!(policy.resolve(&conflict).unwrap(), Some(Resolution::Manual));
+    }
+
+    #[cfg(feature = "plugins")]
+    #[test]
+    fn test_script_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("merge.rhai");
+        std::fs::write(
+            &script,
+            "fn resolve(conflict) {\n\
+                 if conflict.name.ends_with(\"Dto\") { return \"template\"; }\n\
+                 if conflict.change == \"delete\" { return #{ custom: \"fn kept() {}\" }; }\n\
+             }\n",
+        )
+        .unwrap();
+        let policy = ExternalPolicy::Script(script);
+
+        let item: Item = syn::parse_str("struct UserDto;").unwrap();
+        let conflict = Conflict::new("UserDto", ConflictChange::Insert, Some(&item), Some(&item));
+        assert_eq!(
+            policy.resolve(&conflict).unwrap(),
+            Some(Resolution::Template)
+        );
+
+        let conflict = Conflict::new("User", ConflictChange::Modify, Some(&item), Some(&item));
+        assert_eq!(policy.resolve(&conflict).unwrap(), None);
+
+        let conflict = Conflict::new("User", ConflictChange::Delete, Some(&item), None);
+        assert_eq!(
+            policy.resolve(&conflict).unwrap(),
+            Some(Resolution::Custom("fn kept() {}".to_string()))
+        );
+    }
+}
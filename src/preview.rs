@@ -0,0 +1,338 @@
+//! Merge preview module
+//!
+//! This module writes the report of `--report html <path>` on `generate` and
+//! `generate-all`: a standalone HTML page with, for every written file, the
+//! items the merge added, removed or modified, each with a colored line diff
+//! and the conflicts it ran into. It is meant to be attached to a review of a
+//! large regeneration, where terminal output doesn't travel well.
+//!
+//! Files that are not Rust, or don't parse, are diffed as a whole.
+
+use anyhow::{Context, Result};
+use similar::{ChangeTag, TextDiff};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::Item;
+
+use crate::ast_parser::item_kind;
+use crate::diff::extract_item_name;
+use crate::report::escape;
+use crate::review::item_source;
+
+/// Report formats `--report` accepts
+pub const FORMATS: &[&str] = &["html"];
+
+/// Lines of context shown around changed lines
+const CONTEXT_LINES: usize = 3;
+
+/// How the merge changed an item
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl Change {
+    /// Name used in reports
+    pub fn label(self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Modified => "modified",
+        }
+    }
+}
+
+/// One changed item of a file, or the whole file when it isn't Rust
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemPreview {
+    /// Kind and name of the item, such as `struct User`
+    pub label: String,
+    pub change: Change,
+    /// Source of the item before the merge
+    pub before: String,
+    /// Source of the item after the merge
+    pub after: String,
+    /// Conflicts naming the item
+    pub conflicts: Vec<String>,
+}
+
+/// Changes the merge made to one written file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePreview {
+    pub path: PathBuf,
+    /// Whether the file didn't exist before
+    pub created: bool,
+    pub items: Vec<ItemPreview>,
+    /// Conflicts not naming any changed item
+    pub conflicts: Vec<String>,
+}
+
+impl FilePreview {
+    /// Compare the content of a file before and after the merge
+    pub fn new(path: &Path, before: Option<&str>, after: &str, conflicts: Vec<String>) -> Self {
+        let mut items = match (
+            before.map(syn::parse_file).transpose(),
+            syn::parse_file(after),
+        ) {
+            (Ok(before), Ok(after)) if path.extension().is_none_or(|ext| ext == "rs") => {
+                item_changes(
+                    before.map(|file| file.items).unwrap_or_default(),
+                    after.items,
+                )
+            }
+            _ if before == Some(after) => Vec::new(),
+            _ => vec![ItemPreview {
+                label: path.display().to_string(),
+                change: if before.is_some() {
+                    Change::Modified
+                } else {
+                    Change::Added
+                },
+                before: before.unwrap_or_default().to_string(),
+                after: after.to_string(),
+                conflicts: Vec::new(),
+            }],
+        };
+
+        let mut unattached = Vec::new();
+        for conflict in conflicts {
+            match items
+                .iter_mut()
+                .find(|item| names_item(&conflict, &item.label))
+            {
+                Some(item) => item.conflicts.push(conflict),
+                None => unattached.push(conflict),
+            }
+        }
+
+        FilePreview {
+            path: path.to_path_buf(),
+            created: before.is_none(),
+            items,
+            conflicts: unattached,
+        }
+    }
+}
+
+/// Path of the report of a `--report <format> <path>` argument
+pub fn report_path(report: &[String]) -> Result<PathBuf> {
+    match report {
+        [format, path] if FORMATS.contains(&format.as_str()) => Ok(PathBuf::from(path)),
+        [format, _] => anyhow::bail!(
+            "Unknown report format '{}', available: {}",
+            format,
+            FORMATS.join(", ")
+        ),
+        _ => anyhow::bail!("--report takes a format and a path"),
+    }
+}
+
+/// Write the report of the given files
+pub fn write_report(path: &Path, files: &[FilePreview]) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, to_html(files))
+        .with_context(|| format!("Failed to write report to {}", path.display()))
+}
+
+/// Render previews as a standalone HTML page
+pub fn to_html(files: &[FilePreview]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Merge preview</title>\n<style>\n\
+         body { font-family: sans-serif; }\n\
+         pre { background: #f6f8fa; padding: 8px; overflow-x: auto; }\n\
+         .added { color: #2e7d32; } .removed { color: #c62828; } .modified { color: #ef6c00; }\n\
+         .insert { background: #e6ffec; display: block; }\n\
+         .delete { background: #ffebe9; display: block; }\n\
+         .gap { color: #888; display: block; }\n\
+         .conflict { border-left: 4px solid #c62828; padding-left: 8px; }\n\
+         </style>\n</head>\n<body>\n<h1>Merge preview</h1>\n",
+    );
+    if files.is_empty() {
+        html.push_str("<p>No files written.</p>\n");
+    }
+
+    for file in files {
+        let _ = writeln!(
+            html,
+            "<h2>{}</h2>\n<p>{}{} changed items, {} conflicts</p>",
+            escape(&file.path.display().to_string()),
+            if file.created { "New file, " } else { "" },
+            file.items.len(),
+            file.conflicts.len()
+                + file
+                    .items
+                    .iter()
+                    .map(|item| item.conflicts.len())
+                    .sum::<usize>()
+        );
+        for conflict in &file.conflicts {
+            let _ = writeln!(html, "<p class=\"conflict\">{}</p>", escape(conflict));
+        }
+        for item in &file.items {
+            let _ = writeln!(
+                html,
+                "<h3 class=\"{change}\">{} ({change})</h3>",
+                escape(&item.label),
+                change = item.change.label()
+            );
+            for conflict in &item.conflicts {
+                let _ = writeln!(html, "<p class=\"conflict\">{}</p>", escape(conflict));
+            }
+            html.push_str("<pre>");
+            html.push_str(&diff_html(&item.before, &item.after));
+            html.push_str("</pre>\n");
+        }
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Added, removed and modified items, in the order of the merged file then of the removed items
+fn item_changes(before: Vec<Item>, after: Vec<Item>) -> Vec<ItemPreview> {
+    // Unnamed items, such as `use` declarations, only match identical ones
+    let key = |item: &Item| extract_item_name(item).unwrap_or_else(|| item_source(item));
+    let mut before: Vec<(String, Item)> =
+        before.into_iter().map(|item| (key(&item), item)).collect();
+
+    let mut items = Vec::new();
+    for item in after {
+        let key = key(&item);
+        let after = item_source(&item);
+        let (change, before) = match before.iter().position(|(other, _)| *other == key) {
+            Some(index) => {
+                let (_, previous) = before.remove(index);
+                if previous == item {
+                    continue;
+                }
+                (Change::Modified, item_source(&previous))
+            }
+            None => (Change::Added, String::new()),
+        };
+        items.push(ItemPreview {
+            label: label(&item),
+            change,
+            before,
+            after,
+            conflicts: Vec::new(),
+        });
+    }
+    items.extend(before.into_iter().map(|(_, item)| ItemPreview {
+        label: label(&item),
+        change: Change::Removed,
+        before: item_source(&item),
+        after: String::new(),
+        conflicts: Vec::new(),
+    }));
+
+    items
+}
+
+/// `struct User`, or the first line of items without a name
+fn label(item: &Item) -> String {
+    match extract_item_name(item) {
+        Some(name) => format!("{} {}", item_kind(item), name),
+        None => item_source(item)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+/// Whether a conflict message names the item of the given label, quoted
+fn names_item(conflict: &str, label: &str) -> bool {
+    let name = label.rsplit(' ').next().unwrap_or(label);
+    conflict.contains(&format!("'{}'", name)) || conflict.contains(&format!("`{}`", name))
+}
+
+/// Changed lines and their context, colored by the tag of each line
+fn diff_html(before: &str, after: &str) -> String {
+    let diff = TextDiff::from_lines(before, after);
+    let mut html = String::new();
+    for (index, group) in diff.grouped_ops(CONTEXT_LINES).iter().enumerate() {
+        if index > 0 {
+            html.push_str("<span class=\"gap\">…</span>");
+        }
+        for change in group.iter().flat_map(|op| diff.iter_changes(op)) {
+            let (class, sign) = match change.tag() {
+                ChangeTag::Insert => ("insert", "+"),
+                ChangeTag::Delete => ("delete", "-"),
+                ChangeTag::Equal => ("equal", " "),
+            };
+            let line = change.value().trim_end_matches('\n');
+            if change.tag() == ChangeTag::Equal {
+                let _ = writeln!(html, "{} {}", sign, escape(line));
+            } else {
+                let _ = write!(
+                    html,
+                    "<span class=\"{}\">{} {}</span>",
+                    class,
+                    sign,
+                    escape(line)
+                );
+            }
+        }
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_preview() {
+        let before = "pub struct User;\n\npub fn id() -> u64 {\n    0\n}\n\npub fn old() {}\n";
+        let after = "pub struct User;\n\npub fn id() -> u64 {\n    7\n}\n\npub fn new() {}\n";
+        let preview = FilePreview::new(
+            Path::new("src/user.rs"),
+            Some(before),
+            after,
+            vec![
+                "Conflict: Item 'id' has manual changes conflicting with template".to_string(),
+                "Unrelated conflict".to_string(),
+            ],
+        );
+
+        let changes: Vec<(&str, Change)> = preview
+            .items
+            .iter()
+            .map(|item| (item.label.as_str(), item.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("fn id", Change::Modified),
+                ("fn new", Change::Added),
+                ("fn old", Change::Removed),
+            ]
+        );
+        assert_eq!(preview.items[0].conflicts.len(), 1);
+        assert_eq!(preview.conflicts, vec!["Unrelated conflict"]);
+
+        let html = to_html(&[preview]);
+        assert!(html.contains("<span class=\"delete\">-     0</span>"));
+        assert!(html.contains("<span class=\"insert\">+     7</span>"));
+        assert!(html.contains("<p class=\"conflict\">Conflict: Item 'id' has manual"));
+
+        let text = FilePreview::new(Path::new("notes.md"), None, "# Notes\n", Vec::new());
+        assert_eq!(text.items[0].change, Change::Added);
+        assert!(text.created);
+
+        assert!(report_path(&["pdf".to_string(), "out.pdf".to_string()]).is_err());
+        assert_eq!(
+            report_path(&["html".to_string(), "out.html".to_string()]).unwrap(),
+            PathBuf::from("out.html")
+        );
+    }
+}
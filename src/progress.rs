@@ -0,0 +1,142 @@
+//! Progress module
+//!
+//! This module draws the progress bar of `generate-all` on stderr, from the
+//! generation events of each target (see [`crate::events`]):
+//!
+//! ```text
+//! [###########-------------------] 4/11 model.rs.tera
+//! ```
+//!
+//! The bar is only drawn when stderr is a terminal and there are several
+//! targets; lines printed through [`ProgressBar::println`] go above it.
+
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+
+use crate::events::{Event, EventSink};
+
+/// Width of the bar, in characters
+const WIDTH: usize = 30;
+
+/// Progress of a batch of targets
+#[derive(Debug)]
+pub struct ProgressBar {
+    state: Mutex<State>,
+    enabled: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    total: usize,
+    done: usize,
+    message: String,
+}
+
+impl ProgressBar {
+    /// Bar for `total` targets, hidden unless stderr is a terminal and there are several
+    pub fn new(total: usize) -> Self {
+        ProgressBar {
+            state: Mutex::new(State {
+                total,
+                ..State::default()
+            }),
+            enabled: total > 1 && std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// Count a target as done
+    pub fn advance(&self) {
+        let mut state = self.lock();
+        state.done = (state.done + 1).min(state.total);
+        self.draw(&state);
+    }
+
+    /// Print a line to stdout above the bar
+    pub fn println(&self, line: &str) {
+        let state = self.lock();
+        self.clear();
+        println!("{}", line);
+        self.draw(&state);
+    }
+
+    /// Remove the bar
+    pub fn finish(&self) {
+        let _state = self.lock();
+        self.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn clear(&self) {
+        if self.enabled {
+            eprint!("\r\x1b[2K");
+        }
+    }
+
+    fn draw(&self, state: &State) {
+        if self.enabled {
+            eprint!("\r\x1b[2K{}", render(state));
+            let _ = std::io::stderr().flush();
+        }
+    }
+}
+
+impl EventSink for ProgressBar {
+    fn emit(&self, event: Event) {
+        match event {
+            Event::RenderStarted { template } => {
+                let mut state = self.lock();
+                state.message = template
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                self.draw(&state);
+            }
+            Event::FileWritten { .. } => self.advance(),
+            _ => {}
+        }
+    }
+}
+
+/// Line of the bar for `state`
+fn render(state: &State) -> String {
+    let filled = (WIDTH * state.done)
+        .checked_div(state.total)
+        .unwrap_or(WIDTH);
+    format!(
+        "[{}{}] {}/{} {}",
+        "#".repeat(filled),
+        "-".repeat(WIDTH - filled),
+        state.done,
+        state.total,
+        state.message
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_progress() {
+        let bar = ProgressBar::new(3);
+        bar.emit(Event::RenderStarted {
+            template: "templates/model.rs.tera".into(),
+        });
+        bar.emit(Event::DiffComputed { ops: 2 });
+        bar.emit(Event::FileWritten {
+            path: "src/user.rs".into(),
+        });
+
+        assert_eq!(
+            render(&bar.lock()),
+            "[##########--------------------] 1/3 model.rs.tera"
+        );
+        assert_eq!(
+            render(&State::default()),
+            "[##############################] 0/0 "
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Provenance header module
+//!
+//! This module writes the header comment recording where a generated Rust file
+//! comes from, with `--provenance` or `provenance = true` in `rpt.toml`:
+//!
+//! ```text
+//! // @generated by rust-patchs-templates 0.1.0
+//! // template: model.rs.tera sha256:3f2a9c0e51d7b6a4
+//! // context: sha256:9be1d04c7a3e8f21
+//! ```
+//!
+//! The header belongs to the tool: it is taken off the existing file before
+//! merging, so it never shows up as a manual edit, and written again with the
+//! current hashes on top of the merged output.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::hash::{sha256_hex, sha256_path};
+
+/// First line of the header
+pub const MARKER: &str = "// @generated by rust-patchs-templates";
+
+/// Hex digits of the hashes shown in the header
+const HASH_LEN: usize = 16;
+
+/// Where a generated file comes from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    /// File name of the template
+    pub template: String,
+    /// Hash of the template file, or of every file of a template directory
+    pub template_hash: String,
+    /// Hash of the context, independent of its key order
+    pub context_hash: String,
+}
+
+impl Provenance {
+    /// Provenance of the output of `template` rendered with `context`
+    pub fn new(template: &Path, context: &HashMap<String, Value>) -> Result<Self> {
+        let context: serde_json::Map<String, Value> = context
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Ok(Provenance {
+            template: template
+                .file_name()
+                .unwrap_or(template.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            template_hash: sha256_path(template)?,
+            context_hash: sha256_hex(serde_json::to_string(&context)?),
+        })
+    }
+
+    /// The header comment, followed by an empty line
+    pub fn header(&self) -> String {
+        format!(
+            "{} {}\n// template: {} sha256:{}\n// context: sha256:{}\n\n",
+            MARKER,
+            env!("CARGO_PKG_VERSION"),
+            self.template,
+            &self.template_hash[..HASH_LEN],
+            &self.context_hash[..HASH_LEN]
+        )
+    }
+
+    /// `code` under the header, replacing the header it has
+    pub fn apply(&self, code: &str) -> String {
+        self.header() + strip_header(code)
+    }
+}
+
+/// `code` without its provenance header, if it has one
+pub fn strip_header(code: &str) -> &str {
+    if !code.starts_with(MARKER) {
+        return code;
+    }
+
+    let mut rest = code;
+    for prefix in [MARKER, "// template: ", "// context: "] {
+        if !rest.starts_with(prefix) {
+            break;
+        }
+        rest = rest.split_once('\n').map_or("", |(_, rest)| rest);
+    }
+    rest.strip_prefix('\n').unwrap_or(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_provenance_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("model.rs.tera");
+        std::fs::write(&template, "pub struct {{ name }};\n").unwrap();
+        let context = HashMap::from([
+            ("name".to_string(), json!("User")),
+            ("derives".to_string(), json!(["Debug"])),
+        ]);
+        let provenance = Provenance::new(&template, &context).unwrap();
+
+        let code = provenance.apply("pub struct User;\n");
+        let mut lines = code.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            format!("{} {}", MARKER, env!("CARGO_PKG_VERSION"))
+        );
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("// template: model.rs.tera sha256:"));
+        assert_eq!(strip_header(&code), "pub struct User;\n");
+        assert_eq!(provenance.apply(&code), code);
+        assert_eq!(
+            strip_header("// A note\npub struct User;\n"),
+            "// A note\npub struct User;\n"
+        );
+    }
+}
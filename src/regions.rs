@@ -0,0 +1,222 @@
+//! Keep regions module
+//!
+//! This module merges generated files that aren't Rust, such as SQL migrations
+//! or config files, when generating with `--language text`. The generated text
+//! replaces the existing file, except between keep markers, whose content is
+//! carried over from the existing file:
+//!
+//! ```sql
+//! CREATE TABLE users (id BIGINT PRIMARY KEY);
+//!
+//! -- <rpt:keep seed>
+//! INSERT INTO users (id) VALUES (1);
+//! -- </rpt:keep>
+//! ```
+//!
+//! Only the tags count, so markers can sit in any comment syntax: `//`, `--`,
+//! `#` or `<!-- -->`. Regions are matched by name, or by position among
+//! unnamed `<rpt:keep>` regions. A region the template no longer emits is
+//! reported as a conflict, and its content is dropped.
+
+use anyhow::Result;
+
+use crate::diff::compute_text_diff;
+use crate::generator::MergedOutput;
+use crate::merger::{MergeStrategy, Warning};
+
+/// Opening tag of a keep region, followed by an optional name and `>`
+const OPEN_TAG: &str = "<rpt:keep";
+
+/// Closing tag of a keep region
+const CLOSE_TAG: &str = "</rpt:keep>";
+
+/// A keep region: its key and the lines between its markers
+#[derive(Debug, Clone, PartialEq)]
+struct Region {
+    /// Region name, or `#n` for the n-th unnamed region
+    key: String,
+    /// Index of the opening marker line
+    open: usize,
+    /// Index of the closing marker line
+    close: usize,
+}
+
+impl Region {
+    /// Name shown in conflict messages
+    fn label(&self) -> String {
+        match self.key.strip_prefix('#') {
+            Some(index) => format!("Keep region {}", index),
+            None => format!("Keep region '{}'", self.key),
+        }
+    }
+}
+
+/// Merge `generated` text into `existing`, keeping the content of keep regions
+///
+/// With the `fail` strategy, regions the template dropped are an error.
+pub fn merge_regions(
+    generated: &str,
+    existing: &str,
+    strategy: MergeStrategy,
+) -> Result<MergedOutput> {
+    let generated_lines: Vec<&str> = generated.split_inclusive('\n').collect();
+    let existing_lines: Vec<&str> = existing.split_inclusive('\n').collect();
+    let generated_regions =
+        find_regions(&generated_lines).map_err(|e| e.context("In generated text"))?;
+    let existing_regions =
+        find_regions(&existing_lines).map_err(|e| e.context("In existing file"))?;
+
+    let mut code = String::with_capacity(generated.len());
+    let mut copied = 0;
+    for region in &generated_regions {
+        let kept = existing_regions
+            .iter()
+            .find(|other| other.key == region.key);
+        let content = match kept {
+            Some(kept) => &existing_lines[kept.open + 1..kept.close],
+            None => &generated_lines[region.open + 1..region.close],
+        };
+        code.push_str(&generated_lines[copied..=region.open].concat());
+        code.push_str(&content.concat());
+        copied = region.close;
+    }
+    code.push_str(&generated_lines[copied..].concat());
+
+    let conflicts: Vec<String> = existing_regions
+        .iter()
+        .filter(|region| {
+            !generated_regions
+                .iter()
+                .any(|other| other.key == region.key)
+        })
+        .filter(|region| region.close > region.open + 1)
+        .map(|region| {
+            format!(
+                "{} is no longer generated by the template, its manual content was dropped",
+                region.label()
+            )
+        })
+        .collect();
+
+    if !conflicts.is_empty() && strategy == MergeStrategy::FailOnConflict {
+        anyhow::bail!("Merge conflicts detected:\n{}", conflicts.join("\n"));
+    }
+
+    Ok(MergedOutput {
+        code,
+        conflicts: conflicts.into_iter().map(Warning::conflict).collect(),
+    })
+}
+
+/// Line diff between the existing text and the generated text merged into it
+pub fn diff_regions(generated: &str, existing: &str) -> Result<String> {
+    let merged = merge_regions(generated, existing, MergeStrategy::PreferManual)?;
+    if merged.code == existing {
+        return Ok("No differences found.".to_string());
+    }
+    Ok(compute_text_diff(existing, &merged.code))
+}
+
+/// Keep regions of a file, in order
+fn find_regions(lines: &[&str]) -> Result<Vec<Region>> {
+    let mut regions = Vec::new();
+    let mut open: Option<(String, usize)> = None;
+    let mut unnamed = 0;
+
+    for (index, line) in lines.iter().enumerate() {
+        if line.contains(CLOSE_TAG) {
+            let Some((key, start)) = open.take() else {
+                anyhow::bail!("Line {}: {} without an opening tag", index + 1, CLOSE_TAG);
+            };
+            regions.push(Region {
+                key,
+                open: start,
+                close: index,
+            });
+        } else if let Some(tag) = line.find(OPEN_TAG).map(|at| &line[at + OPEN_TAG.len()..]) {
+            if let Some((_, start)) = open {
+                anyhow::bail!(
+                    "Line {}: keep region opened on line {} isn't closed",
+                    index + 1,
+                    start + 1
+                );
+            }
+            let Some(end) = tag.find('>') else {
+                anyhow::bail!("Line {}: unterminated {} tag", index + 1, OPEN_TAG);
+            };
+            let name = tag[..end].trim();
+            let key = if name.is_empty() {
+                unnamed += 1;
+                format!("#{}", unnamed)
+            } else {
+                name.to_string()
+            };
+            if regions.iter().any(|region: &Region| region.key == key) {
+                anyhow::bail!(
+                    "Line {}: keep region '{}' is declared twice",
+                    index + 1,
+                    key
+                );
+            }
+            open = Some((key, index));
+        }
+    }
+
+    if let Some((_, start)) = open {
+        anyhow::bail!("Line {}: keep region isn't closed", start + 1);
+    }
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_regions() {
+        let existing = "CREATE TABLE users (id BIGINT);\n\
+            -- <rpt:keep seed>\n\
+            INSERT INTO users VALUES (1);\n\
+            -- </rpt:keep>\n\
+            # <rpt:keep>\n\
+            custom = true\n\
+            # </rpt:keep>\n\
+            -- <rpt:keep old>\n\
+            DROP TABLE legacy;\n\
+            -- </rpt:keep>\n";
+        let generated = "CREATE TABLE users (id BIGINT, name TEXT);\n\
+            # <rpt:keep>\n\
+            # </rpt:keep>\n\
+            -- <rpt:keep seed>\n\
+            -- </rpt:keep>\n\
+            -- <rpt:keep new>\n\
+            -- default\n\
+            -- </rpt:keep>\n";
+
+        let merged = merge_regions(generated, existing, MergeStrategy::PreferManual).unwrap();
+        assert_eq!(
+            merged.code,
+            "CREATE TABLE users (id BIGINT, name TEXT);\n\
+            # <rpt:keep>\n\
+            custom = true\n\
+            # </rpt:keep>\n\
+            -- <rpt:keep seed>\n\
+            INSERT INTO users VALUES (1);\n\
+            -- </rpt:keep>\n\
+            -- <rpt:keep new>\n\
+            -- default\n\
+            -- </rpt:keep>\n"
+        );
+        assert_eq!(
+            merged.conflicts,
+            vec!["Keep region 'old' is no longer generated by the template, its manual content was dropped"]
+        );
+        assert!(merge_regions(generated, existing, MergeStrategy::FailOnConflict).is_err());
+
+        assert!(merge_regions("<rpt:keep>\n", "", MergeStrategy::PreferManual).is_err());
+        assert_eq!(
+            diff_regions(&merged.code, &merged.code).unwrap(),
+            "No differences found."
+        );
+    }
+}
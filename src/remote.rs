@@ -0,0 +1,246 @@
+//! Remote template module
+//!
+//! This module resolves templates that live in a git repository, written as
+//! `<url>#<path in repo>`, e.g.
+//! `https://github.com/org/templates.git#models/entity.rs.tera`. Repositories
+//! are cloned once into a local cache and fetched again on later runs; when the
+//! fetch fails the cached checkout is used as is.
+
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::hash::sha256_hex;
+
+/// Environment variable overriding the cache directory
+pub const CACHE_DIR_VAR: &str = "RPT_CACHE_DIR";
+
+/// URL prefixes that mark a template as remote
+const REMOTE_PREFIXES: &[&str] = &["https://", "http://", "ssh://", "git://", "file://", "git@"];
+
+/// A template inside a git repository
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteTemplate {
+    /// Repository URL, as given to `git clone`
+    pub url: String,
+    /// Template file or directory inside the repository
+    pub path: PathBuf,
+}
+
+impl RemoteTemplate {
+    /// Parse a `<url>#<path>` template argument, `None` for local paths
+    pub fn parse(template: &Path) -> Option<Self> {
+        let template = template.to_str()?;
+        if !REMOTE_PREFIXES
+            .iter()
+            .any(|prefix| template.starts_with(prefix))
+        {
+            return None;
+        }
+
+        let (url, path) = template.split_once('#').unwrap_or((template, ""));
+        Some(RemoteTemplate {
+            url: url.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// A remote template checked out into the cache
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkout {
+    /// Local path of the template
+    pub path: PathBuf,
+    /// Commit the repository is checked out at
+    pub revision: String,
+}
+
+/// Directory holding cached template repositories
+///
+/// `$RPT_CACHE_DIR`, else `$XDG_CACHE_HOME/rpt`, else `~/.cache/rpt`.
+pub fn cache_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(CACHE_DIR_VAR) {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME") {
+        return Ok(PathBuf::from(dir).join("rpt"));
+    }
+
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .context("Cannot locate the template cache, set RPT_CACHE_DIR")?;
+    Ok(PathBuf::from(home).join(".cache").join("rpt"))
+}
+
+/// Resolve a template argument to a local path, checking out remote templates
+///
+/// `git_ref` pins a branch, tag or commit; without it the remote's default
+/// branch is used. Local paths are returned unchanged.
+pub fn resolve_template(template: &Path, git_ref: Option<&str>) -> Result<PathBuf> {
+    match RemoteTemplate::parse(template) {
+        Some(remote) => Ok(fetch(&remote, git_ref)?.path),
+        None => Ok(template.to_path_buf()),
+    }
+}
+
+/// Check out a remote template in the cache directory, see [`resolve_template`]
+pub fn fetch(remote: &RemoteTemplate, git_ref: Option<&str>) -> Result<Checkout> {
+    checkout(&cache_dir()?, remote, git_ref)
+}
+
+/// Clone or update the repository of `remote` under `cache` and check out `git_ref`
+fn checkout(cache: &Path, remote: &RemoteTemplate, git_ref: Option<&str>) -> Result<Checkout> {
+    let dir = cache.join("git").join(&sha256_hex(&remote.url)[..16]);
+
+    if dir.join(".git").exists() {
+        if let Err(err) = git(&dir, ["fetch", "--quiet", "--tags", "--force", "origin"]) {
+            eprintln!(
+                "Warning: {:#}, using cached templates from {}",
+                err, remote.url
+            );
+        }
+    } else {
+        std::fs::create_dir_all(cache.join("git"))?;
+        git(
+            cache,
+            [
+                OsStr::new("clone"),
+                OsStr::new("--quiet"),
+                OsStr::new("--no-checkout"),
+                OsStr::new(&remote.url),
+                dir.as_os_str(),
+            ],
+        )
+        .with_context(|| format!("Failed to clone template repository {}", remote.url))?;
+    }
+
+    // Branches resolve to their fetched remote-tracking ref, tags and commits as is
+    let revision = match git_ref {
+        Some(git_ref) => {
+            let tracking = format!("origin/{}", git_ref);
+            if git(&dir, ["rev-parse", "--verify", "--quiet", &tracking]).is_ok() {
+                tracking
+            } else {
+                git_ref.to_string()
+            }
+        }
+        None => "origin/HEAD".to_string(),
+    };
+    git(
+        &dir,
+        ["checkout", "--quiet", "--force", "--detach", &revision],
+    )
+    .with_context(|| format!("Failed to check out '{}' of {}", revision, remote.url))?;
+
+    let path = dir.join(&remote.path);
+    if !path.exists() {
+        anyhow::bail!(
+            "'{}' not found in template repository {}",
+            remote.path.display(),
+            remote.url
+        );
+    }
+
+    Ok(Checkout {
+        path,
+        revision: git(&dir, ["rev-parse", "HEAD"])?,
+    })
+}
+
+/// Run a git command in `dir` and return its trimmed stdout
+fn git<I, S>(dir: &Path, args: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<_> = args
+        .into_iter()
+        .map(|arg| arg.as_ref().to_owned())
+        .collect();
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(&args)
+        .output()
+        .context("Failed to run git")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed:\n{}",
+            args.join(OsStr::new(" ")).to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_parse_remote_template() {
+        assert_eq!(
+            RemoteTemplate::parse(Path::new(
+                "https://github.com/org/templates.git#models/entity.rs.tera"
+            )),
+            Some(RemoteTemplate {
+                url: "https://github.com/org/templates.git".to_string(),
+                path: PathBuf::from("models/entity.rs.tera"),
+            })
+        );
+        assert_eq!(
+            RemoteTemplate::parse(Path::new("templates/model.tera")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_checkout_pinned_ref() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        fs::create_dir_all(&repo).unwrap();
+
+        let commit = |content: &str| {
+            fs::write(repo.join("model.tera"), content).unwrap();
+            git(&repo, ["add", "."]).unwrap();
+            git(
+                &repo,
+                [
+                    "-c",
+                    "user.name=test",
+                    "-c",
+                    "user.email=test@example.com",
+                    "commit",
+                    "--quiet",
+                    "-m",
+                    content,
+                ],
+            )
+            .unwrap();
+        };
+        git(&repo, ["init", "--quiet"]).unwrap();
+        commit("struct V1;");
+        git(&repo, ["tag", "v1"]).unwrap();
+        commit("struct V2;");
+
+        let remote = RemoteTemplate {
+            url: format!("file://{}", repo.display()),
+            path: PathBuf::from("model.tera"),
+        };
+        let cache = dir.path().join("cache");
+
+        let latest = checkout(&cache, &remote, None).unwrap();
+        assert_eq!(fs::read_to_string(&latest.path).unwrap(), "struct V2;");
+
+        let pinned = checkout(&cache, &remote, Some("v1")).unwrap();
+        assert_eq!(fs::read_to_string(&pinned.path).unwrap(), "struct V1;");
+        assert_eq!(pinned.revision, git(&repo, ["rev-parse", "v1"]).unwrap());
+
+        let by_commit = checkout(&cache, &remote, Some(&latest.revision)).unwrap();
+        assert_eq!(fs::read_to_string(&by_commit.path).unwrap(), "struct V2;");
+    }
+}
@@ -0,0 +1,167 @@
+//! Report module
+//!
+//! This module takes an inventory of the items in configured outputs, telling
+//! how much of each file still matches its template. Every item is compared
+//! with the template rendered in memory: items the template produces as is
+//! are `generated`, items it produces differently are `modified`, and items
+//! it doesn't produce at all are `manual`. Template updates that were not
+//! applied yet also count as modifications, see `status`.
+
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::ast_parser::{ItemInfo, ParsedFile};
+use crate::diff::extract_item_name;
+
+/// Where an item of a generated file comes from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    /// Produced by the template, unchanged
+    Generated,
+    /// Produced by the template, then edited by hand
+    Modified,
+    /// Added by hand
+    Manual,
+}
+
+impl Origin {
+    /// Name used in reports
+    pub fn label(self) -> &'static str {
+        match self {
+            Origin::Generated => "generated",
+            Origin::Modified => "modified",
+            Origin::Manual => "manual",
+        }
+    }
+}
+
+/// One item of an output and its origin
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ItemReport {
+    #[serde(flatten)]
+    pub info: ItemInfo,
+    pub origin: Origin,
+}
+
+/// Items of one output file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TargetReport {
+    pub output: PathBuf,
+    pub items: Vec<ItemReport>,
+}
+
+impl TargetReport {
+    /// Number of items of the given origin
+    pub fn count(&self, origin: Origin) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.origin == origin)
+            .count()
+    }
+}
+
+/// Classify the items of an existing file against the template's output
+pub fn classify(existing: &ParsedFile, generated: &ParsedFile) -> Vec<ItemReport> {
+    existing
+        .items()
+        .iter()
+        .map(|item| {
+            let origin = if generated.items().contains(item) {
+                Origin::Generated
+            } else if extract_item_name(item).is_some_and(|name| {
+                generated
+                    .items()
+                    .iter()
+                    .any(|other| extract_item_name(other).as_ref() == Some(&name))
+            }) {
+                Origin::Modified
+            } else {
+                Origin::Manual
+            };
+
+            ItemReport {
+                info: ItemInfo::of(item),
+                origin,
+            }
+        })
+        .collect()
+}
+
+/// Render reports as a standalone HTML page
+pub fn to_html(reports: &[TargetReport]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Generated code report</title>\n<style>\n\
+         body { font-family: sans-serif; }\n\
+         td, th { padding: 2px 12px; text-align: left; }\n\
+         .generated { color: #2e7d32; } .modified { color: #ef6c00; } .manual { color: #1565c0; }\n\
+         </style>\n</head>\n<body>\n<h1>Generated code report</h1>\n",
+    );
+
+    for report in reports {
+        let _ = writeln!(
+            html,
+            "<h2>{}</h2>\n<p>{} generated, {} modified, {} manual</p>",
+            escape(&report.output.display().to_string()),
+            report.count(Origin::Generated),
+            report.count(Origin::Modified),
+            report.count(Origin::Manual)
+        );
+        html.push_str(
+            "<table>\n<tr><th>Lines</th><th>Kind</th><th>Name</th><th>Origin</th></tr>\n",
+        );
+        for item in &report.items {
+            let _ = writeln!(
+                html,
+                "<tr class=\"{origin}\"><td>{}-{}</td><td>{}</td><td>{}</td><td>{origin}</td></tr>",
+                item.info.start_line,
+                item.info.end_line,
+                item.info.kind,
+                escape(item.info.name.as_deref().unwrap_or("-")),
+                origin = item.origin.label()
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Escape text for HTML
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let generated = ParsedFile::parse("pub struct User;\npub fn id() -> u64 { 0 }").unwrap();
+        let existing = ParsedFile::parse(
+            "pub struct User;\npub fn id() -> u64 { 7 }\nimpl Display for User {}",
+        )
+        .unwrap();
+
+        let items = classify(&existing, &generated);
+        let origins: Vec<Origin> = items.iter().map(|item| item.origin).collect();
+        assert_eq!(
+            origins,
+            vec![Origin::Generated, Origin::Modified, Origin::Manual]
+        );
+
+        let report = TargetReport {
+            output: "src/user.rs".into(),
+            items,
+        };
+        assert_eq!(report.count(Origin::Manual), 1);
+        assert!(to_html(&[report]).contains("<td>Display for User</td>"));
+    }
+}
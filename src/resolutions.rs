@@ -0,0 +1,149 @@
+//! Recorded resolutions module
+//!
+//! This module remembers how conflicts were resolved in `.rpt/resolutions.json`,
+//! so the same conflict is resolved the same way on later runs instead of being
+//! reported again. Each entry is keyed by item name and holds the chosen side
+//! and a hash of both conflicting versions; a conflict only matches while
+//! neither version changes. `review` records its accepted and rejected changes.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::Item;
+
+use crate::config::Config;
+use crate::hash::sha256_hex;
+use crate::review::item_source;
+
+/// Location of the resolutions file, relative to the config root
+pub const RESOLUTIONS_FILE: &str = ".rpt/resolutions.json";
+
+/// Side a conflict was resolved to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    /// The existing item was kept
+    Manual,
+    /// The template's version was applied
+    Template,
+}
+
+/// One remembered resolution
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recorded {
+    pub side: Side,
+    /// Hash of the manual and template versions of the item
+    pub hash: String,
+}
+
+/// Resolutions remembered for a project
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Resolutions {
+    /// Entries keyed by item name, several per name for different conflicts
+    items: BTreeMap<String, Vec<Recorded>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Resolutions {
+    /// Load the resolutions of a project, starting empty if there are none
+    pub fn load(config: &Config) -> Result<Self> {
+        let path = config.resolve_path(Path::new(RESOLUTIONS_FILE));
+
+        let mut resolutions: Resolutions = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(_) => Resolutions::default(),
+        };
+        resolutions.path = path;
+        Ok(resolutions)
+    }
+
+    /// Side recorded for this exact conflict, if any
+    pub fn lookup(
+        &self,
+        name: &str,
+        manual: Option<&Item>,
+        template: Option<&Item>,
+    ) -> Option<Side> {
+        let hash = conflict_hash(manual, template);
+        self.items
+            .get(name)?
+            .iter()
+            .find(|recorded| recorded.hash == hash)
+            .map(|recorded| recorded.side)
+    }
+
+    /// Remember how a conflict was resolved, replacing an earlier answer
+    pub fn record(
+        &mut self,
+        name: &str,
+        manual: Option<&Item>,
+        template: Option<&Item>,
+        side: Side,
+    ) {
+        let hash = conflict_hash(manual, template);
+        let entries = self.items.entry(name.to_string()).or_default();
+        entries.retain(|recorded| recorded.hash != hash);
+        entries.push(Recorded { side, hash });
+    }
+
+    /// Whether nothing is recorded
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Write the resolutions back to disk
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// Hash both versions of a conflicting item, formatting aside
+fn conflict_hash(manual: Option<&Item>, template: Option<&Item>) -> String {
+    let source = |item: Option<&Item>| item.map(item_source).unwrap_or_default();
+    sha256_hex(format!("{}\0{}", source(manual), source(template)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let manual: Item = syn::parse_str("fn handler() { manual(); }").unwrap();
+        let template: Item = syn::parse_str("fn handler() {}").unwrap();
+
+        let mut resolutions = Resolutions::load(&config).unwrap();
+        assert!(resolutions.is_empty());
+        resolutions.record("handler", Some(&manual), Some(&template), Side::Manual);
+        resolutions.save().unwrap();
+
+        let resolutions = Resolutions::load(&config).unwrap();
+        assert_eq!(
+            resolutions.lookup("handler", Some(&manual), Some(&template)),
+            Some(Side::Manual)
+        );
+
+        // A new template version is a new conflict
+        let changed: Item = syn::parse_str("fn handler() -> u8 { 0 }").unwrap();
+        assert_eq!(
+            resolutions.lookup("handler", Some(&manual), Some(&changed)),
+            None
+        );
+        assert_eq!(resolutions.lookup("handler", Some(&manual), None), None);
+    }
+}
@@ -0,0 +1,336 @@
+//! Patch review module
+//!
+//! This module holds the state of an interactive review: every insert,
+//! modify and delete of a patch is presented on its own and can be accepted,
+//! rejected or replaced by an edited version before the result is assembled.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use syn::{File, Item};
+
+use crate::ast_parser::{item_kind, ParsedFile};
+use crate::diff::{compute_patch, compute_text_diff, extract_item_name, PatchOp};
+use crate::imports::insert_imports;
+use crate::merger::{merge_file_header, FileHeader, MergeStrategy};
+use crate::resolutions::{Resolutions, Side};
+use crate::summary::describe_op;
+
+/// Kind of change under review
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Insert,
+    Modify,
+    Delete,
+}
+
+/// Reviewer's decision for one change
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// Not decided yet, the existing code is kept
+    Pending,
+    /// Apply the template change
+    Accept,
+    /// Keep the existing code
+    Reject,
+    /// Use a hand-edited version of the item
+    Edit(Box<Item>),
+}
+
+/// A single reviewable change
+#[derive(Debug, Clone)]
+pub struct ReviewEntry {
+    pub name: String,
+    pub kind: &'static str,
+    pub change: Change,
+    /// One-line description, as in `diff` summaries
+    pub summary: String,
+    /// Text diff of the item from the existing version to the template version
+    pub diff: String,
+    pub existing: Option<Item>,
+    pub template: Option<Item>,
+    pub decision: Decision,
+    /// Template item an insert follows, used to place it
+    after: Option<String>,
+}
+
+impl ReviewEntry {
+    /// Source of the item a reviewer would start editing from
+    pub fn edit_source(&self) -> String {
+        let item = match &self.decision {
+            Decision::Edit(item) => Some(item.as_ref()),
+            _ => self.template.as_ref().or(self.existing.as_ref()),
+        };
+        item.map(item_source).unwrap_or_default()
+    }
+}
+
+/// Review of a template patch against an existing file
+#[derive(Debug)]
+pub struct Review {
+    pub entries: Vec<ReviewEntry>,
+    /// File attributes and docs, existing ones kept over template edits
+    pub header: FileHeader,
+    existing_items: Vec<Item>,
+    /// Template imports the existing file lacks, always added
+    imports: Vec<Item>,
+}
+
+impl Review {
+    /// Compute the changes between existing code and freshly generated code
+    pub fn new(existing_code: &str, generated_code: &str) -> Result<Self> {
+        let existing = ParsedFile::parse(existing_code)?;
+        let generated = ParsedFile::parse(generated_code)?;
+        let patch = compute_patch(existing.items(), generated.items())?;
+
+        let existing_by_name: HashMap<String, &Item> = existing
+            .items()
+            .iter()
+            .filter_map(|item| extract_item_name(item).map(|name| (name, item)))
+            .collect();
+
+        // Remember which template item precedes each insert
+        let mut predecessors = HashMap::new();
+        let mut previous = None;
+        for item in generated.items() {
+            if let Some(name) = extract_item_name(item) {
+                predecessors.insert(name.clone(), previous.replace(name));
+            }
+        }
+
+        let mut entries = Vec::new();
+        for op in &patch.operations {
+            let (name, change, existing_item, template_item) = match op {
+                PatchOp::Insert { name, item } => (name, Change::Insert, None, Some(*item)),
+                PatchOp::Modify {
+                    name,
+                    old_item,
+                    new_item,
+                } => (name, Change::Modify, Some(*old_item), Some(*new_item)),
+                // Reviewed as a change of the existing item
+                PatchOp::Rename {
+                    from,
+                    old_item,
+                    new_item,
+                    ..
+                } => (from, Change::Modify, Some(*old_item), Some(*new_item)),
+                PatchOp::Delete { name } => (
+                    name,
+                    Change::Delete,
+                    existing_by_name.get(name).copied(),
+                    None,
+                ),
+                PatchOp::Keep { .. } | PatchOp::Move { .. } => continue,
+            };
+
+            let Some(item) = template_item.or(existing_item) else {
+                continue;
+            };
+
+            entries.push(ReviewEntry {
+                name: name.clone(),
+                kind: item_kind(item),
+                change,
+                summary: describe_op(op).unwrap_or_default(),
+                diff: compute_text_diff(
+                    &existing_item.map(item_source).unwrap_or_default(),
+                    &template_item.map(item_source).unwrap_or_default(),
+                ),
+                existing: existing_item.cloned(),
+                template: template_item.cloned(),
+                decision: Decision::Pending,
+                after: predecessors.get(name).cloned().flatten(),
+            });
+        }
+
+        let (header, _) = merge_file_header(
+            &existing.syntax_tree,
+            &generated.syntax_tree,
+            MergeStrategy::PreferManual,
+        );
+
+        let imports = patch.imports.clone();
+        Ok(Review {
+            entries,
+            header,
+            existing_items: existing.syntax_tree.items,
+            imports,
+        })
+    }
+
+    /// Decide the conflicts resolved in an earlier review the same way
+    pub fn apply_recorded(&mut self, resolutions: &Resolutions) {
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|e| e.change != Change::Insert)
+        {
+            let side = resolutions.lookup(
+                &entry.name,
+                entry.existing.as_ref(),
+                entry.template.as_ref(),
+            );
+            entry.decision = match side {
+                Some(Side::Manual) => Decision::Reject,
+                Some(Side::Template) => Decision::Accept,
+                None => continue,
+            };
+        }
+    }
+
+    /// Record the accepted and rejected conflicts, so later merges resolve them alike
+    ///
+    /// Inserts aren't conflicts, and edited items match neither side.
+    pub fn record(&self, resolutions: &mut Resolutions) {
+        for entry in self.entries.iter().filter(|e| e.change != Change::Insert) {
+            let side = match entry.decision {
+                Decision::Accept => Side::Template,
+                Decision::Reject => Side::Manual,
+                _ => continue,
+            };
+            resolutions.record(
+                &entry.name,
+                entry.existing.as_ref(),
+                entry.template.as_ref(),
+                side,
+            );
+        }
+    }
+
+    /// Number of changes still waiting for a decision
+    pub fn pending(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.decision == Decision::Pending)
+            .count()
+    }
+
+    /// Assemble the final items from the existing file and the decisions
+    ///
+    /// Existing items keep their order. Accepted inserts are placed after the
+    /// item that precedes them in the template.
+    pub fn apply(&self) -> Vec<Item> {
+        let by_name: HashMap<&str, &ReviewEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.change != Change::Insert)
+            .map(|entry| (entry.name.as_str(), entry))
+            .collect();
+
+        let mut result: Vec<(Option<String>, Item)> = Vec::new();
+        for item in &self.existing_items {
+            let name = extract_item_name(item);
+            let entry = name.as_deref().and_then(|name| by_name.get(name));
+            let item = match entry.map(|entry| (&entry.decision, entry)) {
+                Some((Decision::Edit(edited), _)) => edited.as_ref().clone(),
+                Some((Decision::Accept, entry)) => match &entry.template {
+                    Some(template) => template.clone(),
+                    // Accepted delete
+                    None => continue,
+                },
+                _ => item.clone(),
+            };
+            result.push((name, item));
+        }
+
+        for entry in self.entries.iter().filter(|e| e.change == Change::Insert) {
+            let item = match (&entry.decision, &entry.template) {
+                (Decision::Edit(edited), _) => edited.as_ref().clone(),
+                (Decision::Accept, Some(template)) => template.clone(),
+                _ => continue,
+            };
+
+            let position = match &entry.after {
+                Some(after) => result
+                    .iter()
+                    .position(|(name, _)| name.as_ref() == Some(after))
+                    .map_or(result.len(), |idx| idx + 1),
+                // Leading items are kept first (use statements, extern crates)
+                None => result.iter().take_while(|(name, _)| name.is_none()).count(),
+            };
+            result.insert(position, (Some(entry.name.clone()), item));
+        }
+
+        let mut items: Vec<Item> = result.into_iter().map(|(_, item)| item).collect();
+        insert_imports(&mut items, &self.imports);
+        items
+    }
+}
+
+/// Pretty-print a single item
+pub fn item_source(item: &Item) -> String {
+    prettyplease::unparse(&File {
+        shebang: None,
+        attrs: Vec::new(),
+        items: vec![item.clone()],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(items: &[Item]) -> Vec<String> {
+        items.iter().filter_map(extract_item_name).collect()
+    }
+
+    const EXISTING: &str = "fn a() {}\nfn b() -> u32 { 1 }\nfn manual() {}\n";
+    const GENERATED: &str = "fn a() {}\nfn added() {}\nfn b() -> u64 { 1 }\n";
+
+    #[test]
+    fn test_review_entries() {
+        let review = Review::new(EXISTING, GENERATED).unwrap();
+        let changes: Vec<_> = review
+            .entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.change))
+            .collect();
+
+        assert_eq!(
+            changes,
+            vec![
+                ("added", Change::Insert),
+                ("b", Change::Modify),
+                ("manual", Change::Delete),
+            ]
+        );
+        assert_eq!(review.pending(), 3);
+    }
+
+    #[test]
+    fn test_review_apply_decisions() {
+        let mut review = Review::new(EXISTING, GENERATED).unwrap();
+
+        // Nothing decided: existing file is unchanged
+        assert_eq!(names(&review.apply()), vec!["a", "b", "manual"]);
+
+        review.entries[0].decision = Decision::Accept;
+        review.entries[1].decision = Decision::Edit(Box::new(syn::parse_quote! {
+            fn b() -> u64 { 2 }
+        }));
+        review.entries[2].decision = Decision::Accept;
+
+        let items = review.apply();
+        assert_eq!(names(&items), vec!["a", "added", "b"]);
+        assert_eq!(item_source(&items[2]), "fn b() -> u64 {\n    2\n}\n");
+    }
+
+    #[test]
+    fn test_review_recorded_decisions() {
+        let mut review = Review::new(EXISTING, GENERATED).unwrap();
+        review.entries[0].decision = Decision::Accept;
+        review.entries[1].decision = Decision::Accept;
+        review.entries[2].decision = Decision::Reject;
+
+        let mut resolutions = Resolutions::default();
+        review.record(&mut resolutions);
+
+        // Inserts aren't recorded
+        let mut review = Review::new(EXISTING, GENERATED).unwrap();
+        review.apply_recorded(&resolutions);
+        let decisions: Vec<_> = review.entries.iter().map(|e| e.decision.clone()).collect();
+        assert_eq!(
+            decisions,
+            vec![Decision::Pending, Decision::Accept, Decision::Reject]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! SARIF module
+//!
+//! This module prints check and diff findings as SARIF 2.1.0, the format code
+//! review tools such as GitHub code scanning and GitLab read to show inline
+//! annotations. Each finding becomes a result on its file, spanning the item
+//! it concerns, or the first line for changes that don't belong to an
+//! existing item. Severities map to SARIF levels: `error`, `warning` and
+//! `note` for info.
+
+use serde_json::{json, Value};
+
+use crate::check::{Severity, TargetCheck};
+
+/// Rule id, SARIF level and description of the rule behind each severity
+pub(crate) fn rule(severity: Severity) -> (&'static str, &'static str, &'static str) {
+    match severity {
+        Severity::Error => (
+            "divergent-change",
+            "error",
+            "Template change to code edited by hand",
+        ),
+        Severity::Warning => (
+            "template-deletion",
+            "warning",
+            "Item the template doesn't produce",
+        ),
+        Severity::Info => ("pending-update", "note", "Pending template update"),
+    }
+}
+
+/// Render the findings of the given files as a SARIF log
+pub fn to_sarif(checks: &[TargetCheck]) -> String {
+    let rules: Vec<Value> = [Severity::Error, Severity::Warning, Severity::Info]
+        .into_iter()
+        .map(|severity| {
+            let (id, level, description) = rule(severity);
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+                "defaultConfiguration": { "level": level },
+            })
+        })
+        .collect();
+
+    let results: Vec<Value> = checks
+        .iter()
+        .flat_map(|check| {
+            let uri = check.output.to_string_lossy().replace('\\', "/");
+            check.findings.iter().map(move |finding| {
+                let (id, level, _) = rule(finding.severity);
+                let (start, end) = finding.lines.unwrap_or((1, 1));
+                json!({
+                    "ruleId": id,
+                    "level": level,
+                    "message": { "text": finding.message },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": uri },
+                            "region": { "startLine": start, "endLine": end },
+                        }
+                    }],
+                })
+            })
+        })
+        .collect();
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::find_changes;
+    use crate::edition::Edition;
+
+    #[test]
+    fn test_to_sarif() {
+        let findings = find_changes(
+            "pub fn a() -> u8 { 2 }\n\npub fn c() {\n}\n",
+            "pub fn a() -> u8 { 1 }\npub fn b() {}",
+            true,
+            Edition::default(),
+        )
+        .unwrap();
+        let sarif = to_sarif(&[TargetCheck {
+            output: "src/lib.rs".into(),
+            findings,
+        }]);
+
+        let log: Value = serde_json::from_str(&sarif).unwrap();
+        let results = log["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(results[1]["ruleId"], "template-deletion");
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["region"],
+            json!({ "startLine": 3, "endLine": 4 })
+        );
+        assert_eq!(
+            results[2]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "src/lib.rs"
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! JSON-RPC server module
+//!
+//! This module serves render, diff, check and merge over JSON-RPC 2.0 with
+//! LSP-style `Content-Length` framing, so editor integrations can keep one
+//! process (and its compiled templates) alive.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use crate::ast_parser::ParsedFile;
+use crate::backend::Engine;
+use crate::cli::Commands;
+use crate::diff::{compute_patch, compute_text_diff};
+use crate::generator::{merge_generated, GenerateOptions};
+use crate::merger::{merge_patch, messages, MergeStrategy};
+use crate::template::{parse_context, TemplateCache};
+
+/// Methods answered by the server
+pub const METHODS: &[&str] = &["initialize", "render", "diff", "check", "merge", "shutdown"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const OPERATION_FAILED: i64 = -32000;
+
+/// Parameters shared by every operation
+///
+/// The context is given inline (`context`) or as a JSON file
+/// (`contextPath`); existing code as text, e.g. an unsaved editor buffer
+/// (`existing`), or as a file (`existingPath`).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Params {
+    template: PathBuf,
+    #[serde(default)]
+    context: Option<HashMap<String, Value>>,
+    #[serde(default)]
+    context_path: Option<PathBuf>,
+    #[serde(default)]
+    existing: Option<String>,
+    #[serde(default)]
+    existing_path: Option<PathBuf>,
+    #[serde(default)]
+    strategy: Option<String>,
+    #[serde(default)]
+    respect_order: bool,
+    #[serde(default)]
+    lenient: bool,
+    #[serde(default)]
+    engine: Option<String>,
+}
+
+/// Request handler holding state shared across requests
+#[derive(Default)]
+pub struct Server {
+    cache: TemplateCache,
+    shutdown: bool,
+}
+
+impl Server {
+    /// Create a server with an empty template cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle one JSON-RPC message, returning the response for requests
+    ///
+    /// Notifications (messages without an `id`) get no response.
+    pub fn handle(&mut self, message: &Value) -> Option<Value> {
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            return Some(error_response(
+                id.unwrap_or(Value::Null),
+                INVALID_REQUEST,
+                "Missing method",
+            ));
+        };
+
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let result = self.dispatch(method, params);
+        let id = id?;
+
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, &message),
+        })
+    }
+
+    /// Whether a `shutdown` request was received
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown
+    }
+
+    fn dispatch(&mut self, method: &str, params: Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(json!({
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+                "methods": METHODS,
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "render" | "diff" | "check" | "merge" => {
+                let params: Params = serde_json::from_value(params)
+                    .map_err(|err| (INVALID_PARAMS, err.to_string()))?;
+                self.operation(method, params)
+                    .map_err(|err| (OPERATION_FAILED, format!("{:#}", err)))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        }
+    }
+
+    fn operation(&mut self, method: &str, params: Params) -> Result<Value> {
+        let generated = self.render(&params)?;
+        if method == "render" {
+            return Ok(json!({ "code": generated }));
+        }
+
+        let existing = match (&params.existing, &params.existing_path) {
+            (Some(existing), _) => Some(existing.clone()),
+            (None, Some(path)) if path.exists() => {
+                Some(fs::read_to_string(path).context("Failed to read existing file")?)
+            }
+            _ => None,
+        };
+
+        match method {
+            "merge" => {
+                let options = GenerateOptions {
+                    strategy: Commands::parse_strategy(params.strategy.as_deref().unwrap_or("")),
+                    respect_order: params.respect_order,
+                    ..GenerateOptions::default()
+                };
+                let project_dir = params.existing_path.as_deref().and_then(|p| p.parent());
+                let output = merge_generated(generated, existing.as_deref(), options, project_dir)?;
+                Ok(json!({ "code": output.code, "conflicts": messages(&output.conflicts) }))
+            }
+            _ => {
+                let existing = existing.unwrap_or_default();
+                let generated_ast = ParsedFile::parse(&generated)?;
+                let existing_ast = ParsedFile::parse(&existing)?;
+                let patch = compute_patch(existing_ast.items(), generated_ast.items())?;
+
+                if method == "diff" {
+                    return Ok(json!({
+                        "summary": patch.summary(),
+                        "diff": compute_text_diff(&existing, &generated),
+                    }));
+                }
+
+                let merge_result = merge_patch(
+                    existing_ast.items(),
+                    &patch,
+                    MergeStrategy::FailOnConflict,
+                    false,
+                )?;
+                Ok(json!({
+                    "changes": patch.summary(),
+                    "conflicts": messages(&merge_result.conflicts),
+                }))
+            }
+        }
+    }
+
+    /// Render the requested template, reusing compiled templates
+    fn render(&mut self, params: &Params) -> Result<String> {
+        let context_data = match (&params.context, &params.context_path) {
+            (Some(context), _) => context.clone(),
+            (None, Some(path)) => {
+                parse_context(&fs::read_to_string(path).context("Failed to read context file")?)?
+            }
+            (None, None) => HashMap::new(),
+        };
+
+        let template_name = params
+            .template
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("Invalid template file name")?;
+        let template_content =
+            fs::read_to_string(&params.template).context("Failed to read template file")?;
+
+        let engine = match params.engine.as_deref() {
+            Some(name) => Engine::parse(name)?,
+            None => Engine::Tera,
+        };
+        if engine != Engine::Tera {
+            let backend = engine.from_file(&params.template, &template_content)?;
+            return backend.render_template(template_name, &context_data, params.lenient);
+        }
+
+        let engine = self
+            .cache
+            .get_or_compile_file(&params.template, &template_content)?;
+        engine.render_with(template_name, &context_data, params.lenient)
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Serve JSON-RPC over stdin/stdout until `shutdown` or end of input
+pub fn serve_stdio() -> Result<()> {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    serve(&mut stdin.lock(), &mut stdout.lock())
+}
+
+/// Serve JSON-RPC messages from `input`, writing responses to `output`
+pub fn serve(input: &mut impl BufRead, output: &mut impl Write) -> Result<()> {
+    let mut server = Server::new();
+
+    while let Some(body) = read_message(input)? {
+        let response = match serde_json::from_slice::<Value>(&body) {
+            Ok(message) => server.handle(&message),
+            Err(err) => Some(error_response(Value::Null, PARSE_ERROR, &err.to_string())),
+        };
+
+        if let Some(response) = response {
+            write_message(output, &response)?;
+        }
+        if server.is_shutdown() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read one `Content-Length` framed message, `None` at end of input
+fn read_message(input: &mut impl BufRead) -> Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .context("Invalid Content-Length")?,
+                );
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> Result<()> {
+    let body = serde_json::to_string(message)?;
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(message: &Value) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn test_merge_over_stdio() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("t.tera");
+        fs::write(&template, "fn {{ name }}() {}\n").unwrap();
+
+        let params = json!({
+            "template": template,
+            "context": { "name": "generated" },
+            "existing": "fn manual() {}\n",
+        });
+        let input = [
+            frame(&json!({ "jsonrpc": "2.0", "id": 1, "method": "merge", "params": params })),
+            frame(&json!({ "jsonrpc": "2.0", "id": 2, "method": "unknown" })),
+            frame(&json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" })),
+        ]
+        .concat();
+
+        let mut output = Vec::new();
+        serve(&mut input.as_bytes(), &mut output).unwrap();
+
+        let mut reader = output.as_slice();
+        let responses: Vec<Value> = std::iter::from_fn(|| read_message(&mut reader).unwrap())
+            .map(|body| serde_json::from_slice(&body).unwrap())
+            .collect();
+
+        assert_eq!(responses.len(), 3);
+        let code = responses[0]["result"]["code"].as_str().unwrap();
+        assert!(code.contains("fn manual()"));
+        assert!(code.contains("fn generated()"));
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[2]["result"], Value::Null);
+    }
+}
@@ -0,0 +1,162 @@
+//! Smart merge module
+//!
+//! This module resolves conflicts for the `smart` strategy by combining both
+//! versions of an item instead of picking one:
+//!
+//! - the template owns signatures, visibility and attributes,
+//! - manual edits own function bodies,
+//! - of the two doc comments, the longer one is kept.
+//!
+//! When both sides changed what the other owns, such as a function whose
+//! signature changed in the template and whose body was edited by hand, the
+//! heuristics disagree and the item is left as a conflict.
+
+use syn::{Attribute, Item};
+
+use crate::ast_parser::doc_line;
+
+/// Combine the manual and template versions of an item
+///
+/// Returns `None` when the heuristics can't decide.
+pub fn smart_merge(manual: &Item, template: &Item) -> Option<Item> {
+    let docs = longer_docs(attrs(manual)?, attrs(template)?);
+
+    let mut merged = match (manual, template) {
+        (Item::Fn(manual), Item::Fn(template)) => {
+            if manual.block != template.block && manual.sig != template.sig {
+                return None;
+            }
+            let mut merged = template.clone();
+            merged.block = manual.block.clone();
+            Item::Fn(merged)
+        }
+        // Modules, traits and extern blocks hold code of their own, only their
+        // attributes are merged
+        (Item::Mod(_), Item::Mod(_))
+        | (Item::Trait(_), Item::Trait(_))
+        | (Item::ForeignMod(_), Item::ForeignMod(_)) => {
+            if without_attrs(manual) != without_attrs(template) {
+                return None;
+            }
+            template.clone()
+        }
+        _ if std::mem::discriminant(manual) == std::mem::discriminant(template) => template.clone(),
+        _ => return None,
+    };
+
+    let attrs = attrs_mut(&mut merged)?;
+    attrs.retain(|attr| !attr.path().is_ident("doc"));
+    attrs.splice(0..0, docs);
+    Some(merged)
+}
+
+/// Doc attributes of the version with the longer doc comment, the template's on ties
+fn longer_docs(manual: &[Attribute], template: &[Attribute]) -> Vec<Attribute> {
+    let docs = |attrs: &[Attribute]| -> Vec<Attribute> {
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("doc"))
+            .cloned()
+            .collect()
+    };
+    let length = |attrs: &[Attribute]| -> usize {
+        attrs
+            .iter()
+            .filter_map(doc_line)
+            .map(|line| line.len())
+            .sum()
+    };
+
+    if length(manual) > length(template) {
+        docs(manual)
+    } else {
+        docs(template)
+    }
+}
+
+/// Item with its attributes removed
+fn without_attrs(item: &Item) -> Item {
+    let mut item = item.clone();
+    if let Some(attrs) = attrs_mut(&mut item) {
+        attrs.clear();
+    }
+    item
+}
+
+/// Outer attributes of the item kinds the merger matches by name
+fn attrs(item: &Item) -> Option<&[Attribute]> {
+    match item {
+        Item::Fn(i) => Some(&i.attrs),
+        Item::Struct(i) => Some(&i.attrs),
+        Item::Enum(i) => Some(&i.attrs),
+        Item::Trait(i) => Some(&i.attrs),
+        Item::Type(i) => Some(&i.attrs),
+        Item::Const(i) => Some(&i.attrs),
+        Item::Static(i) => Some(&i.attrs),
+        Item::Mod(i) => Some(&i.attrs),
+        Item::Union(i) => Some(&i.attrs),
+        Item::TraitAlias(i) => Some(&i.attrs),
+        Item::ForeignMod(i) => Some(&i.attrs),
+        _ => None,
+    }
+}
+
+/// Mutable outer attributes of the item kinds the merger matches by name
+fn attrs_mut(item: &mut Item) -> Option<&mut Vec<Attribute>> {
+    match item {
+        Item::Fn(i) => Some(&mut i.attrs),
+        Item::Struct(i) => Some(&mut i.attrs),
+        Item::Enum(i) => Some(&mut i.attrs),
+        Item::Trait(i) => Some(&mut i.attrs),
+        Item::Type(i) => Some(&mut i.attrs),
+        Item::Const(i) => Some(&mut i.attrs),
+        Item::Static(i) => Some(&mut i.attrs),
+        Item::Mod(i) => Some(&mut i.attrs),
+        Item::Union(i) => Some(&mut i.attrs),
+        Item::TraitAlias(i) => Some(&mut i.attrs),
+        Item::ForeignMod(i) => Some(&mut i.attrs),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(code: &str) -> Item {
+        syn::parse_str(code).unwrap()
+    }
+
+    #[test]
+    fn test_smart_merge_fn() {
+        let manual = item(
+            "/// Validates the name, rejecting empty and overlong names\n\
+             fn validate(name: &str) -> bool { !name.is_empty() && name.len() < 64 }",
+        );
+        let template = item(
+            "/// Validates the name\n#[must_use]\npub fn validate(name: &str) -> bool { true }",
+        );
+
+        let expected = item(
+            "/// Validates the name, rejecting empty and overlong names\n#[must_use]\n\
+             pub fn validate(name: &str) -> bool { !name.is_empty() && name.len() < 64 }",
+        );
+        assert_eq!(smart_merge(&manual, &template), Some(expected));
+
+        // The template changed the signature the manual body was written for
+        let template = item("pub fn validate(name: &str, max: usize) -> bool { true }");
+        assert_eq!(smart_merge(&manual, &template), None);
+    }
+
+    #[test]
+    fn test_smart_merge_struct() {
+        let manual = item("/// A user\n#[derive(Debug)]\npub struct User { pub id: u64 }");
+        let template =
+            item("#[derive(Debug, Clone)]\npub struct User { pub id: u64, pub name: String }");
+
+        let expected =
+            item("/// A user\n#[derive(Debug, Clone)]\npub struct User { pub id: u64, pub name: String }");
+        assert_eq!(smart_merge(&manual, &template), Some(expected));
+        assert_eq!(smart_merge(&manual, &item("pub enum User {}")), None);
+    }
+}
@@ -0,0 +1,107 @@
+//! Snapshot module
+//!
+//! This module records the current content of generated files as the base of
+//! later three-way merges (see [`crate::three_way`]), in `.rpt/base/` under
+//! the path of each output. A project adopting the tool on code generated by
+//! other means runs `snapshot --all` once: items still matching their snapshot
+//! take template updates, and only items edited since are treated as manual
+//! edits, instead of every difference being a conflict.
+//!
+//! `generate` and `generate-all` merge against the snapshot of an output when
+//! there is one and no `--base` is given, then record the template's new
+//! output as the next base.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::Config;
+
+/// Directory holding the snapshots, relative to the config root
+pub const BASE_DIR: &str = ".rpt/base";
+
+/// Location of the snapshot of `output`, a path relative to the config root
+pub fn base_path(config: &Config, output: &Path) -> PathBuf {
+    config.resolve_path(&Path::new(BASE_DIR).join(output))
+}
+
+/// Snapshot of `output`, a path relative to the config root, when one was recorded
+pub fn base_of(config: &Config, output: &Path) -> Option<PathBuf> {
+    Some(base_path(config, output)).filter(|base| base.is_file())
+}
+
+/// Record the current content of `output` as its base, returning where it was stored
+pub fn snapshot(config: &Config, output: &Path) -> Result<PathBuf> {
+    let relative = relative_output(config, output)?;
+    let source = config.resolve_path(&relative);
+    let content =
+        fs::read(&source).with_context(|| format!("Failed to read {}", source.display()))?;
+    record(config, &relative, &content)
+}
+
+/// Store `content` as the base of `output`, a path relative to the config root
+pub fn record(config: &Config, output: &Path, content: &[u8]) -> Result<PathBuf> {
+    let base = base_path(config, output);
+    if let Some(parent) = base.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&base, content).with_context(|| format!("Failed to write {}", base.display()))?;
+    Ok(base)
+}
+
+/// `output` relative to the config root, whether given from there or from the current directory
+pub fn relative_output(config: &Config, output: &Path) -> Result<PathBuf> {
+    if output.is_relative() && config.resolve_path(output).exists() {
+        return Ok(normalize(output));
+    }
+
+    let absolute = std::env::current_dir()?.join(output);
+    let (absolute, root) = match (absolute.canonicalize(), config.root.canonicalize()) {
+        (Ok(absolute), Ok(root)) => (absolute, root),
+        _ => (normalize(&absolute), normalize(&config.root)),
+    };
+    absolute
+        .strip_prefix(&root)
+        .map(Path::to_path_buf)
+        .map_err(|_| anyhow::anyhow!("{} is outside the project", output.display()))
+}
+
+/// `path` without `.` and `..` components
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("rpt.toml"), "").unwrap();
+        fs::write(dir.path().join("src/user.rs"), "pub struct User;\n").unwrap();
+        let config = Config::load(&dir.path().join("rpt.toml")).unwrap();
+
+        assert_eq!(base_of(&config, Path::new("src/user.rs")), None);
+        let relative = relative_output(&config, Path::new("./src/user.rs")).unwrap();
+        assert_eq!(relative, Path::new("src/user.rs"));
+        let base = snapshot(&config, &relative).unwrap();
+
+        assert_eq!(base, dir.path().join(".rpt/base/src/user.rs"));
+        assert_eq!(fs::read_to_string(&base).unwrap(), "pub struct User;\n");
+        assert_eq!(base_of(&config, Path::new("src/user.rs")), Some(base));
+        assert!(snapshot(&config, Path::new("src/missing.rs")).is_err());
+        assert!(relative_output(&config, &dir.path().join("../outside.rs")).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! Status module
+//!
+//! This module compares every manifest target with what regenerating it would
+//! produce, like `git status` for generated code. Hashes recorded in
+//! `.rpt/cache.json` tell template updates apart from manual edits: inputs
+//! that changed since the last generation mean a pending update, an output
+//! that changed means manual modifications. Targets that were never generated
+//! count every difference from the rendered template as a manual modification.
+
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+
+use crate::config::{Config, Target};
+use crate::edition::Edition;
+use crate::generator::{merge_generated, render_template_file, same_code, GenerateOptions};
+use crate::incremental::{hash_target, RegenerationCache};
+use crate::merger::{messages, MergeStrategy};
+
+/// State of a target compared with its regenerated output
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetStatus {
+    /// Regenerating would not change the output
+    UpToDate,
+    /// The output was never generated
+    Missing,
+    /// Template or context changes would update the output
+    Pending,
+    /// The output was edited by hand, regenerating keeps the edits
+    Modified,
+    /// Template or context changes collide with manual edits
+    Conflict(Vec<String>),
+}
+
+impl TargetStatus {
+    /// Short label used in the report
+    pub fn label(&self) -> &'static str {
+        match self {
+            TargetStatus::UpToDate => "up to date",
+            TargetStatus::Missing => "not generated",
+            TargetStatus::Pending => "template updates",
+            TargetStatus::Modified => "modified",
+            TargetStatus::Conflict(_) => "conflict",
+        }
+    }
+}
+
+/// Compute the status of a manifest target, in memory
+pub fn target_status(
+    config: &Config,
+    target: &Target,
+    cache: &RegenerationCache,
+    context_data: HashMap<String, Value>,
+    mut options: GenerateOptions,
+) -> Result<TargetStatus> {
+    let output = config.resolve_path(&target.output);
+    let Ok(existing) = fs::read_to_string(&output) else {
+        return Ok(TargetStatus::Missing);
+    };
+
+    let render = options.render;
+    let edition = Edition::resolve(options.edition, Some(&config.root));
+    let generated = render_template_file(
+        &config.resolve_path(&target.template),
+        &context_data,
+        render,
+    )?;
+
+    // Collect conflicts instead of failing on the first one
+    if options.strategy == MergeStrategy::FailOnConflict {
+        options.strategy = MergeStrategy::PreferManual;
+    }
+    let merged = merge_generated(
+        generated.clone(),
+        Some(&existing),
+        options,
+        Some(&config.root),
+    )?;
+
+    let hashes = hash_target(config, target, render)?;
+    let (inputs_changed, modified) = match cache.recorded(target) {
+        Some(recorded) => (
+            recorded.template != hashes.template
+                || recorded.context != hashes.context
+                || recorded.strategy != hashes.strategy
+                || recorded.settings != hashes.settings,
+            recorded.output != hashes.output,
+        ),
+        None => (true, !same_code(&existing, &generated, edition)),
+    };
+
+    let status = if inputs_changed && modified && !merged.conflicts.is_empty() {
+        TargetStatus::Conflict(messages(&merged.conflicts))
+    } else if inputs_changed && !same_code(&existing, &merged.code, edition) {
+        TargetStatus::Pending
+    } else if modified {
+        TargetStatus::Modified
+    } else {
+        TargetStatus::UpToDate
+    };
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::RenderOptions;
+    use crate::template::parse_context;
+
+    #[test]
+    fn test_target_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            root: dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let target = Target {
+            name: None,
+            depends_on: Vec::new(),
+            template: "t.tera".into(),
+            context: "c.json".into(),
+            output: "out.rs".into(),
+            strategy: "manual".to_string(),
+            language: "rust".to_string(),
+        };
+        fs::write(dir.path().join("t.tera"), "pub fn {{ name }}() {}").unwrap();
+        fs::write(dir.path().join("c.json"), r#"{"name": "a"}"#).unwrap();
+
+        let status = |cache: &RegenerationCache| {
+            let context = fs::read_to_string(dir.path().join("c.json")).unwrap();
+            target_status(
+                &config,
+                &target,
+                cache,
+                parse_context(&context).unwrap(),
+                GenerateOptions::default(),
+            )
+            .unwrap()
+        };
+
+        let mut cache = RegenerationCache::load(&config);
+        assert_eq!(status(&cache), TargetStatus::Missing);
+
+        fs::write(dir.path().join("out.rs"), "pub fn a() {}").unwrap();
+        assert_eq!(status(&cache), TargetStatus::UpToDate);
+        cache.record(
+            &target,
+            hash_target(&config, &target, RenderOptions::default()).unwrap(),
+        );
+
+        fs::write(dir.path().join("out.rs"), "pub fn a() { edited(); }").unwrap();
+        assert_eq!(status(&cache), TargetStatus::Modified);
+
+        fs::write(dir.path().join("out.rs"), "pub fn a() {}").unwrap();
+        fs::write(dir.path().join("c.json"), r#"{"name": "b"}"#).unwrap();
+        assert_eq!(status(&cache), TargetStatus::Pending);
+
+        fs::write(dir.path().join("out.rs"), "pub fn b() { edited(); }").unwrap();
+        assert!(matches!(status(&cache), TargetStatus::Conflict(_)));
+    }
+}
@@ -0,0 +1,307 @@
+//! Stored patch module
+//!
+//! This module turns a [`Patch`] into a [`StoredPatch`], which owns its items
+//! and serializes with serde, so a patch can be saved, sent to another machine
+//! and applied there. Items are stored as formatted source with their kind and
+//! name, not as syntax trees:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "operations": [
+//!     { "op": "insert", "name": "new_user",
+//!       "item": { "kind": "fn", "name": "new_user", "source": "fn new_user() {}\n" } },
+//!     { "op": "delete", "name": "legacy" }
+//!   ],
+//!   "imports": ["use std::fmt;\n"]
+//! }
+//! ```
+//!
+//! [`StoredPatch::from_json`] refuses other schema versions than
+//! [`SCHEMA_VERSION`]; [`StoredPatch::load`] parses the items back so the
+//! patch can be merged with [`crate::merger::merge_patch`].
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use syn::Item;
+
+use crate::ast_parser::item_kind;
+use crate::diff::{extract_item_name, Patch, PatchOp};
+use crate::review::item_source;
+
+/// Version of the schema written by this release
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Serializable form of a [`Patch`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredPatch {
+    /// Schema version, see [`SCHEMA_VERSION`]
+    pub version: u32,
+    pub operations: Vec<StoredOp>,
+    /// Source of the imports the new side adds
+    #[serde(default)]
+    pub imports: Vec<String>,
+}
+
+/// Serializable form of a [`PatchOp`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum StoredOp {
+    Insert {
+        name: String,
+        item: StoredItem,
+    },
+    Delete {
+        name: String,
+    },
+    Modify {
+        name: String,
+        old_item: StoredItem,
+        new_item: StoredItem,
+    },
+    Rename {
+        from: String,
+        name: String,
+        old_item: StoredItem,
+        new_item: StoredItem,
+    },
+    Keep {
+        name: String,
+    },
+    Move {
+        name: String,
+        from: usize,
+        to: usize,
+    },
+}
+
+/// An item of a stored patch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredItem {
+    /// Item kind, such as `struct` or `fn`
+    pub kind: String,
+    /// Key the item is matched by, see [`crate::matching`]
+    pub name: Option<String>,
+    /// Formatted source of the item
+    pub source: String,
+}
+
+/// A stored patch with its items parsed, see [`LoadedPatch::patch`]
+#[derive(Debug, Clone)]
+pub struct LoadedPatch {
+    stored: StoredPatch,
+    /// Items of the operations, in the order they appear
+    items: Vec<Item>,
+    imports: Vec<Item>,
+}
+
+impl StoredPatch {
+    /// Store `patch`, printing its items
+    pub fn new(patch: &Patch) -> Self {
+        let operations = patch
+            .operations
+            .iter()
+            .map(|op| match op {
+                PatchOp::Insert { name, item } => StoredOp::Insert {
+                    name: name.clone(),
+                    item: StoredItem::new(item),
+                },
+                PatchOp::Delete { name } => StoredOp::Delete { name: name.clone() },
+                PatchOp::Modify {
+                    name,
+                    old_item,
+                    new_item,
+                } => StoredOp::Modify {
+                    name: name.clone(),
+                    old_item: StoredItem::new(old_item),
+                    new_item: StoredItem::new(new_item),
+                },
+                PatchOp::Rename {
+                    from,
+                    name,
+                    old_item,
+                    new_item,
+                } => StoredOp::Rename {
+                    from: from.clone(),
+                    name: name.clone(),
+                    old_item: StoredItem::new(old_item),
+                    new_item: StoredItem::new(new_item),
+                },
+                PatchOp::Keep { name } => StoredOp::Keep { name: name.clone() },
+                PatchOp::Move { name, from, to } => StoredOp::Move {
+                    name: name.clone(),
+                    from: *from,
+                    to: *to,
+                },
+            })
+            .collect();
+
+        StoredPatch {
+            version: SCHEMA_VERSION,
+            operations,
+            imports: patch.imports.iter().map(item_source).collect(),
+        }
+    }
+
+    /// Read a patch serialized as JSON, checking its schema version
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json).context("Invalid patch JSON")?;
+        let version = value.get("version").and_then(|version| version.as_u64());
+        if version != Some(SCHEMA_VERSION.into()) {
+            anyhow::bail!(
+                "Unsupported patch schema version {}, expected {}",
+                version.map_or("none".to_string(), |version| version.to_string()),
+                SCHEMA_VERSION
+            );
+        }
+        serde_json::from_value(value).context("Invalid patch")
+    }
+
+    /// Parse the items back, to apply the patch
+    pub fn load(&self) -> Result<LoadedPatch> {
+        let mut items = Vec::new();
+        for op in &self.operations {
+            match op {
+                StoredOp::Insert { item, .. } => items.push(item.parse()?),
+                StoredOp::Modify {
+                    old_item, new_item, ..
+                }
+                | StoredOp::Rename {
+                    old_item, new_item, ..
+                } => {
+                    items.push(old_item.parse()?);
+                    items.push(new_item.parse()?);
+                }
+                StoredOp::Delete { .. } | StoredOp::Keep { .. } | StoredOp::Move { .. } => {}
+            }
+        }
+        let imports = self
+            .imports
+            .iter()
+            .map(|source| syn::parse_str(source).context("Stored import is not a Rust item"))
+            .collect::<Result<_>>()?;
+
+        Ok(LoadedPatch {
+            stored: self.clone(),
+            items,
+            imports,
+        })
+    }
+}
+
+impl StoredItem {
+    /// Store an item with its kind and name
+    pub fn new(item: &Item) -> Self {
+        StoredItem {
+            kind: item_kind(item).to_string(),
+            name: extract_item_name(item),
+            source: item_source(item),
+        }
+    }
+
+    /// Parse the item's source
+    pub fn parse(&self) -> Result<Item> {
+        syn::parse_str(&self.source).with_context(|| {
+            format!(
+                "Stored {} '{}' is not a Rust item",
+                self.kind,
+                self.name.as_deref().unwrap_or("-")
+            )
+        })
+    }
+}
+
+impl LoadedPatch {
+    /// The patch, borrowing the parsed items
+    pub fn patch(&self) -> Patch<'_> {
+        let mut items = self.items.iter();
+        let mut next = || {
+            items
+                .next()
+                .expect("an item was parsed for each stored one")
+        };
+        let mut patch = Patch::new();
+        for op in &self.stored.operations {
+            patch.add_operation(match op {
+                StoredOp::Insert { name, .. } => PatchOp::Insert {
+                    name: name.clone(),
+                    item: next(),
+                },
+                StoredOp::Delete { name } => PatchOp::Delete { name: name.clone() },
+                StoredOp::Modify { name, .. } => PatchOp::Modify {
+                    name: name.clone(),
+                    old_item: next(),
+                    new_item: next(),
+                },
+                StoredOp::Rename { from, name, .. } => PatchOp::Rename {
+                    from: from.clone(),
+                    name: name.clone(),
+                    old_item: next(),
+                    new_item: next(),
+                },
+                StoredOp::Keep { name } => PatchOp::Keep { name: name.clone() },
+                StoredOp::Move { name, from, to } => PatchOp::Move {
+                    name: name.clone(),
+                    from: *from,
+                    to: *to,
+                },
+            });
+        }
+        patch.imports = self.imports.clone();
+        patch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::compute_patch;
+    use crate::merger::{merge_patch, MergeStrategy};
+    use syn::parse_quote;
+
+    #[test]
+    fn test_stored_patch_round_trip() {
+        let old_items: Vec<Item> = vec![
+            parse_quote! { fn legacy() {} },
+            parse_quote! { pub struct User { id: u32 } },
+        ];
+        let new_items: Vec<Item> = vec![
+            parse_quote! { use std::fmt; },
+            parse_quote! {
+                /// A user
+                pub struct User { id: u64 }
+            },
+            parse_quote! { fn new_user() -> User { todo!() } },
+        ];
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+
+        let json = serde_json::to_string(&StoredPatch::new(&patch)).unwrap();
+        let stored = StoredPatch::from_json(&json).unwrap();
+        assert_eq!(stored, StoredPatch::new(&patch));
+        assert!(json.contains(r#""op":"insert","name":"new_user","item":{"kind":"fn""#));
+
+        let loaded = stored.load().unwrap();
+        let merged = merge_patch(
+            &old_items,
+            &loaded.patch(),
+            MergeStrategy::PreferTemplate,
+            false,
+        )
+        .unwrap();
+        let expected =
+            merge_patch(&old_items, &patch, MergeStrategy::PreferTemplate, false).unwrap();
+        // Printed items lose their spans and token spelling, so compare them printed
+        let printed = |items: &[Item]| items.iter().map(item_source).collect::<Vec<_>>();
+        assert_eq!(
+            printed(&merged.merged_items),
+            printed(&expected.merged_items)
+        );
+        assert_eq!(printed(&loaded.patch().imports), printed(&patch.imports));
+
+        let newer = json.replacen("\"version\":1", "\"version\":2", 1);
+        assert_eq!(
+            StoredPatch::from_json(&newer).unwrap_err().to_string(),
+            "Unsupported patch schema version 2, expected 1"
+        );
+    }
+}
@@ -0,0 +1,641 @@
+//! Per-item strategy module
+//!
+//! This module lets `rpt.toml` override the merge strategy for conflicts on
+//! some items. `preserve` and `always_replace` list item names, with `*` and
+//! `?` wildcards, whose existing or template version always wins; the
+//! `[strategies]` table sets the strategy for items of a given kind:
+//!
+//! ```toml
+//! preserve = ["helper_*", "validate_name"]
+//! always_replace = ["*Dto"]
+//!
+//! [strategies]
+//! fn = "hybrid"            # template signature, manual body
+//! impl = "merge-methods"   # keep manual methods next to generated ones
+//! struct = "merge-fields"  # keep manual field attributes such as #[serde(...)]
+//! enum = "manual"          # enums are edited by hand
+//! ```
+//!
+//! Name lists come first, `preserve` before `always_replace`, then the
+//! resolutions recorded for the same conflict, see [`crate::resolutions`].
+//! Kinds without a rule use the `default` rule, then `--strategy`.
+
+use anyhow::Result;
+use quote::ToTokens;
+use std::collections::HashMap;
+use syn::{Attribute, Block, Field, Fields, ImplItem, Item, ItemImpl, ItemStruct, Signature};
+
+use crate::ast_parser::item_kind;
+use crate::config::Config;
+use crate::merger::MergeStrategy;
+use crate::placement::KINDS;
+use crate::resolutions::{Resolutions, Side};
+use crate::summary::{param, return_type};
+
+/// How a conflict on an item is resolved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemStrategy {
+    /// Resolve it with a merge strategy
+    Strategy(MergeStrategy),
+    /// Merge impl blocks method by method, see [`merge_methods`]
+    MergeMethods,
+    /// Merge structs field by field, see [`merge_fields`]
+    MergeFields,
+    /// Take signatures from the template and bodies from the manual version, see [`merge_signatures`]
+    Hybrid,
+    /// Keep the existing item, without reporting a conflict
+    Preserve,
+    /// Apply the template's version, without reporting a conflict
+    AlwaysReplace,
+}
+
+impl ItemStrategy {
+    /// Parse a strategy: template, manual, fail, smart, merge-methods, merge-fields or hybrid
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value {
+            "template" => ItemStrategy::Strategy(MergeStrategy::PreferTemplate),
+            "manual" => ItemStrategy::Strategy(MergeStrategy::PreferManual),
+            "fail" => ItemStrategy::Strategy(MergeStrategy::FailOnConflict),
+            "smart" => ItemStrategy::Strategy(MergeStrategy::Smart),
+            "merge-methods" => ItemStrategy::MergeMethods,
+            "merge-fields" => ItemStrategy::MergeFields,
+            "hybrid" => ItemStrategy::Hybrid,
+            _ => anyhow::bail!(
+                "Unknown strategy '{}', expected template, manual, fail, smart, merge-methods, merge-fields or hybrid",
+                value
+            ),
+        })
+    }
+}
+
+/// Merge strategy by item name and kind
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StrategyRules {
+    rules: HashMap<String, ItemStrategy>,
+    preserve: Vec<String>,
+    always_replace: Vec<String>,
+    recorded: Resolutions,
+}
+
+impl StrategyRules {
+    /// Read the rules declared in the config
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut rules = HashMap::new();
+        for (kind, value) in &config.strategies {
+            if !KINDS.contains(&kind.as_str()) {
+                anyhow::bail!(
+                    "Unknown item kind '{}' in [strategies], expected one of {}",
+                    kind,
+                    KINDS.join(", ")
+                );
+            }
+            let strategy = ItemStrategy::parse(value)?;
+            if strategy == ItemStrategy::MergeMethods && kind != "impl" {
+                anyhow::bail!("merge-methods only applies to impl blocks, not '{}'", kind);
+            }
+            if strategy == ItemStrategy::MergeFields && kind != "struct" {
+                anyhow::bail!("merge-fields only applies to structs, not '{}'", kind);
+            }
+            if strategy == ItemStrategy::Hybrid && kind != "fn" && kind != "impl" {
+                anyhow::bail!(
+                    "hybrid only applies to functions and impl blocks, not '{}'",
+                    kind
+                );
+            }
+            rules.insert(kind.clone(), strategy);
+        }
+
+        for pattern in config.preserve.iter().chain(&config.always_replace) {
+            if pattern.is_empty() {
+                anyhow::bail!("Empty item name pattern in preserve or always_replace");
+            }
+        }
+
+        Ok(StrategyRules {
+            rules,
+            preserve: config.preserve.clone(),
+            always_replace: config.always_replace.clone(),
+            recorded: Resolutions::default(),
+        })
+    }
+
+    /// Resolve conflicts the way they were resolved before
+    pub fn with_resolutions(mut self, recorded: Resolutions) -> Self {
+        self.recorded = recorded;
+        self
+    }
+
+    /// Strategy for a conflict between two versions of the item `name`
+    ///
+    /// Like [`StrategyRules::resolve`], with recorded resolutions applied after
+    /// the name lists.
+    pub fn resolve_conflict(
+        &self,
+        name: &str,
+        manual: Option<&Item>,
+        template: Option<&Item>,
+        strategy: MergeStrategy,
+    ) -> ItemStrategy {
+        let kind = manual.or(template).map(item_kind).unwrap_or("item");
+        let rule = self.resolve(name, kind, strategy);
+        if matches!(rule, ItemStrategy::Preserve | ItemStrategy::AlwaysReplace) {
+            return rule;
+        }
+
+        match self.recorded.lookup(name, manual, template) {
+            Some(Side::Manual) => ItemStrategy::Preserve,
+            Some(Side::Template) => ItemStrategy::AlwaysReplace,
+            None => rule,
+        }
+    }
+
+    /// Strategy for a conflict on the item `name` of `kind`, falling back to `strategy`
+    pub fn resolve(&self, name: &str, kind: &str, strategy: MergeStrategy) -> ItemStrategy {
+        let matches = |patterns: &[String]| patterns.iter().any(|p| glob_match(p, name));
+        if matches(&self.preserve) {
+            return ItemStrategy::Preserve;
+        }
+        if matches(&self.always_replace) {
+            return ItemStrategy::AlwaysReplace;
+        }
+
+        self.rules
+            .get(kind)
+            .or_else(|| self.rules.get("default"))
+            .copied()
+            .unwrap_or(ItemStrategy::Strategy(strategy))
+    }
+}
+
+/// Match a name against a pattern where `*` is any run of characters and `?` one character
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // Position after the last `*` and the name position it was tried at
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character
+                Some((after, at)) => {
+                    star = Some((after, at + 1));
+                    p = after;
+                    n = at + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Merge two versions of an impl block method by method
+///
+/// The block itself comes from the template. Members present on both sides
+/// keep the manual version, members only the template has are added, and
+/// members only the existing block has are kept after them.
+pub fn merge_methods(manual: &ItemImpl, template: &ItemImpl) -> Item {
+    let manual_names: HashMap<String, &ImplItem> = manual
+        .items
+        .iter()
+        .filter_map(|item| member_name(item).map(|name| (name, item)))
+        .collect();
+
+    let mut merged = template.clone();
+    merged.items = template
+        .items
+        .iter()
+        .map(|item| {
+            member_name(item)
+                .and_then(|name| manual_names.get(&name).copied())
+                .unwrap_or(item)
+                .clone()
+        })
+        .collect();
+
+    for item in &manual.items {
+        let in_template = match member_name(item) {
+            Some(name) => template
+                .items
+                .iter()
+                .any(|other| member_name(other).as_ref() == Some(&name)),
+            None => template.items.contains(item),
+        };
+        if !in_template {
+            merged.items.push(item.clone());
+        }
+    }
+
+    Item::Impl(merged)
+}
+
+/// Merge two versions of a struct field by field
+///
+/// The struct itself comes from the template, and so do fields the manual
+/// version didn't change. Fields only the existing struct has are kept after
+/// the template's. Manual attributes of a field, such as `#[serde(rename)]`,
+/// are kept when the template left its name and type as they were in `base`,
+/// the previous template output; attributes are compared by path, and one
+/// both sides changed is a conflict where the manual version is kept.
+/// Without `base`, attributes only one side has are kept.
+///
+/// Returns the merged struct and its conflicts.
+pub fn merge_fields(
+    manual: &ItemStruct,
+    template: &ItemStruct,
+    base: Option<&ItemStruct>,
+) -> (Item, Vec<String>) {
+    let mut conflicts = Vec::new();
+    let mut merged = template.clone();
+    if std::mem::discriminant(&manual.fields) != std::mem::discriminant(&template.fields) {
+        conflicts.push(format!(
+            "Struct '{}' changed shape on both sides, template version used",
+            template.ident
+        ));
+        return (Item::Struct(merged), conflicts);
+    }
+
+    let find = |fields: &Fields, key: &str| -> Option<Field> {
+        fields
+            .iter()
+            .enumerate()
+            .find(|(idx, field)| field_key(*idx, field) == key)
+            .map(|(_, field)| field.clone())
+    };
+
+    let mut template_keys = Vec::new();
+    for (idx, field) in merged.fields.iter_mut().enumerate() {
+        let key = field_key(idx, field);
+        let old = base.and_then(|base| find(&base.fields, &key));
+        if let Some(manual) = find(&manual.fields, &key) {
+            if old.as_ref() == Some(field) {
+                // The template left the field as it was
+                *field = manual;
+            } else if manual.ty == field.ty && old.as_ref().is_none_or(|old| old.ty == field.ty) {
+                let old_attrs = old.map(|old| old.attrs);
+                field.attrs = merge_attrs(
+                    &manual.attrs,
+                    &field.attrs,
+                    old_attrs.as_deref(),
+                    |path| {
+                        conflicts.push(format!(
+                            "Field '{}' of '{}' has manual and template changes to `{}`, manual version kept",
+                            key, template.ident, path
+                        ))
+                    },
+                );
+            }
+        }
+        template_keys.push(key);
+    }
+
+    if let Fields::Named(fields) = &mut merged.fields {
+        for field in &manual.fields {
+            if let Some(ident) = &field.ident {
+                if !template_keys.contains(&ident.to_string()) {
+                    fields.named.push(field.clone());
+                }
+            }
+        }
+    }
+
+    (Item::Struct(merged), conflicts)
+}
+
+/// Merge a function, or the methods of an impl block, keeping manual bodies
+///
+/// Signatures, attributes and visibility come from the template and bodies
+/// from the manual version, so a parameter the template adds doesn't discard
+/// code written by hand. Methods only one side has are kept, like
+/// [`merge_methods`]. Returns `None` for other items.
+///
+/// The conflicts name the signature changes the manual bodies may not handle:
+/// new parameters, removed parameters the body still uses, and new return types.
+pub fn merge_signatures(manual: &Item, template: &Item) -> Option<(Item, Vec<String>)> {
+    let mut conflicts = Vec::new();
+    let merged = match (manual, template) {
+        (Item::Fn(manual), Item::Fn(template)) => {
+            let mut merged = template.clone();
+            merged.block = manual.block.clone();
+            conflicts.extend(signature_notes(
+                "Function",
+                &manual.sig,
+                &template.sig,
+                &manual.block,
+            ));
+            Item::Fn(merged)
+        }
+        (Item::Impl(manual), Item::Impl(template)) => {
+            let Item::Impl(mut merged) = merge_methods(manual, template) else {
+                return None;
+            };
+            for item in &mut merged.items {
+                let ImplItem::Fn(method) = item else {
+                    continue;
+                };
+                let template_method = template.items.iter().find_map(|item| match item {
+                    ImplItem::Fn(f) if f.sig.ident == method.sig.ident => Some(f),
+                    _ => None,
+                });
+                if let Some(template_method) = template_method {
+                    let body = method.block.clone();
+                    conflicts.extend(signature_notes(
+                        "Method",
+                        &method.sig,
+                        &template_method.sig,
+                        &body,
+                    ));
+                    *method = template_method.clone();
+                    method.block = body;
+                }
+            }
+            Item::Impl(merged)
+        }
+        _ => return None,
+    };
+    Some((merged, conflicts))
+}
+
+/// Signature changes a manual body kept under a new template signature may not handle
+fn signature_notes(
+    kind: &str,
+    manual: &Signature,
+    template: &Signature,
+    body: &Block,
+) -> Vec<String> {
+    let name = &template.ident;
+    let manual_params: Vec<(String, String)> = manual.inputs.iter().map(param).collect();
+    let template_params: Vec<(String, String)> = template.inputs.iter().map(param).collect();
+    let body_idents: Vec<String> = body
+        .to_token_stream()
+        .to_string()
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .map(str::to_string)
+        .collect();
+
+    let mut notes = Vec::new();
+    for (param, ty) in &template_params {
+        if !manual_params.iter().any(|(other, _)| other == param) {
+            notes.push(format!(
+                "{} '{}' has new parameter `{}: {}` from the template, manual body may need updating",
+                kind, name, param, ty
+            ));
+        }
+    }
+    for (param, _) in &manual_params {
+        if !template_params.iter().any(|(other, _)| other == param) && body_idents.contains(param) {
+            notes.push(format!(
+                "{} '{}' no longer has parameter `{}`, which the manual body still uses",
+                kind, name, param
+            ));
+        }
+    }
+    let (old_ret, new_ret) = (return_type(&manual.output), return_type(&template.output));
+    if old_ret != new_ret {
+        notes.push(format!(
+            "{} '{}' now returns `{}` instead of `{}`, manual body may need updating",
+            kind, name, new_ret, old_ret
+        ));
+    }
+    notes
+}
+
+/// Key a field is matched by: its name, or its index in tuple structs
+fn field_key(idx: usize, field: &Field) -> String {
+    field
+        .ident
+        .as_ref()
+        .map_or_else(|| idx.to_string(), |ident| ident.to_string())
+}
+
+/// Merge the attributes of a field, grouped by path
+///
+/// Groups follow the manual order, then the template's. `conflict` is called
+/// with the path of each group both sides changed.
+fn merge_attrs(
+    manual: &[Attribute],
+    template: &[Attribute],
+    base: Option<&[Attribute]>,
+    mut conflict: impl FnMut(&str),
+) -> Vec<Attribute> {
+    let path = |attr: &Attribute| attr.path().to_token_stream().to_string().replace(' ', "");
+    let group = |attrs: &[Attribute], name: &str| -> Vec<Attribute> {
+        attrs
+            .iter()
+            .filter(|attr| path(attr) == name)
+            .cloned()
+            .collect()
+    };
+
+    let mut paths: Vec<String> = Vec::new();
+    for attr in manual.iter().chain(template) {
+        let name = path(attr);
+        if !paths.contains(&name) {
+            paths.push(name);
+        }
+    }
+
+    let mut merged = Vec::new();
+    for name in paths {
+        let (ours, theirs) = (group(manual, &name), group(template, &name));
+        let old = base.map(|base| group(base, &name)).unwrap_or_default();
+        if ours == theirs || ours == old {
+            merged.extend(theirs);
+        } else if theirs == old {
+            merged.extend(ours);
+        } else {
+            conflict(&name);
+            merged.extend(ours);
+        }
+    }
+    merged
+}
+
+/// Name of a method, associated const or associated type
+fn member_name(item: &ImplItem) -> Option<String> {
+    match item {
+        ImplItem::Fn(f) => Some(f.sig.ident.to_string()),
+        ImplItem::Const(c) => Some(c.ident.to_string()),
+        ImplItem::Type(t) => Some(t.ident.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_from_config() {
+        let config = Config {
+            strategies: [
+                ("struct".to_string(), "template".to_string()),
+                ("impl".to_string(), "merge-methods".to_string()),
+            ]
+            .into(),
+            ..Config::default()
+        };
+
+        let rules = StrategyRules::from_config(&config).unwrap();
+        assert_eq!(
+            rules.resolve("User", "struct", MergeStrategy::PreferManual),
+            ItemStrategy::Strategy(MergeStrategy::PreferTemplate)
+        );
+        assert_eq!(
+            rules.resolve("handler", "fn", MergeStrategy::Smart),
+            ItemStrategy::Strategy(MergeStrategy::Smart)
+        );
+        assert_eq!(
+            rules.resolve("impl User", "impl", MergeStrategy::PreferManual),
+            ItemStrategy::MergeMethods
+        );
+
+        let config = Config {
+            strategies: [("fn".to_string(), "merge-methods".to_string())].into(),
+            ..Config::default()
+        };
+        assert!(StrategyRules::from_config(&config).is_err());
+        let config = Config {
+            strategies: [("enum".to_string(), "merge-fields".to_string())].into(),
+            ..Config::default()
+        };
+        assert!(StrategyRules::from_config(&config).is_err());
+        let config = Config {
+            strategies: [("struct".to_string(), "hybrid".to_string())].into(),
+            ..Config::default()
+        };
+        assert!(StrategyRules::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_name_rules() {
+        let config = Config {
+            preserve: vec!["helper_*".to_string(), "validate_name".to_string()],
+            always_replace: vec!["*Dto".to_string(), "helper_?".to_string()],
+            strategies: [("default".to_string(), "fail".to_string())].into(),
+            ..Config::default()
+        };
+        let rules = StrategyRules::from_config(&config).unwrap();
+        let resolve = |name| rules.resolve(name, "fn", MergeStrategy::PreferManual);
+
+        assert_eq!(resolve("helper_a"), ItemStrategy::Preserve);
+        assert_eq!(resolve("validate_name"), ItemStrategy::Preserve);
+        assert_eq!(resolve("UserDto"), ItemStrategy::AlwaysReplace);
+        assert_eq!(resolve("Dto"), ItemStrategy::AlwaysReplace);
+        assert_eq!(
+            resolve("validate_names"),
+            ItemStrategy::Strategy(MergeStrategy::FailOnConflict)
+        );
+
+        let manual: Item = syn::parse_str("fn handler() { manual(); }").unwrap();
+        let template: Item = syn::parse_str("fn handler() {}").unwrap();
+        let mut recorded = Resolutions::default();
+        recorded.record("handler", Some(&manual), Some(&template), Side::Template);
+        let rules = rules.with_resolutions(recorded);
+        assert_eq!(
+            rules.resolve_conflict(
+                "handler",
+                Some(&manual),
+                Some(&template),
+                MergeStrategy::PreferManual
+            ),
+            ItemStrategy::AlwaysReplace
+        );
+
+        assert!(glob_match("a*b*c", "axxbyybc"));
+        assert!(!glob_match("a*b?c", "abc"));
+    }
+
+    #[test]
+    fn test_merge_methods() {
+        let manual: ItemImpl =
+            syn::parse_str("impl User { fn id(&self) -> u64 { 7 } fn helper(&self) {} }").unwrap();
+        let template: ItemImpl =
+            syn::parse_str("impl User { fn id(&self) -> u64 { 0 } fn name(&self) {} }").unwrap();
+
+        let expected: Item = syn::parse_str(
+            "impl User { fn id(&self) -> u64 { 7 } fn name(&self) {} fn helper(&self) {} }",
+        )
+        .unwrap();
+        assert_eq!(merge_methods(&manual, &template), expected);
+    }
+
+    #[test]
+    fn test_merge_fields() {
+        let base: ItemStruct = syn::parse_str(
+            "pub struct User { #[serde(default)] pub id: u64, pub name: String, pub age: u8 }",
+        )
+        .unwrap();
+        let manual: ItemStruct = syn::parse_str(
+            "pub struct User { #[serde(rename = \"userId\")] pub id: u64, \
+             #[validate(length(min = 1))] pub name: String, pub age: u8, pub note: String }",
+        )
+        .unwrap();
+        let template: ItemStruct = syn::parse_str(
+            "pub struct User { #[serde(skip)] pub id: u64, #[doc = \"Name\"] pub name: String, pub age: u16 }",
+        )
+        .unwrap();
+
+        let (merged, conflicts) = merge_fields(&manual, &template, Some(&base));
+        let expected: Item = syn::parse_str(
+            "pub struct User { #[serde(rename = \"userId\")] pub id: u64, \
+             #[validate(length(min = 1))] #[doc = \"Name\"] pub name: String, pub age: u16, pub note: String }",
+        )
+        .unwrap();
+        assert_eq!(merged, expected);
+        assert_eq!(
+            conflicts,
+            vec!["Field 'id' of 'User' has manual and template changes to `serde`, manual version kept"]
+        );
+    }
+
+    #[test]
+    fn test_merge_signatures() {
+        let manual: Item = syn::parse_str(
+            "fn load(id: u64, cache: &Cache) -> User { cache.get(id).unwrap_or_default() }",
+        )
+        .unwrap();
+        let template: Item =
+            syn::parse_str("pub fn load(id: u64, db: &Db) -> Option<User> { todo!() }").unwrap();
+
+        let (merged, conflicts) = merge_signatures(&manual, &template).unwrap();
+        let expected: Item = syn::parse_str(
+            "pub fn load(id: u64, db: &Db) -> Option<User> { cache.get(id).unwrap_or_default() }",
+        )
+        .unwrap();
+        assert_eq!(merged, expected);
+        assert_eq!(
+            conflicts,
+            vec![
+                "Function 'load' has new parameter `db: &Db` from the template, manual body may need updating",
+                "Function 'load' no longer has parameter `cache`, which the manual body still uses",
+                "Function 'load' now returns `Option<User>` instead of `User`, manual body may need updating",
+            ]
+        );
+
+        let manual: Item =
+            syn::parse_str("impl User { fn id(&self) -> u64 { self.id } fn extra(&self) {} }")
+                .unwrap();
+        let template: Item =
+            syn::parse_str("impl User { pub fn id(&self) -> u64 { 0 } fn name(&self) {} }")
+                .unwrap();
+        let (merged, conflicts) = merge_signatures(&manual, &template).unwrap();
+        let expected: Item = syn::parse_str(
+            "impl User { pub fn id(&self) -> u64 { self.id } fn name(&self) {} fn extra(&self) {} }",
+        )
+        .unwrap();
+        assert_eq!(merged, expected);
+        assert!(conflicts.is_empty());
+    }
+}
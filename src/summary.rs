@@ -0,0 +1,479 @@
+//! Human-readable patch summaries
+//!
+//! This module turns patch operations into one-line descriptions by inspecting
+//! what actually changed between the old and new AST of each item. Struct
+//! fields whose type changed are also listed on their own, with the items
+//! that use them, since such changes often break code written by hand.
+
+use crate::ast_parser::item_kind;
+use crate::diff::{extract_item_name, Patch, PatchOp};
+use crate::imports::import_paths;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use std::collections::HashSet;
+use std::fmt;
+use syn::{Attribute, Fields, File, FnArg, Item, ReturnType, Visibility};
+
+impl Patch<'_> {
+    /// Describe every change in the patch, one line per added import and operation
+    ///
+    /// Unchanged items are omitted.
+    pub fn summary(&self) -> Vec<String> {
+        let imports = self.imports.iter().flat_map(|item| match item {
+            Item::Use(item) => import_paths(item),
+            _ => Vec::new(),
+        });
+
+        imports
+            .map(|path| format!("Import `{}`", path))
+            .chain(self.operations.iter().filter_map(describe_op))
+            .collect()
+    }
+}
+
+/// A struct field whose type changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldTypeChange {
+    /// Name of the struct
+    pub item: String,
+    /// Name of the field, or its index in tuple structs
+    pub field: String,
+    pub old_type: String,
+    pub new_type: String,
+    /// Other items naming both the struct and the field
+    pub used_by: Vec<String>,
+}
+
+impl fmt::Display for FieldTypeChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}.{}`: {} -> {}",
+            self.item, self.field, self.old_type, self.new_type
+        )?;
+        if !self.used_by.is_empty() {
+            let used_by: Vec<String> = self.used_by.iter().map(|n| format!("`{}`", n)).collect();
+            write!(f, ", used by {}", used_by.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Struct fields whose type the patch changes
+///
+/// `items` are searched for the items using each field: those whose tokens
+/// mention both the struct and the field, such as `impl User` reading
+/// `self.id`.
+pub fn field_type_changes(patch: &Patch, items: &[Item]) -> Vec<FieldTypeChange> {
+    let mut changes = Vec::new();
+    for op in &patch.operations {
+        let PatchOp::Modify {
+            name,
+            old_item: Item::Struct(old_struct),
+            new_item: Item::Struct(new_struct),
+        } = op
+        else {
+            continue;
+        };
+
+        let old_fields = fields(&old_struct.fields);
+        for (field, new_type) in fields(&new_struct.fields) {
+            let Some((_, old_type)) = old_fields.iter().find(|(n, _)| *n == field) else {
+                continue;
+            };
+            if *old_type == new_type {
+                continue;
+            }
+
+            let struct_name = new_struct.ident.to_string();
+            let used_by = items
+                .iter()
+                .filter(|item| !matches!(item, Item::Struct(s) if s.ident == struct_name))
+                .filter(|item| {
+                    let idents = idents(item.to_token_stream());
+                    idents.contains(&struct_name) && idents.contains(&field)
+                })
+                .filter_map(extract_item_name)
+                .collect();
+
+            changes.push(FieldTypeChange {
+                item: name.clone(),
+                field,
+                old_type: old_type.clone(),
+                new_type,
+                used_by,
+            });
+        }
+    }
+    changes
+}
+
+/// Every identifier in a token stream
+fn idents(stream: TokenStream) -> HashSet<String> {
+    let mut idents = HashSet::new();
+    for token in stream {
+        match token {
+            TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            TokenTree::Group(group) => idents.extend(self::idents(group.stream())),
+            _ => {}
+        }
+    }
+    idents
+}
+
+/// Describe changes to the shebang, inner attributes and `//!` docs of a file
+///
+/// Returns `None` when the file headers are the same.
+pub fn describe_file_header(old: &File, new: &File) -> Option<String> {
+    let mut changes = Vec::new();
+    if old.shebang != new.shebang {
+        changes.push("shebang changed".to_string());
+    }
+    describe_attrs(&old.attrs, &new.attrs, &mut changes);
+
+    (!changes.is_empty()).then(|| format!("Modify file header: {}", changes.join("; ")))
+}
+
+/// Describe a single patch operation, or `None` for `Keep`
+pub(crate) fn describe_op(op: &PatchOp) -> Option<String> {
+    match op {
+        PatchOp::Insert { name, item } => Some(format!("Insert {} `{}`", item_kind(item), name)),
+        PatchOp::Delete { name } => Some(format!("Delete `{}`", name)),
+        PatchOp::Modify {
+            name,
+            old_item,
+            new_item,
+        } => {
+            let changes = describe_changes(old_item, new_item);
+            Some(format!(
+                "Modify {} `{}`: {}",
+                item_kind(new_item),
+                name,
+                changes.join("; ")
+            ))
+        }
+        PatchOp::Rename {
+            from,
+            name,
+            old_item,
+            new_item,
+        } => {
+            let kind = item_kind(new_item);
+            let changes = describe_rename(from, name, old_item, new_item);
+            Some(if changes.is_empty() {
+                format!("Rename {} `{}` to `{}`", kind, from, name)
+            } else {
+                format!(
+                    "Rename {} `{}` to `{}`: {}",
+                    kind,
+                    from,
+                    name,
+                    changes.join("; ")
+                )
+            })
+        }
+        PatchOp::Move { name, from, to } => {
+            Some(format!("Move `{}` from position {} to {}", name, from, to))
+        }
+        PatchOp::Keep { .. } => None,
+    }
+}
+
+/// List the differences between an item and the one renamed from it, besides the name
+pub(crate) fn describe_rename(from: &str, name: &str, old: &Item, new: &Item) -> Vec<String> {
+    let old_tokens = old.to_token_stream().to_string();
+    let new_tokens = new.to_token_stream().to_string();
+    let renamed_only = old_tokens
+        .split_whitespace()
+        .map(|token| if token == from { name } else { token })
+        .eq(new_tokens.split_whitespace());
+
+    if renamed_only {
+        Vec::new()
+    } else {
+        describe_changes(old, new)
+    }
+}
+
+/// List the individual differences between two versions of an item
+pub(crate) fn describe_changes(old: &Item, new: &Item) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    match (old, new) {
+        (Item::Fn(old_fn), Item::Fn(new_fn)) => {
+            describe_visibility(&old_fn.vis, &new_fn.vis, &mut changes);
+            describe_attrs(&old_fn.attrs, &new_fn.attrs, &mut changes);
+
+            let old_sig = &old_fn.sig;
+            let new_sig = &new_fn.sig;
+
+            if tokens(&old_sig.generics) != tokens(&new_sig.generics)
+                || tokens(&old_sig.generics.where_clause) != tokens(&new_sig.generics.where_clause)
+            {
+                changes.push("generics changed".to_string());
+            }
+
+            if old_sig.asyncness.is_some() != new_sig.asyncness.is_some()
+                || old_sig.constness.is_some() != new_sig.constness.is_some()
+                || old_sig.unsafety.is_some() != new_sig.unsafety.is_some()
+            {
+                changes.push("qualifiers changed".to_string());
+            }
+
+            let old_params: Vec<(String, String)> = old_sig.inputs.iter().map(param).collect();
+            let new_params: Vec<(String, String)> = new_sig.inputs.iter().map(param).collect();
+            describe_members("parameter", &old_params, &new_params, &mut changes);
+
+            let old_ret = return_type(&old_sig.output);
+            let new_ret = return_type(&new_sig.output);
+            if old_ret != new_ret {
+                changes.push(format!(
+                    "return type changed from {} to {}",
+                    old_ret, new_ret
+                ));
+            }
+
+            if tokens(&old_fn.block) != tokens(&new_fn.block) {
+                changes.push("body changed".to_string());
+            }
+        }
+
+        (Item::Struct(old_struct), Item::Struct(new_struct)) => {
+            describe_visibility(&old_struct.vis, &new_struct.vis, &mut changes);
+            describe_attrs(&old_struct.attrs, &new_struct.attrs, &mut changes);
+
+            if tokens(&old_struct.generics) != tokens(&new_struct.generics) {
+                changes.push("generics changed".to_string());
+            }
+
+            let old_fields = fields(&old_struct.fields);
+            let new_fields = fields(&new_struct.fields);
+            describe_members("field", &old_fields, &new_fields, &mut changes);
+        }
+
+        (Item::Enum(old_enum), Item::Enum(new_enum)) => {
+            describe_visibility(&old_enum.vis, &new_enum.vis, &mut changes);
+            describe_attrs(&old_enum.attrs, &new_enum.attrs, &mut changes);
+
+            let old_variants: Vec<(String, String)> = old_enum
+                .variants
+                .iter()
+                .map(|v| (v.ident.to_string(), tokens(&v.fields)))
+                .collect();
+            let new_variants: Vec<(String, String)> = new_enum
+                .variants
+                .iter()
+                .map(|v| (v.ident.to_string(), tokens(&v.fields)))
+                .collect();
+
+            for (name, _) in &new_variants {
+                if !old_variants.iter().any(|(n, _)| n == name) {
+                    changes.push(format!("variant `{}` added", name));
+                }
+            }
+            for (name, old_fields) in &old_variants {
+                match new_variants.iter().find(|(n, _)| n == name) {
+                    None => changes.push(format!("variant `{}` removed", name)),
+                    Some((_, new_fields)) if new_fields != old_fields => {
+                        changes.push(format!("variant `{}` changed", name))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        _ => {}
+    }
+
+    if changes.is_empty() {
+        changes.push("definition changed".to_string());
+    }
+
+    changes
+}
+
+/// Report added, removed and retyped named members (fields or parameters)
+fn describe_members(
+    label: &str,
+    old: &[(String, String)],
+    new: &[(String, String)],
+    changes: &mut Vec<String>,
+) {
+    for (name, ty) in new {
+        match old.iter().find(|(n, _)| n == name) {
+            None => changes.push(format!("{} `{}: {}` added", label, name, ty)),
+            Some((_, old_ty)) if old_ty != ty => changes.push(format!(
+                "{} `{}` type changed from {} to {}",
+                label, name, old_ty, ty
+            )),
+            _ => {}
+        }
+    }
+
+    for (name, _) in old {
+        if !new.iter().any(|(n, _)| n == name) {
+            changes.push(format!("{} `{}` removed", label, name));
+        }
+    }
+}
+
+fn describe_visibility(old: &Visibility, new: &Visibility, changes: &mut Vec<String>) {
+    let old_vis = visibility(old);
+    let new_vis = visibility(new);
+    if old_vis != new_vis {
+        changes.push(format!(
+            "visibility changed from {} to {}",
+            old_vis, new_vis
+        ));
+    }
+}
+
+fn describe_attrs(old: &[Attribute], new: &[Attribute], changes: &mut Vec<String>) {
+    let (old_docs, old_attrs): (Vec<&Attribute>, Vec<&Attribute>) =
+        old.iter().partition(|attr| attr.path().is_ident("doc"));
+    let (new_docs, new_attrs): (Vec<&Attribute>, Vec<&Attribute>) =
+        new.iter().partition(|attr| attr.path().is_ident("doc"));
+
+    if tokens_all(&old_docs) != tokens_all(&new_docs) {
+        changes.push("doc comment changed".to_string());
+    }
+    if tokens_all(&old_attrs) != tokens_all(&new_attrs) {
+        changes.push("attributes changed".to_string());
+    }
+}
+
+fn visibility(vis: &Visibility) -> String {
+    match vis {
+        Visibility::Inherited => "private".to_string(),
+        other => tokens(other),
+    }
+}
+
+pub(crate) fn param(arg: &FnArg) -> (String, String) {
+    match arg {
+        FnArg::Receiver(receiver) => ("self".to_string(), tokens(receiver)),
+        FnArg::Typed(pat_type) => (tokens(&pat_type.pat), tokens(&pat_type.ty)),
+    }
+}
+
+pub(crate) fn return_type(output: &ReturnType) -> String {
+    match output {
+        ReturnType::Default => "()".to_string(),
+        ReturnType::Type(_, ty) => tokens(ty),
+    }
+}
+
+fn fields(fields: &Fields) -> Vec<(String, String)> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| {
+            let name = field
+                .ident
+                .as_ref()
+                .map(|ident| ident.to_string())
+                .unwrap_or_else(|| idx.to_string());
+            (name, tokens(&field.ty))
+        })
+        .collect()
+}
+
+fn tokens_all(attrs: &[&Attribute]) -> String {
+    attrs.iter().map(|attr| tokens(*attr)).collect()
+}
+
+/// Render tokens compactly, e.g. `Result<(), AgeError>` rather than `Result < () , AgeError >`
+pub(crate) fn tokens<T: ToTokens>(node: &T) -> String {
+    let raw = node.to_token_stream().to_string();
+    let mut result = String::with_capacity(raw.len());
+
+    for token in raw.split_whitespace() {
+        let glue = result.ends_with(['<', '(', '[', '&', ':', '\'', '.', '!', '#'])
+            || token.starts_with(['<', '>', '(', ')', '[', ']', ',', ':', '.', ';', '?'])
+            || (token.starts_with('!') && !result.ends_with(','));
+        if !result.is_empty() && !glue {
+            result.push(' ');
+        }
+        result.push_str(token);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe_file_header, field_type_changes};
+    use crate::diff::compute_patch;
+    use syn::{parse_quote, Item};
+
+    #[test]
+    fn test_summary_return_type_change() {
+        let old_items: Vec<Item> = vec![parse_quote! {
+            fn validate_age(age: u32) -> bool { age < 150 }
+        }];
+        let new_items: Vec<Item> = vec![parse_quote! {
+            fn validate_age(age: u32) -> Result<(), AgeError> { age < 150 }
+        }];
+
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+        assert_eq!(
+            patch.summary(),
+            vec!["Modify fn `validate_age`: return type changed from bool to Result<(), AgeError>"]
+        );
+    }
+
+    #[test]
+    fn test_summary_struct_fields() {
+        let old_items: Vec<Item> = vec![parse_quote! {
+            pub struct Person { pub name: String, pub age: u32 }
+        }];
+        let new_items: Vec<Item> = vec![
+            parse_quote! {
+                pub struct Person { pub name: String, pub age: u64, pub email: String }
+            },
+            parse_quote! { fn helper() {} },
+        ];
+
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+        assert_eq!(
+            patch.summary(),
+            vec![
+                "Modify struct `Person`: field `age` type changed from u32 to u64; field `email: String` added",
+                "Insert fn `helper`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_field_type_changes() {
+        let old_items: Vec<Item> = vec![
+            parse_quote! { pub struct Person { pub name: String, pub age: u32 } },
+            parse_quote! { impl Person { fn adult(&self) -> bool { self.age >= 18 } } },
+            parse_quote! { fn greet(person: &Person) -> String { person.name.clone() } },
+        ];
+        let new_items: Vec<Item> =
+            vec![parse_quote! { pub struct Person { pub name: String, pub age: u64 } }];
+
+        let patch = compute_patch(&old_items, &new_items).unwrap();
+        let changes = field_type_changes(&patch, &old_items);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            changes[0].to_string(),
+            "`Person.age`: u32 -> u64, used by `impl Person`"
+        );
+    }
+
+    #[test]
+    fn test_summary_file_header() {
+        let old = syn::parse_file("//! Models\nstruct A;").unwrap();
+        let new = syn::parse_file("//! Models\n#![allow(dead_code)]\nstruct A;").unwrap();
+
+        assert_eq!(
+            describe_file_header(&old, &new).as_deref(),
+            Some("Modify file header: attributes changed")
+        );
+        assert_eq!(describe_file_header(&old, &old), None);
+    }
+}
@@ -0,0 +1,342 @@
+//! Target references module
+//!
+//! This module lets a template read what other manifest targets generated,
+//! through the `targets` context variable, to generate a registry or module
+//! index from them. Targets get a `name`, shared by the targets of a group,
+//! and `targets.<name>` holds:
+//!
+//! - `outputs`, the `path` and `module` of each output of the group,
+//! - `items`, the `name`, `kind` and `module` of each named item they define.
+//!
+//! ```tera
+//! {% for output in targets.models.outputs %}pub mod {{ output.module }};
+//! {% endfor %}
+//! ```
+//!
+//! Outputs are read from disk, so `generate-all` runs the targets a template
+//! references before it, and a target is stale whenever they change. Targets
+//! listed by name in a target's `depends_on` are run before it and available in
+//! `targets` the same way.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::app::load_context_data;
+use crate::ast_parser::{item_ident, item_kind, ParsedFile};
+use crate::config::{Config, Target};
+use crate::generator::Language;
+
+/// Context variable holding the outputs of other targets
+pub const NAMESPACE: &str = "targets";
+
+/// Names read as `targets.<name>` in a template's source
+pub fn references(source: &str) -> BTreeSet<String> {
+    let prefix = format!("{}.", NAMESPACE);
+    source
+        .match_indices(&prefix)
+        .filter(|(at, _)| {
+            !source[..*at]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        })
+        .map(|(at, _)| {
+            source[at + prefix.len()..]
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect::<String>()
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Other targets `target` depends on: those its template reads and those in its `depends_on`
+pub fn dependencies<'a>(config: &'a Config, target: &Target) -> Result<Vec<&'a Target>> {
+    let template = config.resolve_path(&target.template);
+    let source = fs::read_to_string(&template)
+        .with_context(|| format!("Failed to read template {}", template.display()))?;
+    let mut names = references(&source);
+    for name in &target.depends_on {
+        if !config
+            .targets
+            .iter()
+            .any(|other| other.name.as_ref() == Some(name))
+        {
+            anyhow::bail!(
+                "Target {} depends on '{}', but no target has that name",
+                target.output.display(),
+                name
+            );
+        }
+        names.insert(name.clone());
+    }
+
+    Ok(config
+        .targets
+        .iter()
+        .filter(|other| other.output != target.output)
+        .filter(|other| other.name.as_ref().is_some_and(|name| names.contains(name)))
+        .collect())
+}
+
+/// Context of a target: its context file, with `targets` for the targets it references
+///
+/// A `targets` key of the context file is left as it is.
+pub fn target_context(config: &Config, target: &Target) -> Result<HashMap<String, Value>> {
+    planned_context(config, target, &HashMap::new())
+}
+
+/// [`target_context`], reading the outputs in `planned`, by target output, instead of the files
+///
+/// Lets `generate-all` merge a target against outputs it hasn't written yet.
+pub fn planned_context(
+    config: &Config,
+    target: &Target,
+    planned: &HashMap<PathBuf, String>,
+) -> Result<HashMap<String, Value>> {
+    let mut context = load_context_data(&config.resolve_path(&target.context))?;
+    let dependencies = dependencies(config, target)?;
+    if !dependencies.is_empty() && !context.contains_key(NAMESPACE) {
+        context.insert(
+            NAMESPACE.to_string(),
+            targets_value(config, &dependencies, planned)?,
+        );
+    }
+    Ok(context)
+}
+
+/// Value of `targets` for the given targets, grouped by name
+///
+/// Outputs are read from `planned` when there, from disk otherwise; outputs
+/// that weren't generated yet are listed without items.
+pub fn targets_value(
+    config: &Config,
+    targets: &[&Target],
+    planned: &HashMap<PathBuf, String>,
+) -> Result<Value> {
+    let mut groups = serde_json::Map::new();
+    for target in targets {
+        let Some(name) = &target.name else {
+            continue;
+        };
+        let module = target
+            .output
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let group = groups
+            .entry(name.clone())
+            .or_insert_with(|| json!({ "outputs": [], "items": [] }));
+        group["outputs"]
+            .as_array_mut()
+            .expect("outputs is a list")
+            .push(json!({ "path": target.output, "module": module }));
+
+        let output = config.resolve_path(&target.output);
+        let Some(source) = planned
+            .get(&target.output)
+            .cloned()
+            .or_else(|| fs::read_to_string(&output).ok())
+        else {
+            continue;
+        };
+        if Language::of_path(&output) != Some(Language::Rust) {
+            continue;
+        }
+        let parsed = ParsedFile::parse(&source)
+            .with_context(|| format!("Failed to parse {}", output.display()))?;
+        let items = group["items"].as_array_mut().expect("items is a list");
+        for item in parsed.items() {
+            if let Some(ident) = item_ident(item) {
+                items.push(json!({
+                    "name": ident.to_string(),
+                    "kind": item_kind(item),
+                    "module": module,
+                }));
+            }
+        }
+    }
+    Ok(Value::Object(groups))
+}
+
+/// Targets in the order to generate them: manifest order, each after its dependencies
+pub fn generation_order(config: &Config) -> Result<Vec<&Target>> {
+    let position = |dependency: &Target| {
+        config
+            .targets
+            .iter()
+            .position(|target| target.output == dependency.output)
+    };
+    let dependencies = config
+        .targets
+        .iter()
+        .map(|target| {
+            let dependencies = dependencies(config, target)?;
+            Ok(dependencies.into_iter().filter_map(position).collect())
+        })
+        .collect::<Result<Vec<Vec<usize>>>>()?;
+
+    let mut done = vec![false; config.targets.len()];
+    let mut order = Vec::new();
+    while order.len() < config.targets.len() {
+        let ready = (0..config.targets.len()).find(|&index| {
+            !done[index]
+                && dependencies[index]
+                    .iter()
+                    .all(|&dependency| done[dependency])
+        });
+        let Some(index) = ready else {
+            // Every target left waits on another one left, so following them loops
+            let mut cycle = vec![done
+                .iter()
+                .position(|done| !done)
+                .expect("targets are left")];
+            loop {
+                let last = cycle[cycle.len() - 1];
+                let next = dependencies[last]
+                    .iter()
+                    .copied()
+                    .find(|&dependency| !done[dependency])
+                    .expect("a target left waits on another");
+                if let Some(start) = cycle.iter().position(|&index| index == next) {
+                    cycle.drain(..start);
+                    cycle.push(next);
+                    break;
+                }
+                cycle.push(next);
+            }
+            let cycle: Vec<String> = cycle
+                .into_iter()
+                .map(|index| config.targets[index].output.display().to_string())
+                .collect();
+            anyhow::bail!(
+                "Targets depend on each other in a cycle: {}",
+                cycle.join(" -> ")
+            );
+        };
+        done[index] = true;
+        order.push(&config.targets[index]);
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_references() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/models")).unwrap();
+        fs::write(
+            dir.path().join("rpt.toml"),
+            "[[targets]]\ntemplate = \"registry.tera\"\ncontext = \"empty.json\"\noutput = \"src/models/mod.rs\"\n\n\
+             [[targets]]\nname = \"models\"\ntemplate = \"model.tera\"\ncontext = \"user.json\"\noutput = \"src/models/user.rs\"\n\n\
+             [[targets]]\nname = \"models\"\ntemplate = \"model.tera\"\ncontext = \"post.json\"\noutput = \"src/models/post.rs\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("registry.tera"),
+            "{% for item in targets.models.items %}pub use {{ item.module }}::{{ item.name }};{% endfor %}",
+        )
+        .unwrap();
+        fs::write(dir.path().join("model.tera"), "pub struct {{ name }};").unwrap();
+        fs::write(dir.path().join("empty.json"), "{}").unwrap();
+        fs::write(dir.path().join("user.json"), "{\"name\": \"User\"}").unwrap();
+        fs::write(dir.path().join("post.json"), "{\"name\": \"Post\"}").unwrap();
+        fs::write(
+            dir.path().join("src/models/user.rs"),
+            "pub struct User;\nimpl User {}\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            references("{{ targets.models.items }} self.targets.len() {{ targets }}"),
+            BTreeSet::from(["models".to_string()])
+        );
+
+        let config = Config::load(&dir.path().join("rpt.toml")).unwrap();
+        let order: Vec<_> = generation_order(&config)
+            .unwrap()
+            .iter()
+            .map(|target| target.output.display().to_string())
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "src/models/user.rs",
+                "src/models/post.rs",
+                "src/models/mod.rs"
+            ]
+        );
+
+        let context = target_context(&config, &config.targets[0]).unwrap();
+        assert_eq!(
+            context[NAMESPACE],
+            json!({ "models": {
+                "outputs": [
+                    { "path": "src/models/user.rs", "module": "user" },
+                    { "path": "src/models/post.rs", "module": "post" },
+                ],
+                "items": [{ "name": "User", "kind": "struct", "module": "user" }],
+            }})
+        );
+        assert!(!target_context(&config, &config.targets[1])
+            .unwrap()
+            .contains_key(NAMESPACE));
+
+        let planned = HashMap::from([(
+            PathBuf::from("src/models/post.rs"),
+            "pub struct Post;\n".to_string(),
+        )]);
+        let context = planned_context(&config, &config.targets[0], &planned).unwrap();
+        assert_eq!(
+            context[NAMESPACE]["models"]["items"][1],
+            json!({ "name": "Post", "kind": "struct", "module": "post" })
+        );
+    }
+
+    #[test]
+    fn test_generation_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = |dependencies: [&str; 3]| {
+            let mut manifest = String::new();
+            for (name, depends_on) in ["a", "b", "c"].iter().zip(dependencies) {
+                manifest.push_str(&format!(
+                    "[[targets]]\nname = \"{0}\"\ndepends_on = [{1}]\ntemplate = \"t.tera\"\ncontext = \"c.json\"\noutput = \"{0}.rs\"\n",
+                    name, depends_on
+                ));
+            }
+            fs::write(dir.path().join("rpt.toml"), manifest).unwrap();
+            Config::load(&dir.path().join("rpt.toml")).unwrap()
+        };
+        fs::write(dir.path().join("t.tera"), "").unwrap();
+        let outputs = |order: Vec<&Target>| -> Vec<String> {
+            order
+                .iter()
+                .map(|target| target.output.display().to_string())
+                .collect()
+        };
+
+        let config = manifest(["\"c\"", "", "\"b\""]);
+        assert_eq!(
+            outputs(generation_order(&config).unwrap()),
+            ["b.rs", "c.rs", "a.rs"]
+        );
+
+        let config = manifest(["", "\"c\"", "\"b\""]);
+        assert_eq!(
+            generation_order(&config).unwrap_err().to_string(),
+            "Targets depend on each other in a cycle: b.rs -> c.rs -> b.rs"
+        );
+
+        let config = manifest(["\"d\"", "", ""]);
+        assert_eq!(
+            generation_order(&config).unwrap_err().to_string(),
+            "Target a.rs depends on 'd', but no target has that name"
+        );
+    }
+}
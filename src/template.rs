@@ -4,54 +4,460 @@
 
 use anyhow::{Context, Result};
 use serde_json::Value;
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 use tera::{Context as TeraContext, Tera};
+use walkdir::WalkDir;
+
+use crate::analysis::TemplateUsage;
+use crate::deterministic;
+use crate::file_blocks::expand_file_tags;
+use crate::front_matter::{split_front_matter, FrontMatter};
+
+/// Template directories set by [`install_template_dirs`]
+static TEMPLATE_DIRS: RwLock<Vec<PathBuf>> = RwLock::new(Vec::new());
+
+/// Overrides directory set by [`install_overrides_dir`]
+static OVERRIDES_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
 
 /// Template engine for generating code
 pub struct TemplateEngine {
     tera: Tera,
+    /// Front matter of the templates that declare one, by template name
+    front_matter: HashMap<String, FrontMatter>,
 }
 
 impl TemplateEngine {
     /// Create a new template engine from a templates directory
+    ///
+    /// Every `.tera` file under the directory is loaded, named by its path
+    /// relative to the directory.
     pub fn new(template_dir: &Path) -> Result<Self> {
-        let pattern = template_dir
-            .join("**/*.tera")
-            .to_str()
-            .context("Invalid template directory path")?
-            .to_string();
+        let mut templates = Vec::new();
+        let mut front_matter = HashMap::new();
+        read_template_dir(template_dir, &mut templates, &mut front_matter)?;
 
-        let tera = Tera::new(&pattern).context("Failed to initialize Tera template engine")?;
+        let mut tera = new_tera();
+        tera.add_raw_templates(templates)
+            .context("Failed to initialize Tera template engine")?;
 
-        Ok(TemplateEngine { tera })
+        Ok(TemplateEngine { tera, front_matter })
+    }
+
+    /// Create a template engine for a template file, named by its file name
+    ///
+    /// When the template includes, imports or extends other templates, the
+    /// `.tera` files under the installed overrides directory (see
+    /// [`install_overrides_dir`]), its directory and the installed template
+    /// directories (see [`install_template_dirs`]) are added too, named by
+    /// their relative path, so `{% include "partials/fields.tera" %}` resolves
+    /// next to the template. `template` is the source of the file, which may
+    /// come from stdin.
+    pub fn from_file(path: &Path, template: &str) -> Result<Self> {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("Invalid template file name")?;
+        if !refers_to_templates(template) {
+            return Self::from_string(name, template);
+        }
+
+        let mut templates = Vec::new();
+        let mut front_matter = HashMap::new();
+        for (partial, file) in included_files(path, template)? {
+            read_template_file(partial, &file, &mut templates, &mut front_matter)?;
+        }
+
+        let (matter, body) = split_front_matter(template)?;
+        if matter != FrontMatter::default() {
+            front_matter.insert(name.to_string(), matter);
+        }
+        templates.push((name.to_string(), expand_file_tags(&body)?));
+
+        let mut tera = new_tera();
+        tera.add_raw_templates(templates)
+            .with_context(|| format!("Failed to load template {}", path.display()))?;
+
+        Ok(TemplateEngine { tera, front_matter })
     }
 
     /// Create a template engine from a single template string
+    ///
+    /// Front matter is stripped, see [`crate::front_matter`], and `{% file %}`
+    /// blocks are expanded, see [`crate::file_blocks`].
     pub fn from_string(name: &str, template: &str) -> Result<Self> {
-        let mut tera = Tera::default();
-        tera.add_raw_template(name, template)
+        let mut engine = TemplateEngine {
+            tera: new_tera(),
+            front_matter: HashMap::new(),
+        };
+        engine.add_raw_template(name, template)?;
+
+        Ok(engine)
+    }
+
+    /// Add a template from a string, preprocessed as in [`TemplateEngine::from_string`]
+    pub fn add_raw_template(&mut self, name: &str, template: &str) -> Result<()> {
+        let (matter, body) = split_front_matter(template)?;
+        let template = expand_file_tags(&body)?;
+        self.tera
+            .add_raw_template(name, &template)
             .context("Failed to add template")?;
 
-        Ok(TemplateEngine { tera })
+        if matter != FrontMatter::default() {
+            self.front_matter.insert(name.to_string(), matter);
+        }
+
+        Ok(())
+    }
+
+    /// Get the front matter a template declares, if any
+    pub fn front_matter(&self, template_name: &str) -> Option<&FrontMatter> {
+        self.front_matter.get(template_name)
     }
 
     /// Render a template with the given context data
+    ///
+    /// Context variables the template needs must be present and not null, see
+    /// [`TemplateEngine::render_with`]. Front-matter defaults fill missing keys.
     pub fn render(&self, template_name: &str, context: &HashMap<String, Value>) -> Result<String> {
+        self.render_with(template_name, context, false)
+    }
+
+    /// Render a template, skipping the missing-variable check when `lenient`
+    ///
+    /// Lenient rendering is plain Tera, where undefined values in conditions
+    /// are false and null values print as empty strings.
+    pub fn render_with(
+        &self,
+        template_name: &str,
+        context: &HashMap<String, Value>,
+        lenient: bool,
+    ) -> Result<String> {
+        let with_defaults;
+        let context = match self.front_matter(template_name) {
+            Some(matter) if !matter.defaults.is_empty() => {
+                with_defaults = matter.apply_defaults(context);
+                &with_defaults
+            }
+            _ => context,
+        };
+
+        if !lenient {
+            self.check_context(template_name, context)?;
+        }
+
         let mut tera_context = TeraContext::new();
 
         for (key, value) in context {
             tera_context.insert(key, value);
         }
 
-        self.tera
+        let code = self
+            .tera
             .render(template_name, &tera_context)
-            .context("Failed to render template")
+            .context("Failed to render template")?;
+        if deterministic::enabled() {
+            let again = self
+                .tera
+                .render(template_name, &tera_context)
+                .context("Failed to render template")?;
+            deterministic::verify(template_name, &code, &again)?;
+        }
+
+        match self.front_matter(template_name) {
+            Some(matter) => matter.add_imports(code, context),
+            None => Ok(code),
+        }
+    }
+
+    /// Fail on the first required variable that is missing from the context or null
+    fn check_context(&self, template_name: &str, context: &HashMap<String, Value>) -> Result<()> {
+        for variable in self.required_variables(template_name)? {
+            match context.get(&variable) {
+                None => anyhow::bail!(
+                    "Variable `{}` not found in context of '{}' (use --lenient to render it as empty)",
+                    variable,
+                    template_name
+                ),
+                Some(Value::Null) => anyhow::bail!(
+                    "Variable `{}` is null in context of '{}' (use --lenient to render it as empty)",
+                    variable,
+                    template_name
+                ),
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
     }
 
     /// Get list of available templates
     pub fn get_template_names(&self) -> Vec<&str> {
-        self.tera.get_template_names().collect()
+        self.tera
+            .get_template_names()
+            .filter(|name| *name != crate::macros::MACROS_TEMPLATE)
+            .collect()
+    }
+
+    /// Get the parent templates of a template, closest first
+    pub fn get_parents(&self, template_name: &str) -> Result<Vec<String>> {
+        let template = self
+            .tera
+            .get_template(template_name)
+            .context("Template not found")?;
+
+        Ok(template.parents.clone())
+    }
+
+    /// Get the context variables a template needs, see [`crate::analysis`]
+    pub fn required_variables(&self, template_name: &str) -> Result<BTreeSet<String>> {
+        Ok(self.analyze(template_name)?.variables)
+    }
+
+    /// Get everything a template uses from its context, see [`crate::analysis`]
+    ///
+    /// Variables with a front-matter default are reported as optional.
+    pub fn analyze(&self, template_name: &str) -> Result<TemplateUsage> {
+        let mut usage = crate::analysis::analyze(&self.tera, template_name)?;
+
+        if let Some(matter) = self.front_matter(template_name) {
+            for variable in matter.defaults.keys() {
+                if usage.variables.remove(variable) {
+                    usage.optional.insert(variable.clone());
+                }
+            }
+        }
+
+        Ok(usage)
+    }
+}
+
+/// Compiled templates shared across a batch run
+///
+/// Engines are keyed by a hash of the template name and source, so each
+/// distinct template is parsed by Tera only once.
+#[derive(Default)]
+pub struct TemplateCache {
+    engines: HashMap<u64, Arc<TemplateEngine>>,
+}
+
+impl TemplateCache {
+    /// Create an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the compiled engine for a template file, see [`TemplateEngine::from_file`]
+    pub fn get_or_compile_file(
+        &mut self,
+        path: &Path,
+        template: &str,
+    ) -> Result<Arc<TemplateEngine>> {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        template.hash(&mut hasher);
+        let key = hasher.finish();
+
+        if let Some(engine) = self.engines.get(&key) {
+            return Ok(Arc::clone(engine));
+        }
+
+        let engine = Arc::new(TemplateEngine::from_file(path, template)?);
+        self.engines.insert(key, Arc::clone(&engine));
+        Ok(engine)
+    }
+
+    /// Get the compiled engine for a template, compiling it on first use
+    pub fn get_or_compile(&mut self, name: &str, template: &str) -> Result<Arc<TemplateEngine>> {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        template.hash(&mut hasher);
+        let key = hasher.finish();
+
+        if let Some(engine) = self.engines.get(&key) {
+            return Ok(Arc::clone(engine));
+        }
+
+        let engine = Arc::new(TemplateEngine::from_string(name, template)?);
+        self.engines.insert(key, Arc::clone(&engine));
+        Ok(engine)
+    }
+}
+
+/// Name of a template inside a template directory: its relative path with `/` separators
+pub fn template_name(template_dir: &Path, path: &Path) -> Result<String> {
+    let relative = path
+        .strip_prefix(template_dir)
+        .context("Template is outside the template directory")?;
+
+    let segments: Vec<_> = relative
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<_>>()
+        .context("Invalid template file name")?;
+
+    Ok(segments.join("/"))
+}
+
+/// Directories whose templates single template files can include, from `template_dirs`
+pub fn install_template_dirs(dirs: Vec<PathBuf>) {
+    *TEMPLATE_DIRS.write().unwrap_or_else(|e| e.into_inner()) = dirs;
+}
+
+/// Directory whose templates win over same-named ones a template file includes
+pub fn install_overrides_dir(dir: PathBuf) {
+    *OVERRIDES_DIR.write().unwrap_or_else(|e| e.into_inner()) = Some(dir);
+}
+
+/// Files a template file loads besides itself, by template name
+///
+/// Templates it includes, imports or extends are looked up in the overrides
+/// directory, then the template's own directory, then the template directories;
+/// the first directory holding a name wins. Empty when the template refers to
+/// no other template.
+pub fn included_files(path: &Path, template: &str) -> Result<Vec<(String, PathBuf)>> {
+    if !refers_to_templates(template) {
+        return Ok(Vec::new());
+    }
+
+    let overrides = OVERRIDES_DIR
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    let dirs = TEMPLATE_DIRS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    let parent = path
+        .parent()
+        .map(|parent| match parent.as_os_str().is_empty() {
+            true => PathBuf::from("."),
+            false => parent.to_path_buf(),
+        });
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("Invalid template file name")?;
+
+    let mut seen = BTreeSet::from([name.to_string()]);
+    let mut files = Vec::new();
+    for dir in overrides.into_iter().chain(parent).chain(dirs) {
+        if dir.is_dir() {
+            for (partial, file) in template_files(&dir)? {
+                if seen.insert(partial.clone()) {
+                    files.push((partial, file));
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Every `.tera` file under `dir`, named by its path relative to it
+fn template_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let walk = WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+        });
+    let mut files = Vec::new();
+    for entry in walk {
+        let entry = entry.context("Failed to read template directory")?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || path.extension().is_none_or(|ext| ext != "tera") {
+            continue;
+        }
+        files.push((template_name(dir, path)?, path.to_path_buf()));
+    }
+
+    Ok(files)
+}
+
+/// Add every `.tera` file under `dir`, named by its path relative to it
+fn read_template_dir(
+    dir: &Path,
+    templates: &mut Vec<(String, String)>,
+    front_matter: &mut HashMap<String, FrontMatter>,
+) -> Result<()> {
+    for (name, path) in template_files(dir)? {
+        read_template_file(name, &path, templates, front_matter)?;
+    }
+
+    Ok(())
+}
+
+/// Add the template in `path`, named `name`, and its front matter
+fn read_template_file(
+    name: String,
+    path: &Path,
+    templates: &mut Vec<(String, String)>,
+    front_matter: &mut HashMap<String, FrontMatter>,
+) -> Result<()> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read template {}", path.display()))?;
+    let (matter, body) = split_front_matter(&content)
+        .with_context(|| format!("Invalid template {}", path.display()))?;
+    if matter != FrontMatter::default() {
+        front_matter.entry(name.clone()).or_insert(matter);
+    }
+    templates.push((name, expand_file_tags(&body)?));
+
+    Ok(())
+}
+
+/// Whether a template includes, imports or extends other templates than the built-in macros
+fn refers_to_templates(template: &str) -> bool {
+    template.split("{%").skip(1).any(|tag| {
+        let tag = tag.trim_start_matches('-').trim_start();
+        ["include", "import", "extends"]
+            .iter()
+            .any(|keyword| tag.starts_with(keyword))
+            && !tag
+                .split("%}")
+                .next()
+                .is_some_and(|tag| tag.contains(crate::macros::MACROS_TEMPLATE))
+    })
+}
+
+/// Render a one-off template string, such as a templated path
+pub fn render_str(template: &str, context: &HashMap<String, Value>) -> Result<String> {
+    let mut tera_context = TeraContext::new();
+
+    for (key, value) in context {
+        tera_context.insert(key, value);
+    }
+
+    let mut tera = new_tera();
+    tera.render_str(template, &tera_context)
+        .with_context(|| format!("Failed to render '{}'", template))
+}
+
+/// Create a Tera instance holding the built-in macros, with the installed plugins registered
+fn new_tera() -> Tera {
+    let mut tera = Tera::default();
+    crate::macros::register(&mut tera);
+    if deterministic::enabled() {
+        deterministic::register(&mut tera);
+    }
+    #[cfg(feature = "plugins")]
+    crate::plugins::register_installed(&mut tera);
+    tera
+}
+
+/// Parse context data from a JSON object
+pub fn parse_context(json: &str) -> Result<HashMap<String, Value>> {
+    let value: Value = serde_json::from_str(json)?;
+
+    if let Value::Object(map) = value {
+        Ok(map.into_iter().collect())
+    } else {
+        anyhow::bail!("Context file must contain a JSON object")
     }
 }
 
@@ -113,4 +519,67 @@ pub struct {{ struct_name }} {
         assert!(result.contains("pub name: String"));
         assert!(result.contains("pub age: u32"));
     }
+
+    #[test]
+    fn test_strict_render_names_missing_variable() {
+        let template = "{% if derive %}#[derive(Debug)]{% endif %}\npub struct {{ name }};";
+        let engine = TemplateEngine::from_string("t", template).unwrap();
+
+        let err = engine.render("t", &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("Variable `name` not found"));
+
+        let context = create_context(vec![("name", json!(null)), ("derive", json!(true))]);
+        let err = engine.render("t", &context).unwrap_err();
+        assert!(err.to_string().contains("Variable `name` is null"));
+
+        // Variables only tested by `if` may be missing
+        let context = create_context(vec![("name", json!("User"))]);
+        assert_eq!(engine.render("t", &context).unwrap(), "\npub struct User;");
+        let rendered = engine.render_with("t", &context, true).unwrap();
+        assert_eq!(rendered, "\npub struct User;");
+    }
+
+    #[test]
+    fn test_template_file_includes_siblings() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("models/partials")).unwrap();
+        fs::create_dir(dir.path().join("shared")).unwrap();
+        fs::write(
+            dir.path().join("models/partials/fields.tera"),
+            "{% for field in fields %}    pub {{ field }}: u32,\n{% endfor %}",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("shared/derive.tera"),
+            "{% macro derive() %}#[derive(Debug)]{% endmacro %}",
+        )
+        .unwrap();
+        install_template_dirs(vec![dir.path().join("shared")]);
+
+        let template = "{% import \"derive.tera\" as m %}{{ m::derive() }}\npub struct {{ name }} {\n{% include \"partials/fields.tera\" %}}\n";
+        let engine =
+            TemplateEngine::from_file(&dir.path().join("models/model.tera"), template).unwrap();
+        install_template_dirs(Vec::new());
+        let context = create_context(vec![("name", "User".into()), ("fields", json!(["id"]))]);
+
+        assert_eq!(
+            engine.render("model.tera", &context).unwrap(),
+            "#[derive(Debug)]\npub struct User {\n    pub id: u32,\n}\n"
+        );
+        assert!(refers_to_templates("{%- extends \"base.tera\" %}"));
+        assert!(!refers_to_templates("{% if include %}{% endif %}"));
+        assert!(!refers_to_templates("{% import \"rpt::macros\" as rpt %}"));
+    }
+
+    #[test]
+    fn test_template_cache_compiles_once() {
+        let mut cache = TemplateCache::new();
+
+        let first = cache.get_or_compile("t", "fn {{ name }}() {}").unwrap();
+        let second = cache.get_or_compile("t", "fn {{ name }}() {}").unwrap();
+        let other = cache.get_or_compile("t", "struct {{ name }};").unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &other));
+    }
 }
@@ -0,0 +1,226 @@
+//! Templatize module
+//!
+//! This module turns an existing Rust file into a Tera template and the context
+//! that renders it back, to bring legacy code under generation. Variables are
+//! chosen with `--vars`:
+//!
+//! - `name`: the first struct or enum of the file, replaced wherever its name
+//!   appears as a whole word,
+//! - `fields`: the named fields of that struct, replaced by a loop over
+//!   `{ name, type, doc, attrs }` entries, with one `doc` entry per `///` line,
+//! - `key=text`: any other identifier or word, replaced by `{{ key }}`.
+//!
+//! Everything else is copied as is, so rendering the template with the
+//! emitted context gives back the original file.
+
+use anyhow::{Context, Result};
+use proc_macro2::LineColumn;
+use quote::ToTokens;
+use serde_json::{json, Map, Value};
+use syn::spanned::Spanned;
+use syn::{Expr, Fields, Item, Lit, Meta};
+
+/// A template and the context that renders the original file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Templatized {
+    pub template: String,
+    pub context: Value,
+}
+
+/// Replace the variables listed in `vars` in `source` with template variables
+pub fn templatize(source: &str, vars: &[String]) -> Result<Templatized> {
+    if source.contains("{{") || source.contains("{%") || source.contains("{#") {
+        anyhow::bail!(
+            "The file contains Tera delimiters ({{{{, {{% or {{#), it can't be templatized"
+        );
+    }
+
+    let file = syn::parse_file(source).context("Failed to parse Rust file")?;
+    let primary = file.items.iter().find_map(|item| match item {
+        Item::Struct(item) => Some((item.ident.to_string(), Some(&item.fields))),
+        Item::Enum(item) => Some((item.ident.to_string(), None)),
+        _ => None,
+    });
+
+    let mut context = Map::new();
+    let mut words = Vec::new();
+    let mut fields_block = None;
+    for var in vars {
+        match var.split_once('=') {
+            Some((key, text)) => {
+                context.insert(key.to_string(), json!(text));
+                words.push((text.to_string(), key.to_string()));
+            }
+            None if var == "name" => {
+                let (name, _) = primary
+                    .as_ref()
+                    .context("No struct or enum to take `name` from")?;
+                context.insert("name".to_string(), json!(name));
+                words.push((name.clone(), "name".to_string()));
+            }
+            None if var == "fields" => {
+                let Some((_, Some(Fields::Named(fields)))) = &primary else {
+                    anyhow::bail!("No struct with named fields to take `fields` from");
+                };
+                let entries: Vec<Value> = fields
+                    .named
+                    .iter()
+                    .map(|field| {
+                        let (docs, attrs): (Vec<_>, Vec<_>) = field
+                            .attrs
+                            .iter()
+                            .partition(|attr| attr.path().is_ident("doc"));
+                        json!({
+                            "name": field.ident.as_ref().map(ToString::to_string),
+                            "type": slice(source, field.ty.span().start(), field.ty.span().end()),
+                            "doc": docs.iter().filter_map(|attr| doc_line(&attr.meta)).collect::<Vec<_>>(),
+                            "attrs": attrs.iter().map(|attr| slice(source, attr.span().start(), attr.span().end())).collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect();
+                let vis = fields
+                    .named
+                    .first()
+                    .map(|field| field.vis.to_token_stream().to_string())
+                    .filter(|vis| !vis.is_empty())
+                    .map(|vis| format!("{} ", vis.replace(" (", "(").replace(" )", ")")))
+                    .unwrap_or_default();
+
+                context.insert("fields".to_string(), Value::Array(entries));
+                let span = fields.brace_token.span;
+                fields_block = Some((
+                    offset(source, span.open().end()),
+                    offset(source, span.close().start()),
+                    fields_loop(&vis),
+                ));
+            }
+            None => anyhow::bail!(
+                "Unknown variable '{}', expected name, fields or key=text",
+                var
+            ),
+        }
+    }
+
+    let substitute = |text: &str| {
+        words.iter().fold(text.to_string(), |text, (word, key)| {
+            replace_word(&text, word, &format!("{{{{ {} }}}}", key))
+        })
+    };
+    let template = match fields_block {
+        Some((start, end, fields_loop)) => format!(
+            "{}{}{}",
+            substitute(&source[..start]),
+            fields_loop,
+            substitute(&source[end..])
+        ),
+        None => substitute(source),
+    };
+
+    Ok(Templatized {
+        template,
+        context: Value::Object(context),
+    })
+}
+
+/// Loop rendering struct fields, each with its docs and attributes
+fn fields_loop(vis: &str) -> String {
+    [
+        "\n{%- for field in fields %}",
+        "\n{%- for line in field.doc %}\n    ///{{ line }}\n{%- endfor %}",
+        "\n{%- for attr in field.attrs %}\n    {{ attr }}\n{%- endfor %}",
+        &format!("\n    {}{{{{ field.name }}}}: {{{{ field.type }}}},", vis),
+        "\n{%- endfor %}\n",
+    ]
+    .concat()
+}
+
+/// Text of a `///` line from its `#[doc = "…"]` attribute
+fn doc_line(meta: &Meta) -> Option<String> {
+    let Meta::NameValue(meta) = meta else {
+        return None;
+    };
+    match &meta.value {
+        Expr::Lit(expr) => match &expr.lit {
+            Lit::Str(text) => Some(text.value()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Byte offset of a span location in `source`
+fn offset(source: &str, location: LineColumn) -> usize {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(location.line - 1)
+        .map(str::len)
+        .sum();
+    source[line_start..]
+        .char_indices()
+        .nth(location.column)
+        .map_or(source.len(), |(index, _)| line_start + index)
+}
+
+/// Source text between two span locations
+fn slice(source: &str, start: LineColumn, end: LineColumn) -> &str {
+    &source[offset(source, start)..offset(source, end)]
+}
+
+/// Replace whole-word occurrences of `word` in `text`
+fn replace_word(text: &str, word: &str, with: &str) -> String {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+
+    for (start, _) in text.match_indices(word) {
+        let end = start + word.len();
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        if !before.is_some_and(is_ident) && !after.is_some_and(is_ident) {
+            result.push_str(&text[copied..start]);
+            result.push_str(with);
+            copied = end;
+        }
+    }
+    result.push_str(&text[copied..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{parse_context, render_str};
+
+    #[test]
+    fn test_templatize() {
+        let source = "/// A registered user\n\
+            #[derive(Debug)]\n\
+            pub struct User {\n    \
+                /// Unique identifier\n    \
+                pub id: u64,\n    \
+                #[serde(rename = \"mail\")]\n    \
+                pub email: Vec<String>,\n\
+            }\n\n\
+            impl User {\n    \
+                pub fn table() -> &'static str {\n        \
+                    \"users\"\n    \
+                }\n\
+            }\n";
+        let vars = ["name", "fields", "table=users"].map(String::from);
+        let templatized = templatize(source, &vars).unwrap();
+
+        assert!(templatized.template.contains("pub struct {{ name }} {"));
+        assert!(templatized.template.contains("impl {{ name }} {"));
+        assert!(templatized.template.contains("\"{{ table }}\""));
+        assert_eq!(templatized.context["fields"][1]["type"], "Vec<String>");
+        assert_eq!(
+            templatized.context["fields"][1]["attrs"][0],
+            "#[serde(rename = \"mail\")]"
+        );
+
+        let context = parse_context(&templatized.context.to_string()).unwrap();
+        assert_eq!(render_str(&templatized.template, &context).unwrap(), source);
+
+        assert!(templatize(source, &["size".to_string()]).is_err());
+    }
+}
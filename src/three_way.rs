@@ -0,0 +1,331 @@
+//! Three-way merge module
+//!
+//! This module merges two edited versions of a Rust file against their common
+//! ancestor at the item level, the way `git merge` does for lines.
+//!
+//! `generate --base` gets the same semantics through the regular merge:
+//! [`apply_unedited`] takes the template's changes since the base for items not
+//! edited by hand, and [`narrow_patch`] leaves the merge strategy only the items
+//! changed on both sides.
+
+use anyhow::{Context, Result};
+use quote::ToTokens;
+use std::collections::HashMap;
+use syn::{File, Item};
+
+use crate::ast_parser::{item_kind, ParsedFile};
+use crate::diff::{extract_item_name, Patch, PatchOp};
+
+/// Result of a three-way merge
+#[derive(Debug)]
+pub struct ThreeWayResult {
+    /// Merged source, with conflict markers around conflicting items
+    pub merged: String,
+    /// Descriptions of the conflicting items
+    pub conflicts: Vec<String>,
+}
+
+/// One piece of the merged output
+enum Segment<'a> {
+    Clean(&'a Item),
+    Conflict {
+        ours: Option<&'a Item>,
+        theirs: Option<&'a Item>,
+    },
+}
+
+/// Merge `ours` and `theirs` against their common ancestor `base`
+pub fn merge_three_way(base: &str, ours: &str, theirs: &str) -> Result<ThreeWayResult> {
+    let base_file = ParsedFile::parse(base).context("Failed to parse base version")?;
+    let ours_file = ParsedFile::parse(ours).context("Failed to parse our version")?;
+    let theirs_file = ParsedFile::parse(theirs).context("Failed to parse their version")?;
+
+    let base_map: HashMap<String, &Item> = keyed(base_file.items()).into_iter().collect();
+    let ours_items = keyed(ours_file.items());
+    let theirs_items = keyed(theirs_file.items());
+    let theirs_map: HashMap<&str, &Item> = theirs_items
+        .iter()
+        .map(|(key, item)| (key.as_str(), *item))
+        .collect();
+
+    let mut segments: Vec<(String, Segment)> = Vec::new();
+    let mut conflicts = Vec::new();
+
+    // Walk our version, which decides the layout
+    for (key, ours_item) in &ours_items {
+        let base_item = base_map.get(key).copied();
+        let theirs_item = theirs_map.get(key.as_str()).copied();
+
+        let segment = match (base_item, theirs_item) {
+            (_, Some(theirs_item)) if same(ours_item, theirs_item) => Segment::Clean(ours_item),
+            (Some(base_item), Some(theirs_item)) if same(ours_item, base_item) => {
+                Segment::Clean(theirs_item)
+            }
+            (Some(base_item), Some(theirs_item)) if same(theirs_item, base_item) => {
+                Segment::Clean(ours_item)
+            }
+            (Some(_), Some(theirs_item)) => {
+                conflicts.push(format!("`{}` was modified on both sides", key));
+                Segment::Conflict {
+                    ours: Some(ours_item),
+                    theirs: Some(theirs_item),
+                }
+            }
+            (None, Some(theirs_item)) => {
+                conflicts.push(format!("`{}` was added differently on both sides", key));
+                Segment::Conflict {
+                    ours: Some(ours_item),
+                    theirs: Some(theirs_item),
+                }
+            }
+            (None, None) => Segment::Clean(ours_item),
+            (Some(base_item), None) if same(ours_item, base_item) => continue,
+            (Some(_), None) => {
+                conflicts.push(format!("`{}` was modified by us and deleted by them", key));
+                Segment::Conflict {
+                    ours: Some(ours_item),
+                    theirs: None,
+                }
+            }
+        };
+
+        segments.push((key.clone(), segment));
+    }
+
+    // Place items only present in their version after their predecessor
+    let ours_keys: std::collections::HashSet<&str> =
+        ours_items.iter().map(|(key, _)| key.as_str()).collect();
+    for (position, (key, theirs_item)) in theirs_items.iter().enumerate() {
+        if ours_keys.contains(key.as_str()) {
+            continue;
+        }
+
+        let segment = match base_map.get(key) {
+            None => Segment::Clean(theirs_item),
+            Some(base_item) if same(theirs_item, base_item) => continue,
+            Some(_) => {
+                conflicts.push(format!("`{}` was deleted by us and modified by them", key));
+                Segment::Conflict {
+                    ours: None,
+                    theirs: Some(theirs_item),
+                }
+            }
+        };
+
+        let insert_at = theirs_items[..position]
+            .iter()
+            .rev()
+            .find_map(|(previous, _)| segments.iter().position(|(k, _)| k == previous))
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+        segments.insert(insert_at, (key.clone(), segment));
+    }
+
+    let merged = render(&ours_file.syntax_tree, &segments);
+
+    Ok(ThreeWayResult { merged, conflicts })
+}
+
+/// Existing items, with the template's changes since `base` applied to those not edited by hand
+///
+/// An item still equal to its `base` version takes its `generated` version,
+/// or is dropped when the template no longer generates it.
+pub fn apply_unedited(base: &[Item], existing: &[Item], generated: &[Item]) -> Vec<Item> {
+    let base_map = named(base);
+    let generated_map = named(generated);
+
+    existing
+        .iter()
+        .filter_map(|item| {
+            let Some(name) = extract_item_name(item) else {
+                return Some(item.clone());
+            };
+            match base_map.get(&name) {
+                Some(base_item) if same(base_item, item) => {
+                    generated_map.get(&name).map(|item| (*item).clone())
+                }
+                _ => Some(item.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Narrow a patch from the existing items to the template down to the template's changes since `base`
+///
+/// Items added or deleted by hand are left alone, as are items edited by hand
+/// the template didn't change. Modifications left take the `base` version as
+/// their ancestor.
+pub fn narrow_patch<'a>(patch: &mut Patch<'a>, base: &'a [Item]) {
+    let base_map = named(base);
+
+    patch.operations.retain_mut(|op| match op {
+        PatchOp::Insert { name, item } => base_map
+            .get(name.as_str())
+            .is_none_or(|base_item| !same(base_item, item)),
+        PatchOp::Delete { name } => {
+            if !base_map.contains_key(name.as_str()) {
+                *op = PatchOp::Keep { name: name.clone() };
+            }
+            true
+        }
+        PatchOp::Modify {
+            name,
+            old_item,
+            new_item,
+        } => {
+            match base_map.get(name.as_str()) {
+                Some(base_item) if same(base_item, new_item) => {
+                    *op = PatchOp::Keep { name: name.clone() };
+                }
+                Some(base_item) => *old_item = base_item,
+                None => {}
+            }
+            true
+        }
+        _ => true,
+    });
+}
+
+/// Named items by name, as [`crate::diff::compute_patch`] pairs them
+fn named(items: &[Item]) -> HashMap<String, &Item> {
+    let mut named = HashMap::new();
+    for item in items {
+        if let Some(name) = extract_item_name(item) {
+            named.entry(name).or_insert(item);
+        }
+    }
+    named
+}
+
+/// Pair every item with a key that identifies it across versions
+///
+/// Named items use their kind and name; impl blocks use their header, and any
+/// other item its full token text.
+fn keyed(items: &[Item]) -> Vec<(String, &Item)> {
+    items.iter().map(|item| (item_key(item), item)).collect()
+}
+
+fn item_key(item: &Item) -> String {
+    if let Some(name) = extract_item_name(item) {
+        return format!("{} {}", item_kind(item), name);
+    }
+
+    match item {
+        Item::Impl(imp) => {
+            let trait_part = imp
+                .trait_
+                .as_ref()
+                .map(|(bang, path, _)| {
+                    let bang = if bang.is_some() { "!" } else { "" };
+                    format!("{}{} for ", bang, path.to_token_stream())
+                })
+                .unwrap_or_default();
+            format!(
+                "impl{} {}{}",
+                imp.generics.to_token_stream(),
+                trait_part,
+                imp.self_ty.to_token_stream()
+            )
+        }
+        other => other.to_token_stream().to_string(),
+    }
+}
+
+fn same(a: &Item, b: &Item) -> bool {
+    a == b
+}
+
+/// Print the merged segments, keeping file-level attributes of our version
+fn render(ours: &File, segments: &[(String, Segment)]) -> String {
+    let mut chunks = Vec::new();
+    let mut pending = Vec::new();
+
+    if !ours.attrs.is_empty() || ours.shebang.is_some() {
+        chunks.push(unparse(
+            ours.shebang.clone(),
+            ours.attrs.clone(),
+            Vec::new(),
+        ));
+    }
+
+    for (_, segment) in segments {
+        match segment {
+            Segment::Clean(item) => pending.push((*item).clone()),
+            Segment::Conflict { ours, theirs } => {
+                if !pending.is_empty() {
+                    chunks.push(unparse(None, Vec::new(), std::mem::take(&mut pending)));
+                }
+                chunks.push(format!(
+                    "<<<<<<< ours\n{}=======\n{}>>>>>>> theirs\n",
+                    render_side(*ours),
+                    render_side(*theirs)
+                ));
+            }
+        }
+    }
+
+    if !pending.is_empty() {
+        chunks.push(unparse(None, Vec::new(), pending));
+    }
+
+    chunks.join("\n")
+}
+
+fn render_side(item: Option<&Item>) -> String {
+    item.map(|item| unparse(None, Vec::new(), vec![item.clone()]))
+        .unwrap_or_default()
+}
+
+fn unparse(shebang: Option<String>, attrs: Vec<syn::Attribute>, items: Vec<Item>) -> String {
+    prettyplease::unparse(&File {
+        shebang,
+        attrs,
+        items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_independent_changes() {
+        let base = "fn a() {}\nfn b() {}\nimpl Foo { fn x() {} }\n";
+        let ours = "fn a() { one(); }\nfn b() {}\nimpl Foo { fn x() {} }\n";
+        let theirs = "fn a() {}\nfn b() { two(); }\nimpl Foo { fn x() {} }\nfn c() {}\n";
+
+        let result = merge_three_way(base, ours, theirs).unwrap();
+
+        assert!(result.conflicts.is_empty());
+        let merged = ParsedFile::parse(&result.merged).unwrap();
+        assert_eq!(merged.items().len(), 4);
+        assert!(result.merged.contains("one()"));
+        assert!(result.merged.contains("two()"));
+        assert!(result.merged.contains("impl Foo"));
+    }
+
+    #[test]
+    fn test_merge_conflicting_changes() {
+        let base = "fn a() {}\n";
+        let ours = "fn a() { one(); }\n";
+        let theirs = "fn a() { two(); }\n";
+
+        let result = merge_three_way(base, ours, theirs).unwrap();
+
+        assert_eq!(result.conflicts, vec!["`fn a` was modified on both sides"]);
+        assert!(result.merged.contains("<<<<<<< ours"));
+        assert!(result.merged.contains(">>>>>>> theirs"));
+    }
+
+    #[test]
+    fn test_merge_deletion() {
+        let base = "fn a() {}\nfn b() {}\n";
+        let ours = "fn a() {}\nfn b() {}\n";
+        let theirs = "fn a() {}\n";
+
+        let result = merge_three_way(base, ours, theirs).unwrap();
+
+        assert!(result.conflicts.is_empty());
+        assert!(!result.merged.contains("fn b"));
+    }
+}
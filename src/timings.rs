@@ -0,0 +1,188 @@
+//! Timings module
+//!
+//! This module measures how long each stage of generation takes, for
+//! `--timings`. The pipeline wraps each stage in [`time`], which records its
+//! duration for the current thread once [`enable`] was called, and the caller
+//! collects the stages of each file with [`take`]. [`report`] prints them as a
+//! table with one row per file and a total row:
+//!
+//! ```text
+//! file         render  parse existing  parse generated  diff   merge  format  write  total
+//! src/user.rs  1.2ms   0.4ms           0.3ms            0.1ms  0.2ms  2.1ms   0.1ms  4.4ms
+//! ```
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Whether stages are recorded
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Stages recorded on this thread since the last [`take`]
+    static RECORDED: RefCell<Timings> = RefCell::new(Timings::default());
+}
+
+/// A stage of generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Render,
+    ParseExisting,
+    ParseGenerated,
+    Diff,
+    Merge,
+    Format,
+    Write,
+}
+
+impl Stage {
+    /// Every stage, in pipeline order
+    pub const ALL: [Stage; 7] = [
+        Stage::Render,
+        Stage::ParseExisting,
+        Stage::ParseGenerated,
+        Stage::Diff,
+        Stage::Merge,
+        Stage::Format,
+        Stage::Write,
+    ];
+
+    /// Column header of the stage
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Render => "render",
+            Stage::ParseExisting => "parse existing",
+            Stage::ParseGenerated => "parse generated",
+            Stage::Diff => "diff",
+            Stage::Merge => "merge",
+            Stage::Format => "format",
+            Stage::Write => "write",
+        }
+    }
+}
+
+/// Time spent in each stage
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    durations: [Duration; 7],
+}
+
+impl Timings {
+    /// Time spent in `stage`
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.durations[stage as usize]
+    }
+
+    /// Add time spent in `stage`
+    pub fn add(&mut self, stage: Stage, duration: Duration) {
+        self.durations[stage as usize] += duration;
+    }
+
+    /// Time spent in all stages
+    pub fn total(&self) -> Duration {
+        self.durations.iter().sum()
+    }
+}
+
+/// Start recording stages, for the rest of the process
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Run `f`, recording its duration under `stage` when timings are enabled
+pub fn time<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    RECORDED.with(|recorded| recorded.borrow_mut().add(stage, elapsed));
+    result
+}
+
+/// Stages recorded on this thread since the last call
+pub fn take() -> Timings {
+    RECORDED.with(|recorded| recorded.take())
+}
+
+/// Table of the stages of each file, with a total row when there are several
+pub fn report(files: &[(PathBuf, Timings)]) -> String {
+    let mut rows: Vec<(String, &Timings)> = files
+        .iter()
+        .map(|(path, timings)| (path.display().to_string(), timings))
+        .collect();
+    let mut total = Timings::default();
+    for (_, timings) in files {
+        for stage in Stage::ALL {
+            total.add(stage, timings.get(stage));
+        }
+    }
+    if files.len() > 1 {
+        rows.push(("total".to_string(), &total));
+    }
+
+    let mut header = vec!["file".to_string()];
+    header.extend(Stage::ALL.iter().map(|stage| stage.label().to_string()));
+    header.push("total".to_string());
+    let mut table = vec![header];
+    for (name, timings) in rows {
+        let mut row = vec![name];
+        row.extend(
+            Stage::ALL
+                .iter()
+                .map(|&stage| format_duration(timings.get(stage))),
+        );
+        row.push(format_duration(timings.total()));
+        table.push(row);
+    }
+
+    let widths: Vec<usize> = (0..table[0].len())
+        .map(|column| table.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect();
+    table
+        .iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            format!("{}\n", cells.join("  ").trim_end())
+        })
+        .collect()
+}
+
+/// Duration in milliseconds, with a tenth of a millisecond
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut user = Timings::default();
+        user.add(Stage::Render, Duration::from_micros(1200));
+        user.add(Stage::Format, Duration::from_micros(2100));
+        let mut order = Timings::default();
+        order.add(Stage::Render, Duration::from_micros(800));
+
+        enable();
+        let value = time(Stage::Diff, || 42);
+        assert_eq!(value, 42);
+        take();
+        assert_eq!(take(), Timings::default());
+
+        let report = report(&[("src/user.rs".into(), user), ("src/order.rs".into(), order)]);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("file          render  parse existing"));
+        assert!(lines[1].starts_with("src/user.rs   1.2ms   0.0ms"));
+        assert!(lines[1].ends_with("3.3ms"));
+        assert!(lines[3].starts_with("total         2.0ms"));
+    }
+}
@@ -0,0 +1,118 @@
+//! Directory-tree template module
+//!
+//! This module renders a template directory that mirrors an output tree, for
+//! scaffolding whole modules or crates. Every `.tera` file becomes the file at
+//! the same relative path without the extension, and path segments may use
+//! Tera expressions such as `src/{{ name }}.rs.tera`. Files whose name starts
+//! with `_` are only available to `extends` and `include`.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::file_blocks::{split_files, validate_path, RenderedFile};
+use crate::template::{render_str, TemplateEngine};
+
+/// Render every file of a template directory
+///
+/// Returned paths are relative to the output directory. Templates with
+/// `{% file %}` blocks contribute one file per block instead.
+pub fn render_tree(
+    template_dir: &Path,
+    context_data: &HashMap<String, Value>,
+    lenient: bool,
+) -> Result<Vec<RenderedFile>> {
+    let engine = TemplateEngine::new(template_dir)?;
+
+    let mut names = engine.get_template_names();
+    names.sort_unstable();
+
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    for name in names {
+        if name
+            .rsplit('/')
+            .next()
+            .is_some_and(|file| file.starts_with('_'))
+        {
+            continue;
+        }
+
+        let rendered = engine
+            .render_with(name, context_data, lenient)
+            .with_context(|| format!("Failed to render {}", name))?;
+
+        let rendered_files = match split_files(&rendered)? {
+            Some(blocks) => blocks,
+            None => vec![RenderedFile {
+                path: output_path(name, context_data)?,
+                code: rendered,
+                conflicts: Vec::new(),
+            }],
+        };
+
+        for file in rendered_files {
+            if !seen.insert(file.path.clone()) {
+                anyhow::bail!("File '{}' is generated more than once", file.path.display());
+            }
+            files.push(file);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Output path of a tree template: its name with the path rendered and `.tera` removed
+fn output_path(template_name: &str, context_data: &HashMap<String, Value>) -> Result<PathBuf> {
+    let stripped = template_name.strip_suffix(".tera").unwrap_or(template_name);
+
+    validate_path(&render_str(stripped, context_data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::create_context;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn test_render_tree_with_templated_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/{{ name }}")).unwrap();
+        fs::write(dir.path().join("_header.tera"), "// {{ name }}\n").unwrap();
+        fs::write(
+            dir.path().join("src/{{ name }}/mod.rs.tera"),
+            "{% include \"_header.tera\" %}pub struct {{ name | title }};\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml.tera"),
+            "name = \"{{ name }}\"\n",
+        )
+        .unwrap();
+
+        let context = create_context(vec![("name", json!("user"))]);
+        let files = render_tree(dir.path(), &context, false).unwrap();
+
+        let paths: Vec<_> = files.iter().map(|file| file.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("Cargo.toml"),
+                PathBuf::from("src/user/mod.rs")
+            ]
+        );
+        assert_eq!(files[1].code, "// user\npub struct User;\n");
+    }
+
+    #[test]
+    fn test_render_tree_rejects_escaping_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("{{ name }}.rs.tera"), "").unwrap();
+
+        let context = create_context(vec![("name", json!("../escape"))]);
+        assert!(render_tree(dir.path(), &context, false).is_err());
+    }
+}
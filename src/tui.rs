@@ -0,0 +1,182 @@
+//! Interactive review terminal UI
+//!
+//! This module drives a [`Review`] with ratatui: changes are listed on the
+//! left, the selected change's diff is shown on the right, and nothing is
+//! written until the reviewer confirms.
+
+use anyhow::{Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::fs;
+use std::process::Command;
+
+use crate::review::{Decision, Review};
+
+const HELP: &str =
+    "↑/↓ select  a accept  r reject  e edit  PgUp/PgDn scroll  w write  q quit without writing";
+
+/// UI state besides the review itself
+struct State {
+    list: ListState,
+    scroll: u16,
+    status: String,
+}
+
+/// Run the review UI until the reviewer writes or quits
+///
+/// Returns true when the reviewer asked to write the result.
+pub fn run(review: &mut Review) -> Result<bool> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, review);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, review: &mut Review) -> Result<bool> {
+    let mut state = State {
+        list: ListState::default().with_selected(Some(0)),
+        scroll: 0,
+        status: HELP.to_string(),
+    };
+
+    loop {
+        terminal.draw(|frame| draw(frame, review, &mut state))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        let selected = state.list.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char('w') => return Ok(true),
+            KeyCode::Down | KeyCode::Char('j') => {
+                state.list.select_next();
+                state.scroll = 0;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                state.list.select_previous();
+                state.scroll = 0;
+            }
+            KeyCode::PageDown => state.scroll = state.scroll.saturating_add(10),
+            KeyCode::PageUp => state.scroll = state.scroll.saturating_sub(10),
+            KeyCode::Char('a') => {
+                review.entries[selected].decision = Decision::Accept;
+                state.list.select_next();
+                state.scroll = 0;
+            }
+            KeyCode::Char('r') => {
+                review.entries[selected].decision = Decision::Reject;
+                state.list.select_next();
+                state.scroll = 0;
+            }
+            KeyCode::Char('e') => {
+                // The editor needs the terminal back while it runs
+                ratatui::restore();
+                let edited = edit_item(&review.entries[selected].edit_source());
+                *terminal = ratatui::init();
+
+                state.status = match edited {
+                    Ok(item) => {
+                        review.entries[selected].decision = Decision::Edit(Box::new(item));
+                        format!("Edited `{}`", review.entries[selected].name)
+                    }
+                    Err(err) => format!("Edit discarded: {:#}", err),
+                };
+            }
+            _ => {}
+        }
+
+        // Keep the selection within the list
+        let last = review.entries.len().saturating_sub(1);
+        if state.list.selected().is_some_and(|idx| idx > last) {
+            state.list.select(Some(last));
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, review: &Review, state: &mut State) {
+    let [main, footer] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let [changes, diff] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+
+    let items: Vec<ListItem> = review
+        .entries
+        .iter()
+        .map(|entry| {
+            let (marker, color) = match entry.decision {
+                Decision::Pending => ("[ ]", Color::Reset),
+                Decision::Accept => ("[✓]", Color::Green),
+                Decision::Reject => ("[✗]", Color::Red),
+                Decision::Edit(_) => ("[✎]", Color::Yellow),
+            };
+            ListItem::new(format!("{} {}", marker, entry.summary)).style(Style::new().fg(color))
+        })
+        .collect();
+
+    let title = format!(" Changes ({} pending, kept as is) ", review.pending());
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, changes, &mut state.list);
+
+    let selected = state
+        .list
+        .selected()
+        .and_then(|idx| review.entries.get(idx));
+    let lines: Vec<Line> = selected
+        .map(|entry| {
+            entry
+                .diff
+                .lines()
+                .map(|line| {
+                    let color = match line.chars().next() {
+                        Some('+') => Color::Green,
+                        Some('-') => Color::Red,
+                        _ => Color::Reset,
+                    };
+                    Line::styled(line.to_string(), Style::new().fg(color))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let title = selected
+        .map(|entry| format!(" {} `{}` ", entry.kind, entry.name))
+        .unwrap_or_default();
+    let diff_pane = Paragraph::new(lines)
+        .block(Block::bordered().title(title))
+        .scroll((state.scroll, 0));
+    frame.render_widget(diff_pane, diff);
+
+    frame.render_widget(Paragraph::new(state.status.as_str()), footer);
+}
+
+/// Open `$EDITOR` on an item and parse the result back
+fn edit_item(source: &str) -> Result<syn::Item> {
+    let path = std::env::temp_dir().join(format!("rpt-review-{}.rs", std::process::id()));
+    fs::write(&path, source)?;
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let status = Command::new(&editor)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to run editor '{}'", editor))?;
+
+    let edited = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+
+    if !status.success() {
+        anyhow::bail!("editor exited with {}", status);
+    }
+    syn::parse_str(&edited?).context("edited item is not valid Rust")
+}
@@ -0,0 +1,356 @@
+//! Post-merge verification module
+//!
+//! This module checks that a written output still builds, and restores the
+//! previous file contents when it does not.
+//!
+//! `--verify clippy` runs `cargo clippy` instead, and also fails on warnings
+//! in the items the generation added or changed, found from the lint spans.
+//! Warnings in items left as they were are not the generation's doing and are
+//! ignored. `--verify clippy-report` prints those warnings without failing.
+
+use anyhow::{Context, Result};
+use quote::ToTokens;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use syn::spanned::Spanned;
+use syn::Item;
+
+use crate::ast_parser::{item_kind, ParsedFile};
+use crate::diff::extract_item_name;
+
+/// How a written output is verified
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verification {
+    /// `cargo check`, or syntax checks outside a cargo project
+    Check,
+    /// `cargo clippy`, failing on warnings in generated items
+    Clippy,
+    /// `cargo clippy`, reporting warnings in generated items
+    ClippyReport,
+}
+
+impl Verification {
+    /// Parse `check`, `clippy` or `clippy-report`
+    pub fn parse(verification: &str) -> Result<Self> {
+        match verification {
+            "check" => Ok(Verification::Check),
+            "clippy" => Ok(Verification::Clippy),
+            "clippy-report" => Ok(Verification::ClippyReport),
+            _ => anyhow::bail!(
+                "Unknown verification '{}', expected check, clippy or clippy-report",
+                verification
+            ),
+        }
+    }
+
+    /// The verification as `--verify` takes it
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Verification::Check => "check",
+            Verification::Clippy => "clippy",
+            Verification::ClippyReport => "clippy-report",
+        }
+    }
+}
+
+/// Write `contents` to `path` and roll back if verification fails
+///
+/// The previous contents are saved to a `.bak` file next to the output. It is
+/// removed once the new file verifies and left in place on failure. Returns
+/// the clippy warnings reported in generated items.
+pub fn write_verified(
+    path: &Path,
+    contents: &str,
+    verification: Verification,
+) -> Result<Vec<String>> {
+    let backup = backup_path(path);
+    let had_original = path.exists();
+    let previous = fs::read_to_string(path).ok();
+
+    if had_original {
+        fs::copy(path, &backup).context("Failed to back up existing file")?;
+    }
+
+    fs::write(path, contents)?;
+
+    match verify_output(path, verification, previous.as_deref()) {
+        Ok(lints) => {
+            if had_original {
+                fs::remove_file(&backup)?;
+            }
+            Ok(lints)
+        }
+        Err(err) => {
+            if had_original {
+                fs::copy(&backup, path).context("Failed to restore backup")?;
+                Err(err.context(format!(
+                    "Verification failed, previous file restored (backup kept at {})",
+                    backup.display()
+                )))
+            } else {
+                fs::remove_file(path)?;
+                Err(err.context("Verification failed, generated file removed"))
+            }
+        }
+    }
+}
+
+/// Verify a written file, `previous` being its contents before generation
+///
+/// Returns the clippy warnings reported in generated items.
+pub fn verify_output(
+    path: &Path,
+    verification: Verification,
+    previous: Option<&str>,
+) -> Result<Vec<String>> {
+    match (verification, find_manifest(path)) {
+        (Verification::Check, Some(manifest)) => cargo_check(&manifest).map(|()| Vec::new()),
+        (Verification::Check, None) => sanity_check(path).map(|()| Vec::new()),
+        (_, None) => anyhow::bail!(
+            "--verify clippy needs a Cargo.toml above {}",
+            path.display()
+        ),
+        (_, Some(manifest)) => {
+            let lints = clippy_lints(&manifest, path, previous)?;
+            if verification == Verification::Clippy && !lints.is_empty() {
+                anyhow::bail!("clippy warnings in generated items:\n{}", lints.join("\n"));
+            }
+            Ok(lints)
+        }
+    }
+}
+
+/// Run `cargo check` for the package owning the output file
+fn cargo_check(manifest: &Path) -> Result<()> {
+    let output = Command::new("cargo")
+        .args(["check", "--quiet", "--message-format", "short"])
+        .arg("--manifest-path")
+        .arg(manifest)
+        .output()
+        .context("Failed to run cargo check")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "cargo check failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(())
+}
+
+/// Run `cargo clippy` for the package owning the output file, returning the
+/// warnings in its items that differ from `previous`
+fn clippy_lints(manifest: &Path, path: &Path, previous: Option<&str>) -> Result<Vec<String>> {
+    let output = Command::new("cargo")
+        .args(["clippy", "--quiet", "--message-format=json"])
+        .arg("--manifest-path")
+        .arg(manifest)
+        .output()
+        .context("Failed to run cargo clippy (is clippy installed?)")?;
+    let messages = String::from_utf8_lossy(&output.stdout);
+
+    if !output.status.success() {
+        let errors: Vec<String> = compiler_messages(&messages)
+            .filter(|message| message["level"] == "error")
+            .filter_map(|message| message["rendered"].as_str().map(str::to_string))
+            .collect();
+        anyhow::bail!(
+            "cargo clippy failed:\n{}{}",
+            errors.concat(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    if path.extension().is_none_or(|extension| extension != "rs") {
+        return Ok(Vec::new());
+    }
+    let source = fs::read_to_string(path)?;
+    let items = changed_items(&source, previous)?;
+    let file = |name: &str| {
+        // Spans are relative to the workspace root, somewhere above the package
+        manifest
+            .parent()
+            .into_iter()
+            .flat_map(Path::ancestors)
+            .filter_map(|dir| dir.join(name).canonicalize().ok())
+            .any(|candidate| Some(candidate) == path.canonicalize().ok())
+    };
+    Ok(lints_in_items(&messages, file, &items))
+}
+
+/// Warnings of cargo's JSON `messages` in `items` of the file `file` accepts
+///
+/// Items are given by name and line range.
+fn lints_in_items(
+    messages: &str,
+    file: impl Fn(&str) -> bool,
+    items: &[(String, usize, usize)],
+) -> Vec<String> {
+    let mut lints = Vec::new();
+    for message in compiler_messages(messages).filter(|message| message["level"] == "warning") {
+        let Some(span) = message["spans"]
+            .as_array()
+            .and_then(|spans| spans.iter().find(|span| span["is_primary"] == true))
+        else {
+            continue;
+        };
+        if !span["file_name"].as_str().is_some_and(&file) {
+            continue;
+        }
+        let line = span["line_start"].as_u64().unwrap_or(0) as usize;
+        let Some((item, _, _)) = items
+            .iter()
+            .find(|(_, start, end)| (*start..=*end).contains(&line))
+        else {
+            continue;
+        };
+        let lint = format!(
+            "{} in {} (line {}): {}",
+            message["code"]["code"].as_str().unwrap_or("warning"),
+            item,
+            line,
+            message["message"].as_str().unwrap_or_default()
+        );
+        if !lints.contains(&lint) {
+            lints.push(lint);
+        }
+    }
+    lints
+}
+
+/// Diagnostics of cargo's JSON messages
+fn compiler_messages(messages: &str) -> impl Iterator<Item = Value> + '_ {
+    messages
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-message")
+        .map(|message| message["message"].clone())
+}
+
+/// Name and line range of the items of `source` that `previous` lacks or has otherwise
+///
+/// Every item is changed when `previous` is missing or doesn't parse.
+fn changed_items(source: &str, previous: Option<&str>) -> Result<Vec<(String, usize, usize)>> {
+    let parsed = ParsedFile::parse(source)?;
+    let previous: HashSet<String> = previous
+        .and_then(|previous| ParsedFile::parse(previous).ok())
+        .map(|previous| {
+            previous
+                .items()
+                .iter()
+                .map(|item| item.to_token_stream().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(parsed
+        .items()
+        .iter()
+        .filter(|item| !previous.contains(&item.to_token_stream().to_string()))
+        .map(|item| {
+            let name = match extract_item_name(item) {
+                Some(name) => format!("{} `{}`", item_kind(item), name),
+                None => item_kind(item).to_string(),
+            };
+            (name, item.span().start().line, item.span().end().line)
+        })
+        .collect())
+}
+
+/// Parse the file and reject duplicate definitions of the same kind
+fn sanity_check(path: &Path) -> Result<()> {
+    let source = fs::read_to_string(path)?;
+    let parsed = ParsedFile::parse(&source)?;
+
+    let mut seen = HashSet::new();
+    for item in parsed.items() {
+        // A type can have several inherent impl blocks
+        if matches!(item, Item::Impl(imp) if imp.trait_.is_none()) {
+            continue;
+        }
+        let Some(name) = extract_item_name(item) else {
+            continue;
+        };
+        if !seen.insert((item_kind(item), name.clone())) {
+            anyhow::bail!("Duplicate {} `{}`", item_kind(item), name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the nearest Cargo.toml above the output file
+fn find_manifest(path: &Path) -> Option<PathBuf> {
+    let dir = path.canonicalize().ok()?.parent()?.to_path_buf();
+    dir.ancestors()
+        .map(|ancestor| ancestor.join("Cargo.toml"))
+        .find(|candidate| candidate.is_file())
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_verified_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.rs");
+        fs::write(&output, "fn original() {}\n").unwrap();
+
+        let result = write_verified(&output, "fn dup() {}\nfn dup() {}\n", Verification::Check);
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "fn original() {}\n");
+        assert!(backup_path(&output).exists());
+    }
+
+    #[test]
+    fn test_write_verified_success_removes_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.rs");
+        fs::write(&output, "fn original() {}\n").unwrap();
+
+        write_verified(&output, "fn updated() {}\n", Verification::Check).unwrap();
+
+        assert_eq!(fs::read_to_string(&output).unwrap(), "fn updated() {}\n");
+        assert!(!backup_path(&output).exists());
+    }
+
+    #[test]
+    fn test_lints_in_items() {
+        let previous = "fn kept() -> u32 {\n    return 1;\n}\n";
+        let source =
+            "fn kept() -> u32 {\n    return 1;\n}\n\nfn added() -> u32 {\n    return 2;\n}\n";
+        let items = changed_items(source, Some(previous)).unwrap();
+        assert_eq!(items, vec![("fn `added`".to_string(), 5, 7)]);
+
+        let warning = |line: usize| {
+            serde_json::json!({
+                "reason": "compiler-message",
+                "message": {
+                    "level": "warning",
+                    "message": "unneeded `return` statement",
+                    "code": { "code": "clippy::needless_return" },
+                    "spans": [{ "file_name": "src/out.rs", "is_primary": true, "line_start": line }]
+                }
+            })
+            .to_string()
+        };
+        let messages = [warning(2), warning(6), warning(6)].join("\n");
+        assert_eq!(
+            lints_in_items(&messages, |name| name == "src/out.rs", &items),
+            vec!["clippy::needless_return in fn `added` (line 6): unneeded `return` statement"]
+        );
+        assert!(lints_in_items(&messages, |_| false, &items).is_empty());
+    }
+}
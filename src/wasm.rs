@@ -0,0 +1,71 @@
+//! WebAssembly facade
+//!
+//! This module exposes the in-memory render/parse/diff/merge core through
+//! `wasm-bindgen`, for the browser playground and editor extensions. Build it
+//! with `wasm-pack build --features wasm`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::cli::Commands;
+use crate::generator::{self, GenerateOptions};
+use crate::merger::messages;
+use crate::template::parse_context;
+
+/// Merged code and conflicts returned to JavaScript
+#[wasm_bindgen]
+pub struct MergedOutput {
+    code: String,
+    conflicts: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl MergedOutput {
+    /// Final source code
+    #[wasm_bindgen(getter)]
+    pub fn code(&self) -> String {
+        self.code.clone()
+    }
+
+    /// Conflicts resolved by the merge strategy
+    #[wasm_bindgen(getter)]
+    pub fn conflicts(&self) -> Vec<String> {
+        self.conflicts.clone()
+    }
+}
+
+/// Render `template` with `context_json` and merge it into `existing`
+///
+/// `strategy` accepts the same values as the CLI (`manual`, `template`, `fail`, `smart`).
+/// Without `existing`, the rendered template is returned as-is.
+#[wasm_bindgen]
+pub fn render_and_merge(
+    template: &str,
+    context_json: &str,
+    existing: Option<String>,
+    strategy: Option<String>,
+) -> Result<MergedOutput, JsError> {
+    let context = parse_context(context_json).map_err(to_js_error)?;
+    let options = GenerateOptions {
+        strategy: Commands::parse_strategy(strategy.as_deref().unwrap_or("manual")),
+        ..GenerateOptions::default()
+    };
+
+    let output = generator::render_and_merge(
+        "template",
+        template,
+        &context,
+        existing.as_deref(),
+        options,
+        None,
+    )
+    .map_err(to_js_error)?;
+
+    Ok(MergedOutput {
+        code: output.code,
+        conflicts: messages(&output.conflicts),
+    })
+}
+
+fn to_js_error(err: anyhow::Error) -> JsError {
+    JsError::new(&format!("{:#}", err))
+}